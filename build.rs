@@ -0,0 +1,15 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    #[cfg(feature = "grpc")]
+    {
+        // Use the bundled compiler, so building doesn't need protoc installed.
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("No bundled protoc for this platform");
+        std::env::set_var("PROTOC", protoc);
+
+        tonic_prost_build::configure()
+            .build_client(false)
+            .compile_protos(&["proto/heightmap.proto"], &["proto"])
+            .expect("Failed to compile the gRPC protocol");
+    }
+}
@@ -0,0 +1,307 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Instant;
+
+use crate::DecodeArgs;
+use crate::ExportOptions;
+use crate::MapHeader;
+use crate::Normalize;
+use crate::summary;
+
+/// The extension of the maps picked up when batching a whole directory.
+const MAP_EXTENSION: &str = "gti";
+
+/// Converts every map `pattern` matches with the options of `args`,
+/// mirroring the directories below the pattern's base into the output
+/// directory. Failures are reported at the end instead of stopping the
+/// rest, and returns whether every map converted.
+///
+/// With `--jobs` several maps are converted at once, each worker taking the
+/// next map when it is done, so their progress lines interleave. The
+/// summary rows and the failures are still listed in file order, so
+/// repeated runs produce the same tables.
+///
+/// With `--global-range` every map is read once before any is converted, to
+/// render all of them over the height range they span together.
+pub fn run(pattern: &str, args: &DecodeArgs) -> io::Result<bool> {
+    let (base, files) = discover(pattern)?;
+    if files.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("No maps match '{}'", pattern)));
+    }
+    if let Some(template) = &args.export.output {
+        check_output(template, &base, &files)?;
+    }
+
+    let shared;
+    let args = match args.global_range {
+        Some(normalize) if !args.dry_run => {
+            let (min, max) = global_range(&files, args, normalize)?;
+            println!("Rendering every map over {} to {}", min, max);
+
+            shared = DecodeArgs { export: ExportOptions { height_range: Some((min, max)), ..args.export.clone() }, ..args.clone() };
+            &shared
+        }
+        _ => args,
+    };
+
+    let next = AtomicUsize::new(0);
+    let failures = Mutex::new(Vec::new());
+    let rows = Mutex::new(HeldRows { next: 0, done: BTreeMap::new() });
+
+    thread::scope(|scope| {
+        for _ in 0..args.jobs.clamp(1, files.len()) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let file = match files.get(i) {
+                    Some(file) => file,
+                    None => break,
+                };
+
+                let row = convert_file(file, &base, args).unwrap_or_else(|e| {
+                    eprintln!("Failed to convert {}: {}", file.display(), e);
+                    failures.lock().expect("Failure list poisoned").push((i, file, e));
+                    None
+                });
+
+                if let Some(path) = &args.summary {
+                    let finished = rows.lock().expect("Summary rows poisoned").finish(i, row);
+                    if let Err(e) = summary::append_rows(path, &finished) {
+                        let e = format!("Failed to append to {}: {}", path.display(), e);
+                        eprintln!("{}", e);
+                        failures.lock().expect("Failure list poisoned").push((i, file, e));
+                    }
+                }
+            });
+        }
+    });
+
+    // Listed in file order, however the workers finished.
+    let mut failures = failures.into_inner().expect("Failure list poisoned");
+    failures.sort_by_key(|&(i, _, _)| i);
+    let failures: Vec<_> = failures.into_iter().map(|(_, file, e)| (file, e)).collect();
+
+    println!("Converted {} of {} maps", files.len() - failures.len(), files.len());
+    if !failures.is_empty() {
+        println!("Failed:");
+        for (file, e) in &failures {
+            println!("  {}: {}", file.display(), e);
+        }
+    }
+
+    Ok(failures.is_empty())
+}
+
+/// Fails unless `--output` gives every map its own files: the template has
+/// to name `{stem}`, and without `{dir}` no two maps in different
+/// directories may share a stem.
+fn check_output(template: &str, base: &Path, files: &[PathBuf]) -> io::Result<()> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+    if !template.contains("{stem}") {
+        return Err(invalid(format!("--output '{}' would write every map to the same files, name {{stem}} in it", template)));
+    }
+
+    let mirrored = template.contains("{dir}");
+    let mut seen = BTreeMap::new();
+    for file in files {
+        let relative = file.strip_prefix(base).unwrap_or(file);
+        let dir = relative.parent().filter(|_| mirrored).unwrap_or_else(|| Path::new(""));
+        let key = (dir, crate::file_stem(&file.to_string_lossy()));
+
+        if let Some(other) = seen.insert(key, file) {
+            return Err(invalid(format!("--output '{}' writes {} and {} to the same files, add {{dir}} to it",
+                                       template, other.display(), file.display())));
+        }
+    }
+
+    Ok(())
+}
+
+/// The combined height range of every map, from their headers or from their
+/// stored heights after the edits of `args`, read on `--jobs` threads. Maps
+/// that can't be read are left out, they fail again when converted.
+fn global_range(files: &[PathBuf], args: &DecodeArgs, normalize: Normalize) -> io::Result<(f32, f32)> {
+    let next = AtomicUsize::new(0);
+    let range: Mutex<Option<(f32, f32)>> = Mutex::new(None);
+
+    thread::scope(|scope| {
+        for _ in 0..args.jobs.clamp(1, files.len()) {
+            scope.spawn(|| while let Some(file) = files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                let location = file.to_string_lossy().into_owned();
+
+                let map_range = match normalize {
+                    Normalize::Header => crate::open_input(&location, &args.limits)
+                        .and_then(|mut input| Ok(MapHeader::parse(&mut input)?))
+                        .ok()
+                        .map(|header| (header.min_height, header.max_height))
+                        .filter(|(min, max)| min.is_finite() && max.is_finite()),
+                    Normalize::Data => crate::load_map(&DecodeArgs { file: Some(location), ..args.clone() })
+                        .ok()
+                        .and_then(|map| map.data_range())
+                        .map(|range| (range.start, range.end)),
+                };
+
+                if let Some((min, max)) = map_range {
+                    let mut range = range.lock().expect("Height range poisoned");
+                    *range = Some(range.map_or((min, max), |(low, high)| (low.min(min), high.max(max))));
+                }
+            });
+        }
+    });
+
+    range.into_inner().expect("Height range poisoned")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "None of the maps has a height range to share"))
+}
+
+/// Summary rows waiting for the maps before them, so they are appended in
+/// file order whichever worker finishes first.
+struct HeldRows {
+    /// The first map whose row hasn't been appended yet.
+    next: usize,
+    /// The rows of the maps after it, `None` for maps without one.
+    done: BTreeMap<usize, Option<String>>,
+}
+
+impl HeldRows {
+    /// Records the row of map `i` and returns the rows that can be appended
+    /// now, in order.
+    fn finish(&mut self, i: usize, row: Option<String>) -> Vec<String> {
+        self.done.insert(i, row);
+
+        let mut ready = Vec::new();
+        while let Some(row) = self.done.remove(&self.next) {
+            ready.extend(row);
+            self.next += 1;
+        }
+
+        ready
+    }
+}
+
+/// Converts one of the maps below `base`, into the same directory below the
+/// output directory, and returns its summary row if `--summary` asks for
+/// one.
+fn convert_file(file: &Path, base: &Path, args: &DecodeArgs) -> Result<Option<String>, String> {
+    let relative = file.strip_prefix(base).unwrap_or(file);
+    let mut file_args = args.clone();
+    file_args.file = Some(file.to_string_lossy().into_owned());
+    file_args.export.output_dir = args.export.output_dir.join(relative.parent().unwrap_or_else(|| Path::new("")));
+    // `run` appends the row, once the maps before this one are done.
+    file_args.summary = None;
+
+    // A bad map shouldn't stop the ones after it.
+    panic::catch_unwind(AssertUnwindSafe(|| convert(&file_args, args.summary.is_some())))
+        .unwrap_or_else(|panic| Err(crate::panic_message(&*panic)))
+}
+
+fn convert(args: &DecodeArgs, summarize: bool) -> Result<Option<String>, String> {
+    if args.dry_run {
+        return crate::print_plan(args, None).map(|_| None).map_err(|e| format!("Dry run failed: {}", e));
+    }
+
+    let started = Instant::now();
+    let map = crate::load_map(args).map_err(|e| format!("Decoding failed: {}", e))?;
+
+    let outputs = crate::convert(args, &map, started).map_err(|e| format!("Export failed: {}", e))?;
+    Ok(summarize.then(|| summary::row(args.file(), &map, &outputs, started.elapsed())))
+}
+
+/// The files `pattern` stands for, sorted, and the directory their relative
+/// paths start from. A directory matches every map below it, anything else
+/// is a glob of `*`, `?` and `**` for any number of directories.
+fn discover(pattern: &str) -> io::Result<(PathBuf, Vec<PathBuf>)> {
+    let path = Path::new(pattern);
+
+    if path.is_dir() {
+        let mut files = Vec::new();
+        walk(path, &mut files)?;
+        files.retain(|file| file.extension().is_some_and(|extension| extension == MAP_EXTENSION));
+        files.sort();
+
+        return Ok((path.to_path_buf(), files));
+    }
+
+    // Everything up to the first wildcard is a plain directory to start from.
+    let mut base = PathBuf::new();
+    let mut rest = Vec::new();
+    for component in path.components() {
+        let text = component.as_os_str().to_string_lossy();
+        if rest.is_empty() && !text.contains(['*', '?']) {
+            base.push(component);
+        } else {
+            rest.push(text.into_owned());
+        }
+    }
+
+    if rest.is_empty() {
+        return Ok((base.parent().map(Path::to_path_buf).unwrap_or_default(), if base.is_file() { vec![base] } else { Vec::new() }));
+    }
+
+    let start = if base.as_os_str().is_empty() { PathBuf::from(".") } else { base.clone() };
+    let mut files = Vec::new();
+    walk(&start, &mut files)?;
+
+    let mut matched: Vec<PathBuf> = files.into_iter()
+        .filter(|file| {
+            let names: Vec<String> = file.strip_prefix(&start).unwrap_or(file).components()
+                .filter_map(|component| match component {
+                    Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                    _ => None,
+                })
+                .collect();
+            matches_path(&rest, &names)
+        })
+        .map(|file| if base.as_os_str().is_empty() { file.strip_prefix(".").map(Path::to_path_buf).unwrap_or(file) } else { file })
+        .collect();
+    matched.sort();
+
+    Ok((base, matched))
+}
+
+/// Every file below `dir`, descending into subdirectories.
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            walk(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the components of a path match those of a pattern, where `**`
+/// stands for any number of directories.
+fn matches_path(pattern: &[String], names: &[String]) -> bool {
+    match (pattern.first(), names.first()) {
+        (Some(part), _) if part == "**" =>
+            matches_path(&pattern[1..], names) || (!names.is_empty() && matches_path(pattern, &names[1..])),
+        (Some(part), Some(name)) => matches_name(part.as_bytes(), name.as_bytes()) && matches_path(&pattern[1..], &names[1..]),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// Whether a file name matches a pattern of `*` for any run of characters
+/// and `?` for a single one.
+fn matches_name(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (Some(b'*'), _) => matches_name(&pattern[1..], name) || (!name.is_empty() && matches_name(pattern, &name[1..])),
+        (Some(b'?'), Some(_)) => matches_name(&pattern[1..], &name[1..]),
+        (Some(a), Some(b)) => a == b && matches_name(&pattern[1..], &name[1..]),
+        (None, None) => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,169 @@
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::prelude::*;
+use std::path::Path;
+use std::path::PathBuf;
+
+use byteorder::LE;
+use byteorder::ReadBytesExt;
+use byteorder::WriteBytesExt;
+
+use crate::Map;
+use crate::MapHeader;
+use crate::TilePoint;
+use crate::limits::Limits;
+use crate::status;
+
+const CACHE_MAGIC: &[u8; 4] = b"HMC2";
+
+/// The default cache location, `$XDG_CACHE_HOME/heightmap-generator` or
+/// `~/.cache/heightmap-generator`.
+pub fn default_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .map(|dir| dir.join("heightmap-generator"))
+}
+
+/// Loads a map through the cache in `dir`, parsing and storing it on a miss.
+///
+/// Entries are keyed by a hash of the file contents, so edited maps never
+/// hit a stale entry.
+pub fn load(file_location: &str, dir: &Path, limits: &Limits) -> io::Result<Map> {
+    let entry = dir.join(format!("{:016x}.bin", hash_file(file_location)?));
+
+    if let Ok(file) = File::open(&entry) {
+        match read_map(&mut BufReader::new(file), limits) {
+            Ok(map) => {
+                status!("Loaded from cache: {}", entry.display());
+                return Ok(map);
+            }
+            Err(e) => status!("Ignoring broken cache entry {}: {}", entry.display(), e),
+        }
+    }
+
+    let map = Map::parse(&mut BufReader::new(File::open(file_location)?), limits)?;
+
+    fs::create_dir_all(dir)?;
+    write_map(&mut BufWriter::new(File::create(&entry)?), &map)?;
+
+    Ok(map)
+}
+
+/// The FNV-1a hash of no bytes, to start `fnv1a` from.
+pub(crate) const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Continues the FNV-1a hash `hash` over `bytes`.
+pub(crate) fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// FNV-1a over the file contents.
+fn hash_file(file_location: &str) -> io::Result<u64> {
+    let mut reader = BufReader::new(File::open(file_location)?);
+    let mut hash = FNV_OFFSET;
+
+    loop {
+        let buf = reader.fill_buf()?;
+
+        if buf.is_empty() {
+            return Ok(hash);
+        }
+
+        hash = fnv1a(hash, buf);
+
+        let len = buf.len();
+        reader.consume(len);
+    }
+}
+
+fn write_map<W: Write>(w: &mut W, map: &Map) -> io::Result<()> {
+    let header = &map.header;
+
+    w.write_all(CACHE_MAGIC)?;
+    w.write_all(&header.to_bytes())?;
+    w.write_u32::<LE>(header.raw_name.len() as u32)?;
+    w.write_all(&header.raw_name)?;
+
+    w.write_u32::<LE>(map.enabled.len() as u32)?;
+    w.write_all(&map.enabled)?;
+
+    w.write_u32::<LE>(map.points.len() as u32)?;
+    for point in &map.points {
+        w.write_f32::<LE>(point.h)?;
+        w.write_all(&[point.unk, point.r, point.g, point.b])?;
+    }
+
+    w.write_u32::<LE>(map.runs.len() as u32)?;
+    for &run in &map.runs {
+        w.write_i8(run)?;
+    }
+
+    w.flush()
+}
+
+fn read_map<R: Read>(r: &mut R, limits: &Limits) -> io::Result<Map> {
+    let mut magic = [0u8; 4];
+    r.read_exact(&mut magic)?;
+
+    if &magic != CACHE_MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Unknown cache format"));
+    }
+
+    let mut raw = [0u8; crate::HEADER_FIELDS_SIZE];
+    r.read_exact(&mut raw)?;
+
+    // The lengths are checked before anything is allocated for them, a
+    // broken entry mustn't get by the limits.
+    let name_len = r.read_u32::<LE>()? as usize;
+    if name_len > crate::NAME_SIZE {
+        return Err(broken(format!("a {} byte name", name_len)));
+    }
+
+    let mut name = vec![0u8; name_len];
+    r.read_exact(&mut name)?;
+
+    let header = MapHeader::from_bytes(&raw, &name);
+
+    limits.check_header(&header)?;
+
+    let tiles = u64::from(header.w) * u64::from(header.h);
+    let mask_len = u64::from(r.read_u32::<LE>()?);
+    if mask_len != tiles {
+        return Err(broken(format!("a mask of {} tiles for {}x{}", mask_len, header.w, header.h)));
+    }
+
+    let mut enabled = vec![0u8; mask_len as usize];
+    r.read_exact(&mut enabled)?;
+
+    let count = r.read_u32::<LE>()? as usize;
+    limits.check_points(count as u64)?;
+
+    let mut points = Vec::with_capacity(count);
+    for _ in 0..count {
+        points.push(TilePoint {
+            h: r.read_f32::<LE>()?,
+            unk: r.read_u8()?,
+            r: r.read_u8()?,
+            g: r.read_u8()?,
+            b: r.read_u8()?,
+            filled: false,
+        });
+    }
+
+    let count = r.read_u32::<LE>()? as usize;
+    limits.check_points(count as u64)?;
+
+    let mut runs = vec![0i8; count];
+    r.read_i8_into(&mut runs)?;
+
+    Ok(Map { header, points, enabled, runs })
+}
+
+fn broken(what: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("The cache entry has {}", what))
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The finished steps of a long operation, appended to a file as they
+/// complete so an interrupted run can skip them when started again.
+///
+/// The first line holds a key describing the input and settings; a file
+/// with another key belongs to a different run and is started over.
+pub struct Checkpoint {
+    path: PathBuf,
+    file: File,
+    done: HashMap<String, Vec<PathBuf>>,
+}
+
+impl Checkpoint {
+    /// Opens the checkpoint at `path`, keeping the steps recorded under
+    /// `key` if `resume` is set and starting a new one otherwise.
+    pub fn open(path: &Path, key: &str, resume: bool) -> io::Result<Checkpoint> {
+        let done = if resume { load(path, key)? } else { HashMap::new() };
+
+        if !done.is_empty() {
+            println!("Resuming from {}, {} steps already done", path.display(), done.len());
+        }
+
+        // Rewritten rather than appended to, dropping a step cut off mid-line.
+        let mut checkpoint = Checkpoint { path: path.to_path_buf(), file: File::create(path)?, done: HashMap::new() };
+        writeln!(checkpoint.file, "{}", key)?;
+
+        for (step, outputs) in &done {
+            checkpoint.record(step, outputs)?;
+        }
+        checkpoint.done = done;
+
+        Ok(checkpoint)
+    }
+
+    /// The files a step wrote, if it already finished.
+    pub fn done(&self, step: &str) -> Option<&[PathBuf]> {
+        self.done.get(step).map(Vec::as_slice)
+    }
+
+    /// Records a finished step, flushed right away so it survives the process
+    /// being killed.
+    pub fn record(&mut self, step: &str, outputs: &[PathBuf]) -> io::Result<()> {
+        let outputs: Vec<String> = outputs.iter().map(|path| path.display().to_string()).collect();
+
+        writeln!(self.file, "{}\t{}", step, outputs.join("\t"))?;
+        self.file.sync_data()
+    }
+
+    /// Removes the checkpoint once every step has finished.
+    pub fn finish(self) -> io::Result<()> {
+        drop(self.file);
+        fs::remove_file(&self.path)
+    }
+}
+
+/// The steps recorded under `key`, none if the checkpoint is missing or
+/// belongs to another run.
+fn load(path: &Path, key: &str) -> io::Result<HashMap<String, Vec<PathBuf>>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut lines = text.split_inclusive('\n');
+    if lines.next().map(|line| line.trim_end_matches('\n')) != Some(key) {
+        println!("Ignoring {}, it was written for other settings", path.display());
+        return Ok(HashMap::new());
+    }
+
+    let done = lines
+        // The last step may have been cut off mid-line.
+        .filter_map(|line| line.strip_suffix('\n'))
+        .map(|line| {
+            let mut fields = line.split('\t');
+            let step = fields.next().unwrap_or_default().to_string();
+            (step, fields.filter(|path| !path.is_empty()).map(PathBuf::from).collect())
+        })
+        .collect();
+
+    Ok(done)
+}
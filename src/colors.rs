@@ -0,0 +1,28 @@
+use std::io;
+
+use crate::Map;
+use crate::raster::Raster;
+
+impl Map {
+    /// Overwrites the color of every enabled tile with the matching pixel
+    /// of an image the size of the map. Returns the number of tiles recolored.
+    pub fn set_colors(&mut self, colors: &Raster) -> io::Result<usize> {
+        colors.check_size(self.header.w, self.header.h)?;
+
+        let w = self.header.w;
+        let h = self.header.h;
+        let tiles: Vec<(usize, usize)> = self.enabled_tiles().collect();
+
+        for &(index, offset) in &tiles {
+            let (x, y) = crate::get_position(&index, &w, &h);
+            let (r, g, b) = colors.rgb(x, y);
+            let point = &mut self.points[offset];
+
+            point.r = r;
+            point.g = g;
+            point.b = b;
+        }
+
+        Ok(tiles.len())
+    }
+}
@@ -0,0 +1,87 @@
+use std::io;
+
+use bmp::Image;
+use bmp::Pixel;
+
+use crate::raster::Raster;
+
+/// How far two images differ, with samples in `0.0..=1.0`.
+#[derive(Debug)]
+pub struct Comparison {
+    pub max_diff: f32,
+    pub mean_diff: f32,
+    pub rmse: f32,
+    /// Pixels with at least one sample beyond the tolerance.
+    pub failed_pixels: usize,
+    pub total_pixels: usize,
+    /// The largest per-pixel difference, row-major.
+    diffs: Vec<f32>,
+    width: u32,
+    height: u32,
+}
+
+impl Comparison {
+    /// Compares two images of the same size sample by sample; images with a
+    /// different channel count are compared by brightness.
+    pub fn new(a: &Raster, b: &Raster, tolerance: f32) -> io::Result<Comparison> {
+        b.check_size(a.width, a.height)?;
+
+        let mut diffs = Vec::with_capacity((a.width * a.height) as usize);
+        let mut sum = 0f64;
+        let mut sum_sq = 0f64;
+        let mut samples = 0usize;
+
+        for y in 0..a.height {
+            for x in 0..a.width {
+                let pixel_diffs: Vec<f32> = if a.channels == b.channels {
+                    a.pixel(x, y).iter().zip(b.pixel(x, y)).map(|(l, r)| (l - r).abs()).collect()
+                } else {
+                    vec![(a.luma(x, y) - b.luma(x, y)).abs()]
+                };
+
+                for &d in &pixel_diffs {
+                    sum += f64::from(d);
+                    sum_sq += f64::from(d) * f64::from(d);
+                }
+                samples += pixel_diffs.len();
+
+                diffs.push(pixel_diffs.into_iter().fold(0f32, f32::max));
+            }
+        }
+
+        let samples = samples.max(1) as f64;
+
+        Ok(Comparison {
+            max_diff: diffs.iter().cloned().fold(0f32, f32::max),
+            mean_diff: (sum / samples) as f32,
+            rmse: (sum_sq / samples).sqrt() as f32,
+            failed_pixels: diffs.iter().filter(|&&d| d > tolerance).count(),
+            total_pixels: diffs.len(),
+            diffs,
+            width: a.width,
+            height: a.height,
+        })
+    }
+
+    pub fn passed(&self) -> bool {
+        self.failed_pixels == 0
+    }
+
+    /// Renders the differences stretched to full brightness, with pixels
+    /// beyond the tolerance shown in red.
+    pub fn diff_image(&self, tolerance: f32) -> Image {
+        let mut img = Image::new(self.width, self.height);
+        let scale = if self.max_diff > 0f32 { 255f32 / self.max_diff } else { 0f32 };
+
+        for (i, &d) in self.diffs.iter().enumerate() {
+            let x = i as u32 % self.width;
+            let y = i as u32 / self.width;
+            let level = (d * scale) as u8;
+
+            let pixel = if d > tolerance { Pixel::new(255, 0, 0) } else { Pixel::new(level, level, level) };
+            img.set_pixel(x, y, pixel);
+        }
+
+        img
+    }
+}
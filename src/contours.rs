@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+
+use crate::Map;
+
+/// A line of constant height through the tile centers, in image
+/// coordinates with tile `(x, y)` covering `x..x + 1` and `y..y + 1`.
+#[derive(Debug, Clone)]
+pub struct Contour {
+    pub points: Vec<(f32, f32)>,
+    /// Whether the line closes on itself, rather than ending at the map edge
+    /// or at disabled tiles.
+    pub closed: bool,
+}
+
+/// Where a line crosses between two neighboring tile centers: the index of
+/// the upper or left tile, doubled, plus 1 for the edge going down.
+type Crossing = usize;
+
+impl Map {
+    /// The lines where the surface crosses `level`, traced with marching
+    /// squares between the centers of every 2x2 block of enabled tiles.
+    /// Tiles exactly at the level count as above it.
+    pub fn contours(&self, level: f32) -> Vec<Contour> {
+        let w = self.header.w as usize;
+        let h = self.header.h as usize;
+        let heights = self.tile_heights();
+
+        let right = |i: usize| 2 * i;
+        let down = |i: usize| 2 * i + 1;
+        let point = |crossing: Crossing| {
+            let i = crossing / 2;
+            let (x, y) = ((i % w) as f32 + 0.5, (i / w) as f32 + 0.5);
+            let downwards = crossing % 2 == 1;
+            let j = if downwards { i + w } else { i + 1 };
+            let (a, b) = (heights[i].unwrap_or_default(), heights[j].unwrap_or_default());
+            let t = (level - a) / (b - a);
+            if downwards { (x, y + t) } else { (x + t, y) }
+        };
+
+        let mut segments: Vec<(Crossing, Crossing)> = Vec::new();
+        for y in 0..h.saturating_sub(1) {
+            for x in 0..w.saturating_sub(1) {
+                let i = y * w + x;
+                let corners = [heights[i], heights[i + 1], heights[i + w + 1], heights[i + w]];
+                let [top_left, top_right, bottom_right, bottom_left] = match corners {
+                    [Some(a), Some(b), Some(c), Some(d)] => [a, b, c, d],
+                    _ => continue,
+                };
+
+                let (top, bottom, left, right_edge) = (right(i), right(i + w), down(i), down(i + 1));
+                let case = (usize::from(top_left >= level) << 3) | (usize::from(top_right >= level) << 2)
+                    | (usize::from(bottom_right >= level) << 1) | usize::from(bottom_left >= level);
+                // Saddles are split the way the mean of the corners falls.
+                let center_above = (top_left + top_right + bottom_right + bottom_left) / 4f32 >= level;
+
+                match case {
+                    1 | 14 => segments.push((left, bottom)),
+                    2 | 13 => segments.push((bottom, right_edge)),
+                    3 | 12 => segments.push((left, right_edge)),
+                    4 | 11 => segments.push((top, right_edge)),
+                    6 | 9 => segments.push((top, bottom)),
+                    7 | 8 => segments.push((left, top)),
+                    5 if center_above => segments.extend([(left, top), (bottom, right_edge)]),
+                    5 => segments.extend([(left, bottom), (top, right_edge)]),
+                    10 if center_above => segments.extend([(top, right_edge), (left, bottom)]),
+                    10 => segments.extend([(left, top), (bottom, right_edge)]),
+                    _ => {}
+                }
+            }
+        }
+
+        // Every crossing is shared by the segments of at most two blocks.
+        let mut at: HashMap<Crossing, Vec<usize>> = HashMap::new();
+        for (s, &(a, b)) in segments.iter().enumerate() {
+            at.entry(a).or_default().push(s);
+            at.entry(b).or_default().push(s);
+        }
+
+        let mut used = vec![false; segments.len()];
+        let follow = |from: Crossing, used: &mut Vec<bool>| -> Vec<Crossing> {
+            let mut line = Vec::new();
+            let mut end = from;
+            while let Some(&s) = at.get(&end).and_then(|touching| touching.iter().find(|&&s| !used[s])) {
+                used[s] = true;
+                let (a, b) = segments[s];
+                end = if a == end { b } else { a };
+                line.push(end);
+            }
+            line
+        };
+
+        let mut contours = Vec::new();
+        for s in 0..segments.len() {
+            if used[s] {
+                continue;
+            }
+            used[s] = true;
+
+            let (a, b) = segments[s];
+            let mut line = vec![a, b];
+            line.extend(follow(b, &mut used));
+            let closed = line.len() > 2 && line.last() == Some(&a);
+            if !closed {
+                let before = follow(a, &mut used);
+                line.splice(0..0, before.into_iter().rev());
+            }
+
+            contours.push(Contour { points: line.into_iter().map(point).collect(), closed });
+        }
+
+        contours
+    }
+}
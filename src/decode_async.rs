@@ -0,0 +1,164 @@
+use std::io;
+use std::pin::Pin;
+use std::task;
+use std::task::Poll;
+
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::ReadBuf;
+
+use crate::Map;
+use crate::MapHeader;
+use crate::TilePoint;
+use crate::error::Counting;
+use crate::error::HeightmapError;
+use crate::limits::Limits;
+
+impl<R: AsyncRead + Unpin> AsyncRead for Counting<R> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut task::Context, buf: &mut ReadBuf) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let polled = Pin::new(&mut self.inner).poll_read(cx, buf);
+        self.offset += (buf.filled().len() - before) as u64;
+
+        polled
+    }
+}
+
+impl MapHeader {
+    async fn parse_async<R: AsyncRead + Unpin>(r: &mut Counting<R>) -> Result<MapHeader, HeightmapError> {
+        let mut raw = [0u8; crate::HEADER_FIELDS_SIZE];
+        r.read_exact(&mut raw).await.map_err(|e| r.error(e))?;
+
+        let mut name = [0u8; crate::NAME_SIZE];
+        r.read_exact(&mut name).await.map_err(|e| r.error(e))?;
+
+        let header = MapHeader::from_bytes(&raw, &name);
+        header.check_signature()?;
+
+        Ok(header)
+    }
+}
+
+impl TilePoint {
+    async fn parse_async<R: AsyncRead + Unpin>(r: &mut R) -> io::Result<TilePoint> {
+        Ok(TilePoint {
+            h: r.read_f32_le().await?,
+            unk: r.read_u8().await?,
+            r: r.read_u8().await?,
+            g: r.read_u8().await?,
+            b: r.read_u8().await?,
+            filled: false,
+        })
+    }
+}
+
+impl Map {
+    /// Decodes a map from an async reader, yielding to the runtime while
+    /// waiting for data. Broken maps fail like they do in `Map::parse`.
+    ///
+    /// Wrap unbuffered sources in a `tokio::io::BufReader`, the stream is read
+    /// in small pieces.
+    pub async fn parse_async<R: AsyncRead + Unpin>(r: &mut R, limits: &Limits) -> Result<Map, HeightmapError> {
+        let mut r = Counting::new(r, 0);
+        let header = MapHeader::parse_async(&mut r).await?;
+        limits.check_header(&header).map_err(|e| HeightmapError::Io { offset: Some(0), source: e })?;
+
+        let total = u64::from(header.w) * u64::from(header.h);
+        let size = total as usize;
+
+        let mut points = Vec::with_capacity(size);
+        let mut enabled = Vec::with_capacity(size);
+        let mut runs = Vec::new();
+        let mut counter = 0u64;
+
+        while counter < total {
+            let start = r.offset;
+            let n = r.read_i8().await.map_err(|e| crate::read_error(r.offset, counter, total, e))? as i32;
+            runs.push(n as i8);
+
+            // Negative values = skip |n|
+            // Positive value = read n + 1
+
+            let amount = if n >= 0 {
+                let read_size = 1 + n as u64;
+                limits.check_points(points.len() as u64 + read_size)
+                    .map_err(|e| HeightmapError::Io { offset: Some(start), source: e })?;
+
+                for _ in 0..read_size {
+                    let point = TilePoint::parse_async(&mut r).await;
+                    points.push(point.map_err(|e| crate::read_error(r.offset, counter, total, e))?);
+                }
+
+                read_size
+            } else {
+                u64::from(n.unsigned_abs())
+            };
+
+            crate::check_run(start, counter, amount, total)?;
+
+            enabled.extend(vec![if n >= 0 { 1 } else { 0 }; amount as usize]);
+            counter += amount;
+        }
+
+        let map = Map { header, points, enabled, runs };
+        map.validate()?;
+
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task;
+    use std::task::Poll;
+    use std::task::Waker;
+
+    use tokio::io::BufReader;
+
+    use crate::Map;
+    use crate::error::HeightmapError;
+    use crate::fixture;
+    use crate::limits::Limits;
+
+    /// Runs a future over in-memory data, which is always ready.
+    fn ready<T>(future: impl Future<Output = T>) -> T {
+        let mut future = pin!(future);
+        match future.as_mut().poll(&mut task::Context::from_waker(Waker::noop())) {
+            Poll::Ready(value) => value,
+            Poll::Pending => panic!("In-memory reads shouldn't wait"),
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Map, HeightmapError> {
+        ready(Map::parse_async(&mut BufReader::new(bytes), &Limits::default()))
+    }
+
+    #[test]
+    fn async_decodes_match_the_sync_one() {
+        let bytes = fixture::map_bytes(&fixture::RUNS);
+        let decoded = decode(&bytes).unwrap();
+        let expected = Map::from_reader(&bytes[..]).unwrap();
+
+        assert_eq!(decoded.header.to_bytes(), expected.header.to_bytes());
+        assert_eq!(decoded.header.raw_name, expected.header.raw_name);
+        assert_eq!(decoded.enabled, expected.enabled);
+        assert_eq!(decoded.runs, expected.runs);
+        assert_eq!(format!("{:?}", decoded.points), format!("{:?}", expected.points));
+    }
+
+    #[test]
+    fn async_decodes_fail_like_the_sync_one() {
+        let overlong = fixture::map_bytes(&[1, 0, -3, 6]);
+        let truncated = &fixture::map_bytes(&fixture::RUNS)[..100];
+
+        for bytes in [&overlong[..], truncated] {
+            let e = decode(bytes).unwrap_err();
+            let expected = Map::from_reader(bytes).unwrap_err();
+
+            assert_eq!(e.to_string(), expected.to_string());
+            assert_eq!(e.offset(), expected.offset());
+        }
+    }
+}
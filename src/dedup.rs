@@ -0,0 +1,58 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hash;
+use std::hash::Hasher;
+
+use crate::Map;
+
+impl Map {
+    /// Hashes the decoded content — size, enabled mask, heights and colors —
+    /// and none of the remaining header fields, so re-saved copies of a map
+    /// hash the same.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+
+        self.header.w.hash(&mut hasher);
+        self.header.h.hash(&mut hasher);
+        self.enabled.iter().map(|&enabled| enabled > 0).for_each(|enabled| enabled.hash(&mut hasher));
+
+        for point in &self.points {
+            point.h.to_bits().hash(&mut hasher);
+            (point.r, point.g, point.b).hash(&mut hasher);
+        }
+
+        hasher.finish()
+    }
+
+    /// The largest height difference to `other` if both maps have the same
+    /// size and enabled tiles, `None` otherwise.
+    pub fn max_height_difference(&self, other: &Map) -> Option<f32> {
+        let same_mask = self.header.w == other.header.w && self.header.h == other.header.h
+            && self.enabled.iter().zip(&other.enabled).all(|(a, b)| (*a > 0) == (*b > 0));
+
+        if !same_mask {
+            return None;
+        }
+
+        Some(self.points.iter()
+            .zip(&other.points)
+            .fold(0f32, |max, (a, b)| max.max((a.h - b.h).abs())))
+    }
+}
+
+/// Groups of files whose contents hash the same, in input order; files
+/// without a duplicate are left out.
+pub fn identical_groups(hashes: &[u64]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<(u64, Vec<usize>)> = Vec::new();
+
+    for (i, &hash) in hashes.iter().enumerate() {
+        match groups.iter_mut().find(|(group_hash, _)| *group_hash == hash) {
+            Some((_, members)) => members.push(i),
+            None => groups.push((hash, vec![i])),
+        }
+    }
+
+    groups.into_iter()
+        .map(|(_, members)| members)
+        .filter(|members| members.len() > 1)
+        .collect()
+}
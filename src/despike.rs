@@ -0,0 +1,70 @@
+use crate::Map;
+use crate::TilePoint;
+
+/// The offsets of the eight tiles surrounding a tile.
+const NEIGHBORS: [(i64, i64); 8] = [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)];
+
+/// Spikes need this many enabled neighbors to be told apart from a ridge
+/// running along the edge of the data.
+const MIN_NEIGHBORS: usize = 3;
+
+/// Finds tiles standing more than `threshold` above or below all of their
+/// enabled neighbors, paired with the mean height of those neighbors.
+fn find_spikes(tiles: &[Option<TilePoint>], w: u32, h: u32, threshold: f32) -> Vec<(usize, f32)> {
+    let mut spikes = Vec::new();
+
+    for (index, tile) in tiles.iter().enumerate() {
+        let point = match tile {
+            Some(point) if point.h.is_finite() => point,
+            _ => continue,
+        };
+
+        let x = (index as u32 % w) as i64;
+        let y = (index as u32 / w) as i64;
+        let heights: Vec<f32> = NEIGHBORS.iter()
+            .map(|(dx, dy)| (x + dx, y + dy))
+            .filter(|&(nx, ny)| nx >= 0 && ny >= 0 && nx < i64::from(w) && ny < i64::from(h))
+            .filter_map(|(nx, ny)| tiles[(ny * i64::from(w) + nx) as usize])
+            .map(|neighbor| neighbor.h)
+            .filter(|h| h.is_finite())
+            .collect();
+
+        if heights.len() < MIN_NEIGHBORS {
+            continue;
+        }
+
+        let low = heights.iter().cloned().fold(f32::INFINITY, f32::min);
+        let high = heights.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+
+        if point.h - high > threshold || low - point.h > threshold {
+            spikes.push((index, heights.iter().sum::<f32>() / heights.len() as f32));
+        }
+    }
+
+    spikes
+}
+
+impl Map {
+    /// Replaces single-tile spikes with the mean of their neighbors.
+    ///
+    /// A tile is a spike when it differs by more than `threshold` from every
+    /// enabled neighbor. Returns the spike counts before and after the pass;
+    /// a non-zero second count means neighboring spikes shielded each other.
+    pub fn despike(&mut self, threshold: f32) -> (usize, usize) {
+        let w = self.header.w;
+        let h = self.header.h;
+        let mut tiles = self.tiles();
+        let spikes = find_spikes(&tiles, w, h, threshold);
+
+        for &(index, height) in &spikes {
+            if let Some(point) = tiles[index].as_mut() {
+                point.h = height;
+            }
+        }
+
+        let remaining = find_spikes(&tiles, w, h, threshold).len();
+        self.set_tiles(tiles);
+
+        (spikes.len(), remaining)
+    }
+}
@@ -0,0 +1,164 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::io::IsTerminal;
+use std::path::Path;
+
+use serde_json::json;
+
+use crate::Map;
+use crate::MapHeader;
+use crate::TilePoint;
+use crate::mask;
+use crate::region::Region;
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// Whether stdout should get ANSI colors, honoring `NO_COLOR`.
+pub fn use_color() -> bool {
+    env::var_os("NO_COLOR").is_none() && io::stdout().is_terminal()
+}
+
+/// Prints every header field side by side, highlighting the ones that differ.
+/// Returns the number of differing fields.
+pub fn print_header_diff(a: &MapHeader, b: &MapHeader, color: bool) -> usize {
+    let paint = |code: &str, text: &str| {
+        if color { format!("{}{}{}", code, text, RESET) } else { text.to_string() }
+    };
+
+    let mut changed = 0usize;
+
+    for ((name, left), (_, right)) in a.fields().into_iter().zip(b.fields()) {
+        if left == right {
+            println!("  {:<12} {}", name, paint(DIM, &left));
+        } else {
+            println!("* {:<12} {} -> {}", name, paint(RED, &left), paint(GREEN, &right));
+            changed += 1;
+        }
+    }
+
+    changed
+}
+
+/// Which tiles of `b` changed relative to `a`, in image order: those whose
+/// heights differ by more than `threshold` and those only one of the maps
+/// has enabled.
+pub fn changed_tiles(a: &Map, b: &Map, threshold: f32) -> io::Result<Vec<bool>> {
+    let w = b.header.w;
+    let h = b.header.h;
+    check_sizes(a, b)?;
+
+    let mut changed = vec![false; w as usize * h as usize];
+    for (index, (left, right)) in a.tiles().into_iter().zip(b.tiles()).enumerate() {
+        let differs = match (left, right) {
+            (Some(left), Some(right)) => (right.h - left.h).abs() > threshold,
+            (None, None) => false,
+            _ => true,
+        };
+
+        let (x, y) = crate::get_position(&index, &w, &h);
+        changed[(y * w + x) as usize] = differs;
+    }
+
+    Ok(changed)
+}
+
+/// `b - a` as a map of its own, with the header and colors of `b`, enabled
+/// where both maps are. The header range is centered on zero and reaches the
+/// largest difference either way, so raster exports show no change as mid
+/// gray, rises brighter and falls darker.
+pub fn difference_map(a: &Map, b: &Map) -> io::Result<Map> {
+    check_sizes(a, b)?;
+
+    let tiles: Vec<_> = a.tiles().into_iter().zip(b.tiles())
+        .map(|tiles| match tiles {
+            (Some(left), Some(right)) => Some(TilePoint { h: right.h - left.h, ..right }),
+            _ => None,
+        })
+        .collect();
+
+    let largest = tiles.iter().flatten().map(|point| point.h.abs()).filter(|h| h.is_finite()).fold(0f32, f32::max);
+    let largest = if largest > 0f32 { largest } else { 1f32 };
+
+    let mut header = b.header.clone();
+    header.min_height = -largest;
+    header.max_height = largest;
+
+    let mut map = Map { header, points: Vec::new(), enabled: Vec::new(), runs: Vec::new() };
+    map.set_tiles(tiles);
+
+    Ok(map)
+}
+
+fn check_sizes(a: &Map, b: &Map) -> io::Result<()> {
+    if a.header.w != b.header.w || a.header.h != b.header.h {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+            "The maps have different sizes, {}x{} and {}x{}", a.header.w, a.header.h, b.header.w, b.header.h)));
+    }
+
+    Ok(())
+}
+
+/// The changed area of `b` relative to `a`, cropped out of `b` so it can be
+/// shared as a patch. Tiles count as changed when their heights differ by
+/// more than `threshold` or only one of the maps has them enabled.
+///
+/// The crop is grown by `margin` tiles on every side, for stitching the patch
+/// into the surrounding terrain. With `changed_only`, tiles further than the
+/// margin from any change are disabled. Returns `None` if nothing changed.
+pub fn changed_patch(a: &Map, b: &Map, threshold: f32, margin: u32, changed_only: bool)
+    -> io::Result<Option<(Region, Map)>> {
+    let w = b.header.w;
+    let h = b.header.h;
+    let changed = changed_tiles(a, b, threshold)?;
+
+    let positions = || changed.iter().enumerate().filter(|&(_, &changed)| changed).map(|(i, _)| (i as u32 % w, i as u32 / w));
+    let (min_x, min_y, max_x, max_y) = match positions().next() {
+        Some((x, y)) => positions().fold((x, y, x, y), |(min_x, min_y, max_x, max_y), (x, y)| {
+            (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+        }),
+        None => return Ok(None),
+    };
+
+    let region = Region {
+        x: min_x.saturating_sub(margin),
+        y: min_y.saturating_sub(margin),
+        w: (max_x + margin).min(w - 1) + 1 - min_x.saturating_sub(margin),
+        h: (max_y + margin).min(h - 1) + 1 - min_y.saturating_sub(margin),
+    };
+    let mut patch = b.crop(&region);
+
+    if changed_only {
+        let near = mask::dilate(&changed, w, h, margin);
+        let mut tiles = patch.tiles();
+
+        for (index, tile) in tiles.iter_mut().enumerate() {
+            let (x, y) = crate::get_position(&index, &region.w, &region.h);
+            if !near[((region.y + y) * w + region.x + x) as usize] {
+                *tile = None;
+            }
+        }
+        patch.set_tiles(tiles);
+    }
+
+    Ok(Some((region, patch)))
+}
+
+/// Writes where a patch from `changed_patch` sits in the map of `header`,
+/// as JSON next to its outputs, so rasters and meshes without
+/// georeferencing can be put back in place.
+pub fn write_placement(path: &Path, region: &Region, header: &MapHeader) -> io::Result<()> {
+    let placement = json!({
+        "x": region.x,
+        "y": region.y,
+        "w": region.w,
+        "h": region.h,
+        "map_w": header.w,
+        "map_h": header.h,
+    });
+
+    fs::write(path, serde_json::to_string_pretty(&placement)? + "\n")
+}
@@ -0,0 +1,200 @@
+use std::io;
+
+use crate::Map;
+use crate::raster::Raster;
+
+/// The tiles a distance field measures the distance to.
+#[derive(Debug, Clone, Copy)]
+pub enum DistanceSource<'a> {
+    /// The disabled tiles and the area outside of the map, giving the
+    /// distance from the edge of the enabled area.
+    Edge,
+    /// The tiles under the white pixels of a mask image of the map's size.
+    Mask(&'a Raster),
+    /// The enabled tiles with exactly this color.
+    Color([u8; 3]),
+}
+
+impl Map {
+    /// The distance from every tile to the nearest tile of `source`, in
+    /// tiles and image order. Every distance is infinite if no tile is part
+    /// of the source.
+    pub fn distance_field(&self, source: DistanceSource) -> io::Result<Vec<f32>> {
+        let (w, h) = (self.header.w, self.header.h);
+        let mut mask = vec![false; self.enabled.len()];
+
+        match source {
+            DistanceSource::Edge => {
+                // A border of sources around the map stands for the outside.
+                let (padded_w, padded_h) = (w + 2, h + 2);
+                let mut padded = vec![true; (padded_w * padded_h) as usize];
+                for (index, _) in self.enabled_tiles() {
+                    let (x, y) = crate::get_position(&index, &w, &h);
+                    padded[((y + 1) * padded_w + x + 1) as usize] = false;
+                }
+
+                let distances = distance_transform(&padded, padded_w, padded_h);
+                return Ok((0..h)
+                    .flat_map(|y| (0..w).map(move |x| ((y + 1) * padded_w + x + 1) as usize))
+                    .map(|i| distances[i])
+                    .collect());
+            }
+            DistanceSource::Mask(raster) => {
+                raster.check_size(w, h)?;
+                for (i, set) in mask.iter_mut().enumerate() {
+                    *set = raster.luma(i as u32 % w, i as u32 / w) >= 0.5;
+                }
+            }
+            DistanceSource::Color(color) => {
+                for (index, offset) in self.enabled_tiles() {
+                    let (x, y) = crate::get_position(&index, &w, &h);
+                    let point = &self.points[offset];
+                    mask[(y * w + x) as usize] = [point.r, point.g, point.b] == color;
+                }
+            }
+        }
+
+        Ok(distance_transform(&mask, w, h))
+    }
+}
+
+/// The Euclidean distance from every cell of a `w` x `h` grid to the nearest
+/// cell set in `mask`, in cells and row-major like the mask. Every distance
+/// is infinite if no cell is set.
+///
+/// Exact, in linear time, by the separable transform of Felzenszwalb and
+/// Huttenlocher: squared distances along the columns first, then the lower
+/// envelope of the parabolas they span along every row.
+pub fn distance_transform(mask: &[bool], w: u32, h: u32) -> Vec<f32> {
+    let (w, h) = (w as usize, h as usize);
+    let mut squared: Vec<f64> = mask.iter().map(|&set| if set { 0f64 } else { f64::INFINITY }).collect();
+
+    let mut line = Vec::with_capacity(w.max(h));
+    for x in 0..w {
+        line.clear();
+        line.extend((0..h).map(|y| squared[y * w + x]));
+        for (y, distance) in transform_line(&line).into_iter().enumerate() {
+            squared[y * w + x] = distance;
+        }
+    }
+    for y in 0..h {
+        let row = transform_line(&squared[y * w..(y + 1) * w]);
+        squared[y * w..(y + 1) * w].copy_from_slice(&row);
+    }
+
+    squared.into_iter().map(|distance| distance.sqrt() as f32).collect()
+}
+
+/// The one-dimensional squared distance transform of samples `f`, the
+/// lowest `f[q] + (p - q)²` over all `q` for every `p`.
+fn transform_line(f: &[f64]) -> Vec<f64> {
+    let n = f.len();
+    let mut distances = vec![f64::INFINITY; n];
+
+    // The parabolas of the lower envelope, by their vertex, and where each
+    // one starts to be the lowest.
+    let mut vertices: Vec<usize> = Vec::with_capacity(n);
+    let mut starts: Vec<f64> = Vec::with_capacity(n + 1);
+
+    for q in (0..n).filter(|&q| f[q].is_finite()) {
+        loop {
+            let intersection = match vertices.last() {
+                Some(&v) => ((f[q] + (q * q) as f64) - (f[v] + (v * v) as f64)) / (2 * (q - v)) as f64,
+                None => f64::NEG_INFINITY,
+            };
+
+            if vertices.len() > 1 && intersection <= starts[starts.len() - 1] {
+                vertices.pop();
+                starts.pop();
+            } else {
+                vertices.push(q);
+                starts.push(intersection);
+                break;
+            }
+        }
+    }
+
+    if vertices.is_empty() {
+        return distances;
+    }
+
+    let mut k = 0;
+    for (p, distance) in distances.iter_mut().enumerate() {
+        while k + 1 < vertices.len() && starts[k + 1] <= p as f64 {
+            k += 1;
+        }
+        let v = vertices[k];
+        *distance = (p as f64 - v as f64).powi(2) + f[v];
+    }
+
+    distances
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Map;
+    use crate::fixture;
+
+    use super::DistanceSource;
+    use super::distance_transform;
+
+    /// The distances of `distance_transform`, checked against every cell.
+    fn brute_force(mask: &[bool], w: u32) -> Vec<f32> {
+        let w = w as i64;
+        let sources: Vec<(i64, i64)> = (0..mask.len() as i64).filter(|&i| mask[i as usize]).map(|i| (i % w, i / w)).collect();
+
+        (0..mask.len() as i64)
+            .map(|i| sources.iter()
+                .map(|&(x, y)| (((x - i % w).pow(2) + (y - i / w).pow(2)) as f64).sqrt() as f32)
+                .fold(f32::INFINITY, f32::min))
+            .collect()
+    }
+
+    fn mask(w: u32, h: u32, sources: &[(u32, u32)]) -> Vec<bool> {
+        (0..w * h).map(|i| sources.contains(&(i % w, i / w))).collect()
+    }
+
+    fn assert_exact(w: u32, h: u32, sources: &[(u32, u32)]) {
+        let mask = mask(w, h, sources);
+        assert_eq!(distance_transform(&mask, w, h), brute_force(&mask, w), "{}x{} {:?}", w, h, sources);
+    }
+
+    #[test]
+    fn one_source_transforms_match_brute_force() {
+        assert_exact(7, 5, &[(3, 2)]);
+        assert_exact(1, 6, &[(0, 5)]);
+    }
+
+    #[test]
+    fn several_source_transforms_match_brute_force() {
+        assert_exact(7, 5, &[(0, 0), (6, 4)]);
+        assert_exact(9, 4, &[(1, 3), (2, 3)]);
+        assert_exact(6, 6, &[(5, 0), (0, 2), (3, 5)]);
+    }
+
+    #[test]
+    fn empty_masks_are_infinitely_far() {
+        assert!(distance_transform(&mask(5, 3, &[]), 5, 3).iter().all(|distance| distance.is_infinite()));
+    }
+
+    #[test]
+    fn edge_distances_reach_the_disabled_tiles_and_the_outside() {
+        let map = Map::from_reader(&fixture::map_bytes(&fixture::RUNS)[..]).unwrap();
+        let (w, h) = (fixture::W, fixture::H);
+
+        // The map with a border of sources around it, like the outside.
+        let heights = map.tile_heights();
+        let padded: Vec<bool> = (0..(w + 2) * (h + 2))
+            .map(|i| (i % (w + 2), i / (w + 2)))
+            .map(|(x, y)| x == 0 || y == 0 || x > w || y > h || heights[((y - 1) * w + x - 1) as usize].is_none())
+            .collect();
+        let padded = brute_force(&padded, w + 2);
+        let expected: Vec<f32> = (0..w * h).map(|i| padded[((i / w + 1) * (w + 2) + i % w + 1) as usize]).collect();
+
+        let distances = map.distance_field(DistanceSource::Edge).unwrap();
+        assert_eq!(distances, expected);
+        // Every tile is on the border or next to a disabled one, which are sources themselves.
+        assert!(distances.iter().all(|&distance| distance <= 1f32));
+        assert!(distances.iter().zip(&heights).all(|(&distance, height)| height.is_some() || distance == 0f32));
+    }
+}
@@ -0,0 +1,288 @@
+use std::io;
+
+use clap::Args;
+use clap::Subcommand;
+
+use crate::Map;
+use crate::MapHeader;
+use crate::fill::FillMethod;
+use crate::limits::Limits;
+use crate::mask::MaskMode;
+use crate::mask::Morphology;
+use crate::ops::HeightOp;
+use crate::ops::OpKind;
+use crate::ops;
+use crate::patch::Blend;
+use crate::polygon::Polygon;
+use crate::raster::Raster;
+use crate::region;
+use crate::region::Region;
+use crate::resize;
+use crate::resize::ResizeFilter;
+use crate::status;
+
+#[derive(Args, Debug, Clone)]
+pub struct OpEdit {
+    /// The arithmetic to apply to the heights.
+    pub kind: OpKind,
+
+    /// The operand of the operation.
+    #[arg(long, allow_hyphen_values = true)]
+    pub value: f32,
+
+    /// Only adjust the tiles inside `x,y,w,h`.
+    #[arg(long)]
+    pub region: Option<Region>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct MaskEdit {
+    /// The black/white mask image.
+    pub image: String,
+
+    /// Whether white mask pixels also enable disabled tiles.
+    #[arg(long, value_enum, default_value = "clip")]
+    pub mode: MaskMode,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct MorphEdit {
+    /// The operation on the enabled mask.
+    pub morphology: Morphology,
+
+    /// How many tiles around each tile the operation looks at.
+    #[arg(long, default_value_t = 1)]
+    pub radius: u32,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ColorsEdit {
+    /// The RGB image holding the new tile colors.
+    pub image: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct PatchEdit {
+    /// The grayscale image holding the replacement heights.
+    #[arg(long, value_name = "IMAGE")]
+    pub from: String,
+
+    /// The tile position of the patch's top-left corner, as `x,y`.
+    #[arg(long, value_parser = region::parse_position, default_value = "0,0")]
+    pub at: (u32, u32),
+
+    /// How the patch is merged with the existing heights.
+    #[arg(long, value_enum, default_value = "replace")]
+    pub blend: Blend,
+
+    /// The width in tiles of the feathered border.
+    #[arg(long, default_value_t = 8)]
+    pub feather: u32,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ClampEdit {
+    /// How many standard deviations from the mean a height may be.
+    #[arg(long, default_value_t = 4.0)]
+    pub sigma: f32,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct DespikeEdit {
+    /// How far a tile has to stand out from all of its neighbors.
+    #[arg(long, default_value_t = 10.0)]
+    pub threshold: f32,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct FillEdit {
+    /// How the heights of the disabled tiles are interpolated.
+    pub method: FillMethod,
+
+    /// How far in tiles IDW looks for enabled tiles and natural neighbor
+    /// hands heights on.
+    #[arg(long, default_value_t = 16)]
+    pub radius: u32,
+
+    /// How many relaxation passes Laplace runs.
+    #[arg(long, default_value_t = 500)]
+    pub iterations: u32,
+
+    /// How many tiles around the fill boundary are smoothed, 0 to disable.
+    #[arg(long, default_value_t = 0)]
+    pub feather: u32,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ExtractEdit {
+    /// The tile at the center of the circle, as `x,y`.
+    #[arg(long, value_parser = region::parse_position)]
+    pub center: (u32, u32),
+
+    /// The radius of the circle in tiles.
+    #[arg(long)]
+    pub radius: u32,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct PolygonEdit {
+    /// A GeoJSON or WKT file with the polygon, in tile coordinates of the
+    /// decoded image.
+    pub path: String,
+
+    /// Also crop the map to the polygon's bounding box.
+    #[arg(long)]
+    pub crop: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct QuantizeEdit {
+    /// The most colors the tiles may have afterwards.
+    #[arg(value_parser = clap::value_parser!(u32).range(1..=256))]
+    pub colors: u32,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct RotateEdit {
+    /// The clockwise rotation in degrees.
+    #[arg(long, allow_hyphen_values = true)]
+    pub degrees: f64,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ResizeEdit {
+    /// The new size as `WxH`.
+    #[arg(value_parser = resize::parse_size)]
+    pub size: (u32, u32),
+
+    /// How the heights are interpolated.
+    #[arg(long, value_enum, default_value = "bilinear")]
+    pub filter: ResizeFilter,
+
+    /// How much of the highest height the `peaks` filter keeps, from 0 for
+    /// the mean of the covered tiles to 1 for their maximum.
+    #[arg(long, default_value_t = 0.5, value_parser = resize::parse_weight)]
+    pub peak_weight: f32,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct FitRangeEdit {
+    /// The new height range as `MIN,MAX`, the stored heights stretched onto
+    /// it. Without it, the header takes the range of the stored heights.
+    #[arg(value_parser = ops::parse_height_range, allow_hyphen_values = true)]
+    pub range: Option<(f32, f32)>,
+}
+
+/// A single modification of a decoded map.
+#[derive(Subcommand, Debug, Clone)]
+pub enum Edit {
+    /// Adjusts heights numerically.
+    Op(OpEdit),
+    /// Enables or disables tiles from a black/white image.
+    Mask(MaskEdit),
+    /// Erodes, dilates, opens or closes the enabled mask.
+    Morph(MorphEdit),
+    /// Replaces the tile colors from an RGB image.
+    Colors(ColorsEdit),
+    /// Splices the heights of a grayscale image into the map.
+    Patch(PatchEdit),
+    /// Clamps outliers and replaces NaN or infinite heights.
+    Clamp(ClampEdit),
+    /// Smooths out single-tile spikes.
+    Despike(DespikeEdit),
+    /// Enables the disabled tiles with interpolated heights.
+    Fill(FillEdit),
+    /// Crops to a circle, disabling the tiles outside of it.
+    Extract(ExtractEdit),
+    /// Disables the tiles outside of a polygon.
+    Polygon(PolygonEdit),
+    /// Rotates the map by any angle.
+    Rotate(RotateEdit),
+    /// Reduces the tile colors to a small palette.
+    Quantize(QuantizeEdit),
+    /// Resamples the map to another size.
+    Resize(ResizeEdit),
+    /// Fits the header height range to the stored heights.
+    FitRange(FitRangeEdit),
+}
+
+impl Edit {
+    /// Applies the edit, refusing to grow the map past `limits`.
+    pub fn apply(&self, map: &mut Map, limits: &Limits) -> io::Result<()> {
+        match self {
+            Edit::Op(edit) => {
+                let op = HeightOp::new(edit.kind, edit.value);
+                let changed = map.apply_height_op(&op, edit.region.as_ref());
+                status!("Adjusted {} tiles", changed);
+            }
+            Edit::Mask(edit) => {
+                let mask = Raster::open(&edit.image)?;
+                let (disabled, enabled) = map.apply_mask(&mask, edit.mode)?;
+                status!("Mask disabled {} and enabled {} tiles", disabled, enabled);
+            }
+            Edit::Morph(edit) => {
+                let (disabled, enabled) = map.morph_mask(edit.morphology, edit.radius);
+                status!("{:?} disabled {} and enabled {} tiles", edit.morphology, disabled, enabled);
+            }
+            Edit::Colors(edit) => {
+                let colors = Raster::open(&edit.image)?;
+                let changed = map.set_colors(&colors)?;
+                status!("Recolored {} tiles", changed);
+            }
+            Edit::Patch(edit) => {
+                let patch = Raster::open(&edit.from)?;
+                let changed = map.patch(&patch, edit.at, edit.blend, edit.feather);
+                status!("Patched {} tiles", changed);
+            }
+            Edit::Clamp(edit) => {
+                let changed = map.clamp_outliers(edit.sigma);
+                status!("Clamped {} heights", changed);
+            }
+            Edit::Despike(edit) => {
+                let (before, after) = map.despike(edit.threshold);
+                status!("Spikes: {} before, {} after", before, after);
+            }
+            Edit::Fill(edit) => {
+                let filled = map.fill_holes(edit.method, edit.radius, edit.iterations, edit.feather);
+                status!("Filled {} tiles", filled);
+            }
+            Edit::Extract(edit) => {
+                *map = map.extract_circle(edit.center, edit.radius)?;
+                status!("Extracted {}x{} tiles, {} enabled", map.header.w, map.header.h, map.points.len());
+            }
+            Edit::Polygon(edit) => {
+                let polygon = Polygon::load(&edit.path)?;
+                let disabled = map.mask_polygon(&polygon, edit.crop)?;
+                status!("Polygon disabled {} tiles", disabled);
+            }
+            Edit::Rotate(edit) => {
+                let (w, h) = map.rotated_size(edit.degrees);
+                limits.check_header(&MapHeader { w, h, ..map.header.clone() })?;
+                *map = map.rotate(edit.degrees);
+                status!("Rotated by {} degrees to {}x{} tiles", edit.degrees, map.header.w, map.header.h);
+            }
+            Edit::Quantize(edit) => {
+                let palette = map.quantize_colors(edit.colors as usize);
+                status!("Quantized the tile colors to {} colors:", palette.len());
+                for entry in &palette {
+                    let [r, g, b] = entry.color;
+                    status!("  #{:02x}{:02x}{:02x} {} tiles", r, g, b, entry.tiles);
+                }
+            }
+            Edit::Resize(edit) => {
+                let (w, h) = edit.size;
+                limits.check_header(&MapHeader { w, h, ..map.header.clone() })?;
+                *map = map.resize(w, h, edit.filter, edit.peak_weight);
+                status!("Resized {:?} to {}x{} tiles", edit.filter, w, h);
+            }
+            Edit::FitRange(edit) => match map.fit_height_range(edit.range) {
+                Some((min, max)) => status!("Fit the height range {} to {} to {} to {}",
+                                             min, max, map.header.min_height, map.header.max_height),
+                None => status!("No finite heights to fit the range to"),
+            },
+        }
+
+        // Edits rebuild the points, catch one leaving them out of step with the mask.
+        map.validate()
+    }
+}
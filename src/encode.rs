@@ -0,0 +1,166 @@
+use std::io;
+use std::io::prelude::*;
+
+use byteorder::LE;
+use byteorder::WriteBytesExt;
+
+use crate::Map;
+use crate::MapHeader;
+use crate::TilePoint;
+
+/// The longest run a single control byte can describe.
+const MAX_RUN: usize = 128;
+
+impl MapHeader {
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(&self.to_bytes())?;
+
+        if self.raw_name.len() == crate::NAME_SIZE && crate::decode_name(&self.raw_name) == self.name {
+            w.write_all(&self.raw_name)
+        } else {
+            write_fixed_string(w, &self.name, crate::NAME_SIZE)
+        }
+    }
+}
+
+impl TilePoint {
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_f32::<LE>(self.h)?;
+        w.write_all(&[self.unk, self.r, self.g, self.b])
+    }
+}
+
+impl Map {
+    /// Encodes the map in the game's format, writing the header and then
+    /// each run as soon as it is known, so nothing is buffered in memory.
+    ///
+    /// A decoded map that wasn't edited is written byte for byte as it was
+    /// read; otherwise runs are as long as a control byte allows.
+    pub fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.header.write(w)?;
+
+        let mut points = self.points.iter();
+        let mut write_points = |w: &mut W, length: usize| {
+            for _ in 0..length {
+                points.next()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Fewer points than enabled tiles"))?
+                    .write(w)?;
+            }
+
+            Ok::<_, io::Error>(())
+        };
+
+        if self.runs_match() {
+            for &n in &self.runs {
+                w.write_i8(n)?;
+
+                if n >= 0 {
+                    write_points(w, n as usize + 1)?;
+                }
+            }
+
+            return Ok(());
+        }
+
+        let mut index = 0usize;
+
+        while index < self.enabled.len() {
+            let enabled = self.enabled[index] > 0u8;
+            let length = self.enabled[index..].iter()
+                .take(MAX_RUN)
+                .take_while(|&&e| (e > 0u8) == enabled)
+                .count();
+
+            // Negative values = skip |n|
+            // Positive value = read n + 1
+
+            if enabled {
+                w.write_i8((length - 1) as i8)?;
+                write_points(w, length)?;
+            } else {
+                w.write_i8(-(length as i32) as i8)?;
+            }
+
+            index += length;
+        }
+
+        Ok(())
+    }
+
+    /// Whether the runs the map was decoded with still describe its mask.
+    fn runs_match(&self) -> bool {
+        let mut index = 0usize;
+
+        for &n in &self.runs {
+            let (enabled, length) = if n >= 0 { (1u8, n as usize + 1) } else { (0u8, n.unsigned_abs() as usize) };
+
+            match self.enabled.get(index..index + length) {
+                Some(run) if run.iter().all(|&e| e == enabled) => index += length,
+                _ => return false,
+            }
+        }
+
+        !self.runs.is_empty() && index == self.enabled.len()
+    }
+}
+
+fn write_fixed_string<W: Write>(w: &mut W, s: &str, size: usize) -> io::Result<()> {
+    let mut buf = s.as_bytes().to_vec();
+    buf.resize(size, 0u8);
+
+    w.write_all(&buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Map;
+    use crate::fixture;
+
+    fn encode(map: &Map) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        map.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn unedited_maps_encode_byte_for_byte() {
+        let bytes = fixture::map_bytes(&fixture::RUNS);
+        let map = Map::from_reader(&bytes[..]).unwrap();
+
+        assert!(map.runs_match());
+        assert_eq!(encode(&map), bytes);
+    }
+
+    #[test]
+    fn edited_maps_encode_with_the_longest_runs() {
+        let mut map = Map::from_reader(&fixture::map_bytes(&fixture::RUNS)[..]).unwrap();
+        let mut tiles = map.tiles();
+        tiles[11] = None;
+        map.set_tiles(tiles.clone());
+
+        assert!(!map.runs_match());
+
+        let bytes = encode(&map);
+        let decoded = Map::from_reader(&bytes[..]).unwrap();
+
+        assert_eq!(decoded.runs, vec![2, -3, 4, -1]);
+        assert_eq!(decoded.enabled, map.enabled);
+        assert_eq!(decoded.header.raw_name, map.header.raw_name);
+        let heights = |tiles: Vec<Option<crate::TilePoint>>| -> Vec<Option<f32>> {
+            tiles.into_iter().map(|tile| tile.map(|point| point.h)).collect()
+        };
+        assert_eq!(heights(decoded.tiles()), heights(tiles));
+    }
+
+    #[test]
+    fn renamed_maps_pad_the_new_name() {
+        let mut map = Map::from_reader(&fixture::map_bytes(&fixture::RUNS)[..]).unwrap();
+        map.header.name = String::from("Renamed");
+
+        let bytes = encode(&map);
+        let decoded = Map::from_reader(&bytes[..]).unwrap();
+
+        assert_eq!(decoded.header.name, "Renamed");
+        assert!(decoded.header.raw_name[7..].iter().all(|&byte| byte == 0));
+    }
+}
@@ -0,0 +1,174 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+
+/// What went wrong decoding a map, with the byte offset in the file where
+/// it was noticed when there is one.
+#[derive(Debug)]
+pub enum HeightmapError {
+    /// The file doesn't start with the `GTI ` signature.
+    InvalidSignature { found: u32 },
+    /// The file ended in the run-length encoded points, after `tiles` of the
+    /// header's `total`.
+    TruncatedRle { offset: u64, tiles: u64, total: u64 },
+    /// The runs cover `tiles` tiles, more than the header's `total`. The run
+    /// overshooting it starts at `offset`.
+    PointCountMismatch { offset: u64, tiles: u64, total: u64 },
+    /// Reading failed, or the map broke a limit.
+    Io { offset: Option<u64>, source: io::Error },
+}
+
+impl HeightmapError {
+    /// The byte offset in the file the error was noticed at, if known.
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            HeightmapError::InvalidSignature { .. } => Some(0),
+            HeightmapError::TruncatedRle { offset, .. } | HeightmapError::PointCountMismatch { offset, .. } => Some(*offset),
+            HeightmapError::Io { offset, .. } => *offset,
+        }
+    }
+}
+
+impl fmt::Display for HeightmapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HeightmapError::InvalidSignature { found } => write!(f,
+                "Invalid signature {:#010x} at byte 0x0, expected {:#010x} ('GTI ')", found, crate::SIGNATURE),
+            HeightmapError::TruncatedRle { offset, tiles, total } => write!(f,
+                "Truncated RLE stream at byte {:#x}, the file ends after {} of {} tiles", offset, tiles, total),
+            HeightmapError::PointCountMismatch { offset, tiles, total } => write!(f,
+                "Point count mismatch at byte {:#x}, the runs cover {} tiles but the map has {}", offset, tiles, total),
+            HeightmapError::Io { offset: Some(offset), source } => write!(f, "{} at byte {:#x}", source, offset),
+            HeightmapError::Io { offset: None, source } => source.fmt(f),
+        }
+    }
+}
+
+impl error::Error for HeightmapError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            HeightmapError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for HeightmapError {
+    fn from(source: io::Error) -> HeightmapError {
+        HeightmapError::Io { offset: None, source }
+    }
+}
+
+/// Keeps the offset in the message for callers working with `io::Error`.
+impl From<HeightmapError> for io::Error {
+    fn from(e: HeightmapError) -> io::Error {
+        let kind = match &e {
+            HeightmapError::Io { source, .. } => source.kind(),
+            HeightmapError::TruncatedRle { .. } => io::ErrorKind::UnexpectedEof,
+            _ => io::ErrorKind::InvalidData,
+        };
+
+        io::Error::new(kind, e)
+    }
+}
+
+/// A reader keeping track of how far into the file it is.
+pub(crate) struct Counting<R> {
+    pub inner: R,
+    pub offset: u64,
+}
+
+impl<R> Counting<R> {
+    pub fn new(inner: R, offset: u64) -> Counting<R> {
+        Counting { inner, offset }
+    }
+
+    /// Attaches the offset to an error reading from here.
+    pub fn error(&self, source: io::Error) -> HeightmapError {
+        HeightmapError::Io { offset: Some(self.offset), source }
+    }
+}
+
+impl<R: Read> Read for Counting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.offset += read as u64;
+
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HeightmapError;
+    use crate::Map;
+    use crate::fixture;
+
+    /// Where the points start, after the header and the name.
+    const POINTS: u64 = (crate::HEADER_FIELDS_SIZE + crate::NAME_SIZE) as u64;
+
+    fn decode(bytes: &[u8]) -> HeightmapError {
+        Map::from_reader(bytes).unwrap_err()
+    }
+
+    #[test]
+    fn wrong_signatures_are_reported_at_the_start() {
+        let mut bytes = fixture::map_bytes(&fixture::RUNS);
+        bytes[0] = b'X';
+
+        let e = decode(&bytes);
+        assert!(matches!(e, HeightmapError::InvalidSignature { .. }));
+        assert_eq!(e.offset(), Some(0));
+    }
+
+    #[test]
+    fn truncated_streams_report_where_the_file_ends() {
+        let bytes = fixture::map_bytes(&fixture::RUNS);
+        // The first run's control byte and one of its two points.
+        let end = POINTS + 1 + 8;
+
+        match decode(&bytes[..end as usize]) {
+            e @ HeightmapError::TruncatedRle { .. } => {
+                assert_eq!(e.offset(), Some(end));
+                assert!(e.to_string().contains("at byte 0x69"), "{}", e);
+            }
+            e => panic!("Expected a truncated stream, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn truncated_streams_count_the_tiles_read() {
+        let bytes = fixture::map_bytes(&fixture::RUNS);
+        // Ends right after the runs of the first three tiles.
+        let end = POINTS + 1 + 16 + 1 + 8;
+
+        match decode(&bytes[..end as usize]) {
+            HeightmapError::TruncatedRle { offset, tiles, total } => {
+                assert_eq!((offset, tiles, total), (end, 3, 12));
+            }
+            e => panic!("Expected a truncated stream, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn overlong_runs_report_their_control_byte() {
+        let bytes = fixture::map_bytes(&[1, 0, -3, 6]);
+
+        match decode(&bytes) {
+            HeightmapError::PointCountMismatch { offset, tiles, total } => {
+                assert_eq!((offset, tiles, total), (POINTS + 1 + 16 + 1 + 8 + 1, 13, 12));
+            }
+            e => panic!("Expected a point count mismatch, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn io_errors_keep_the_offset_as_io_errors() {
+        let bytes = fixture::map_bytes(&fixture::RUNS);
+        let e: std::io::Error = decode(&bytes[..40]).into();
+
+        assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof);
+        assert!(e.to_string().contains("at byte 0x28"), "{}", e);
+    }
+}
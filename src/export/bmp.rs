@@ -0,0 +1,33 @@
+use std::io;
+use std::io::prelude::*;
+
+use bmp::Image;
+use bmp::Pixel;
+
+use super::Format;
+use super::pixels::PixelBuffer;
+use super::pixels::PixelFormat;
+use super::pixels::Samples;
+
+pub fn write(pixels: &PixelBuffer, mut w: impl Write) -> io::Result<()> {
+    let data = match (&pixels.samples, pixels.format) {
+        (Samples::U8(data), PixelFormat::Gray8) | (Samples::U8(data), PixelFormat::Rgb8) => data,
+        _ => return Err(super::unsupported(Format::Bmp, pixels.format)),
+    };
+
+    let channels = pixels.format.channels();
+    let mut img = Image::new(pixels.width, pixels.height);
+
+    for (i, pixel) in data.chunks_exact(channels).enumerate() {
+        let x = i as u32 % pixels.width;
+        let y = i as u32 / pixels.width;
+
+        let pixel = match pixel {
+            [r, g, b] => Pixel::new(*r, *g, *b),
+            _ => Pixel::new(pixel[0], pixel[0], pixel[0]),
+        };
+        img.set_pixel(x, y, pixel);
+    }
+
+    img.to_writer(&mut w)
+}
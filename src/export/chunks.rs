@@ -0,0 +1,103 @@
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::prelude::*;
+use std::path::Path;
+
+use crate::Map;
+use crate::region::Region;
+
+/// Splits the map into `size` x `size` tile chunks in image order, row by
+/// row from the top-left; chunks on the right and bottom edge may be smaller.
+pub fn regions(w: u32, h: u32, size: u32) -> Vec<(u32, u32, Region)> {
+    let size = size.max(1);
+    let mut regions = Vec::new();
+
+    for (row, y) in (0..h).step_by(size as usize).enumerate() {
+        for (column, x) in (0..w).step_by(size as usize).enumerate() {
+            let region = Region { x, y, w: size.min(w - x), h: size.min(h - y) };
+            regions.push((column as u32, row as u32, region));
+        }
+    }
+
+    regions
+}
+
+/// Writes one CSV row of statistics per chunk, for streaming systems to base
+/// LOD and culling decisions on.
+///
+/// Slopes are in height units per tile, from central differences over the
+/// whole map so the values don't jump at chunk borders.
+pub fn write_stats(map: &Map, size: u32, path: &Path) -> io::Result<()> {
+    let w = map.header.w;
+    let h = map.header.h;
+    let tiles = map.tiles();
+    let height_at = |x: u32, y: u32| tiles[((h - 1 - y) * w + x) as usize].map(|point| point.h);
+
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "chunk_x,chunk_y,x,y,width,height,coverage,min_height,max_height,mean_height,mean_slope,max_slope")?;
+
+    for (column, row, region) in regions(w, h, size) {
+        let mut count = 0usize;
+        let mut min = f32::INFINITY;
+        let mut max = f32::NEG_INFINITY;
+        let mut sum = 0f64;
+        let mut slope_sum = 0f64;
+        let mut slope_count = 0usize;
+        let mut max_slope = 0f32;
+
+        for y in region.y..region.y + region.h {
+            for x in region.x..region.x + region.w {
+                let height = match height_at(x, y) {
+                    Some(height) => height,
+                    None => continue,
+                };
+
+                count += 1;
+                min = min.min(height);
+                max = max.max(height);
+                sum += f64::from(height);
+
+                let dx = gradient(height, x.checked_sub(1).and_then(|x| height_at(x, y)),
+                                  (x + 1 < w).then(|| height_at(x + 1, y)).flatten());
+                let dy = gradient(height, y.checked_sub(1).and_then(|y| height_at(x, y)),
+                                  (y + 1 < h).then(|| height_at(x, y + 1)).flatten());
+
+                if let (Some(dx), Some(dy)) = (dx, dy) {
+                    let slope = dx.hypot(dy);
+                    slope_sum += f64::from(slope);
+                    slope_count += 1;
+                    max_slope = max_slope.max(slope);
+                }
+            }
+        }
+
+        write!(out, "{},{},{},{},{},{},{}", column, row, region.x, region.y, region.w, region.h,
+               count as f64 / (f64::from(region.w) * f64::from(region.h)))?;
+
+        if count > 0 {
+            write!(out, ",{},{},{}", min, max, sum / count as f64)?;
+        } else {
+            write!(out, ",,,")?;
+        }
+
+        if slope_count > 0 {
+            writeln!(out, ",{},{}", slope_sum / slope_count as f64, max_slope)?;
+        } else {
+            writeln!(out, ",,")?;
+        }
+    }
+
+    out.flush()
+}
+
+/// The height change per tile along one axis, central where both
+/// neighbors are enabled and one-sided otherwise.
+fn gradient(height: f32, before: Option<f32>, after: Option<f32>) -> Option<f32> {
+    match (before, after) {
+        (Some(before), Some(after)) => Some((after - before) / 2.0),
+        (Some(before), None) => Some(height - before),
+        (None, Some(after)) => Some(after - height),
+        (None, None) => None,
+    }
+}
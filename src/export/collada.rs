@@ -0,0 +1,126 @@
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::prelude::*;
+use std::path::Path;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use crate::Map;
+
+use super::Context;
+use super::escape;
+use super::tessellate;
+use super::vertex_position;
+
+/// How many numbers go on a line of the arrays, keeping lines short enough
+/// for older parsers.
+const LINE_LENGTH: usize = 12;
+
+/// Writes the enabled tiles as a COLLADA 1.4.1 document with a single
+/// triangle mesh, Z up, tessellated and skirted like the OBJ meshes.
+/// Vertices are placed in world coordinates when georeferencing is given.
+pub fn write(map: &Map, context: &Context, path: &Path) -> io::Result<()> {
+    let mesh = &context.options.mesh;
+    let width = map.header.w;
+    let height = map.header.h;
+
+    let heights = map.tile_heights();
+
+    let triangles = match mesh.mesh_error {
+        Some(max_error) => tessellate::triangulate(&heights, width, height, max_error),
+        None => tessellate::grid(&heights, width, height),
+    };
+    let (corners, indices) = tessellate::indexed(&triangles, width, height, mesh.mesh_skirt.is_some());
+
+    let positions: Vec<String> = corners.iter()
+        .flat_map(|&((x, y), lowered)| {
+            let (wx, wy) = vertex_position(map, context.options, x, y);
+            let depth = if lowered { mesh.mesh_skirt.unwrap_or_default() } else { 0f32 };
+
+            [wx.to_string(), wy.to_string(), (heights[(y * width + x) as usize].unwrap_or_default() - depth).to_string()]
+        })
+        .collect();
+
+    let mut w = BufWriter::new(File::create(path)?);
+    let now = timestamp();
+
+    writeln!(w, r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+    writeln!(w, r#"<COLLADA xmlns="http://www.collada.org/2005/11/COLLADASchema" version="1.4.1">"#)?;
+    writeln!(w, "  <asset>")?;
+    writeln!(w, "    <contributor><authoring_tool>gti2bmp</authoring_tool></contributor>")?;
+    writeln!(w, "    <created>{}</created>", now)?;
+    writeln!(w, "    <modified>{}</modified>", now)?;
+    writeln!(w, r#"    <unit name="meter" meter="1"/>"#)?;
+    writeln!(w, "    <up_axis>Z_UP</up_axis>")?;
+    writeln!(w, "  </asset>")?;
+    writeln!(w, "  <library_geometries>")?;
+    writeln!(w, r#"    <geometry id="terrain-mesh" name="{}">"#, escape(&map.header.name))?;
+    writeln!(w, "      <mesh>")?;
+    writeln!(w, r#"        <source id="terrain-positions">"#)?;
+    writeln!(w, r#"          <float_array id="terrain-positions-array" count="{}">"#, positions.len())?;
+    write_numbers(&mut w, &positions)?;
+    writeln!(w, "          </float_array>")?;
+    writeln!(w, "          <technique_common>")?;
+    writeln!(w, r##"            <accessor source="#terrain-positions-array" count="{}" stride="3">"##, corners.len())?;
+    for axis in ["X", "Y", "Z"] {
+        writeln!(w, r#"              <param name="{}" type="float"/>"#, axis)?;
+    }
+    writeln!(w, "            </accessor>")?;
+    writeln!(w, "          </technique_common>")?;
+    writeln!(w, "        </source>")?;
+    writeln!(w, r#"        <vertices id="terrain-vertices">"#)?;
+    writeln!(w, r##"          <input semantic="POSITION" source="#terrain-positions"/>"##)?;
+    writeln!(w, "        </vertices>")?;
+    writeln!(w, r#"        <triangles count="{}">"#, indices.len() / 3)?;
+    writeln!(w, r##"          <input semantic="VERTEX" source="#terrain-vertices" offset="0"/>"##)?;
+    writeln!(w, "          <p>")?;
+    write_numbers(&mut w, &indices)?;
+    writeln!(w, "          </p>")?;
+    writeln!(w, "        </triangles>")?;
+    writeln!(w, "      </mesh>")?;
+    writeln!(w, "    </geometry>")?;
+    writeln!(w, "  </library_geometries>")?;
+    writeln!(w, "  <library_visual_scenes>")?;
+    writeln!(w, r#"    <visual_scene id="scene">"#)?;
+    writeln!(w, r#"      <node id="terrain" name="terrain">"#)?;
+    writeln!(w, r##"        <instance_geometry url="#terrain-mesh"/>"##)?;
+    writeln!(w, "      </node>")?;
+    writeln!(w, "    </visual_scene>")?;
+    writeln!(w, "  </library_visual_scenes>")?;
+    writeln!(w, "  <scene>")?;
+    writeln!(w, r##"    <instance_visual_scene url="#scene"/>"##)?;
+    writeln!(w, "  </scene>")?;
+    writeln!(w, "</COLLADA>")?;
+
+    w.flush()
+}
+
+fn write_numbers(w: &mut impl Write, numbers: &[impl fmt::Display]) -> io::Result<()> {
+    for line in numbers.chunks(LINE_LENGTH) {
+        let line: Vec<String> = line.iter().map(ToString::to_string).collect();
+        writeln!(w, "            {}", line.join(" "))?;
+    }
+
+    Ok(())
+}
+
+/// The current time in UTC, as the `xs:dateTime` the asset dates need.
+fn timestamp() -> String {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or_default();
+    let (days, time) = ((seconds / 86400) as i64, seconds % 86400);
+
+    // Days to a proleptic Gregorian date, with years starting in March.
+    let shifted = days + 719_468;
+    let era = shifted.div_euclid(146_097);
+    let day_of_era = shifted - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, time / 3600, time / 60 % 60, time % 60)
+}
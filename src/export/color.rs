@@ -0,0 +1,248 @@
+use std::io;
+
+use clap::Args;
+use clap::ValueEnum;
+
+use crate::Map;
+
+use super::pixels::PixelBuffer;
+use super::pixels::PixelFormat;
+use super::pixels::Samples;
+
+/// How tile colors are interpolated when the color layer is upscaled.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ColorFilter {
+    /// Every tile becomes a solid block.
+    Nearest,
+    /// Linear blends between the four closest tiles.
+    Bilinear,
+    /// Catmull-Rom curves through the sixteen closest tiles, sharper than bilinear.
+    Bicubic,
+}
+
+/// What disabled tiles become in the color map.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ColorMapBackground {
+    Black,
+    /// Written as RGBA, with the enabled mask as alpha.
+    Transparent,
+}
+
+/// Resampling of the rgb8 and rgba8 color layer, so minimap-style renders
+/// don't look blocky, and the color map written next to the other outputs.
+#[derive(Args, Debug, Clone)]
+pub struct ColorOptions {
+    /// Also write the color layer on its own to `<name>_colors.png`, as a
+    /// texture or splat map for the heightmap.
+    #[arg(long)]
+    pub color_map: bool,
+
+    /// What disabled tiles become in the color map.
+    #[arg(long, value_enum, default_value = "black", requires = "color_map")]
+    pub color_map_background: ColorMapBackground,
+
+    /// Render the color layer at this many pixels per tile.
+    #[arg(long, value_name = "FACTOR", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=16),
+          conflicts_with_all = ["grid", "axes"])]
+    pub color_scale: u32,
+
+    /// How colors are interpolated between tiles when upscaling.
+    #[arg(long, value_enum, default_value = "bilinear")]
+    pub color_filter: ColorFilter,
+
+    /// Blur the color layer's chroma over this many pixels, keeping the
+    /// brightness detail sharp.
+    #[arg(long, value_name = "PIXELS", default_value_t = 0)]
+    pub chroma_smooth: u32,
+}
+
+impl Default for ColorOptions {
+    fn default() -> ColorOptions {
+        ColorOptions {
+            color_map: false,
+            color_map_background: ColorMapBackground::Black,
+            color_scale: 1,
+            color_filter: ColorFilter::Bilinear,
+            chroma_smooth: 0,
+        }
+    }
+}
+
+impl ColorOptions {
+    pub fn is_set(&self) -> bool {
+        self.color_scale > 1 || self.chroma_smooth > 0
+    }
+
+    /// Upscales and smooths a rendered color layer. Disabled tiles stay
+    /// disabled and don't bleed into their neighbors.
+    pub fn apply(&self, map: &Map, pixels: PixelBuffer) -> io::Result<PixelBuffer> {
+        let channels = pixels.format.channels();
+        let data = match (&pixels.samples, pixels.format) {
+            (Samples::U8(data), PixelFormat::Rgb8) | (Samples::U8(data), PixelFormat::Rgba8) => data,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                           "Only the rgb8 and rgba8 color layer can be upscaled and smoothed")),
+        };
+
+        let w = pixels.width;
+        let enabled: Vec<bool> = map.tile_heights().iter().map(Option::is_some).collect();
+
+        let colors: Vec<Option<[f32; 3]>> = data.chunks(channels)
+            .zip(&enabled)
+            .map(|(pixel, &enabled)| if enabled {
+                Some([f32::from(pixel[0]), f32::from(pixel[1]), f32::from(pixel[2])])
+            } else {
+                None
+            })
+            .collect();
+
+        let scale = self.color_scale.max(1);
+        let (width, height) = (w * scale, pixels.height * scale);
+        let mut colors = upscale(&colors, w, pixels.height, scale, self.color_filter);
+
+        if self.chroma_smooth > 0 {
+            smooth_chroma(&mut colors, width, height, self.chroma_smooth);
+        }
+
+        let data = colors.iter()
+            .flat_map(|color| {
+                let [r, g, b] = color.unwrap_or_default().map(|c| c.round().clamp(0f32, 255f32) as u8);
+                let rgba = [r, g, b, if color.is_some() { 255 } else { 0 }];
+                IntoIterator::into_iter(rgba).take(channels)
+            })
+            .collect();
+
+        Ok(PixelBuffer { width, height, format: pixels.format, samples: Samples::U8(data) })
+    }
+}
+
+fn upscale(colors: &[Option<[f32; 3]>], w: u32, h: u32, scale: u32, filter: ColorFilter) -> Vec<Option<[f32; 3]>> {
+    let (width, height) = (w * scale, h * scale);
+    let at = |x: i64, y: i64| colors[(y.clamp(0, i64::from(h) - 1) * i64::from(w) + x.clamp(0, i64::from(w) - 1)) as usize];
+
+    let mut upscaled = Vec::with_capacity((width * height) as usize);
+
+    for oy in 0..height {
+        // Where the pixel center falls between the tile centers.
+        let sy = (f64::from(oy) + 0.5) / f64::from(scale) - 0.5;
+        let y_taps = taps(filter, sy);
+
+        for ox in 0..width {
+            let nearest = at(i64::from(ox / scale), i64::from(oy / scale));
+            if nearest.is_none() || filter == ColorFilter::Nearest {
+                upscaled.push(nearest);
+                continue;
+            }
+
+            let sx = (f64::from(ox) + 0.5) / f64::from(scale) - 0.5;
+            let mut sum = [0f64; 3];
+            let mut total = 0f64;
+
+            for &(y, wy) in &y_taps {
+                for (x, wx) in taps(filter, sx) {
+                    if let Some(color) = at(x, y) {
+                        let weight = wx * wy;
+                        for (sum, c) in sum.iter_mut().zip(color) {
+                            *sum += weight * f64::from(c);
+                        }
+                        total += weight;
+                    }
+                }
+            }
+
+            // Neighbors missing on the wrong sides can cancel the weights out.
+            if total.abs() < 1e-6 {
+                upscaled.push(nearest);
+            } else {
+                upscaled.push(Some(sum.map(|sum| (sum / total) as f32)));
+            }
+        }
+    }
+
+    upscaled
+}
+
+/// The source columns or rows sampled for position `s`, with their weights.
+fn taps(filter: ColorFilter, s: f64) -> Vec<(i64, f64)> {
+    let base = s.floor();
+    let t = s - base;
+    let base = base as i64;
+
+    match filter {
+        ColorFilter::Nearest => vec![(s.round() as i64, 1f64)],
+        ColorFilter::Bilinear => vec![(base, 1f64 - t), (base + 1, t)],
+        ColorFilter::Bicubic => {
+            let t2 = t * t;
+            let t3 = t2 * t;
+
+            vec![
+                (base - 1, 0.5 * (-t3 + 2f64 * t2 - t)),
+                (base, 0.5 * (3f64 * t3 - 5f64 * t2 + 2f64)),
+                (base + 1, 0.5 * (-3f64 * t3 + 4f64 * t2 + t)),
+                (base + 2, 0.5 * (t3 - t2)),
+            ]
+        }
+    }
+}
+
+/// Box blurs the color differences to luma over `radius` pixels, within
+/// the enabled pixels only.
+fn smooth_chroma(colors: &mut [Option<[f32; 3]>], w: u32, h: u32, radius: u32) {
+    let luma = |[r, g, b]: [f32; 3]| 0.299 * r + 0.587 * g + 0.114 * b;
+
+    // Blue and red differences to luma.
+    let chroma: Vec<Option<[f32; 2]>> = colors.iter()
+        .map(|color| color.map(|color| [color[2] - luma(color), color[0] - luma(color)]))
+        .collect();
+
+    let horizontal = box_blur(&chroma, w, h, radius, 1, w as usize);
+    let blurred = box_blur(&horizontal, h, w, radius, w as usize, 1);
+
+    for (color, chroma) in colors.iter_mut().zip(blurred) {
+        if let (Some(color), Some([cb, cr])) = (color.as_mut(), chroma) {
+            let y = luma(*color);
+            let r = y + cr;
+            let b = y + cb;
+
+            *color = [r, (y - 0.299 * r - 0.114 * b) / 0.587, b];
+        }
+    }
+}
+
+/// Averages the enabled values within `radius` along lines of `len` values
+/// `step` apart, starting `line_step` apart.
+fn box_blur(values: &[Option<[f32; 2]>], len: u32, lines: u32, radius: u32, step: usize, line_step: usize)
+    -> Vec<Option<[f32; 2]>> {
+    let mut blurred = values.to_vec();
+    let radius = radius as usize;
+
+    for line in 0..lines as usize {
+        let index = |i: usize| line * line_step + i * step;
+
+        // Running sums over the window, so the radius doesn't cost anything.
+        let mut sum = [0f32; 2];
+        let mut count = 0u32;
+        let len = len as usize;
+
+        for i in 0..len + radius {
+            if i < len {
+                if let Some([a, b]) = values[index(i)] {
+                    sum = [sum[0] + a, sum[1] + b];
+                    count += 1;
+                }
+            }
+
+            if i > 2 * radius {
+                if let Some([a, b]) = values[index(i - 2 * radius - 1)] {
+                    sum = [sum[0] - a, sum[1] - b];
+                    count -= 1;
+                }
+            }
+
+            if i >= radius && values[index(i - radius)].is_some() && count > 0 {
+                blurred[index(i - radius)] = Some([sum[0] / count as f32, sum[1] / count as f32]);
+            }
+        }
+    }
+
+    blurred
+}
@@ -0,0 +1,123 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+
+use crate::Map;
+use crate::TilePoint;
+
+use super::Context;
+use super::ExportOptions;
+use super::font;
+use super::pixels::PixelBuffer;
+use super::pixels::PixelFormat;
+use super::pixels::Samples;
+use super::relief;
+use super::tile_spacing;
+
+const GAP: u32 = 4;
+const LABEL_HEIGHT: u32 = font::GLYPH_HEIGHT + 4;
+const BACKGROUND: [u8; 3] = [255, 255, 255];
+const LABEL_COLOR: [u8; 3] = [0, 0, 0];
+/// Tiles only one of the maps has enabled, in the difference panel.
+const MISMATCH_COLOR: [u8; 3] = [96, 96, 96];
+
+/// Renders the shaded reliefs of `a` and `b` next to a heatmap of `b - a`,
+/// each panel labeled, and writes it to `<output dir>/<file_stem>.<ext>`.
+///
+/// The maps are aligned at their top-left corners. The heatmap is blue where
+/// `b` is lower and red where it is higher, saturating at the largest
+/// difference, which is returned.
+pub fn export_comparison(a: (&Map, &str), b: (&Map, &str), file_stem: &str, options: &ExportOptions) -> io::Result<f32> {
+    let (a, a_label) = a;
+    let (b, b_label) = b;
+    let panel_w = a.header.w.max(b.header.w);
+    let panel_h = a.header.h.max(b.header.h);
+    let shade_a = relief::hillshade(a, tile_spacing(a, options), 315f32, 45f32);
+    let shade_b = relief::hillshade(b, tile_spacing(b, options), 315f32, 45f32);
+
+    let tiles_a = a.tiles();
+    let tiles_b = b.tiles();
+    let tile = |tiles: &[Option<TilePoint>], map: &Map, x: u32, y: u32| {
+        if x >= map.header.w || y >= map.header.h {
+            None
+        } else {
+            tiles[((map.header.h - 1 - y) * map.header.w + x) as usize].map(|point| point.h)
+        }
+    };
+
+    let mut diffs = Vec::with_capacity((panel_w * panel_h) as usize);
+    for y in 0..panel_h {
+        for x in 0..panel_w {
+            diffs.push(match (tile(&tiles_a, a, x, y), tile(&tiles_b, b, x, y)) {
+                (Some(ha), Some(hb)) => Some(Some(hb - ha)),
+                (None, None) => None,
+                _ => Some(None),
+            });
+        }
+    }
+
+    let max_diff = diffs.iter()
+        .flatten()
+        .flatten()
+        .fold(0f32, |max, diff| max.max(diff.abs()));
+
+    let width = panel_w * 3 + GAP * 4;
+    let height = panel_h + LABEL_HEIGHT + GAP * 2;
+    let mut pixels = PixelBuffer {
+        width,
+        height,
+        format: PixelFormat::Rgb8,
+        samples: Samples::U8(BACKGROUND.repeat((width * height) as usize)),
+    };
+
+    let top = i64::from(LABEL_HEIGHT + GAP);
+    let panel_left = |panel: u32| i64::from(GAP + panel * (panel_w + GAP));
+
+    for (panel, shade, map) in [(0, &shade_a, a), (1, &shade_b, b)] {
+        for y in 0..map.header.h {
+            for x in 0..map.header.w {
+                if let Some(shade) = shade[(y * map.header.w + x) as usize] {
+                    let level = (255f32 * shade).round() as u8;
+                    pixels.paint(panel_left(panel) + i64::from(x), top + i64::from(y), [level; 3]);
+                }
+            }
+        }
+    }
+
+    for (i, diff) in diffs.iter().enumerate() {
+        let color = match diff {
+            Some(Some(diff)) => heat(*diff, max_diff),
+            Some(None) => MISMATCH_COLOR,
+            None => continue,
+        };
+        let (x, y) = (i as u32 % panel_w, i as u32 / panel_w);
+        pixels.paint(panel_left(2) + i64::from(x), top + i64::from(y), color);
+    }
+
+    let diff_label = format!("B-A +-{:.3}", max_diff);
+    for (panel, label) in [(0, format!("A {}", a_label)), (1, format!("B {}", b_label)), (2, diff_label)] {
+        font::draw_text(&mut pixels, panel_left(panel), i64::from(GAP), &label, 1, LABEL_COLOR);
+    }
+
+    let options = ExportOptions { pixel_format: PixelFormat::Rgb8, ..options.clone() };
+    let context = Context { options: &options, icc_profile: None, metadata: Vec::new() };
+
+    fs::create_dir_all(&options.output_dir)?;
+
+    for &format in &options.formats {
+        let path = format.path(&options.output_dir, file_stem);
+        super::write_raster(&pixels, format, &context, BufWriter::new(File::create(&path)?))?;
+        println!("Wrote {}", path.display());
+    }
+
+    Ok(max_diff)
+}
+
+/// A diverging blue-white-red color for a difference of `diff`.
+fn heat(diff: f32, max_diff: f32) -> [u8; 3] {
+    let t = if max_diff > 0f32 { (diff / max_diff).clamp(-1f32, 1f32) } else { 0f32 };
+    let fade = (255f32 * (1f32 - t.abs())).round() as u8;
+
+    if t >= 0f32 { [255, fade, fade] } else { [fade, fade, 255] }
+}
@@ -0,0 +1,258 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::Map;
+
+use super::overlay::parse_color;
+use super::pixels::PixelBuffer;
+use super::pixels::PixelFormat;
+use super::pixels::Samples;
+use super::relief;
+
+/// The tints of the hypsometric layer from the bottom to the top of the
+/// height range.
+const HYPSOMETRIC: [[f32; 3]; 5] = [
+    [0.20, 0.45, 0.25],
+    [0.55, 0.70, 0.35],
+    [0.90, 0.85, 0.55],
+    [0.60, 0.45, 0.30],
+    [0.95, 0.95, 0.95],
+];
+
+/// What a layer of the stack draws.
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+    /// The height tints of a classic relief map.
+    Hypsometric,
+    /// Gray shading lit from `azimuth` and `altitude` degrees.
+    Hillshade { azimuth: f32, altitude: f32 },
+    /// Lines every `interval` of height.
+    Contours { interval: f32, color: [f32; 3] },
+    /// The tiles below `level`.
+    Water { level: f32, color: [f32; 3] },
+    /// Lines every `spacing` tiles.
+    Grid { spacing: u32, color: [f32; 3] },
+    /// The tile color layer.
+    Color,
+}
+
+/// How a layer is combined with the layers below it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Blend {
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+}
+
+impl Blend {
+    fn apply(self, below: f32, above: f32) -> f32 {
+        match self {
+            Blend::Normal => above,
+            Blend::Multiply => below * above,
+            Blend::Screen => 1f32 - (1f32 - below) * (1f32 - above),
+            Blend::Overlay if below < 0.5 => 2f32 * below * above,
+            Blend::Overlay => 1f32 - 2f32 * (1f32 - below) * (1f32 - above),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Layer {
+    kind: Kind,
+    blend: Blend,
+    opacity: f32,
+}
+
+/// A stack of layers rendered bottom to top into one image, as listed in a
+/// TOML file of `[[layer]]` tables:
+///
+/// ```toml
+/// [[layer]]
+/// kind = "hypsometric"
+///
+/// [[layer]]
+/// kind = "hillshade"   # with azimuth = 315 and altitude = 45
+/// blend = "multiply"   # normal, multiply, screen or overlay
+/// opacity = 0.8
+///
+/// [[layer]]
+/// kind = "contours"    # with interval = 10 and color = "000000"
+///
+/// [[layer]]
+/// kind = "water"       # with level, required, and color = "3a6ea5"
+///
+/// [[layer]]
+/// kind = "grid"        # with spacing = 64 and color = "ff0000"
+///
+/// [[layer]]
+/// kind = "color"
+/// ```
+#[derive(Debug, Clone)]
+pub struct Composite {
+    layers: Vec<Layer>,
+}
+
+impl Composite {
+    /// The stack in the file at `path`.
+    pub fn load(path: &Path) -> io::Result<Composite> {
+        let text = fs::read_to_string(path)?;
+
+        Composite::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData,
+                                                            format!("{}: {}", path.display(), e)))
+    }
+
+    fn parse(text: &str) -> Result<Composite, String> {
+        let table: toml::Table = text.parse().map_err(|e: toml::de::Error| e.message().to_string())?;
+        let mut layers = Vec::new();
+
+        for (key, value) in table {
+            if key != "layer" {
+                return Err(format!("Unknown table '{}', expected [[layer]]", key));
+            }
+            let entries = value.as_array().ok_or("The layers have to be listed as [[layer]] tables")?;
+
+            for (i, entry) in entries.iter().enumerate() {
+                let entry = entry.as_table().ok_or("The layers have to be listed as [[layer]] tables")?;
+                layers.push(parse_layer(entry).map_err(|e| format!("Layer {}: {}", i + 1, e))?);
+            }
+        }
+
+        if layers.is_empty() {
+            return Err(String::from("There are no layers to render"));
+        }
+
+        Ok(Composite { layers })
+    }
+
+    /// Renders the stack over black at one rgb8 pixel per tile, disabled
+    /// tiles staying black, shading tiles `spacing` apart.
+    pub fn render(&self, map: &Map, spacing: f32) -> PixelBuffer {
+        let w = map.header.w;
+        let h = map.header.h;
+        let heights = map.tile_heights();
+        let range = map.height_range();
+        let span = range.end - range.start;
+        let normalized = |height: f32| if span > 0f32 { ((height - range.start) / span).clamp(0f32, 1f32) } else { 0.5 };
+
+        let mut colors = vec![[0f32; 3]; heights.len()];
+        let tile_colors: Vec<[f32; 3]> = map.tile_points().iter()
+            .map(|point| point.map_or([0f32; 3], |point| [point.r, point.g, point.b].map(|c| f32::from(c) / 255f32)))
+            .collect();
+
+        for layer in &self.layers {
+            let shade = match layer.kind {
+                Kind::Hillshade { azimuth, altitude } => relief::hillshade(map, spacing, azimuth, altitude),
+                _ => Vec::new(),
+            };
+
+            for (i, color) in colors.iter_mut().enumerate() {
+                let height = match heights[i] {
+                    Some(height) => height,
+                    None => continue,
+                };
+                let (x, y) = (i as u32 % w, i as u32 / w);
+
+                let above = match layer.kind {
+                    Kind::Hypsometric => Some(tint(normalized(height))),
+                    Kind::Hillshade { .. } => shade[i].map(|shade| [shade; 3]),
+                    Kind::Contours { interval, color } => {
+                        let band = |height: f32| (height / interval).floor();
+                        let crosses = [(x + 1 < w).then(|| i + 1), (y + 1 < h).then(|| i + w as usize)].iter()
+                            .flatten()
+                            .filter_map(|&neighbor| heights[neighbor])
+                            .any(|neighbor| band(neighbor) != band(height));
+                        if crosses { Some(color) } else { None }
+                    }
+                    Kind::Water { level, color } => if height < level { Some(color) } else { None },
+                    Kind::Grid { spacing, color } => if x % spacing == 0 || y % spacing == 0 { Some(color) } else { None },
+                    Kind::Color => Some(tile_colors[i]),
+                };
+
+                if let Some(above) = above {
+                    for c in 0..3 {
+                        let blended = layer.blend.apply(color[c], above[c]);
+                        color[c] += (blended - color[c]) * layer.opacity;
+                    }
+                }
+            }
+        }
+
+        let samples = colors.iter()
+            .flat_map(|color| color.map(|c| (c.clamp(0f32, 1f32) * 255f32).round() as u8))
+            .collect();
+
+        PixelBuffer { width: w, height: h, format: PixelFormat::Rgb8, samples: Samples::U8(samples) }
+    }
+}
+
+fn parse_layer(entry: &toml::Table) -> Result<Layer, String> {
+    let number = |key: &str, default: Option<f64>| match entry.get(key) {
+        Some(value) => value.as_float().or_else(|| value.as_integer().map(|i| i as f64))
+            .ok_or_else(|| format!("'{}' has to be a number", key)),
+        None => default.ok_or_else(|| format!("'{}' is required", key)),
+    };
+    let color = |default: &str| {
+        let text = match entry.get("color") {
+            Some(value) => value.as_str().ok_or("'color' has to be an rrggbb string")?,
+            None => default,
+        };
+        parse_color(text).map(|color| color.map(|c| f32::from(c) / 255f32))
+    };
+
+    let kind_name = entry.get("kind").and_then(|kind| kind.as_str()).ok_or("'kind' has to be given as a string")?;
+    let (kind, keys): (Kind, &[&str]) = match kind_name {
+        "hypsometric" => (Kind::Hypsometric, &[]),
+        "hillshade" => (Kind::Hillshade {
+            azimuth: number("azimuth", Some(315f64))? as f32,
+            altitude: number("altitude", Some(45f64))? as f32,
+        }, &["azimuth", "altitude"]),
+        "contours" => {
+            let interval = number("interval", Some(10f64))? as f32;
+            if interval.is_nan() || interval <= 0f32 {
+                return Err(String::from("'interval' has to be positive"));
+            }
+            (Kind::Contours { interval, color: color("000000")? }, &["interval", "color"])
+        }
+        "water" => (Kind::Water { level: number("level", None)? as f32, color: color("3a6ea5")? }, &["level", "color"]),
+        "grid" => {
+            let spacing = number("spacing", Some(64f64))?;
+            if spacing.is_nan() || spacing < 1f64 {
+                return Err(String::from("'spacing' has to be at least 1"));
+            }
+            (Kind::Grid { spacing: spacing as u32, color: color("ff0000")? }, &["spacing", "color"])
+        }
+        "color" => (Kind::Color, &[]),
+        _ => return Err(format!(
+            "Unknown kind '{}', expected hypsometric, hillshade, contours, water, grid or color", kind_name)),
+    };
+
+    let blend = match entry.get("blend").map(|blend| blend.as_str().ok_or("'blend' has to be a string")).transpose()? {
+        None | Some("normal") => Blend::Normal,
+        Some("multiply") => Blend::Multiply,
+        Some("screen") => Blend::Screen,
+        Some("overlay") => Blend::Overlay,
+        Some(other) => return Err(format!("Unknown blend mode '{}', expected normal, multiply, screen or overlay", other)),
+    };
+    let opacity = number("opacity", Some(1f64))? as f32;
+    if !(0f32..=1f32).contains(&opacity) {
+        return Err(String::from("'opacity' has to be within 0..1"));
+    }
+
+    if let Some(key) = entry.keys().find(|key| !["kind", "blend", "opacity"].contains(&key.as_str()) && !keys.contains(&key.as_str())) {
+        return Err(format!("Unknown setting '{}' for {} layers", key, kind_name));
+    }
+
+    Ok(Layer { kind, blend, opacity })
+}
+
+/// The hypsometric tint at `t` of the height range.
+pub(super) fn tint(t: f32) -> [f32; 3] {
+    let scaled = t * (HYPSOMETRIC.len() - 1) as f32;
+    let lower = (scaled.floor() as usize).min(HYPSOMETRIC.len() - 2);
+    let fraction = scaled - lower as f32;
+    let (a, b) = (HYPSOMETRIC[lower], HYPSOMETRIC[lower + 1]);
+
+    [0, 1, 2].map(|c| a[c] + (b[c] - a[c]) * fraction)
+}
@@ -0,0 +1,162 @@
+use std::io;
+use std::path::Path;
+
+use super::pixels::PixelBuffer;
+use super::truetype;
+use super::truetype::TrueType;
+
+/// Glyphs of the built-in 3x5 pixel font, one row per entry with the
+/// leftmost pixel in the highest of the three bits.
+const GLYPHS: [(char, [u8; 5]); 43] = [
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b010, 0b010]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    (',', [0b000, 0b000, 0b000, 0b010, 0b100]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]),
+    ('_', [0b000, 0b000, 0b000, 0b000, 0b111]),
+    ('+', [0b000, 0b010, 0b111, 0b010, 0b000]),
+];
+
+pub const GLYPH_WIDTH: u32 = 3;
+pub const GLYPH_HEIGHT: u32 = 5;
+
+/// The width in pixels of `text` drawn at `scale`, with one pixel of
+/// spacing between glyphs.
+pub fn text_width(text: &str, scale: u32) -> u32 {
+    let count = text.chars().count() as u32;
+    (count * (GLYPH_WIDTH + 1)).saturating_sub(1) * scale.max(1)
+}
+
+/// Draws `text` with its top-left corner at `(x, y)`, clipped to the image.
+/// Lowercase letters are drawn as uppercase and unknown characters as blanks.
+pub fn draw_text(pixels: &mut PixelBuffer, x: i64, y: i64, text: &str, scale: u32, color: [u8; 3]) {
+    let scale = i64::from(scale.max(1));
+
+    for (i, c) in text.chars().enumerate() {
+        let c = c.to_ascii_uppercase();
+        let rows = match GLYPHS.iter().find(|(glyph, _)| *glyph == c) {
+            Some((_, rows)) => rows,
+            None => continue,
+        };
+        let left = x + i as i64 * i64::from(GLYPH_WIDTH + 1) * scale;
+
+        for (row, bits) in rows.iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - column)) == 0 {
+                    continue;
+                }
+
+                for sy in 0..scale {
+                    for sx in 0..scale {
+                        pixels.paint(left + i64::from(column) * scale + sx, y + row as i64 * scale + sy, color);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The font annotations are drawn in, either the built-in pixel font scaled
+/// by whole pixels or a TrueType font, antialiased.
+#[derive(Debug, Clone)]
+pub struct Font {
+    face: Option<TrueType>,
+    /// The height of the built-in glyphs or the em size of the TrueType
+    /// font, in pixels.
+    size: u32,
+}
+
+impl Default for Font {
+    fn default() -> Font {
+        Font { face: None, size: GLYPH_HEIGHT }
+    }
+}
+
+impl Font {
+    /// The TrueType font at `path` or the built-in font, `size` pixels high,
+    /// 12 for TrueType and the 5 pixels of the built-in font by default.
+    /// The built-in font only grows in steps of 5 pixels.
+    pub fn load(path: Option<&Path>, size: Option<u32>) -> io::Result<Font> {
+        Ok(match path {
+            Some(path) => Font { face: Some(TrueType::open(path)?), size: size.unwrap_or(12) },
+            None => Font { face: None, size: size.unwrap_or(GLYPH_HEIGHT) },
+        })
+    }
+
+    fn scale(&self) -> u32 {
+        (self.size / GLYPH_HEIGHT).max(1)
+    }
+
+    /// The height of a line of text in pixels.
+    pub fn height(&self) -> u32 {
+        match &self.face {
+            Some(face) => face.line_height(self.size as f32).ceil() as u32,
+            None => GLYPH_HEIGHT * self.scale(),
+        }
+    }
+
+    /// The width of `text` in pixels.
+    pub fn text_width(&self, text: &str) -> u32 {
+        match &self.face {
+            Some(face) => face.text_width(text, self.size as f32).ceil() as u32,
+            None => text_width(text, self.scale()),
+        }
+    }
+
+    /// Draws `text` with its top-left corner at `(x, y)`, clipped to the
+    /// image. TrueType glyphs are blended into the image by how much of
+    /// each pixel they cover.
+    pub fn draw(&self, pixels: &mut PixelBuffer, x: i64, y: i64, text: &str, color: [u8; 3]) {
+        let face = match &self.face {
+            Some(face) => face,
+            None => return draw_text(pixels, x, y, text, self.scale(), color),
+        };
+
+        let size = self.size as f32;
+        let (width, height) = (self.text_width(text) as usize + 1, self.height() as usize + 1);
+        let edges = face.layout(text, size, (0f32, face.ascent(size)));
+        let coverage = truetype::rasterize(&edges, width, height);
+
+        for (i, &covered) in coverage.iter().enumerate() {
+            if covered > 0f32 {
+                pixels.blend(x + (i % width) as i64, y + (i / width) as i64, color, covered);
+            }
+        }
+    }
+}
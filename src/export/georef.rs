@@ -0,0 +1,100 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use clap::Args;
+
+/// Placement of the map in a world coordinate system, shared by every
+/// geospatial output.
+#[derive(Args, Debug, Clone, Default)]
+pub struct Georef {
+    /// World coordinates of the top-left corner of the map, as `x,y`.
+    #[arg(long, value_parser = parse_origin, allow_hyphen_values = true)]
+    pub origin: Option<(f64, f64)>,
+
+    /// The size of a tile in world units.
+    #[arg(long)]
+    pub cell_size: Option<f64>,
+
+    /// The coordinate reference system, e.g. `EPSG:32633`.
+    #[arg(long)]
+    pub crs: Option<String>,
+
+    /// Reproject geospatial output from `--crs` into this CRS, given as
+    /// `EPSG:<code>` or a proj string.
+    #[cfg(feature = "reproject")]
+    #[arg(long, value_name = "CRS", requires = "crs")]
+    pub target_crs: Option<String>,
+}
+
+impl Georef {
+    /// Whether any placement was requested, which turns on world files and
+    /// GeoTIFF tags.
+    pub fn is_set(&self) -> bool {
+        self.origin.is_some() || self.cell_size.is_some() || self.crs.is_some()
+    }
+
+    pub fn origin(&self) -> (f64, f64) {
+        self.origin.unwrap_or((0f64, 0f64))
+    }
+
+    pub fn cell_size(&self) -> f64 {
+        self.cell_size.unwrap_or(1f64)
+    }
+
+    /// The EPSG code of the CRS, if it is given as `EPSG:<code>`.
+    pub fn epsg(&self) -> Option<u16> {
+        let crs = self.crs.as_deref()?;
+        let (authority, code) = crs.split_once(':')?;
+
+        if authority.eq_ignore_ascii_case("epsg") { code.trim().parse().ok() } else { None }
+    }
+
+    /// World coordinates of the center of a tile in image coordinates.
+    pub fn tile_center(&self, x: f64, y: f64) -> (f64, f64) {
+        let (ox, oy) = self.origin();
+        let cell_size = self.cell_size();
+
+        (ox + (x + 0.5) * cell_size, oy - (y + 0.5) * cell_size)
+    }
+
+    /// Writes an ESRI world file next to a raster, e.g. `map.pgw` for
+    /// `map.png`, and returns its path.
+    pub fn write_world_file(&self, raster_path: &Path) -> io::Result<PathBuf> {
+        let cell_size = self.cell_size();
+        let (cx, cy) = self.tile_center(0f64, 0f64);
+        let contents = format!("{}\n0\n0\n{}\n{}\n{}\n", cell_size, -cell_size, cx, cy);
+        let path = world_file_path(raster_path);
+
+        fs::write(&path, contents)?;
+
+        Ok(path)
+    }
+}
+
+/// The world file next to a raster, e.g. `.pgw` for `.png`.
+pub fn world_file_path(raster_path: &Path) -> PathBuf {
+    let extension = raster_path.extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+    let mut chars = extension.chars();
+    let world_extension = match (chars.next(), chars.last()) {
+        (Some(first), Some(last)) => format!("{}{}w", first, last),
+        _ => String::from("wld"),
+    };
+
+    raster_path.with_extension(world_extension)
+}
+
+fn parse_origin(s: &str) -> Result<(f64, f64), String> {
+    let parts = s.split(',')
+        .map(|part| part.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Invalid origin '{}': {}", s, e))?;
+
+    match parts[..] {
+        [x, y] => Ok((x, y)),
+        _ => Err(format!("Invalid origin '{}', expected x,y", s)),
+    }
+}
@@ -0,0 +1,200 @@
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::prelude::*;
+use std::path::Path;
+
+use serde_json::json;
+
+use crate::Map;
+
+use super::Context;
+use super::tessellate;
+use super::vertex_position;
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const GLB_VERSION: u32 = 2;
+const CHUNK_JSON: u32 = 0x4e4f_534a;
+const CHUNK_BIN: u32 = 0x004e_4942;
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const UNSIGNED_SHORT: u32 = 5123;
+const UNSIGNED_BYTE: u32 = 5121;
+
+/// Lets positions be stored as integers, scaled back by the node.
+const MESH_QUANTIZATION: &str = "KHR_mesh_quantization";
+
+/// An indexed triangle mesh in glTF coordinates: Y up, facing the viewer
+/// when the corners go counterclockwise.
+#[derive(Debug, Clone, Default)]
+pub struct Mesh {
+    pub positions: Vec<[f32; 3]>,
+    /// The RGBA color of every position, or none.
+    pub colors: Vec<[u8; 4]>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    /// The lowest and highest corner of the box around every position,
+    /// `None` if there are none.
+    pub fn bounds(&self) -> Option<([f32; 3], [f32; 3])> {
+        let first = *self.positions.first()?;
+
+        Some(self.positions.iter().fold((first, first), |(min, max), position| {
+            let mut min = min;
+            let mut max = max;
+            for axis in 0..3 {
+                min[axis] = min[axis].min(position[axis]);
+                max[axis] = max[axis].max(position[axis]);
+            }
+            (min, max)
+        }))
+    }
+}
+
+/// Writes the enabled tiles as a binary glTF surface, tessellated and
+/// skirted like the OBJ meshes and placed the same way, with the tile
+/// colors as vertex colors with `--mesh-colors` and quantized with
+/// `--mesh-quantize`.
+pub fn write(map: &Map, context: &Context, path: &Path) -> io::Result<()> {
+    let options = &context.options.mesh;
+    let width = map.header.w;
+    let height = map.header.h;
+
+    let heights = map.tile_heights();
+
+    let triangles = match options.mesh_error {
+        Some(max_error) => tessellate::triangulate(&heights, width, height, max_error),
+        None => tessellate::grid(&heights, width, height),
+    };
+    let (corners, indices) = tessellate::indexed(&triangles, width, height, options.mesh_skirt.is_some());
+
+    let positions = corners.iter()
+        .map(|&((x, y), lowered)| {
+            let (wx, wy) = vertex_position(map, context.options, x, y);
+            let depth = if lowered { options.mesh_skirt.unwrap_or_default() } else { 0f32 };
+
+            [wx as f32, heights[(y * width + x) as usize].unwrap_or_default() - depth, -wy as f32]
+        })
+        .collect();
+    let colors = if options.mesh_colors {
+        let points = map.tile_points();
+        corners.iter()
+            .map(|&((x, y), _)| points[(y * width + x) as usize].map_or([0u8; 4], |point| [point.r, point.g, point.b, 255]))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let mesh = Mesh { positions, colors, indices };
+    write_glb(&mesh, options.mesh_quantize, BufWriter::new(File::create(path)?))
+}
+
+/// Writes `mesh` as a binary glTF 2.0 file with a single node, the
+/// positions, colors and indices sharing one buffer.
+///
+/// With `quantize` the positions are stored as 16-bit steps from the lowest
+/// corner, which the node's translation and scale turn back into
+/// coordinates, and the indices take 16 bits if there are few enough
+/// vertices.
+pub fn write_glb(mesh: &Mesh, quantize: bool, mut w: impl Write) -> io::Result<()> {
+    let (min, max) = mesh.bounds()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "glTF meshes need at least one vertex"))?;
+    let step = [0, 1, 2].map(|axis| match (max[axis] - min[axis]) / f32::from(u16::MAX) {
+        step if step > 0f32 => step,
+        _ => 1f32,
+    });
+    let short_indices = quantize && mesh.positions.len() <= usize::from(u16::MAX) + 1;
+
+    let mut buffer = Vec::with_capacity(mesh.positions.len() * 12 + mesh.indices.len() * 4);
+    for position in &mesh.positions {
+        if quantize {
+            for axis in 0..3 {
+                let steps = ((position[axis] - min[axis]) / step[axis]).round() as u16;
+                buffer.extend_from_slice(&steps.to_le_bytes());
+            }
+            // Vertex attributes have to start on 4 bytes.
+            buffer.extend_from_slice(&[0, 0]);
+        } else {
+            for coordinate in position {
+                buffer.extend_from_slice(&coordinate.to_le_bytes());
+            }
+        }
+    }
+    let positions_length = buffer.len();
+    for color in &mesh.colors {
+        buffer.extend_from_slice(color);
+    }
+    let colors_length = buffer.len() - positions_length;
+    for &index in &mesh.indices {
+        if short_indices {
+            buffer.extend_from_slice(&(index as u16).to_le_bytes());
+        } else {
+            buffer.extend_from_slice(&index.to_le_bytes());
+        }
+    }
+
+    let indices_offset = positions_length + colors_length;
+    let mut attributes = json!({ "POSITION": 0 });
+    let mut views = vec![
+        json!({ "buffer": 0, "byteOffset": 0, "byteLength": positions_length, "target": ARRAY_BUFFER }),
+        json!({ "buffer": 0, "byteOffset": indices_offset, "byteLength": buffer.len() - indices_offset, "target": ELEMENT_ARRAY_BUFFER }),
+    ];
+    let positions = if quantize {
+        views[0]["byteStride"] = json!(8);
+        let steps = [0, 1, 2].map(|axis| ((max[axis] - min[axis]) / step[axis]).round() as u16);
+        json!({ "bufferView": 0, "componentType": UNSIGNED_SHORT, "count": mesh.positions.len(), "type": "VEC3", "min": [0, 0, 0], "max": steps })
+    } else {
+        json!({ "bufferView": 0, "componentType": FLOAT, "count": mesh.positions.len(), "type": "VEC3", "min": min, "max": max })
+    };
+    let index_type = if short_indices { UNSIGNED_SHORT } else { UNSIGNED_INT };
+    let mut accessors = vec![
+        positions,
+        json!({ "bufferView": 1, "componentType": index_type, "count": mesh.indices.len(), "type": "SCALAR" }),
+    ];
+
+    if !mesh.colors.is_empty() {
+        attributes["COLOR_0"] = json!(accessors.len());
+        accessors.push(json!({
+            "bufferView": views.len(), "componentType": UNSIGNED_BYTE, "normalized": true, "count": mesh.colors.len(), "type": "VEC4",
+        }));
+        views.push(json!({ "buffer": 0, "byteOffset": positions_length, "byteLength": colors_length, "target": ARRAY_BUFFER }));
+    }
+
+    let mut document = json!({
+        "asset": { "version": "2.0", "generator": "gti2bmp" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [{ "mesh": 0 }],
+        "meshes": [{ "primitives": [{ "attributes": attributes, "indices": 1 }] }],
+        "buffers": [{ "byteLength": buffer.len() }],
+        "bufferViews": views,
+        "accessors": accessors,
+    });
+    if quantize {
+        document["nodes"][0] = json!({ "mesh": 0, "translation": min, "scale": step });
+        document["extensionsUsed"] = json!([MESH_QUANTIZATION]);
+        document["extensionsRequired"] = json!([MESH_QUANTIZATION]);
+    }
+
+    // Both chunks have to end on a 4 byte boundary.
+    let mut json = document.to_string().into_bytes();
+    json.resize(json.len().next_multiple_of(4), b' ');
+    buffer.resize(buffer.len().next_multiple_of(4), 0);
+
+    let length = 12 + 8 + json.len() + 8 + buffer.len();
+    w.write_all(GLB_MAGIC)?;
+    w.write_all(&GLB_VERSION.to_le_bytes())?;
+    w.write_all(&(length as u32).to_le_bytes())?;
+
+    for (kind, data) in [(CHUNK_JSON, &json), (CHUNK_BIN, &buffer)] {
+        w.write_all(&(data.len() as u32).to_le_bytes())?;
+        w.write_all(&kind.to_le_bytes())?;
+        w.write_all(data)?;
+    }
+
+    w.flush()
+}
@@ -0,0 +1,82 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+use crate::stats::Histogram;
+
+use super::Context;
+use super::ExportOptions;
+use super::font;
+use super::pixels::PixelBuffer;
+use super::pixels::PixelFormat;
+use super::pixels::Samples;
+
+const BAR_WIDTH: u32 = 8;
+const PLOT_HEIGHT: u32 = 160;
+const MARGIN: u32 = 6;
+const BACKGROUND: [u8; 3] = [255, 255, 255];
+const BAR_COLOR: [u8; 3] = [90, 115, 153];
+const LABEL_COLOR: [u8; 3] = [0, 0, 0];
+
+/// Draws `histogram` as a bar chart with the lowest and highest height
+/// below it and the tallest bar's count above, and writes it to
+/// `<output dir>/<file_stem>.<ext>`. Returns the paths written.
+pub fn export_histogram(histogram: &Histogram, file_stem: &str, options: &ExportOptions) -> io::Result<Vec<PathBuf>> {
+    let tallest = histogram.counts.iter().copied().max().unwrap_or_default().max(1);
+    let (low, high) = (format!("{}", histogram.range.start), format!("{}", histogram.range.end));
+    let top_label = format!("{} tiles", tallest);
+
+    let label_height = font::GLYPH_HEIGHT + 2;
+    let plot_width = (histogram.counts.len() as u32 * BAR_WIDTH)
+        .max(font::text_width(&low, 1) + font::text_width(&high, 1) + MARGIN)
+        .max(font::text_width(&top_label, 1));
+    let width = plot_width + MARGIN * 2;
+    let height = PLOT_HEIGHT + label_height * 2 + MARGIN * 2;
+    let plot_top = MARGIN + label_height;
+    let bar_width = plot_width / histogram.counts.len() as u32;
+
+    let mut pixels = PixelBuffer {
+        width,
+        height,
+        format: PixelFormat::Rgb8,
+        samples: Samples::U8(BACKGROUND.repeat((width * height) as usize)),
+    };
+
+    for (i, &count) in histogram.counts.iter().enumerate() {
+        let bar_height = (count as f64 / tallest as f64 * f64::from(PLOT_HEIGHT)).round() as u32;
+        // Any count at all gets at least a line.
+        let bar_height = if count > 0 { bar_height.max(1) } else { 0 };
+        let left = MARGIN + i as u32 * bar_width;
+
+        for y in plot_top + PLOT_HEIGHT - bar_height..plot_top + PLOT_HEIGHT {
+            for x in left..left + bar_width.saturating_sub(1).max(1) {
+                pixels.paint(i64::from(x), i64::from(y), BAR_COLOR);
+            }
+        }
+    }
+    for x in MARGIN..MARGIN + plot_width {
+        pixels.paint(i64::from(x), i64::from(plot_top + PLOT_HEIGHT), LABEL_COLOR);
+    }
+
+    let bottom = i64::from(plot_top + PLOT_HEIGHT + 2);
+    font::draw_text(&mut pixels, i64::from(MARGIN), i64::from(MARGIN), &top_label, 1, LABEL_COLOR);
+    font::draw_text(&mut pixels, i64::from(MARGIN), bottom, &low, 1, LABEL_COLOR);
+    font::draw_text(&mut pixels, i64::from(MARGIN + plot_width - font::text_width(&high, 1)), bottom, &high, 1, LABEL_COLOR);
+
+    let options = ExportOptions { pixel_format: PixelFormat::Rgb8, color_space: None, ..options.clone() };
+    let context = Context { options: &options, icc_profile: None, metadata: Vec::new() };
+    let mut written = Vec::new();
+
+    fs::create_dir_all(&options.output_dir)?;
+
+    for &format in &options.formats {
+        let path = format.path(&options.output_dir, file_stem);
+        super::write_raster(&pixels, format, &context, BufWriter::new(File::create(&path)?))?;
+        println!("Wrote {}", path.display());
+        written.push(path);
+    }
+
+    Ok(written)
+}
@@ -0,0 +1,138 @@
+use std::f32::consts::FRAC_PI_2;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::path::Path;
+use std::path::PathBuf;
+
+use clap::Args;
+
+use crate::Map;
+
+use super::ColorSpace;
+use super::Context;
+use super::ExportOptions;
+use super::pixels::PixelBuffer;
+use super::pixels::PixelFormat;
+use super::pixels::Samples;
+use super::png;
+use super::tile_spacing;
+
+/// The directions horizons are traced in, as image offsets clockwise from
+/// north. With four directions only every other one is used.
+pub const DIRECTIONS: [(i64, i64); 8] = [(0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1)];
+
+#[derive(Args, Debug, Clone)]
+pub struct HorizonOptions {
+    /// How many directions horizon maps trace, 4 for north, east, south and
+    /// west, or 8 adding the diagonals in a second texture.
+    #[arg(long, value_name = "COUNT", default_value_t = 8, value_parser = parse_directions)]
+    pub horizon_directions: usize,
+
+    /// How many tiles far horizon maps look for terrain blocking the sky.
+    #[arg(long, value_name = "TILES", default_value_t = 32, value_parser = clap::value_parser!(u32).range(1..))]
+    pub horizon_distance: u32,
+}
+
+impl Default for HorizonOptions {
+    fn default() -> HorizonOptions {
+        HorizonOptions { horizon_directions: 8, horizon_distance: 32 }
+    }
+}
+
+/// The textures `write` produces at `path` for `options`, the first at
+/// `path` itself.
+pub fn paths(path: &Path, options: &HorizonOptions) -> Vec<PathBuf> {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+
+    (0..options.horizon_directions / 4)
+        .map(|texture| match texture {
+            0 => path.to_path_buf(),
+            _ => path.with_file_name(format!("{}_{}.png", stem, texture)),
+        })
+        .collect()
+}
+
+/// Writes horizon angle maps to RGBA PNGs, one direction per channel and
+/// four per texture, clockwise from north: north, east, south and west, and
+/// with eight directions northeast, southeast, southwest and northwest in a
+/// second texture. Returns the textures written after the first.
+///
+/// Every sample is the elevation of the highest terrain seen from the tile
+/// within `--horizon-distance` tiles, from 0 for an open horizon to 255 for
+/// straight up, so shaders can tell whether a light is blocked by comparing
+/// its elevation. Tiles are spaced as for the normal map, and disabled
+/// tiles neither block nor get a horizon.
+pub fn write(map: &Map, context: &Context, path: &Path) -> io::Result<Vec<PathBuf>> {
+    let options = &context.options.horizon;
+    let w = i64::from(map.header.w);
+    let h = i64::from(map.header.h);
+    let spacing = tile_spacing(map, context.options);
+
+    let directions: Vec<(i64, i64)> = match options.horizon_directions {
+        4 => DIRECTIONS.iter().step_by(2).copied().collect(),
+        // Cardinal directions first, so the first texture is the same either way.
+        _ => DIRECTIONS.iter().step_by(2).chain(DIRECTIONS.iter().skip(1).step_by(2)).copied().collect(),
+    };
+
+    let angles: Vec<Vec<u8>> = directions.iter()
+        .map(|&direction| horizon_angles(map, spacing, direction, options.horizon_distance).into_iter()
+            .map(|angle| (angle.unwrap_or_default() / FRAC_PI_2 * 255f32).round() as u8)
+            .collect())
+        .collect();
+
+    // Horizons are data, shaders read them linearly.
+    let texture_options = ExportOptions { pixel_format: PixelFormat::Rgba8, color_space: Some(ColorSpace::Linear), ..context.options.clone() };
+    let texture_context = Context { options: &texture_options, icc_profile: None, ..context.clone() };
+    let paths = paths(path, options);
+
+    for (texture, path) in angles.chunks(4).zip(&paths) {
+        let samples = (0..(w * h) as usize)
+            .flat_map(|i| texture.iter().map(move |angles| angles[i]))
+            .collect();
+        let pixels = PixelBuffer { width: map.header.w, height: map.header.h, format: PixelFormat::Rgba8, samples: Samples::U8(samples) };
+
+        png::write(&pixels, &texture_context, BufWriter::new(File::create(path)?))?;
+    }
+
+    Ok(paths.into_iter().skip(1).collect())
+}
+
+/// The elevation of the horizon looking from every tile towards the image
+/// offset `(dx, dy)`, in radians from 0 for an open horizon up to π/2, as far
+/// as `distance` tiles. In image order, `None` for disabled tiles, which
+/// don't block the view either.
+pub fn horizon_angles(map: &Map, spacing: f32, (dx, dy): (i64, i64), distance: u32) -> Vec<Option<f32>> {
+    let w = i64::from(map.header.w);
+    let h = i64::from(map.header.h);
+    let heights = map.tile_heights();
+    let step = ((dx * dx + dy * dy) as f32).sqrt() * spacing;
+
+    (0..w * h)
+        .map(|i| {
+            let (x, y) = (i % w, i / w);
+            let center = heights[i as usize]?;
+
+            let mut highest = 0f32;
+            for k in 1..=i64::from(distance) {
+                let (sx, sy) = (x + dx * k, y + dy * k);
+                if sx < 0 || sy < 0 || sx >= w || sy >= h {
+                    break;
+                }
+                if let Some(height) = heights[(sy * w + sx) as usize] {
+                    highest = highest.max(((height - center) / (k as f32 * step)).atan());
+                }
+            }
+
+            Some(highest)
+        })
+        .collect()
+}
+
+fn parse_directions(s: &str) -> Result<usize, String> {
+    match s {
+        "4" => Ok(4),
+        "8" => Ok(8),
+        _ => Err(format!("Invalid direction count '{}', expected 4 or 8", s)),
+    }
+}
@@ -0,0 +1,81 @@
+use std::fs;
+use std::io;
+
+/// The name of the built-in profile accepted in place of a file path.
+pub const LINEAR_GRAY: &str = "linear-gray";
+
+/// Loads an ICC profile from disk, or generates the built-in linear gray one.
+pub fn load(source: &str) -> io::Result<Vec<u8>> {
+    if source == LINEAR_GRAY {
+        return Ok(linear_gray_profile());
+    }
+
+    let profile = fs::read(source)?;
+
+    if profile.len() < 128 || &profile[36..40] != b"acsp" {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Not an ICC profile: {}", source)));
+    }
+
+    Ok(profile)
+}
+
+/// A minimal ICC v2 monitor profile for grayscale data with a gamma of 1.0,
+/// so readers don't apply any transfer curve to the heights.
+pub fn linear_gray_profile() -> Vec<u8> {
+    let d50 = [0.9642f32, 1.0, 0.8249];
+
+    let mut desc = b"desc\0\0\0\0".to_vec();
+    let text = b"Linear Gray\0";
+    desc.extend((text.len() as u32).to_be_bytes());
+    desc.extend(text);
+    desc.extend([0u8; 4 + 4 + 2 + 1 + 67]); // Empty Unicode and ScriptCode descriptions.
+
+    let mut wtpt = b"XYZ \0\0\0\0".to_vec();
+    wtpt.extend(d50.iter().flat_map(|&v| s15_fixed16(v)));
+
+    // A single u8Fixed8 entry is a pure gamma curve.
+    let mut ktrc = b"curv\0\0\0\0".to_vec();
+    ktrc.extend(1u32.to_be_bytes());
+    ktrc.extend(0x0100u16.to_be_bytes());
+
+    let mut cprt = b"text\0\0\0\0".to_vec();
+    cprt.extend(b"No copyright, use freely\0");
+
+    let tags: [(&[u8; 4], Vec<u8>); 4] = [(b"desc", desc), (b"wtpt", wtpt), (b"kTRC", ktrc), (b"cprt", cprt)];
+
+    let mut table = Vec::new();
+    let mut data = Vec::new();
+    let data_start = 128 + 4 + tags.len() * 12;
+
+    table.extend((tags.len() as u32).to_be_bytes());
+    for (signature, tag) in &tags {
+        table.extend(signature.iter());
+        table.extend(((data_start + data.len()) as u32).to_be_bytes());
+        table.extend((tag.len() as u32).to_be_bytes());
+
+        data.extend(tag);
+        data.resize(data.len().div_ceil(4) * 4, 0);
+    }
+
+    let size = data_start + data.len();
+    let mut header = Vec::with_capacity(128);
+    header.extend((size as u32).to_be_bytes());
+    header.extend([0u8; 4]); // Preferred CMM.
+    header.extend(0x0210_0000u32.to_be_bytes()); // Version 2.1.
+    header.extend(b"mntrGRAYXYZ ");
+    header.extend([2024u16, 1, 1, 0, 0, 0].iter().flat_map(|v| v.to_be_bytes()));
+    header.extend(b"acsp");
+    header.extend([0u8; 4 + 4 + 4 + 4 + 8 + 4]); // Platform, flags, device and intent.
+    header.extend(d50.iter().flat_map(|&v| s15_fixed16(v)));
+    header.resize(128, 0);
+
+    let mut profile = header;
+    profile.extend(table);
+    profile.extend(data);
+
+    profile
+}
+
+fn s15_fixed16(value: f32) -> [u8; 4] {
+    ((value * 65536f32).round() as i32).to_be_bytes()
+}
@@ -0,0 +1,261 @@
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::prelude::*;
+use std::path::Path;
+use std::path::PathBuf;
+
+use clap::Args;
+use clap::ValueEnum;
+
+use crate::Map;
+use crate::distance;
+use crate::distance::DistanceSource;
+use crate::hydrology::Lake;
+use crate::raster::Raster;
+
+use super::Context;
+use super::ExportOptions;
+use super::horizon;
+use super::overlay::parse_color;
+use super::pixels::PixelBuffer;
+use super::pixels::PixelFormat;
+use super::pixels::Samples;
+use super::tiff;
+use super::tile_spacing;
+
+/// A raster derived from the terrain, written next to the other outputs.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Layer {
+    /// D8 codes of the steepest descent, 1 for east doubling clockwise to
+    /// 128 for northeast, and 0 for pits, flats and disabled tiles.
+    FlowDirection,
+    /// How many tiles drain through each tile along the flow directions, as
+    /// 32-bit floats, NaN where tiles are disabled.
+    FlowAccumulation,
+    /// The topographic wetness index ln(a / tan β), from the area draining
+    /// through each tile and its slope, as 32-bit floats, NaN where tiles are
+    /// disabled. Tiles are spaced as for the normal map.
+    WetnessIndex,
+    /// The label of the lake covering each tile as 16-bit integers, 0 for dry
+    /// and disabled tiles, with every lake's level, depth and volume in
+    /// `<name>_lakes.csv`.
+    Lakes,
+    /// The distance to the coastline at `--water-level` as 32-bit floats,
+    /// positive on land, negative under water and NaN where tiles are
+    /// disabled. Tiles are spaced as for the normal map.
+    CoastDistance,
+    /// The distance to the tiles picked by `--distance-from`, in the
+    /// `--distance-format`. Tiles are spaced as for the normal map.
+    DistanceField,
+    /// The share of the sky visible from each tile as 32-bit floats, from 1
+    /// on open ground down towards 0 at the bottom of narrow valleys, NaN
+    /// where tiles are disabled. Horizons are traced in eight directions as
+    /// far as `--horizon-distance`.
+    SkyViewFactor,
+}
+
+/// The tiles the distance field layer measures the distance to.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum DistanceFrom {
+    /// The edge of the enabled area: disabled tiles and the outside of the map.
+    Edge,
+    /// The white pixels of the `--distance-mask` image.
+    Mask,
+    /// The tiles colored `--distance-color`.
+    Color,
+}
+
+/// How the distance field layer stores its distances.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum DistanceFormat {
+    /// Scaled from 0 to `--distance-max` over 16 bits, saturating beyond.
+    Gray16,
+    /// The distances themselves as 32-bit floats, infinite without sources.
+    F32,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct DistanceOptions {
+    /// What the distance field layer measures the distance to.
+    #[arg(long, value_enum, default_value = "edge")]
+    pub distance_from: DistanceFrom,
+
+    /// The black and white image of the map's size whose white pixels the
+    /// distance is measured to, as PNG or BMP.
+    #[arg(long, value_name = "PATH", required_if_eq("distance_from", "mask"))]
+    pub distance_mask: Option<PathBuf>,
+
+    /// The tile color, as rrggbb, the distance is measured to.
+    #[arg(long, value_name = "COLOR", value_parser = parse_color, required_if_eq("distance_from", "color"))]
+    pub distance_color: Option<[u8; 3]>,
+
+    /// How the distance field is stored.
+    #[arg(long, value_enum, default_value = "f32")]
+    pub distance_format: DistanceFormat,
+
+    /// The distance gray16 distance fields saturate at, the largest distance
+    /// on the map by default.
+    #[arg(long, value_name = "DISTANCE")]
+    pub distance_max: Option<f32>,
+}
+
+impl Default for DistanceOptions {
+    fn default() -> DistanceOptions {
+        DistanceOptions {
+            distance_from: DistanceFrom::Edge,
+            distance_mask: None,
+            distance_color: None,
+            distance_format: DistanceFormat::F32,
+            distance_max: None,
+        }
+    }
+}
+
+impl Layer {
+    /// What the layer's file is named after, following the map name.
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Layer::FlowDirection => "flow_direction",
+            Layer::FlowAccumulation => "flow_accumulation",
+            Layer::WetnessIndex => "wetness_index",
+            Layer::Lakes => "lakes",
+            Layer::CoastDistance => "coast_distance",
+            Layer::DistanceField => "distance",
+            Layer::SkyViewFactor => "sky_view_factor",
+        }
+    }
+
+    /// The pixel format the layer is written in with `options`.
+    pub fn pixel_format(&self, options: &ExportOptions) -> PixelFormat {
+        match self {
+            Layer::DistanceField if options.distance.distance_format == DistanceFormat::Gray16 => PixelFormat::Gray16,
+            Layer::DistanceField => PixelFormat::F32,
+            Layer::FlowDirection => PixelFormat::Gray8,
+            Layer::FlowAccumulation | Layer::WetnessIndex | Layer::CoastDistance | Layer::SkyViewFactor => PixelFormat::F32,
+            Layer::Lakes => PixelFormat::Gray16,
+        }
+    }
+}
+
+/// Computes `layer` at one pixel per tile and writes it as a TIFF, with the
+/// same georeferencing as the other outputs. Returns any other files the
+/// layer comes with.
+pub fn write(map: &Map, layer: Layer, context: &Context, path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut extras = Vec::new();
+
+    let samples = match layer {
+        Layer::FlowDirection => Samples::U8(map.flow_directions().codes.iter().map(|code| code.unwrap_or(0)).collect()),
+        Layer::FlowAccumulation => Samples::F32(map.flow_accumulation(&map.flow_directions()).iter()
+            .map(|tiles| tiles.map_or(f32::NAN, |tiles| tiles as f32))
+            .collect()),
+        Layer::WetnessIndex => Samples::F32(map.wetness_index(tile_spacing(map, context.options)).iter()
+            .map(|index| index.unwrap_or(f32::NAN))
+            .collect()),
+        Layer::Lakes => {
+            let spacing = tile_spacing(map, context.options);
+            let lakes = map.lakes(spacing);
+            if lakes.lakes.len() > usize::from(u16::MAX) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+                    "The map has {} lakes, more than 16-bit labels can tell apart", lakes.lakes.len())));
+            }
+
+            let csv_path = path.with_extension("csv");
+            write_lakes(&lakes.lakes, spacing, &csv_path)?;
+            extras.push(csv_path);
+
+            Samples::U16(lakes.labels.iter().map(|&label| label as u16).collect())
+        }
+        Layer::CoastDistance => {
+            let level = context.options.water_level.expect("Checked before exporting");
+            Samples::F32(coast_distance(map, level, tile_spacing(map, context.options)))
+        }
+        Layer::DistanceField => distance_field(map, context.options)?,
+        Layer::SkyViewFactor => Samples::F32(sky_view_factor(map, context.options)),
+    };
+    let pixels = PixelBuffer { width: map.header.w, height: map.header.h, format: layer.pixel_format(context.options), samples };
+
+    // The layers hold data, not colors.
+    let context = Context { icc_profile: None, ..context.clone() };
+    tiff::write(&pixels, &context, BufWriter::new(File::create(path)?))?;
+
+    Ok(extras)
+}
+
+/// The signed distance from every tile to the coastline, which runs halfway
+/// between the tiles below `level` and the enabled ones above. Infinite if
+/// there is no coast, NaN for disabled tiles.
+fn coast_distance(map: &Map, level: f32, spacing: f32) -> Vec<f32> {
+    let heights = map.tile_heights();
+    let water: Vec<bool> = heights.iter().map(|h| h.is_some_and(|h| h < level)).collect();
+    let land: Vec<bool> = heights.iter().map(|h| h.is_some_and(|h| h >= level)).collect();
+
+    let to_water = distance::distance_transform(&water, map.header.w, map.header.h);
+    let to_land = distance::distance_transform(&land, map.header.w, map.header.h);
+
+    heights.iter().enumerate()
+        .map(|(i, h)| match h {
+            Some(_) if water[i] => -(to_land[i] - 0.5) * spacing,
+            Some(_) => (to_water[i] - 0.5) * spacing,
+            None => f32::NAN,
+        })
+        .collect()
+}
+
+/// The distances of the distance field layer, scaled to 16 bits if asked to.
+fn distance_field(map: &Map, options: &ExportOptions) -> io::Result<Samples> {
+    let distance = &options.distance;
+    let mask;
+    let source = match distance.distance_from {
+        DistanceFrom::Edge => DistanceSource::Edge,
+        DistanceFrom::Mask => {
+            mask = Raster::open(distance.distance_mask.as_ref().expect("Required by the arguments"))?;
+            DistanceSource::Mask(&mask)
+        }
+        DistanceFrom::Color => DistanceSource::Color(distance.distance_color.expect("Required by the arguments")),
+    };
+
+    let spacing = tile_spacing(map, options);
+    let distances: Vec<f32> = map.distance_field(source)?.into_iter().map(|d| d * spacing).collect();
+
+    Ok(match distance.distance_format {
+        DistanceFormat::F32 => Samples::F32(distances),
+        DistanceFormat::Gray16 => {
+            let max = distance.distance_max
+                .unwrap_or_else(|| distances.iter().copied().filter(|d| d.is_finite()).fold(0f32, f32::max));
+            Samples::U16(distances.iter()
+                .map(|&d| (if max > 0f32 { (d / max).min(1f32) } else { 1f32 } * 65535f32).round() as u16)
+                .collect())
+        }
+    })
+}
+
+/// One minus the mean sine of the horizon elevation over the directions.
+fn sky_view_factor(map: &Map, options: &ExportOptions) -> Vec<f32> {
+    let spacing = tile_spacing(map, options);
+    let horizons: Vec<Vec<Option<f32>>> = horizon::DIRECTIONS.iter()
+        .map(|&direction| horizon::horizon_angles(map, spacing, direction, options.horizon.horizon_distance))
+        .collect();
+
+    (0..map.enabled.len())
+        .map(|i| {
+            let blocked: Option<f32> = horizons.iter().map(|angles| angles[i].map(f32::sin)).sum();
+            blocked.map_or(f32::NAN, |blocked| 1f32 - blocked / horizons.len() as f32)
+        })
+        .collect()
+}
+
+/// Lists every lake with the image position of its deepest tile and its
+/// area in the units tiles are `spacing` apart in.
+fn write_lakes(lakes: &[Lake], spacing: f32, path: &Path) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "lake,x,y,tiles,area,level,max_depth,volume")?;
+
+    for lake in lakes {
+        let area = f64::from(lake.tiles) * f64::from(spacing) * f64::from(spacing);
+        writeln!(out, "{},{},{},{},{},{},{},{}", lake.label, lake.deepest.0, lake.deepest.1, lake.tiles, area,
+                 lake.level, lake.max_depth, lake.volume)?;
+    }
+
+    out.flush()
+}
@@ -0,0 +1,131 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+
+use crate::Map;
+
+use super::Context;
+use super::ExportOptions;
+use super::Normalize;
+use super::composite;
+use super::font;
+use super::normalized;
+use super::palette::Palette;
+use super::pixels::PixelBuffer;
+use super::pixels::PixelFormat;
+use super::pixels::Samples;
+use super::relief;
+use super::tile_spacing;
+
+const GAP: u32 = 4;
+const BACKGROUND: [u8; 3] = [255, 255, 255];
+const LABEL_COLOR: [u8; 3] = [0, 0, 0];
+
+/// How a cell of the matrix colors the normalized heights.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum MatrixStyle {
+    /// Black at the bottom of the range to white at the top.
+    Gray,
+    /// The height tints of the composite `hypsometric` layer.
+    Hypsometric,
+    /// The hypsometric tints darkened by the hillshade.
+    Shaded,
+    /// The built-in `terrain` palette.
+    Terrain,
+    /// The built-in `viridis` palette.
+    Viridis,
+}
+
+/// Renders `map` once for every style and normalization, in a grid with a
+/// row per style and a column per normalization, labeled along the top and
+/// the left, and writes it to `<output dir>/<file_stem>.<ext>`. Returns the
+/// paths written.
+pub fn export_matrix(map: &Map, styles: &[MatrixStyle], normalizations: &[Normalize], file_stem: &str, options: &ExportOptions)
+                     -> io::Result<Vec<PathBuf>> {
+    let (panel_w, panel_h) = (map.header.w, map.header.h);
+    // Big maps get bigger labels, so they stay readable zoomed out.
+    let scale = (panel_w.min(panel_h) / 128).clamp(1, 8);
+    let label_height = (font::GLYPH_HEIGHT + 2) * scale;
+
+    let style_name = |style: MatrixStyle| style.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default();
+    let columns: Vec<(Normalize, Map)> = normalizations.iter()
+        .map(|&normalize| (normalize, normalized(map, normalize).into_owned()))
+        .collect();
+    let column_labels: Vec<String> = columns.iter()
+        .map(|(normalize, map)| {
+            let name = normalize.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default();
+            format!("{} {:.2}..{:.2}", name, map.header.min_height, map.header.max_height)
+        })
+        .collect();
+
+    let label_width = styles.iter().map(|&style| font::text_width(&style_name(style), scale)).max().unwrap_or_default();
+    // Columns widen to their labels on small maps.
+    let column_w = column_labels.iter().map(|label| font::text_width(label, scale)).fold(panel_w, u32::max);
+    let left = label_width + GAP * 2;
+    let top = label_height + GAP * 2;
+    let width = left + columns.len() as u32 * (column_w + GAP);
+    let height = top + styles.len() as u32 * (panel_h + GAP);
+    let mut pixels = PixelBuffer {
+        width,
+        height,
+        format: PixelFormat::Rgb8,
+        samples: Samples::U8(BACKGROUND.repeat((width * height) as usize)),
+    };
+
+    let shade = relief::hillshade(map, tile_spacing(map, options), options.relief.light_azimuth, options.relief.light_altitude);
+    let panel_left = |column: usize| i64::from(left + column as u32 * (column_w + GAP));
+    let panel_top = |row: usize| i64::from(top + row as u32 * (panel_h + GAP));
+
+    for (row, &style) in styles.iter().enumerate() {
+        let palette = match style {
+            MatrixStyle::Terrain | MatrixStyle::Viridis => Some(Palette::parse(&style_name(style)).expect("Built in")),
+            _ => None,
+        };
+        font::draw_text(&mut pixels, i64::from(GAP), panel_top(row), &style_name(style), scale, LABEL_COLOR);
+
+        for (column, (_, map)) in columns.iter().enumerate() {
+            let heights = map.normalized_heights(map.height_range());
+
+            for (i, &t) in heights.iter().enumerate() {
+                if t.is_nan() {
+                    continue;
+                }
+                let t = t.clamp(0f32, 1f32);
+                let color = match style {
+                    MatrixStyle::Gray => [t; 3],
+                    MatrixStyle::Hypsometric => composite::tint(t),
+                    MatrixStyle::Shaded => composite::tint(t).map(|c| c * shade[i].unwrap_or(1f32)),
+                    MatrixStyle::Terrain | MatrixStyle::Viridis =>
+                        palette.as_ref().expect("Parsed above").color(t).map(|c| f32::from(c) / 255f32),
+                };
+
+                let (x, y) = (i as u32 % panel_w, i as u32 / panel_w);
+                pixels.paint(panel_left(column) + i64::from(x), panel_top(row) + i64::from(y),
+                             color.map(|c| (c * 255f32).round() as u8));
+            }
+        }
+    }
+
+    for (column, label) in column_labels.iter().enumerate() {
+        font::draw_text(&mut pixels, panel_left(column), i64::from(GAP), label, scale, LABEL_COLOR);
+    }
+
+    let options = ExportOptions { pixel_format: PixelFormat::Rgb8, color_space: None, ..options.clone() };
+    let context = Context { options: &options, icc_profile: None, metadata: Vec::new() };
+    let mut written = Vec::new();
+
+    fs::create_dir_all(&options.output_dir)?;
+
+    for &format in &options.formats {
+        let path = format.path(&options.output_dir, file_stem);
+        super::write_raster(&pixels, format, &context, BufWriter::new(File::create(&path)?))?;
+        println!("Wrote {}", path.display());
+        written.push(path);
+    }
+
+    Ok(written)
+}
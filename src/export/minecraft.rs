@@ -0,0 +1,192 @@
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::prelude::*;
+use std::path::Path;
+
+use byteorder::BE;
+use byteorder::WriteBytesExt;
+use clap::Args;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+use crate::Map;
+use crate::limits::Limits;
+
+/// Minecraft 1.20.1, the oldest release current WorldEdit versions load
+/// schematics from without upgrading them.
+const DATA_VERSION: i32 = 3465;
+
+const AIR: i32 = 0;
+const STONE: i32 = 1;
+const DIRT: i32 = 2;
+const GRASS: i32 = 3;
+const SAND: i32 = 4;
+const SNOW: i32 = 5;
+const WATER: i32 = 6;
+const PALETTE: [&str; 7] = [
+    "minecraft:air",
+    "minecraft:stone",
+    "minecraft:dirt",
+    "minecraft:grass_block",
+    "minecraft:sand",
+    "minecraft:snow_block",
+    "minecraft:water",
+];
+
+/// How many blocks of dirt cover the stone below grass.
+const SOIL_DEPTH: u32 = 3;
+
+#[derive(Args, Debug, Clone)]
+pub struct MinecraftOptions {
+    /// The number of block layers the height range is scaled to.
+    #[arg(long, default_value_t = 64, value_parser = clap::value_parser!(u32).range(2..=384))]
+    pub mc_height: u32,
+}
+
+impl Default for MinecraftOptions {
+    fn default() -> MinecraftOptions {
+        MinecraftOptions { mc_height: 64 }
+    }
+}
+
+/// Writes the terrain as a Sponge schematic (version 2), as loaded by
+/// WorldEdit with `//schem load`, one block column per tile.
+///
+/// The height range fills `--mc-height` layers, flooded with water up to
+/// `water_level` if given. Surfaces are sand at and below the water line,
+/// grass up to 60% of the range, bare stone up to 85% and snow above;
+/// disabled tiles are left empty. Schematics with more
+/// blocks than `limits` allows points are rejected before any is placed.
+pub fn write(map: &Map, options: &MinecraftOptions, water_level: Option<f32>, limits: &Limits, path: &Path) -> io::Result<()> {
+    let width = map.header.w;
+    let length = map.header.h;
+    let layers = options.mc_height;
+
+    if width > i16::MAX as u32 || length > i16::MAX as u32 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Schematics are limited to 32767 blocks per side"));
+    }
+
+    let column_size = u64::from(width).checked_mul(u64::from(length));
+    let size = column_size.and_then(|size| size.checked_mul(u64::from(layers)))
+        .filter(|&size| size <= limits.max_points)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!(
+            "A schematic of {}x{}x{} blocks is over the limit of {}, lower --mc-height or raise --max-points",
+            width, length, layers, limits.max_points)))?;
+    let column_size = column_size.unwrap_or_default() as usize;
+
+    let min_height = map.header.min_height;
+    let height_diff = map.header.max_height - min_height;
+    let level = |h: f32| -> u32 {
+        let normalized = if height_diff > 0f32 { ((h - min_height) / height_diff).clamp(0f32, 1f32) } else { 0f32 };
+        (normalized * (layers - 1) as f32).round() as u32
+    };
+    let water = water_level.map(level);
+
+    // Block indices in schematic order: x fastest, then z, then y.
+    let mut blocks = vec![AIR; size as usize];
+
+    for (index, offset) in map.enabled_tiles() {
+        let (x, z) = crate::get_position(&index, &width, &length);
+        let column = (z * width + x) as usize;
+        let surface = level(map.points[offset].h);
+        let normalized = surface as f32 / (layers - 1) as f32;
+
+        let top = match water {
+            Some(water) if surface <= water + 1 => SAND,
+            _ if normalized < 0.6 => GRASS,
+            _ if normalized < 0.85 => STONE,
+            _ => SNOW,
+        };
+
+        for y in 0..=surface {
+            blocks[y as usize * column_size + column] = match surface - y {
+                0 => top,
+                depth if depth <= SOIL_DEPTH && top == GRASS => DIRT,
+                depth if depth <= SOIL_DEPTH && top == SAND => SAND,
+                _ => STONE,
+            };
+        }
+
+        if let Some(water) = water {
+            for y in surface + 1..=water {
+                blocks[y as usize * column_size + column] = WATER;
+            }
+        }
+    }
+
+    let mut block_data = Vec::with_capacity(blocks.len());
+    for block in blocks {
+        write_varint(&mut block_data, block);
+    }
+
+    let mut out = GzEncoder::new(BufWriter::new(File::create(path)?), Compression::default());
+
+    compound_start(&mut out, "Schematic")?;
+    int(&mut out, "Version", 2)?;
+    int(&mut out, "DataVersion", DATA_VERSION)?;
+    short(&mut out, "Width", width as i16)?;
+    short(&mut out, "Height", layers as i16)?;
+    short(&mut out, "Length", length as i16)?;
+    int(&mut out, "PaletteMax", PALETTE.len() as i32)?;
+
+    compound_start(&mut out, "Palette")?;
+    for (id, name) in PALETTE.iter().enumerate() {
+        int(&mut out, name, id as i32)?;
+    }
+    out.write_u8(TAG_END)?;
+
+    tag(&mut out, TAG_BYTE_ARRAY, "BlockData")?;
+    out.write_i32::<BE>(block_data.len() as i32)?;
+    out.write_all(&block_data)?;
+
+    // Empty list of block entities.
+    tag(&mut out, TAG_LIST, "BlockEntities")?;
+    out.write_u8(TAG_COMPOUND)?;
+    out.write_i32::<BE>(0)?;
+
+    out.write_u8(TAG_END)?;
+    out.finish()?.flush()
+}
+
+const TAG_END: u8 = 0;
+const TAG_SHORT: u8 = 2;
+const TAG_INT: u8 = 3;
+const TAG_BYTE_ARRAY: u8 = 7;
+const TAG_LIST: u8 = 9;
+const TAG_COMPOUND: u8 = 10;
+
+/// An NBT tag header: type, then the name as a length-prefixed string.
+fn tag<W: Write>(out: &mut W, id: u8, name: &str) -> io::Result<()> {
+    out.write_u8(id)?;
+    out.write_u16::<BE>(name.len() as u16)?;
+    out.write_all(name.as_bytes())
+}
+
+fn compound_start<W: Write>(out: &mut W, name: &str) -> io::Result<()> {
+    tag(out, TAG_COMPOUND, name)
+}
+
+fn short<W: Write>(out: &mut W, name: &str, value: i16) -> io::Result<()> {
+    tag(out, TAG_SHORT, name)?;
+    out.write_i16::<BE>(value)
+}
+
+fn int<W: Write>(out: &mut W, name: &str, value: i32) -> io::Result<()> {
+    tag(out, TAG_INT, name)?;
+    out.write_i32::<BE>(value)
+}
+
+fn write_varint(out: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+
+    loop {
+        if value & !0x7f == 0 {
+            out.push(value as u8);
+            return;
+        }
+
+        out.push((value & 0x7f | 0x80) as u8);
+        value >>= 7;
+    }
+}
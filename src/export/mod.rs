@@ -0,0 +1,1036 @@
+use std::borrow::Cow;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::prelude::*;
+use std::iter;
+use std::path::Path;
+use std::path::PathBuf;
+
+use clap::Args;
+use clap::ValueEnum;
+
+use crate::Map;
+use crate::checkpoint::Checkpoint;
+use crate::limits::Limits;
+use crate::timings;
+
+pub use self::color::ColorMapBackground;
+pub use self::color::ColorOptions;
+pub use self::comparison::export_comparison;
+pub use self::georef::Georef;
+pub use self::histogram::export_histogram;
+pub use self::horizon::HorizonOptions;
+pub use self::layers::DistanceFormat;
+pub use self::layers::DistanceFrom;
+pub use self::layers::DistanceOptions;
+pub use self::layers::Layer;
+pub use self::matrix::MatrixStyle;
+pub use self::matrix::export_matrix;
+pub use self::minecraft::MinecraftOptions;
+pub use self::obj::MeshOptions;
+pub use self::overlay::Overlay;
+pub use self::palette::Palette;
+pub use self::pixels::PixelFormat;
+pub use self::preset::Preset;
+pub use self::r16::Layout;
+pub use self::r16::R16Options;
+pub use self::r16::RowOrder;
+pub use self::relief::ReliefOptions;
+pub use self::relief::Render;
+pub use self::svg::SvgOptions;
+pub use self::tiles3d::TilesetOptions;
+pub use self::vmf::VmfOptions;
+use self::composite::Composite;
+use self::pixels::PixelBuffer;
+
+mod bmp;
+mod chunks;
+mod collada;
+mod color;
+mod comparison;
+mod composite;
+mod font;
+mod georef;
+mod histogram;
+mod horizon;
+mod gltf;
+mod icc;
+mod layers;
+mod matrix;
+mod minecraft;
+mod normals;
+mod obj;
+mod overlay;
+mod palette;
+mod pdf;
+mod pixels;
+mod png;
+mod preset;
+mod r16;
+mod relief;
+#[cfg(feature = "reproject")]
+mod reproject;
+mod svg;
+mod template;
+mod tessellate;
+mod tiff;
+mod tiles3d;
+mod truetype;
+mod vmf;
+
+/// An output file format.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Format {
+    Bmp,
+    Png,
+    /// A 16-bit grayscale PNG of the heights, whatever `--pixel-format` is.
+    Png16,
+    Tiff,
+    /// A triangulated surface of the enabled tiles.
+    Obj,
+    /// The OBJ surface as a COLLADA document, for editors without OBJ import.
+    Dae,
+    /// The OBJ surface as binary glTF, with the tile colors as vertex colors
+    /// given `--mesh-colors`.
+    Glb,
+    /// Source engine displacement brushes for Hammer.
+    Vmf,
+    /// A Sponge schematic of Minecraft blocks for WorldEdit.
+    Schem,
+    /// Headerless 16-bit heights for the Unreal Engine landscape importer.
+    R16,
+    /// A 3D Tiles tileset of glTF levels of detail, in `<name>/tileset.json`.
+    #[value(name = "3dtiles")]
+    Tileset,
+    /// A tangent-space normal map of the surface, as `<name>_normals.png`.
+    Normals,
+    /// The raw heights as a 32-bit float GeoTIFF, as `<name>.tif`, with
+    /// disabled tiles at the `--nodata` height.
+    Geotiff,
+    /// Horizon angle maps for terrain self-shadowing, as `<name>_horizon.png`.
+    Horizon,
+    /// A vector map of contour lines, the coastline and labels over the
+    /// shaded relief.
+    Svg,
+    /// A review report of the preview, a height histogram, statistics and
+    /// the header fields.
+    Pdf,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Bmp => "bmp",
+            Format::Png | Format::Png16 => "png",
+            Format::Tiff => "tiff",
+            Format::Obj => "obj",
+            Format::Dae => "dae",
+            Format::Glb => "glb",
+            Format::Vmf => "vmf",
+            Format::Schem => "schem",
+            Format::R16 => "r16",
+            Format::Tileset => "json",
+            Format::Normals => "png",
+            Format::Geotiff => "tif",
+            Format::Horizon => "png",
+            Format::Svg => "svg",
+            Format::Pdf => "pdf",
+        }
+    }
+
+    /// Where `export_all` writes the format for `file_stem` in `dir`.
+    pub fn path(&self, dir: &Path, file_stem: &str) -> PathBuf {
+        match self {
+            Format::Tileset => dir.join(file_stem).join("tileset.json"),
+            Format::Normals => dir.join(format!("{}_normals.{}", file_stem, self.extension())),
+            Format::Horizon => dir.join(format!("{}_horizon.{}", file_stem, self.extension())),
+            _ => dir.join(format!("{}.{}", file_stem, self.extension())),
+        }
+    }
+}
+
+/// Which heights the bottom and the top of the output range stand for.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Normalize {
+    /// The height range in the header.
+    Header,
+    /// The lowest and highest stored height, for maps whose header range is
+    /// much wider than their terrain.
+    Data,
+}
+
+/// How consumers should interpret the sample values of an image.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ColorSpace {
+    /// Samples are linear data, as heights should be sampled by engines.
+    Linear,
+    /// Samples are sRGB encoded colors.
+    Srgb,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ExportOptions {
+    /// Write the files a game's editor expects, instead of `--format`.
+    #[arg(long, value_enum)]
+    pub preset: Option<Preset>,
+
+    /// The directory the outputs are written to.
+    #[arg(long, value_name = "DIR", default_value = "./output")]
+    pub output_dir: PathBuf,
+
+    /// Where the outputs go instead of `<output dir>/<file name>`: a path
+    /// like `out/map.png`, whose extension picks the format, or a template
+    /// like `{dir}/{name}_{w}x{h}.{ext}` with the placeholders filled in from
+    /// the header. `{stem}` is the input's file name and `{dir}` the output
+    /// directory, which batch runs mirror the input directories in. Batch
+    /// runs need `{stem}`, so the maps don't overwrite each other.
+    #[arg(short, long, value_name = "PATH|TEMPLATE")]
+    pub output: Option<String>,
+
+    /// The formats to write from a single decode, comma separated or repeated.
+    #[arg(long = "format", visible_alias = "export", value_enum, value_delimiter = ',', default_value = "bmp")]
+    pub formats: Vec<Format>,
+
+    /// How heights, colors and the enabled mask are packed into image pixels.
+    #[arg(long, value_enum, default_value = "gray8")]
+    pub pixel_format: PixelFormat,
+
+    /// The color space images are tagged with, defaults to sRGB for the color
+    /// layer and linear for everything else.
+    #[arg(long, value_enum)]
+    pub color_space: Option<ColorSpace>,
+
+    /// Embed this ICC profile in PNG and TIFF output, or `linear-gray` for a
+    /// built-in gamma 1.0 grayscale profile.
+    #[arg(long, value_name = "PATH")]
+    pub icc_profile: Option<String>,
+
+    /// Don't embed the map header fields as PNG text chunks and TIFF tags.
+    #[arg(long)]
+    pub no_metadata: bool,
+
+    /// Which heights are scaled onto the full range of the output.
+    #[arg(long, value_enum, default_value = "header")]
+    pub normalize: Normalize,
+
+    /// Scale the heights over `MIN,MAX` instead, e.g. to give maps rendered
+    /// separately the same scale.
+    #[arg(long, value_name = "MIN,MAX", allow_hyphen_values = true, value_parser = crate::ops::parse_height_range,
+          conflicts_with = "normalize")]
+    pub height_range: Option<(f32, f32)>,
+
+    /// The gray level used for every tile when the map has no height range.
+    #[arg(long, default_value_t = 128)]
+    pub flat_level: u8,
+
+    /// The height GeoTIFF output stores for disabled tiles, tagged as its no
+    /// data value.
+    #[arg(long, value_name = "HEIGHT", default_value_t = -9999f32, allow_hyphen_values = true)]
+    pub nodata: f32,
+
+    /// The map height everything below is water: flooded in schematics, the
+    /// coastline of the coast distance layer and of SVG maps.
+    #[arg(long, value_name = "HEIGHT", allow_hyphen_values = true)]
+    pub water_level: Option<f32>,
+
+    /// Mirror every output left to right, for engines reading rows the
+    /// other way around.
+    #[arg(long)]
+    pub flip_x: bool,
+
+    /// Mirror every output top to bottom.
+    #[arg(long)]
+    pub flip_y: bool,
+
+    /// Turn every output clockwise by 90, 180 or 270 degrees, after the
+    /// flips.
+    #[arg(long, value_name = "DEGREES", value_parser = parse_rotation)]
+    pub rotate: Option<u32>,
+
+    /// Split the output into chunks of this many tiles square, written as
+    /// `<name>_<column>_<row>`, with per-chunk statistics in `<name>_chunks.csv`.
+    #[arg(long, value_name = "TILES", value_parser = clap::value_parser!(u32).range(1..))]
+    pub tile_size: Option<u32>,
+
+    /// Skip the chunks an interrupted run already wrote, as recorded in
+    /// `<name>_chunks.checkpoint`.
+    #[arg(long, requires = "tile_size")]
+    pub resume: bool,
+
+    /// Derived rasters to write as `<name>_<layer>.tiff` next to the outputs,
+    /// comma separated or repeated.
+    #[arg(long = "layer", value_enum, value_delimiter = ',')]
+    pub layers: Vec<Layer>,
+
+    /// Render rasters as the stack of blended layers listed in this TOML
+    /// file, in rgb8, instead of the plain heights.
+    #[arg(long, value_name = "TOML", conflicts_with_all = ["render", "color_scale"])]
+    pub composite: Option<PathBuf>,
+
+    /// Color rasters by height through this ramp: `terrain`, `viridis`,
+    /// `grayscale`, or a JSON file of stops like `{"at": 0.5, "color": "a8c05a"}`.
+    /// Written in rgb8, or rgba8 with the enabled mask given `--pixel-format rgba8`.
+    #[arg(long, value_name = "NAME|JSON", value_parser = Palette::parse, conflicts_with = "composite")]
+    pub palette: Option<Palette>,
+
+    #[command(flatten)]
+    pub georef: Georef,
+
+    #[command(flatten)]
+    pub overlay: Overlay,
+
+    #[command(flatten)]
+    pub color: ColorOptions,
+
+    #[command(flatten)]
+    pub relief: ReliefOptions,
+
+    #[command(flatten)]
+    pub mesh: MeshOptions,
+
+    #[command(flatten)]
+    pub vmf: VmfOptions,
+
+    #[command(flatten)]
+    pub minecraft: MinecraftOptions,
+
+    #[command(flatten)]
+    pub r16: R16Options,
+
+    #[command(flatten)]
+    pub tileset: TilesetOptions,
+
+    #[command(flatten)]
+    pub distance: DistanceOptions,
+
+    #[command(flatten)]
+    pub horizon: HorizonOptions,
+
+    #[command(flatten)]
+    pub svg: SvgOptions,
+
+    /// The limits of the command, which outputs are held to as well. Set
+    /// from its `--max-*` flags rather than being flags of their own.
+    #[arg(skip)]
+    pub limits: Limits,
+}
+
+/// Everything the writers need besides the map data itself.
+#[derive(Clone)]
+pub struct Context<'a> {
+    pub options: &'a ExportOptions,
+    pub icc_profile: Option<&'a [u8]>,
+    /// Header fields to embed, empty if metadata is turned off.
+    pub metadata: Vec<(&'static str, String)>,
+}
+
+impl Default for ExportOptions {
+    fn default() -> ExportOptions {
+        ExportOptions {
+            preset: None,
+            output_dir: PathBuf::from("./output"),
+            output: None,
+            formats: vec![Format::Bmp],
+            pixel_format: PixelFormat::Gray8,
+            color_space: None,
+            icc_profile: None,
+            no_metadata: false,
+            normalize: Normalize::Header,
+            height_range: None,
+            flat_level: 128,
+            nodata: -9999f32,
+            water_level: None,
+            flip_x: false,
+            flip_y: false,
+            rotate: None,
+            tile_size: None,
+            resume: false,
+            layers: Vec::new(),
+            composite: None,
+            palette: None,
+            georef: Georef::default(),
+            overlay: Overlay::default(),
+            color: ColorOptions::default(),
+            relief: ReliefOptions::default(),
+            mesh: MeshOptions::default(),
+            vmf: VmfOptions::default(),
+            minecraft: MinecraftOptions::default(),
+            r16: R16Options::default(),
+            tileset: TilesetOptions::default(),
+            distance: DistanceOptions::default(),
+            horizon: HorizonOptions::default(),
+            svg: SvgOptions::default(),
+            limits: Limits::default(),
+        }
+    }
+}
+
+impl ExportOptions {
+    /// Where an output named `name` goes.
+    pub fn output_path(&self, name: &str) -> PathBuf {
+        self.output_dir.join(name)
+    }
+
+    /// The options `format` is rendered with.
+    fn for_format(&self, format: Format) -> Cow<'_, ExportOptions> {
+        match format {
+            Format::Png16 if self.pixel_format != PixelFormat::Gray16 =>
+                Cow::Owned(ExportOptions { pixel_format: PixelFormat::Gray16, ..self.clone() }),
+            _ => Cow::Borrowed(self),
+        }
+    }
+
+    /// Whether `--flip-x`, `--flip-y` or `--rotate` change the orientation.
+    fn is_reoriented(&self) -> bool {
+        self.flip_x || self.flip_y || self.rotate.is_some_and(|degrees| degrees != 0)
+    }
+
+    /// The map turned and mirrored as asked, and the options with the
+    /// orientation already applied.
+    fn reoriented(&self, map: &Map) -> io::Result<(Map, ExportOptions)> {
+        if self.georef.is_set() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "--flip-x, --flip-y and --rotate can't be combined with georeferencing"));
+        }
+
+        let map = map.orient(self.flip_x, self.flip_y, self.rotate.unwrap_or_default() / 90);
+        Ok((map, ExportOptions { flip_x: false, flip_y: false, rotate: None, ..self.clone() }))
+    }
+
+    pub fn color_space(&self) -> ColorSpace {
+        self.color_space.unwrap_or(match self.pixel_format {
+            PixelFormat::Rgb8 | PixelFormat::Rgba8 => ColorSpace::Srgb,
+            _ => ColorSpace::Linear,
+        })
+    }
+}
+
+/// Writes every requested format to `<output dir>/<file_stem>.<ext>`,
+/// rendering the shared pixel data only once, and returns the paths written.
+pub fn export_all(map: &Map, file_stem: &str, options: &ExportOptions) -> io::Result<Vec<PathBuf>> {
+    // Before anything is split up, so every part shares the range.
+    if let Cow::Owned(map) = scaled(map, options) {
+        return export_all(&map, file_stem, &ExportOptions { normalize: Normalize::Header, height_range: None, ..options.clone() });
+    }
+
+    if options.is_reoriented() {
+        let (map, options) = options.reoriented(map)?;
+        return export_all(&map, file_stem, &options);
+    }
+
+    #[cfg(feature = "reproject")]
+    if let Some(target) = &options.georef.target_crs {
+        let (map, georef) = options.georef.reproject(map, target)?;
+        println!("Reprojected to {}, {}x{} tiles", target, map.header.w, map.header.h);
+
+        return export_all(&map, file_stem, &ExportOptions { georef, ..options.clone() });
+    }
+
+    // Once the size is final, as the template can name it.
+    if options.output.is_some() {
+        let (options, file_stem) = options.resolve_output(&map.header, file_stem)?;
+        return export_all(map, &file_stem, &options);
+    }
+
+    fs::create_dir_all(&options.output_dir)?;
+
+    if let Some(size) = options.tile_size {
+        return export_chunks(map, file_stem, size, options);
+    }
+
+    if let Some(preset) = options.preset {
+        return preset::export(map, file_stem, preset, options);
+    }
+
+    if options.overlay.is_set() && options.pixel_format == PixelFormat::F32 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Overlays can't be drawn on f32 heights"));
+    }
+
+    if options.formats.contains(&Format::Png) && options.formats.contains(&Format::Png16) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "png and png16 would both write the same file"));
+    }
+
+    if options.layers.contains(&Layer::CoastDistance) && options.water_level.is_none() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "The coast distance needs a --water-level to tell water from land"));
+    }
+
+    // Caught before anything is written rather than at the first raster.
+    if let Some(path) = &options.composite {
+        Composite::load(path)?;
+    }
+
+    let mut pixels = None;
+    let icc_profile = options.icc_profile.as_deref()
+        .map(icc::load)
+        .transpose()?;
+    let context = Context {
+        options,
+        icc_profile: icc_profile.as_deref(),
+        metadata: if options.no_metadata { Vec::new() } else { map.header.fields() },
+    };
+
+    // Axes pad the image, so it no longer matches the georeferencing, and
+    // upscaled colors cover a tile with several pixels.
+    let adjusted;
+    let raster_options = if options.overlay.axes {
+        adjusted = ExportOptions { georef: Georef::default(), ..options.clone() };
+        &adjusted
+    } else if options.color.color_scale > 1 && options.georef.is_set() {
+        let cell_size = options.georef.cell_size() / f64::from(options.color.color_scale);
+        adjusted = ExportOptions { georef: Georef { cell_size: Some(cell_size), ..options.georef.clone() }, ..options.clone() };
+        &adjusted
+    } else {
+        options
+    };
+    let raster_context = Context { options: raster_options, icc_profile: context.icc_profile, metadata: context.metadata.clone() };
+    let mut written = Vec::new();
+
+    for &format in &options.formats {
+        let path = format.path(&options.output_dir, file_stem);
+        let mut extras = Vec::new();
+
+        // Mesh and vector formats render as they write, so they are timed
+        // as encoding as a whole.
+        match format {
+            Format::Obj => extras = timings::measure("encode", || obj::write(map, &context, &path))?,
+            Format::Dae => timings::measure("encode", || collada::write(map, &context, &path))?,
+            Format::Glb => timings::measure("encode", || gltf::write(map, &context, &path))?,
+            Format::Vmf => timings::measure("encode", || vmf::write(map, &context, &path))?,
+            Format::Schem => timings::measure("encode", || minecraft::write(map, &options.minecraft, options.water_level, &options.limits, &path))?,
+            Format::R16 => timings::measure("encode", || r16::write(map, &context, &path))?,
+            Format::Tileset => {
+                fs::create_dir_all(path.parent().unwrap_or_else(|| Path::new(".")))?;
+                extras = timings::measure("encode", || tiles3d::write(map, &context, &path))?;
+            }
+            Format::Normals => {
+                timings::measure("encode", || normals::write(map, &context, &path))?;
+                if options.georef.is_set() {
+                    extras.push(options.georef.write_world_file(&path)?);
+                }
+            }
+            Format::Horizon => extras = timings::measure("encode", || horizon::write(map, &context, &path))?,
+            Format::Svg => timings::measure("encode", || svg::write(map, &context, &path))?,
+            Format::Pdf => timings::measure("encode", || pdf::write(map, &context, file_stem, &path))?,
+            Format::Geotiff => timings::measure("encode", || {
+                let heights = PixelBuffer::render(map, PixelFormat::F32, options.flat_level);
+                tiff::write_geotiff(&heights, &context, options.nodata, BufWriter::new(File::create(&path)?))
+            })?,
+            _ => {
+                let format_options = raster_options.for_format(format);
+                let rendered;
+                let pixels = if format_options.pixel_format == options.pixel_format {
+                    if pixels.is_none() {
+                        pixels = Some(timings::measure("render", || render_pixels(map, options))?);
+                    }
+                    pixels.as_ref().expect("Rendered above")
+                } else {
+                    rendered = timings::measure("render", || render_pixels(map, &options.for_format(format)))?;
+                    &rendered
+                };
+                let context = Context { options: &format_options, ..raster_context.clone() };
+
+                timings::measure("encode", || write_raster(pixels, format, &context, BufWriter::new(File::create(&path)?)))?;
+
+                if raster_options.georef.is_set() {
+                    extras.push(raster_options.georef.write_world_file(&path)?);
+                }
+            }
+        }
+
+        for path in iter::once(path).chain(extras) {
+            println!("Wrote {}", path.display());
+            written.push(path);
+        }
+    }
+
+    if options.color.color_map {
+        let path = options.output_path(&format!("{}_colors.png", file_stem));
+        let extras = timings::measure("encode", || write_color_map(map, &context, &path))?;
+
+        for path in iter::once(path).chain(extras) {
+            println!("Wrote {}", path.display());
+            written.push(path);
+        }
+    }
+
+    for &layer in &options.layers {
+        let path = options.output_path(&format!("{}_{}.tiff", file_stem, layer.suffix()));
+        let mut extras = timings::measure("encode", || layers::write(map, layer, &context, &path))?;
+        if options.georef.is_set() {
+            extras.insert(0, options.georef.write_world_file(&path)?);
+        }
+
+        for path in iter::once(path).chain(extras) {
+            println!("Wrote {}", path.display());
+            written.push(path);
+        }
+    }
+
+    Ok(written)
+}
+
+/// How far apart tiles are, in height units: the header's `u5` scale, or
+/// the georeferenced cell size if it has none.
+fn tile_spacing(map: &Map, options: &ExportOptions) -> f32 {
+    map.header.tile_spacing().unwrap_or(options.georef.cell_size() as f32)
+}
+
+/// The slopes at the tile at `x`, `y` of `heights`, `w` by `h` tiles in
+/// image order and `spacing` apart: to the east and down the image. They
+/// are taken across both neighbors where they are enabled and to the one
+/// that is otherwise, and are `None` for disabled tiles.
+fn gradient(heights: &[Option<f32>], w: usize, h: usize, x: usize, y: usize, spacing: f32) -> Option<(f32, f32)> {
+    let at = |x: usize, y: usize| heights[y * w + x];
+    let center = at(x, y)?;
+    let slope = |before: Option<f32>, after: Option<f32>| match (before, after) {
+        (Some(before), Some(after)) => (after - before) / (2f32 * spacing),
+        (Some(before), None) => (center - before) / spacing,
+        (None, Some(after)) => (after - center) / spacing,
+        (None, None) => 0f32,
+    };
+
+    let east = slope(x.checked_sub(1).and_then(|x| at(x, y)), (x + 1 < w).then(|| at(x + 1, y)).flatten());
+    let down = slope(y.checked_sub(1).and_then(|y| at(x, y)), (y + 1 < h).then(|| at(x, y + 1)).flatten());
+
+    Some((east, down))
+}
+
+/// Where the vertex of the tile at `x`, `y` goes in the mesh formats: at
+/// its world coordinates when georeferencing is given, otherwise one unit
+/// per tile, or `u5` units with `--mesh-scale`, with Y growing northwards.
+fn vertex_position(map: &Map, options: &ExportOptions, x: u32, y: u32) -> (f64, f64) {
+    if options.georef.is_set() {
+        options.georef.tile_center(f64::from(x), f64::from(y))
+    } else {
+        let spacing = if options.mesh.mesh_scale { f64::from(tile_spacing(map, options)) } else { 1f64 };
+        (f64::from(x) * spacing, -f64::from(y) * spacing)
+    }
+}
+
+/// Renders the color layer, without overlays, to a PNG at `path`, and
+/// returns the world file written next to it, if any.
+fn write_color_map(map: &Map, context: &Context, path: &Path) -> io::Result<Option<PathBuf>> {
+    let options = context.options;
+    let scale = options.color.color_scale;
+
+    let color_options = ExportOptions {
+        pixel_format: match options.color.color_map_background {
+            ColorMapBackground::Black => PixelFormat::Rgb8,
+            ColorMapBackground::Transparent => PixelFormat::Rgba8,
+        },
+        color_space: None,
+        overlay: Overlay::default(),
+        relief: ReliefOptions::default(),
+        composite: None,
+        palette: None,
+        georef: if scale > 1 && options.georef.is_set() {
+            Georef { cell_size: Some(options.georef.cell_size() / f64::from(scale)), ..options.georef.clone() }
+        } else {
+            options.georef.clone()
+        },
+        ..options.clone()
+    };
+    let pixels = render_pixels(map, &color_options)?;
+
+    let color_context = Context { options: &color_options, ..context.clone() };
+    png::write(&pixels, &color_context, BufWriter::new(File::create(path)?))?;
+
+    if color_options.georef.is_set() {
+        return color_options.georef.write_world_file(path).map(Some);
+    }
+
+    Ok(None)
+}
+
+/// Exports every chunk on its own, georeferenced at its own top-left
+/// corner, followed by the chunk statistics.
+fn export_chunks(map: &Map, file_stem: &str, size: u32, options: &ExportOptions) -> io::Result<Vec<PathBuf>> {
+    let checkpoint_path = options.output_path(&format!("{}_chunks.checkpoint", file_stem));
+    let key = format!("{:016x} {:?}", map.content_hash(), ExportOptions { resume: false, ..options.clone() });
+    let mut checkpoint = Checkpoint::open(&checkpoint_path, &key, options.resume)?;
+    let mut written = Vec::new();
+
+    for (column, row, region) in chunks::regions(map.header.w, map.header.h, size) {
+        let step = format!("{},{}", column, row);
+        if let Some(outputs) = checkpoint.done(&step) {
+            written.extend_from_slice(outputs);
+            continue;
+        }
+
+        let mut chunk_options = options.clone();
+        chunk_options.tile_size = None;
+
+        if options.georef.is_set() {
+            let (ox, oy) = options.georef.origin();
+            let cell_size = options.georef.cell_size();
+            chunk_options.georef.origin = Some((ox + f64::from(region.x) * cell_size, oy - f64::from(region.y) * cell_size));
+        }
+
+        let outputs = export_all(&map.crop(&region), &format!("{}_{}_{}", file_stem, column, row), &chunk_options)?;
+        checkpoint.record(&step, &outputs)?;
+        written.extend(outputs);
+    }
+
+    let path = options.output_path(&format!("{}_chunks.csv", file_stem));
+    chunks::write_stats(map, size, &path)?;
+    println!("Wrote {}", path.display());
+    written.push(path);
+
+    checkpoint.finish()?;
+
+    Ok(written)
+}
+
+/// A file `export_all` would write.
+pub struct PlannedOutput {
+    pub path: PathBuf,
+    pub description: String,
+}
+
+/// Lists the files `export_all` would write for a `w` x `h` map, in the
+/// same order, without needing the map data.
+pub fn plan(w: u32, h: u32, file_stem: &str, options: &ExportOptions) -> io::Result<Vec<PlannedOutput>> {
+    if options.is_reoriented() {
+        if options.georef.is_set() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "--flip-x, --flip-y and --rotate can't be combined with georeferencing"));
+        }
+
+        let (w, h) = if options.rotate.unwrap_or_default() / 90 % 2 == 1 { (h, w) } else { (w, h) };
+        return plan(w, h, file_stem, &ExportOptions { flip_x: false, flip_y: false, rotate: None, ..options.clone() });
+    }
+
+    if let Some(size) = options.tile_size {
+        let chunk_options = ExportOptions { tile_size: None, ..options.clone() };
+        let regions = chunks::regions(w, h, size);
+        let mut planned = Vec::new();
+
+        for (column, row, region) in &regions {
+            planned.extend(plan(region.w, region.h, &format!("{}_{}_{}", file_stem, column, row), &chunk_options)?);
+        }
+
+        planned.push(PlannedOutput {
+            path: options.output_path(&format!("{}_chunks.csv", file_stem)),
+            description: format!("statistics of {} chunks", regions.len()),
+        });
+
+        return Ok(planned);
+    }
+
+    if let Some(preset) = options.preset {
+        return Ok(preset::plan(w, h, file_stem, preset, options));
+    }
+
+    if options.overlay.is_set() && options.pixel_format == PixelFormat::F32 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Overlays can't be drawn on f32 heights"));
+    }
+
+    if options.formats.contains(&Format::Png) && options.formats.contains(&Format::Png16) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "png and png16 would both write the same file"));
+    }
+
+    if options.layers.contains(&Layer::CoastDistance) && options.water_level.is_none() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "The coast distance needs a --water-level to tell water from land"));
+    }
+
+    let pixel_format = options.pixel_format.to_possible_value().map(|value| value.get_name().to_string()).unwrap_or_default();
+    let margins = if options.overlay.axes { " plus axis margins" } else { "" };
+    let shading = if options.relief.render == Render::Hillshade { " hillshade" } else { "" };
+    let composite = options.composite.as_ref().map(|path| Composite::load(path)).transpose()?;
+    let scale = options.color.color_scale;
+    let mut planned = Vec::new();
+
+    for &format in &options.formats {
+        let path = format.path(&options.output_dir, file_stem);
+        let description = match format {
+            Format::Obj => format!("mesh of {}x{} tiles", w, h),
+            Format::Dae => format!("COLLADA mesh of {}x{} tiles", w, h),
+            Format::Glb => format!("glTF mesh of {}x{} tiles{}", w, h, if options.mesh.mesh_quantize { ", quantized" } else { "" }),
+            Format::Vmf => format!("displacement brushes for {}x{} tiles", w, h),
+            Format::Schem => format!("schematic of {}x{} block columns", w, h),
+            Format::R16 => format!("16-bit RAW, {}x{} samples, {}", w, h, match options.r16.layout {
+                Layout::RowMajor => "row-major",
+                Layout::ColumnMajor => "column-major",
+            }),
+            Format::Tileset => format!("3D Tiles tileset of {}x{} tiles", w, h),
+            Format::Normals => format!("png rgb8 normal map, {}x{} pixels", w, h),
+            Format::Geotiff => format!("GeoTIFF f32 heights, {}x{} pixels", w, h),
+            Format::Horizon => format!("png rgba8 horizon angles, {}x{} pixels", w, h),
+            Format::Svg => format!("SVG map of {}x{} tiles", w, h),
+            Format::Pdf => format!("PDF report of {}x{} tiles", w, h),
+            _ if composite.is_some() => format!("{} rgb8 composite, {}x{} pixels{}", format.extension(), w, h, margins),
+            _ if options.palette.is_some() => format!("{} {} {} palette{}, {}x{} pixels{}", format.extension(),
+                                                      if matches!(options.pixel_format, PixelFormat::Rgba8 | PixelFormat::Gray8Alpha) { "rgba8" } else { "rgb8" },
+                                                      options.palette.as_ref().map_or("", |palette| &palette.name), shading, w * scale, h * scale, margins),
+            Format::Png16 => format!("png gray16{}, {}x{} pixels{}", shading, w * scale, h * scale, margins),
+            _ => format!("{} {}{}, {}x{} pixels{}", format.extension(), pixel_format, shading, w * scale, h * scale, margins),
+        };
+        let raster = !matches!(format, Format::Obj | Format::Dae | Format::Glb | Format::Vmf | Format::Schem | Format::R16 | Format::Tileset | Format::Normals | Format::Geotiff | Format::Horizon | Format::Svg | Format::Pdf);
+
+        if raster && !options.overlay.axes && options.georef.is_set() {
+            let world_file = georef::world_file_path(&path);
+            planned.push(PlannedOutput { path, description });
+            planned.push(PlannedOutput { path: world_file, description: String::from("world file") });
+        } else if format == Format::Obj && options.mesh.mesh_texture {
+            let material = path.with_extension("mtl");
+            let texture = options.output_path(&format!("{}_texture.png", file_stem));
+            planned.push(PlannedOutput { path, description });
+            planned.push(PlannedOutput { path: material, description: String::from("material") });
+            planned.push(PlannedOutput { path: texture, description: format!("baked texture, {}x{} pixels", w * scale, h * scale) });
+        } else if format == Format::Normals && options.georef.is_set() {
+            let world_file = georef::world_file_path(&path);
+            planned.push(PlannedOutput { path, description });
+            planned.push(PlannedOutput { path: world_file, description: String::from("world file") });
+        } else if format == Format::Horizon {
+            let textures = horizon::paths(&path, &options.horizon);
+            planned.extend(textures.into_iter().map(|path| PlannedOutput { path, description: description.clone() }));
+        } else if format == Format::Tileset {
+            let tiles = tiles3d::plan(w, h, &path, &options.tileset);
+            planned.push(PlannedOutput { path, description });
+            planned.extend(tiles);
+        } else {
+            planned.push(PlannedOutput { path, description });
+        }
+    }
+
+    if options.color.color_map {
+        let path = options.output_path(&format!("{}_colors.png", file_stem));
+        let world_file = georef::world_file_path(&path);
+        let channels = match options.color.color_map_background {
+            ColorMapBackground::Black => "rgb8",
+            ColorMapBackground::Transparent => "rgba8",
+        };
+
+        planned.push(PlannedOutput { path, description: format!("png {} color map, {}x{} pixels", channels, w * scale, h * scale) });
+        if options.georef.is_set() {
+            planned.push(PlannedOutput { path: world_file, description: String::from("world file") });
+        }
+    }
+
+    for &layer in &options.layers {
+        let path = options.output_path(&format!("{}_{}.tiff", file_stem, layer.suffix()));
+        let world_file = georef::world_file_path(&path);
+        let description = match layer {
+            Layer::FlowDirection => format!("tiff D8 flow directions, {}x{} tiles", w, h),
+            Layer::FlowAccumulation => format!("tiff f32 flow accumulation, {}x{} tiles", w, h),
+            Layer::WetnessIndex => format!("tiff f32 wetness index, {}x{} tiles", w, h),
+            Layer::Lakes => format!("tiff gray16 lake labels, {}x{} tiles", w, h),
+            Layer::CoastDistance => format!("tiff f32 coast distance, {}x{} tiles", w, h),
+            Layer::DistanceField => format!("tiff {} distance field, {}x{} tiles",
+                                            if layer.pixel_format(options) == PixelFormat::Gray16 { "gray16" } else { "f32" }, w, h),
+            Layer::SkyViewFactor => format!("tiff f32 sky view factor, {}x{} tiles", w, h),
+        };
+        let csv_path = path.with_extension("csv");
+
+        planned.push(PlannedOutput { path, description });
+        if options.georef.is_set() {
+            planned.push(PlannedOutput { path: world_file, description: String::from("world file") });
+        }
+        if layer == Layer::Lakes {
+            planned.push(PlannedOutput { path: csv_path, description: String::from("lake levels and volumes") });
+        }
+    }
+
+    Ok(planned)
+}
+
+/// Encodes a single image format into memory, with the same metadata and
+/// overlays as `export_all` but without any sidecar files.
+pub fn render(map: &Map, format: Format, options: &ExportOptions) -> io::Result<Vec<u8>> {
+    if options.overlay.is_set() && options.pixel_format == PixelFormat::F32 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Overlays can't be drawn on f32 heights"));
+    }
+
+    let icc_profile = options.icc_profile.as_deref()
+        .map(icc::load)
+        .transpose()?;
+    let reoriented;
+    let (map, options) = if options.is_reoriented() {
+        reoriented = options.reoriented(map)?;
+        (&reoriented.0, &reoriented.1)
+    } else {
+        (map, options)
+    };
+    #[cfg(feature = "reproject")]
+    let reprojected;
+    #[cfg(feature = "reproject")]
+    let (map, options) = match &options.georef.target_crs {
+        Some(target) => {
+            let (map, georef) = options.georef.reproject(map, target)?;
+            reprojected = (map, ExportOptions { georef, ..options.clone() });
+            (&reprojected.0, &reprojected.1)
+        }
+        None => (map, options),
+    };
+    let options = &*options.for_format(format);
+    let map = &*scaled(map, options);
+    let context = Context {
+        options,
+        icc_profile: icc_profile.as_deref(),
+        metadata: if options.no_metadata { Vec::new() } else { map.header.fields() },
+    };
+
+    let pixels = render_pixels(map, options)?;
+
+    let mut bytes = Vec::new();
+    write_raster(&pixels, format, &context, &mut bytes)?;
+
+    Ok(bytes)
+}
+
+/// The map with the height range `options` ask for in its header, the
+/// `--height-range` or else the one `--normalize` picks.
+fn scaled<'a>(map: &'a Map, options: &ExportOptions) -> Cow<'a, Map> {
+    match options.height_range {
+        Some((min, max)) => {
+            let mut map = map.clone();
+            map.header.min_height = min;
+            map.header.max_height = max;
+            Cow::Owned(map)
+        }
+        None => normalized(map, options.normalize),
+    }
+}
+
+/// The map with the height range `normalize` asks for in its header.
+fn normalized(map: &Map, normalize: Normalize) -> Cow<'_, Map> {
+    match (normalize, map.data_range()) {
+        (Normalize::Data, Some(range)) => {
+            let mut map = map.clone();
+            map.header.min_height = range.start;
+            map.header.max_height = range.end;
+            Cow::Owned(map)
+        }
+        _ => Cow::Borrowed(map),
+    }
+}
+
+/// The pixels shared by the raster formats, with the color layer resampled
+/// and the overlays drawn.
+fn render_pixels(map: &Map, options: &ExportOptions) -> io::Result<PixelBuffer> {
+    let mut pixels = match &options.composite {
+        Some(path) => Composite::load(path)?.render(map, tile_spacing(map, options)),
+        None => {
+            let mut pixels = match &options.palette {
+                Some(palette) => palette.render(map, options.pixel_format),
+                None => PixelBuffer::render(map, options.pixel_format, options.flat_level),
+            };
+
+            if options.color.is_set() {
+                pixels = options.color.apply(map, pixels)?;
+            }
+
+            options.relief.apply(map, tile_spacing(map, options), &mut pixels);
+            pixels
+        }
+    };
+
+    options.overlay.draw(&mut pixels, &options.georef)?;
+
+    Ok(pixels)
+}
+
+fn write_raster(pixels: &PixelBuffer, format: Format, context: &Context, w: impl Write) -> io::Result<()> {
+    match format {
+        Format::Bmp => bmp::write(pixels, w),
+        Format::Png | Format::Png16 => png::write(pixels, context, w),
+        Format::Tiff => tiff::write(pixels, context, w),
+        Format::Obj | Format::Dae | Format::Glb | Format::Vmf | Format::Schem | Format::R16 | Format::Tileset | Format::Normals | Format::Geotiff | Format::Horizon | Format::Svg | Format::Pdf => Err(unsupported(format, pixels.format)),
+    }
+}
+
+fn parse_rotation(s: &str) -> Result<u32, String> {
+    match s.trim() {
+        "0" => Ok(0),
+        "90" => Ok(90),
+        "180" => Ok(180),
+        "270" => Ok(270),
+        _ => Err(format!("Invalid rotation '{}', expected 90, 180 or 270", s)),
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn unsupported(format: Format, pixel_format: PixelFormat) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!(
+        "{:?} output does not support the {:?} pixel format", format, pixel_format))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+
+    use crate::Map;
+    use crate::fixture;
+
+    use super::ColorOptions;
+    use super::ExportOptions;
+    use super::Format;
+    use super::Georef;
+    use super::Layer;
+    use super::MeshOptions;
+    use super::export_all;
+    use super::plan;
+
+    /// Checks that `plan` lists exactly the files `export_all` writes, in
+    /// the same order.
+    fn assert_planned(case: &str, options: ExportOptions) {
+        let map = Map::from_reader(&fixture::map_bytes(&fixture::RUNS)[..]).unwrap();
+        let dir = env::temp_dir().join(format!("gti2bmp-plan-{}-{}", process::id(), case));
+        let options = ExportOptions { output_dir: dir.clone(), ..options };
+
+        let planned: Vec<PathBuf> = plan(map.header.w, map.header.h, "map", &options).unwrap()
+            .into_iter()
+            .map(|output| output.path)
+            .collect();
+        let written = export_all(&map, "map", &options);
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(planned, written.unwrap(), "{}", case);
+    }
+
+    fn formats(formats: &[Format]) -> ExportOptions {
+        ExportOptions { formats: formats.to_vec(), ..ExportOptions::default() }
+    }
+
+    #[test]
+    fn plans_list_the_rasters() {
+        assert_planned("rasters", formats(&[Format::Bmp, Format::Png, Format::Tiff, Format::Normals, Format::Horizon]));
+        assert_planned("png16", formats(&[Format::Png16, Format::Geotiff]));
+    }
+
+    #[test]
+    fn plans_list_the_meshes_and_their_sidecars() {
+        assert_planned("meshes", ExportOptions {
+            mesh: MeshOptions { mesh_texture: true, ..MeshOptions::default() },
+            ..formats(&[Format::Obj, Format::Dae, Format::Glb, Format::Tileset])
+        });
+        assert_planned("engines", formats(&[Format::R16, Format::Vmf, Format::Schem, Format::Svg, Format::Pdf]));
+    }
+
+    #[test]
+    fn plans_list_world_files_and_layers() {
+        let georef = Georef { origin: Some((100f64, 200f64)), cell_size: Some(2f64), ..Georef::default() };
+
+        assert_planned("georef", ExportOptions {
+            georef: georef.clone(),
+            layers: vec![Layer::FlowDirection, Layer::Lakes],
+            color: ColorOptions { color_map: true, ..ColorOptions::default() },
+            ..formats(&[Format::Png, Format::Normals])
+        });
+        assert_planned("georef-chunks", ExportOptions { georef, tile_size: Some(3), ..formats(&[Format::Tiff]) });
+    }
+
+    #[test]
+    fn plans_list_every_chunk() {
+        assert_planned("chunks", ExportOptions { tile_size: Some(2), ..formats(&[Format::Png, Format::Obj]) });
+        assert_planned("rotated", ExportOptions { rotate: Some(90), tile_size: Some(3), ..formats(&[Format::Bmp]) });
+    }
+}
@@ -0,0 +1,59 @@
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::Map;
+
+use super::ColorSpace;
+use super::Context;
+use super::ExportOptions;
+use super::gradient;
+use super::pixels::PixelBuffer;
+use super::pixels::PixelFormat;
+use super::pixels::Samples;
+use super::png;
+use super::tile_spacing;
+
+/// Writes a tangent-space normal map of the surface to a PNG at `path`, one
+/// pixel per tile with X to the east and Y to the north, OpenGL style.
+///
+/// Slopes are taken from the neighboring heights, like the hillshade's.
+/// Tiles are `u5` apart, or the georeferenced cell size if the header has
+/// no usable scale. Disabled tiles point straight up.
+pub fn write(map: &Map, context: &Context, path: &Path) -> io::Result<()> {
+    let w = map.header.w as usize;
+    let h = map.header.h as usize;
+    let spacing = tile_spacing(map, context.options);
+    let heights = map.tile_heights();
+
+    let mut samples = Vec::with_capacity(w * h * 3);
+    for y in 0..h {
+        for x in 0..w {
+            // Rows run southwards, so the slope down the image is the
+            // negated slope to the north.
+            let normal = match gradient(&heights, w, h, x, y, spacing) {
+                Some((east, south)) => {
+                    let length = (east * east + south * south + 1f32).sqrt();
+
+                    [-east / length, south / length, 1f32 / length]
+                }
+                None => [0f32, 0f32, 1f32],
+            };
+
+            samples.extend(normal.iter().map(|&component| ((component * 0.5 + 0.5) * 255f32).round() as u8));
+        }
+    }
+
+    let pixels = PixelBuffer { width: map.header.w, height: map.header.h, format: PixelFormat::Rgb8, samples: Samples::U8(samples) };
+
+    // Normals are vectors, not colors, so they stay linear unless asked otherwise.
+    let options = ExportOptions {
+        pixel_format: PixelFormat::Rgb8,
+        color_space: Some(context.options.color_space.unwrap_or(ColorSpace::Linear)),
+        ..context.options.clone()
+    };
+    let context = Context { options: &options, icc_profile: None, ..context.clone() };
+
+    png::write(&pixels, &context, BufWriter::new(File::create(path)?))
+}
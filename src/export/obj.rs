@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::prelude::*;
+use std::path::Path;
+use std::path::PathBuf;
+
+use clap::Args;
+
+use crate::Map;
+
+use super::Context;
+use super::ExportOptions;
+use super::Overlay;
+use super::ReliefOptions;
+use super::pixels::PixelFormat;
+use super::pixels::Samples;
+use super::png;
+use super::relief;
+use super::tessellate;
+use super::tile_spacing;
+use super::vertex_position;
+
+/// The material the baked texture is applied through.
+const MATERIAL: &str = "terrain";
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct MeshOptions {
+    /// Tessellate meshes adaptively, merging tiles into larger triangles as
+    /// long as no height is further than this from the surface.
+    #[arg(long, value_name = "HEIGHT")]
+    pub mesh_error: Option<f32>,
+
+    /// Hang walls this deep below the outer edges of every mesh, hiding the
+    /// cracks between neighboring chunks of different detail.
+    #[arg(long, value_name = "DEPTH")]
+    pub mesh_skirt: Option<f32>,
+
+    /// Give meshes planar texture coordinates and bake the tile colors into
+    /// `<name>_texture.png`, applied through `<name>.mtl`.
+    #[arg(long)]
+    pub mesh_texture: bool,
+
+    /// Shade the baked texture with the relief, lit from the northwest.
+    #[arg(long, requires = "mesh_texture")]
+    pub texture_relief: bool,
+
+    /// Mark how far each vertex can be trusted with a vertex color, white
+    /// where the tile was decoded and black where an edit like `--fill`
+    /// made it up.
+    #[arg(long)]
+    pub mesh_confidence: bool,
+
+    /// Give every vertex the color of its tile.
+    #[arg(long, conflicts_with = "mesh_confidence")]
+    pub mesh_colors: bool,
+
+    /// Space the vertices of meshes that aren't georeferenced by the header's
+    /// `u5` scale instead of one unit per tile.
+    #[arg(long)]
+    pub mesh_scale: bool,
+
+    /// Store glTF positions as 16-bit integers over the bounds of the mesh,
+    /// with `KHR_mesh_quantization`, and indices in 16 bits where they fit,
+    /// which takes about half the space. Positions are rounded to 1/65535th
+    /// of the mesh's extent along each axis.
+    #[arg(long)]
+    pub mesh_quantize: bool,
+}
+
+/// Writes the enabled tiles as a Wavefront OBJ surface, one vertex per tile
+/// and two triangles per fully enabled quad, or fewer, larger triangles with
+/// `--mesh-error`, plus skirts with `--mesh-skirt`. Vertices are placed
+/// in world coordinates when georeferencing is given, with Y up.
+///
+/// Returns the material and texture written alongside with `--mesh-texture`.
+pub fn write(map: &Map, context: &Context, path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut w = BufWriter::new(File::create(path)?);
+    let width = map.header.w;
+    let height = map.header.h;
+
+    let mesh = &context.options.mesh;
+
+    let heights = map.tile_heights();
+    let points = map.tile_points();
+
+    let triangles = match mesh.mesh_error {
+        Some(max_error) => tessellate::triangulate(&heights, width, height, max_error),
+        None => tessellate::grid(&heights, width, height),
+    };
+
+    // Only the corners of the triangles need a vertex.
+    let mut used = vec![false; map.enabled.len()];
+    for triangle in &triangles {
+        for &(x, y) in triangle {
+            used[(y * width + x) as usize] = true;
+        }
+    }
+
+    let position = |x: u32, y: u32| vertex_position(map, context.options, x, y);
+
+    // OBJ vertex numbers per tile, in image order.
+    let mut vertices = vec![0usize; map.enabled.len()];
+    let mut count = 0usize;
+
+    writeln!(w, "# {}", map.header.name)?;
+
+    let textured = if mesh.mesh_texture { Some(bake_texture(map, context, path)?) } else { None };
+    if let Some(material) = textured.as_ref().and_then(|files| files[0].file_name()) {
+        writeln!(w, "mtllib {}", material.to_string_lossy())?;
+        writeln!(w, "usemtl {}", MATERIAL)?;
+    }
+
+    // Written after the position, as the vertex color extension to OBJ.
+    let vertex_color = |x: u32, y: u32| {
+        let point = points[(y * width + x) as usize];
+        if mesh.mesh_colors {
+            let [r, g, b] = point.map_or([0u8; 3], |point| [point.r, point.g, point.b]).map(|c| f32::from(c) / 255f32);
+            format!(" {} {} {}", r, g, b)
+        } else {
+            String::from(match (mesh.mesh_confidence, point.is_some_and(|point| point.filled)) {
+                (false, _) => "",
+                (true, false) => " 1 1 1",
+                (true, true) => " 0 0 0",
+            })
+        }
+    };
+
+    // Tile centers across the texture, which has its top row at v = 1.
+    let texture_coordinate = |w: &mut BufWriter<File>, x: u32, y: u32| if textured.is_some() {
+        writeln!(w, "vt {} {}", (f64::from(x) + 0.5) / f64::from(width), 1f64 - (f64::from(y) + 0.5) / f64::from(height))
+    } else {
+        Ok(())
+    };
+    let face = |w: &mut BufWriter<File>, a: usize, b: usize, c: usize| if textured.is_some() {
+        // Every vertex has its own texture coordinate, under the same number.
+        writeln!(w, "f {0}/{0} {1}/{1} {2}/{2}", a, b, c)
+    } else {
+        writeln!(w, "f {} {} {}", a, b, c)
+    };
+
+    // The uniform grid numbers every enabled tile, used or not.
+    for (index, offset) in map.enabled_tiles() {
+        let (x, y) = crate::get_position(&index, &width, &height);
+        let i = (y * width + x) as usize;
+
+        if mesh.mesh_error.is_some() && !used[i] {
+            continue;
+        }
+
+        count += 1;
+        vertices[i] = count;
+        let (wx, wy) = position(x, y);
+        writeln!(w, "v {} {} {}{}", wx, map.points[offset].h, -wy, vertex_color(x, y))?;
+        texture_coordinate(&mut w, x, y)?;
+    }
+
+    let vertex = |(x, y): (u32, u32)| vertices[(y * width + x) as usize];
+
+    for &[a, b, c] in &triangles {
+        face(&mut w, vertex(a), vertex(b), vertex(c))?;
+    }
+
+    if let Some(depth) = mesh.mesh_skirt {
+        let edges = tessellate::outer_edges(&triangles, width, height);
+
+        // One lowered copy of every vertex along the outer edges.
+        let mut lowered = HashMap::new();
+        for &(a, b) in &edges {
+            for (x, y) in [a, b] {
+                if let Entry::Vacant(entry) = lowered.entry((x, y)) {
+                    count += 1;
+                    entry.insert(count);
+                    let (wx, wy) = position(x, y);
+                    let lowered_height = heights[(y * width + x) as usize].unwrap_or_default() - depth;
+                    writeln!(w, "v {} {} {}{}", wx, lowered_height, -wy, vertex_color(x, y))?;
+                    texture_coordinate(&mut w, x, y)?;
+                }
+            }
+        }
+
+        // Walks each edge backwards, so the walls face the same way as the
+        // surface they hang from.
+        for (a, b) in edges {
+            face(&mut w, vertex(b), vertex(a), lowered[&a])?;
+            face(&mut w, vertex(b), lowered[&a], lowered[&b])?;
+        }
+    }
+
+    w.flush()?;
+
+    Ok(textured.unwrap_or_default())
+}
+
+/// Renders the color layer, shaded with `--texture-relief`, next to the mesh
+/// at `path`, along with a material using it. Returns the material and the
+/// texture paths.
+fn bake_texture(map: &Map, context: &Context, path: &Path) -> io::Result<Vec<PathBuf>> {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let texture_path = path.with_file_name(format!("{}_texture.png", stem));
+    let material_path = path.with_extension("mtl");
+
+    // Overlays would no longer line up with the texture coordinates.
+    let options = ExportOptions {
+        pixel_format: PixelFormat::Rgb8,
+        color_space: None,
+        overlay: Overlay::default(),
+        relief: ReliefOptions::default(),
+        composite: None,
+        palette: None,
+        ..context.options.clone()
+    };
+    let mut pixels = super::render_pixels(map, &options)?;
+
+    if context.options.mesh.texture_relief {
+        let shade = relief::hillshade(map, tile_spacing(map, context.options), 315f32, 45f32);
+        let scale = options.color.color_scale.max(1);
+        let width = pixels.width;
+
+        if let Samples::U8(data) = &mut pixels.samples {
+            for (i, pixel) in data.chunks_mut(3).enumerate() {
+                let (x, y) = (i as u32 % width / scale, i as u32 / width / scale);
+                let shade = shade[(y * map.header.w + x) as usize].unwrap_or(1f32);
+
+                for c in pixel {
+                    *c = (f32::from(*c) * shade).round() as u8;
+                }
+            }
+        }
+    }
+
+    let texture_context = Context { options: &options, icc_profile: None, metadata: context.metadata.clone() };
+    png::write(&pixels, &texture_context, BufWriter::new(File::create(&texture_path)?))?;
+
+    let mut material = BufWriter::new(File::create(&material_path)?);
+    writeln!(material, "newmtl {}", MATERIAL)?;
+    writeln!(material, "Kd 1 1 1")?;
+    writeln!(material, "map_Kd {}", texture_path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default())?;
+    material.flush()?;
+
+    Ok(vec![material_path, texture_path])
+}
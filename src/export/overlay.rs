@@ -0,0 +1,267 @@
+use std::io;
+use std::path::PathBuf;
+
+use clap::Args;
+use clap::ValueEnum;
+
+use super::Georef;
+use super::font::Font;
+use super::pixels::PixelBuffer;
+
+const TICK_LENGTH: u32 = 3;
+
+/// What the axis labels count in.
+#[derive(Debug, Clone, Copy, PartialEq, Default, ValueEnum)]
+pub enum AxisUnits {
+    /// Tile coordinates of the decoded image.
+    #[default]
+    Tiles,
+    /// World coordinates from `--origin` and `--cell-size`.
+    World,
+}
+
+/// Annotations drawn on top of rendered images, for previews that are
+/// shared when discussing a map.
+#[derive(Args, Debug, Clone)]
+pub struct Overlay {
+    /// Draw gridlines every N tiles.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub grid: Option<u32>,
+
+    /// The gridline color, as hex `rrggbb`.
+    #[arg(long, value_parser = parse_color, default_value = "ff0000")]
+    pub grid_color: [u8; 3],
+
+    /// Label each grid cell with the tile coordinates of its top-left corner.
+    #[arg(long, requires = "grid")]
+    pub grid_labels: bool,
+
+    /// Add a white margin with axis ticks and labels around the image. The
+    /// image no longer lines up with its georeferencing, so world files and
+    /// GeoTIFF tags are left out.
+    #[arg(long)]
+    pub axes: bool,
+
+    /// Whether the axes are labeled in tiles or world units.
+    #[arg(long, value_enum, default_value = "tiles", requires = "axes")]
+    pub axis_units: AxisUnits,
+
+    /// The distance between ticks in axis units, picked automatically by default.
+    #[arg(long, requires = "axes")]
+    pub tick_spacing: Option<f64>,
+
+    /// A TrueType font to label the grid and axes in, instead of the built-in
+    /// pixel font.
+    #[arg(long, value_name = "TTF")]
+    pub font: Option<PathBuf>,
+
+    /// The size of labels in pixels, 12 with `--font` and 5 for the built-in
+    /// font, which only grows in steps of 5.
+    #[arg(long, value_name = "PX", value_parser = clap::value_parser!(u32).range(1..))]
+    pub font_size: Option<u32>,
+
+    /// The color of grid and axis labels, as hex `rrggbb`. Grid labels are
+    /// drawn in the grid color and axis labels in the axis color by default.
+    #[arg(long, value_name = "COLOR", value_parser = parse_color)]
+    pub label_color: Option<[u8; 3]>,
+
+    /// The color of the axis frame and ticks, as hex `rrggbb`.
+    #[arg(long, value_name = "COLOR", value_parser = parse_color, default_value = "000000")]
+    pub axis_color: [u8; 3],
+
+    /// The color of the margin around axes, as hex `rrggbb`.
+    #[arg(long, value_name = "COLOR", value_parser = parse_color, default_value = "ffffff")]
+    pub margin_color: [u8; 3],
+}
+
+impl Default for Overlay {
+    fn default() -> Overlay {
+        Overlay {
+            grid: None,
+            grid_color: [255, 0, 0],
+            grid_labels: false,
+            axes: false,
+            axis_units: AxisUnits::Tiles,
+            tick_spacing: None,
+            font: None,
+            font_size: None,
+            label_color: None,
+            axis_color: [0, 0, 0],
+            margin_color: [255, 255, 255],
+        }
+    }
+}
+
+impl Overlay {
+    pub fn is_set(&self) -> bool {
+        self.grid.is_some() || self.axes
+    }
+
+    /// Draws the grid and then the axes, which grow the image.
+    pub fn draw(&self, pixels: &mut PixelBuffer, georef: &Georef) -> io::Result<()> {
+        if !self.is_set() {
+            return Ok(());
+        }
+
+        let font = Font::load(self.font.as_deref(), self.font_size)?;
+        self.draw_grid(pixels, &font);
+
+        if self.axes {
+            self.draw_axes(pixels, georef, &font);
+        }
+
+        Ok(())
+    }
+
+    fn draw_grid(&self, pixels: &mut PixelBuffer, font: &Font) {
+        let spacing = match self.grid {
+            Some(spacing) => spacing,
+            None => return,
+        };
+
+        for x in (0..pixels.width).step_by(spacing as usize) {
+            for y in 0..pixels.height {
+                pixels.paint(i64::from(x), i64::from(y), self.grid_color);
+            }
+        }
+
+        for y in (0..pixels.height).step_by(spacing as usize) {
+            for x in 0..pixels.width {
+                pixels.paint(i64::from(x), i64::from(y), self.grid_color);
+            }
+        }
+
+        if self.grid_labels {
+            for y in (0..pixels.height).step_by(spacing as usize) {
+                for x in (0..pixels.width).step_by(spacing as usize) {
+                    let label = format!("{},{}", x, y);
+                    font.draw(pixels, i64::from(x) + 2, i64::from(y) + 2, &label, self.label_color.unwrap_or(self.grid_color));
+                }
+            }
+        }
+    }
+}
+
+impl Overlay {
+    fn draw_axes(&self, pixels: &mut PixelBuffer, georef: &Georef, font: &Font) {
+        let label_color = self.label_color.unwrap_or(self.axis_color);
+        let (width, height) = (pixels.width, pixels.height);
+        let (scale, (x_start, y_start), y_direction) = match self.axis_units {
+            AxisUnits::Tiles => (1f64, (0f64, 0f64), 1f64),
+            AxisUnits::World => (georef.cell_size(), georef.origin(), -1f64),
+        };
+
+        // Axis values at the left/right and top/bottom image edges.
+        let x_range = (x_start, x_start + f64::from(width) * scale);
+        let y_range = (y_start, y_start + y_direction * f64::from(height) * scale);
+        let x_ticks = ticks(x_range, self.tick_spacing, width, |label| font.text_width(label));
+        let y_ticks = ticks(y_range, self.tick_spacing, height, |_| font.height());
+
+        let label_width = |ticks: &[(f64, String)]| ticks.iter()
+            .map(|(_, label)| font.text_width(label))
+            .max()
+            .unwrap_or(0);
+        let last_x_label = x_ticks.last().map_or(0, |(_, label)| font.text_width(label));
+
+        let left = label_width(&y_ticks) + TICK_LENGTH + 3;
+        let top = font.height() / 2 + 2;
+        let right = last_x_label / 2 + 2;
+        let bottom = TICK_LENGTH + font.height() + 3;
+
+        pixels.pad(left, top, right, bottom, self.margin_color);
+
+        let (left, top) = (i64::from(left), i64::from(top));
+        let (width, height) = (i64::from(width), i64::from(height));
+
+        // Frame around the map.
+        for x in left - 1..=left + width {
+            pixels.paint(x, top - 1, self.axis_color);
+            pixels.paint(x, top + height, self.axis_color);
+        }
+        for y in top - 1..=top + height {
+            pixels.paint(left - 1, y, self.axis_color);
+            pixels.paint(left + width, y, self.axis_color);
+        }
+
+        for (value, label) in &x_ticks {
+            let x = left + ((value - x_range.0) / (x_range.1 - x_range.0) * width as f64).round() as i64;
+            for i in 0..i64::from(TICK_LENGTH) {
+                pixels.paint(x, top + height + 1 + i, self.axis_color);
+            }
+            let label_x = x - i64::from(font.text_width(label)) / 2;
+            font.draw(pixels, label_x, top + height + 2 + i64::from(TICK_LENGTH), label, label_color);
+        }
+
+        for (value, label) in &y_ticks {
+            let y = top + ((value - y_range.0) / (y_range.1 - y_range.0) * height as f64).round() as i64;
+            for i in 0..i64::from(TICK_LENGTH) {
+                pixels.paint(left - 2 - i, y, self.axis_color);
+            }
+            let label_x = left - 2 - i64::from(TICK_LENGTH) - i64::from(font.text_width(label));
+            font.draw(pixels, label_x, y - i64::from(font.height()) / 2, label, label_color);
+        }
+    }
+}
+
+/// Tick values and labels between the two ends of an axis `pixels` long,
+/// either of which may be the larger one. Without an explicit spacing, the
+/// step grows until the labels, `size` pixels each along the axis, don't overlap.
+fn ticks((a, b): (f64, f64), spacing: Option<f64>, pixels: u32, size: impl Fn(&str) -> u32) -> Vec<(f64, String)> {
+    let (low, high) = if a <= b { (a, b) } else { (b, a) };
+
+    if let Some(step) = spacing.filter(|&step| step > 0.0) {
+        return ticks_with_step(low, high, step);
+    }
+
+    let mut step = nice_step(high - low);
+    loop {
+        let ticks = ticks_with_step(low, high, step);
+        let gap = step / (high - low) * f64::from(pixels);
+        let widest = ticks.iter().map(|(_, label)| size(label)).max().unwrap_or(0);
+
+        if ticks.len() <= 2 || gap >= f64::from(widest + 4) {
+            return ticks;
+        }
+
+        step = nice_step(step * 8.0 * 1.5);
+    }
+}
+
+fn ticks_with_step(low: f64, high: f64, step: f64) -> Vec<(f64, String)> {
+    let decimals = if step.fract() == 0.0 { 0 } else { (-step.log10()).ceil().max(0.0) as usize };
+    let mut ticks = Vec::new();
+    let mut i = (low / step).ceil();
+
+    while i * step <= high + step * 1e-9 {
+        let value = i * step;
+        ticks.push((value, format!("{:.*}", decimals, value)));
+        i += 1.0;
+    }
+
+    ticks
+}
+
+/// A step of 1, 2 or 5 times a power of ten giving roughly eight ticks.
+pub fn nice_step(extent: f64) -> f64 {
+    let raw = (extent / 8.0).max(f64::MIN_POSITIVE);
+    let magnitude = 10f64.powf(raw.log10().floor());
+
+    [1.0, 2.0, 5.0, 10.0].iter()
+        .map(|factor| factor * magnitude)
+        .find(|&step| step >= raw)
+        .unwrap_or(10.0 * magnitude)
+}
+
+/// Parses a color written as hex `rrggbb`, with an optional leading `#`.
+pub fn parse_color(s: &str) -> Result<[u8; 3], String> {
+    let hex = s.trim_start_matches('#');
+
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(format!("Invalid color '{}', expected rrggbb", s));
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16)
+        .map_err(|e| format!("Invalid color '{}': {}", s, e));
+
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
@@ -0,0 +1,140 @@
+use std::fs;
+
+use serde_json::Value;
+
+use crate::Map;
+
+use super::overlay::parse_color;
+use super::pixels::PixelBuffer;
+use super::pixels::PixelFormat;
+use super::pixels::Samples;
+
+/// The built-in ramps, as colors evenly spaced from the bottom of the
+/// height range to the top.
+const BUILT_IN: [(&str, &[[u8; 3]]); 3] = [
+    ("terrain", &[
+        [0x1f, 0x3f, 0x9a],
+        [0x3a, 0x9b, 0xd9],
+        [0x3c, 0xa0, 0x4c],
+        [0xa8, 0xc0, 0x5a],
+        [0x8b, 0x6a, 0x3e],
+        [0x9e, 0x8e, 0x80],
+        [0xff, 0xff, 0xff],
+    ]),
+    ("viridis", &[
+        [0x44, 0x01, 0x54],
+        [0x48, 0x28, 0x78],
+        [0x3e, 0x49, 0x89],
+        [0x31, 0x68, 0x8e],
+        [0x26, 0x82, 0x8e],
+        [0x1f, 0x9e, 0x89],
+        [0x35, 0xb7, 0x79],
+        [0x6e, 0xce, 0x58],
+        [0xb5, 0xde, 0x2b],
+        [0xfd, 0xe7, 0x25],
+    ]),
+    ("grayscale", &[[0x00, 0x00, 0x00], [0xff, 0xff, 0xff]]),
+];
+
+/// A color ramp the normalized heights are looked up in, from the bottom of
+/// the height range at 0 to the top at 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    pub name: String,
+    /// Positions from 0 to 1 in increasing order, with their colors.
+    stops: Vec<(f32, [u8; 3])>,
+}
+
+impl Palette {
+    /// Parses `--palette`: the name of a built-in ramp, or a JSON file with
+    /// an array of stops like `{"at": 0.5, "color": "a8c05a"}`.
+    pub fn parse(s: &str) -> Result<Palette, String> {
+        if let Some(palette) = Palette::built_in(s) {
+            return Ok(palette);
+        }
+
+        let text = fs::read_to_string(s).map_err(|e| format!(
+            "'{}' is neither a built-in palette ({}) nor a readable file: {}",
+            s, Palette::built_in_names().join(", "), e))?;
+        let document: Value = serde_json::from_str(&text).map_err(|e| format!("{}: {}", s, e))?;
+
+        Palette::from_json(&document)
+            .map(|stops| Palette { name: s.to_string(), stops })
+            .map_err(|e| format!("{}: {}", s, e))
+    }
+
+    /// The built-in ramp called `name`, in any case.
+    pub fn built_in(name: &str) -> Option<Palette> {
+        let (name, colors) = BUILT_IN.iter().find(|(built_in, _)| built_in.eq_ignore_ascii_case(name))?;
+        let last = (colors.len() - 1) as f32;
+        let stops = colors.iter().enumerate().map(|(i, &color)| (i as f32 / last, color)).collect();
+
+        Some(Palette { name: String::from(*name), stops })
+    }
+
+    /// The names of the built-in ramps.
+    pub fn built_in_names() -> Vec<&'static str> {
+        BUILT_IN.iter().map(|(name, _)| *name).collect()
+    }
+
+    fn from_json(document: &Value) -> Result<Vec<(f32, [u8; 3])>, String> {
+        let entries = document.as_array().ok_or("Expected an array of stops")?;
+        let mut stops = Vec::with_capacity(entries.len());
+
+        for (i, entry) in entries.iter().enumerate() {
+            let at = entry.get("at").and_then(Value::as_f64)
+                .ok_or_else(|| format!("Stop {} needs a number 'at'", i))? as f32;
+            let color = entry.get("color").and_then(Value::as_str)
+                .ok_or_else(|| format!("Stop {} needs a 'color' string", i))
+                .and_then(parse_color)?;
+
+            if !(0f32..=1f32).contains(&at) {
+                return Err(format!("Stop {} is at {}, outside of 0 to 1", i, at));
+            }
+            if stops.last().is_some_and(|&(previous, _)| at < previous) {
+                return Err(format!("Stop {} is at {}, before the stop ahead of it", i, at));
+            }
+            stops.push((at, color));
+        }
+
+        if stops.is_empty() {
+            return Err(String::from("A palette needs at least one stop"));
+        }
+
+        Ok(stops)
+    }
+
+    /// The color at `t`, blended between the stops around it; heights past
+    /// the first or last stop take its color.
+    pub fn color(&self, t: f32) -> [u8; 3] {
+        let after = self.stops.iter().position(|&(at, _)| at > t).unwrap_or(self.stops.len());
+        if after == 0 {
+            return self.stops[0].1;
+        }
+        if after == self.stops.len() {
+            return self.stops[after - 1].1;
+        }
+
+        let ((a_at, a), (b_at, b)) = (self.stops[after - 1], self.stops[after]);
+        let fraction = (t - a_at) / (b_at - a_at);
+        [0, 1, 2].map(|c| (f32::from(a[c]) + (f32::from(b[c]) - f32::from(a[c])) * fraction).round() as u8)
+    }
+
+    /// Colors every tile by its height over the header range at one pixel per
+    /// tile, in rgba8 with the enabled mask as alpha when `format` has an
+    /// alpha channel and in rgb8 over black otherwise.
+    pub fn render(&self, map: &Map, format: PixelFormat) -> PixelBuffer {
+        let format = match format {
+            PixelFormat::Rgba8 | PixelFormat::Gray8Alpha => PixelFormat::Rgba8,
+            _ => PixelFormat::Rgb8,
+        };
+        let samples = map.normalized_heights(map.height_range()).iter()
+            .flat_map(|&t| {
+                let [r, g, b] = if t.is_nan() { [0; 3] } else { self.color(t) };
+                IntoIterator::into_iter([r, g, b, if t.is_nan() { 0 } else { 255 }]).take(format.channels())
+            })
+            .collect();
+
+        PixelBuffer { width: map.header.w, height: map.header.h, format, samples: Samples::U8(samples) }
+    }
+}
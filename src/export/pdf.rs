@@ -0,0 +1,302 @@
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::prelude::*;
+use std::path::Path;
+
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+
+use crate::Map;
+use crate::precision::Precision;
+
+use super::Context;
+use super::ExportOptions;
+use super::pixels::PixelBuffer;
+use super::pixels::PixelFormat;
+use super::pixels::Samples;
+use super::render_pixels;
+
+/// A4 portrait, in points.
+const PAGE_WIDTH: f32 = 595f32;
+const PAGE_HEIGHT: f32 = 842f32;
+const MARGIN: f32 = 50f32;
+
+const HISTOGRAM_BINS: usize = 64;
+const HISTOGRAM_HEIGHT: f32 = 110f32;
+const PREVIEW_HEIGHT: f32 = 330f32;
+
+/// The spacing of the header dump lines, which run on over as many pages
+/// as they need.
+const HEADER_LEADING: f32 = 11f32;
+
+/// The fonts every page can use, all base fonts PDF readers have built in.
+const FONTS: [(&str, &str); 3] = [("F1", "Helvetica"), ("F2", "Helvetica-Bold"), ("F3", "Courier")];
+
+/// Writes a review report: the preview rendered like the raster formats,
+/// a histogram of the heights and their statistics on the first page, and
+/// every header field on the pages after it.
+pub fn write(map: &Map, context: &Context, file_stem: &str, path: &Path) -> io::Result<()> {
+    let preview_options = ExportOptions { pixel_format: PixelFormat::Rgb8, color_space: None, ..context.options.clone() };
+    let preview = render_pixels(map, &preview_options)?;
+
+    let mut pages = vec![summary_page(map, file_stem, &preview)];
+    pages.extend(header_pages(map));
+
+    let mut pdf = Pdf::default();
+    let catalog = pdf.reserve();
+    let page_tree = pdf.reserve();
+
+    let fonts: Vec<(&str, usize)> = FONTS.iter()
+        .map(|&(name, base)| (name, pdf.add(format!(
+            "<< /Type /Font /Subtype /Type1 /BaseFont /{} /Encoding /WinAnsiEncoding >>", base).into_bytes())))
+        .collect();
+    let image = pdf.add(image_object(&preview)?);
+    let resources = format!("<< /Font << {} >> /XObject << /Im1 {} 0 R >> >>",
+                            fonts.iter().map(|(name, id)| format!("/{} {} 0 R", name, id)).collect::<Vec<_>>().join(" "),
+                            image);
+
+    let mut kids = Vec::new();
+    for content in pages {
+        let content = pdf.add(stream_object("", content.as_bytes()));
+        kids.push(pdf.add(format!(
+            "<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {} {}] /Resources {} /Contents {} 0 R >>",
+            page_tree, PAGE_WIDTH, PAGE_HEIGHT, resources, content).into_bytes()));
+    }
+
+    pdf.set(page_tree, format!("<< /Type /Pages /Kids [{}] /Count {} >>",
+                               kids.iter().map(|id| format!("{} 0 R", id)).collect::<Vec<_>>().join(" "), kids.len()).into_bytes());
+    pdf.set(catalog, format!("<< /Type /Catalog /Pages {} 0 R >>", page_tree).into_bytes());
+    let info = pdf.add(format!("<< /Title {} /Producer ({}) >>", string(&map.header.name), env!("CARGO_PKG_NAME")).into_bytes());
+
+    pdf.write(catalog, info, BufWriter::new(File::create(path)?))
+}
+
+/// The title, the preview, the histogram and the statistics table.
+fn summary_page(map: &Map, file_stem: &str, preview: &PixelBuffer) -> String {
+    let mut page = String::new();
+    let width = PAGE_WIDTH - 2f32 * MARGIN;
+    let mut y = PAGE_HEIGHT - MARGIN;
+
+    text(&mut page, "F2", 18f32, MARGIN, y, &map.header.name);
+    y -= 16f32;
+    page += "0.4 g\n";
+    text(&mut page, "F1", 10f32, MARGIN, y, file_stem);
+    page += "0 g\n";
+    y -= 14f32;
+
+    // Scaled to fit, keeping the aspect ratio, and centered.
+    let scale = (width / preview.width.max(1) as f32).min(PREVIEW_HEIGHT / preview.height.max(1) as f32);
+    let (image_width, image_height) = (preview.width as f32 * scale, preview.height as f32 * scale);
+    y -= image_height;
+    let _ = writeln!(page, "q {:.2} 0 0 {:.2} {:.2} {:.2} cm /Im1 Do Q", image_width, image_height, MARGIN + (width - image_width) / 2f32, y);
+
+    y -= 28f32;
+    text(&mut page, "F2", 12f32, MARGIN, y, "Height histogram");
+    y -= 8f32 + HISTOGRAM_HEIGHT;
+    histogram(&mut page, map, MARGIN, y, width);
+
+    y -= 36f32;
+    text(&mut page, "F2", 12f32, MARGIN, y, "Statistics");
+    y -= 6f32;
+    for (label, value) in statistics(map) {
+        y -= 13f32;
+        text(&mut page, "F1", 10f32, MARGIN, y, label);
+        text(&mut page, "F1", 10f32, MARGIN + 150f32, y, &value);
+    }
+
+    page
+}
+
+/// Bars of how many enabled tiles fall in each of `HISTOGRAM_BINS` steps of
+/// the data range, with the range and the tallest count along the edges.
+fn histogram(page: &mut String, map: &Map, x: f32, y: f32, width: f32) {
+    let histogram = match map.histogram(HISTOGRAM_BINS) {
+        Some(histogram) => histogram,
+        None => {
+            text(page, "F1", 10f32, x, y + HISTOGRAM_HEIGHT / 2f32, "No finite heights");
+            return;
+        }
+    };
+    let (range, bins) = (histogram.range, histogram.counts);
+    let tallest = bins.iter().copied().max().unwrap_or_default().max(1);
+
+    let bar_width = width / HISTOGRAM_BINS as f32;
+    *page += "0.35 0.45 0.6 rg\n";
+    for (i, &count) in bins.iter().enumerate() {
+        if count > 0 {
+            let bar_height = count as f32 / tallest as f32 * HISTOGRAM_HEIGHT;
+            let _ = writeln!(page, "{:.2} {:.2} {:.2} {:.2} re f", x + i as f32 * bar_width, y, bar_width, bar_height);
+        }
+    }
+    let _ = writeln!(page, "0 g 0.5 G 0.5 w {:.2} {:.2} {:.2} {:.2} re S", x, y, width, HISTOGRAM_HEIGHT);
+
+    let high = format!("{}", range.end);
+    text(page, "F1", 8f32, x, y - 10f32, &range.start.to_string());
+    text(page, "F1", 8f32, x + width - text_width(&high, 8f32), y - 10f32, &high);
+    text(page, "F1", 8f32, x + 2f32, y + HISTOGRAM_HEIGHT - 9f32, &format!("{} tiles", tallest));
+}
+
+/// The rows of the statistics table.
+fn statistics(map: &Map) -> Vec<(&'static str, String)> {
+    let total = u64::from(map.header.w) * u64::from(map.header.h);
+    let enabled = map.points.len();
+    let outliers = map.find_outliers(3f32);
+    let precision = Precision::analyze(map);
+
+    let mut rows = vec![
+        ("Size", format!("{}x{} tiles", map.header.w, map.header.h)),
+        ("Enabled tiles", format!("{} of {} ({:.1}%)", enabled, total, enabled as f64 / total.max(1) as f64 * 100f64)),
+        ("Header height range", format!("{} to {}", map.header.min_height, map.header.max_height)),
+        ("Data height range", map.data_range().map_or_else(|| String::from("none"), |range| format!("{} to {}", range.start, range.end))),
+        ("Mean height", format!("{:.3}", outliers.mean)),
+        ("Standard deviation", format!("{:.3}", outliers.std_dev)),
+        ("Outliers beyond 3 sigma", outliers.outliers.len().to_string()),
+        ("Non-finite heights", outliers.non_finite.len().to_string()),
+        ("Distinct heights", precision.unique.to_string()),
+    ];
+    if let Some(step) = precision.min_step {
+        rows.push(("Smallest height step", format!("{}", step)));
+    }
+    if let Some(bits) = precision.effective_bits() {
+        rows.push(("Effective bits", bits.to_string()));
+    }
+    if let Some(spacing) = map.header.tile_spacing() {
+        rows.push(("Tile spacing", format!("{}", spacing)));
+    }
+
+    rows
+}
+
+/// Every header field, in file order, over as many pages as it takes.
+fn header_pages(map: &Map) -> Vec<String> {
+    let fields = map.header.fields();
+    let width = fields.iter().map(|(name, _)| name.len()).max().unwrap_or_default();
+    let lines_per_page = ((PAGE_HEIGHT - 2f32 * MARGIN - 30f32) / HEADER_LEADING) as usize;
+
+    fields.chunks(lines_per_page)
+        .map(|fields| {
+            let mut page = String::new();
+            let mut y = PAGE_HEIGHT - MARGIN;
+
+            text(&mut page, "F2", 12f32, MARGIN, y, "Header");
+            y -= 10f32;
+            for (name, value) in fields {
+                y -= HEADER_LEADING;
+                text(&mut page, "F3", 9f32, MARGIN, y, &format!("{:width$}  {}", name, value, width = width));
+            }
+
+            page
+        })
+        .collect()
+}
+
+fn text(page: &mut String, font: &str, size: f32, x: f32, y: f32, value: &str) {
+    let _ = writeln!(page, "BT /{} {} Tf {:.2} {:.2} Td {} Tj ET", font, size, x, y, string(value));
+}
+
+/// Roughly how wide Helvetica sets `value`, enough to right-align numbers.
+fn text_width(value: &str, size: f32) -> f32 {
+    value.chars().count() as f32 * size * 0.556
+}
+
+/// A PDF string literal in WinAnsi, with characters it lacks as `?`.
+fn string(value: &str) -> String {
+    let mut literal = String::from("(");
+
+    for c in value.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                literal.push('\\');
+                literal.push(c);
+            }
+            ' '..='~' => literal.push(c),
+            '\u{a0}'..='\u{ff}' => {
+                let _ = write!(literal, "\\{:03o}", u32::from(c));
+            }
+            _ => literal.push('?'),
+        }
+    }
+
+    literal.push(')');
+    literal
+}
+
+/// The preview as a compressed RGB image.
+fn image_object(pixels: &PixelBuffer) -> io::Result<Vec<u8>> {
+    let samples = match &pixels.samples {
+        Samples::U8(samples) => samples,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "The PDF preview needs 8-bit samples")),
+    };
+    let rgb: Vec<u8> = match pixels.format.channels() {
+        1 => samples.iter().flat_map(|&level| [level; 3]).collect(),
+        2 => samples.chunks(2).flat_map(|pixel| [pixel[0]; 3]).collect(),
+        4 => samples.chunks(4).flat_map(|pixel| [pixel[0], pixel[1], pixel[2]]).collect(),
+        _ => samples.clone(),
+    };
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&rgb)?;
+    let data = encoder.finish()?;
+
+    Ok(stream_object(&format!(
+        "/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /FlateDecode ",
+        pixels.width, pixels.height), &data))
+}
+
+fn stream_object(dictionary: &str, data: &[u8]) -> Vec<u8> {
+    let mut object = format!("<< {}/Length {} >>\nstream\n", dictionary, data.len()).into_bytes();
+    object.extend_from_slice(data);
+    object.extend_from_slice(b"\nendstream");
+    object
+}
+
+/// The numbered objects of a document, in the order they are written.
+#[derive(Default)]
+struct Pdf {
+    objects: Vec<Vec<u8>>,
+}
+
+impl Pdf {
+    /// Numbers an object to be filled in with `set` once its contents are
+    /// known.
+    fn reserve(&mut self) -> usize {
+        self.add(Vec::new())
+    }
+
+    fn add(&mut self, object: Vec<u8>) -> usize {
+        self.objects.push(object);
+        self.objects.len()
+    }
+
+    fn set(&mut self, id: usize, object: Vec<u8>) {
+        self.objects[id - 1] = object;
+    }
+
+    /// Writes the objects with the cross-reference table pointing at them.
+    fn write(&self, root: usize, info: usize, mut w: impl Write) -> io::Result<()> {
+        // The binary comment tells transfer tools not to treat it as text.
+        let mut offset = 15;
+        w.write_all(b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n")?;
+
+        let mut offsets = Vec::new();
+        for (i, object) in self.objects.iter().enumerate() {
+            offsets.push(offset);
+            let start = format!("{} 0 obj\n", i + 1);
+            w.write_all(start.as_bytes())?;
+            w.write_all(object)?;
+            w.write_all(b"\nendobj\n")?;
+            offset += start.len() + object.len() + 8;
+        }
+
+        writeln!(w, "xref\n0 {}\n0000000000 65535 f ", self.objects.len() + 1)?;
+        for offset in offsets {
+            writeln!(w, "{:010} 00000 n ", offset)?;
+        }
+        writeln!(w, "trailer\n<< /Size {} /Root {} 0 R /Info {} 0 R >>\nstartxref\n{}\n%%EOF",
+                 self.objects.len() + 1, root, info, offset)?;
+
+        w.flush()
+    }
+}
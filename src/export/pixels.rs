@@ -0,0 +1,189 @@
+use clap::ValueEnum;
+
+use crate::Map;
+
+/// How the map data is packed into the pixels of an image.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum PixelFormat {
+    /// Heights scaled over the header range to 8 bits.
+    Gray8,
+    /// Heights scaled over the header range to 16 bits.
+    Gray16,
+    /// The 8-bit heights as gray with the enabled mask as alpha, so disabled
+    /// tiles come out transparent rather than black.
+    Gray8Alpha,
+    /// The tile color layer.
+    Rgb8,
+    /// The tile color layer with the enabled mask as alpha.
+    Rgba8,
+    /// Raw heights as 32-bit floats, NaN where tiles are disabled.
+    F32,
+}
+
+impl PixelFormat {
+    pub fn channels(&self) -> usize {
+        match self {
+            PixelFormat::Gray8 | PixelFormat::Gray16 | PixelFormat::F32 => 1,
+            PixelFormat::Gray8Alpha => 2,
+            PixelFormat::Rgb8 => 3,
+            PixelFormat::Rgba8 => 4,
+        }
+    }
+}
+
+/// The samples of a rendered image, row-major from the top-left.
+pub enum Samples {
+    U8(Vec<u8>),
+    U16(Vec<u16>),
+    F32(Vec<f32>),
+}
+
+/// Map data rendered into pixels, ready to be written by a container format.
+pub struct PixelBuffer {
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+    pub samples: Samples,
+}
+
+impl PixelBuffer {
+    pub fn render(map: &Map, format: PixelFormat, flat_level: u8) -> PixelBuffer {
+        let size = map.enabled.len();
+        let channels = format.channels();
+        let height_diff = map.header.max_height - map.header.min_height;
+
+        // A flat map has nothing to scale, so the range would divide by zero.
+        let flat = !(height_diff > 0f32 && height_diff.is_finite());
+        if flat && matches!(format, PixelFormat::Gray8 | PixelFormat::Gray16 | PixelFormat::Gray8Alpha) {
+            eprintln!("Warning: the height range is empty ({}..{}), rendering a flat map at level {}",
+                      map.header.min_height, map.header.max_height, flat_level);
+        }
+
+        let heights = map.normalized_heights(map.height_range());
+        let normalized = |i: usize| if flat { f32::from(flat_level) / 255f32 } else { heights[i] };
+
+        let mut samples = match format {
+            PixelFormat::Gray16 => Samples::U16(vec![0u16; size]),
+            PixelFormat::F32 => Samples::F32(vec![f32::NAN; size]),
+            _ => Samples::U8(vec![0u8; size * channels]),
+        };
+
+        for (index, offset) in map.enabled_tiles() {
+            let (x, y) = crate::get_position(&index, &map.header.w, &map.header.h);
+            let point = &map.points[offset];
+            let i = (y * map.header.w + x) as usize;
+
+            match &mut samples {
+                Samples::U8(data) => match format {
+                    PixelFormat::Rgb8 => data[i * 3..i * 3 + 3].copy_from_slice(&[point.r, point.g, point.b]),
+                    PixelFormat::Rgba8 => data[i * 4..i * 4 + 4].copy_from_slice(&[point.r, point.g, point.b, 255]),
+                    PixelFormat::Gray8Alpha => {
+                        data[i * 2..i * 2 + 2].copy_from_slice(&[(255f32 * normalized(i)) as u8, 255]);
+                    }
+                    _ => data[i] = (255f32 * normalized(i)) as u8,
+                },
+                Samples::U16(data) => data[i] = (65535f32 * normalized(i)) as u16,
+                Samples::F32(data) => data[i] = point.h,
+            }
+        }
+
+        PixelBuffer { width: map.header.w, height: map.header.h, format, samples }
+    }
+
+    /// Grows the image by the given margins, filled with `background`.
+    pub fn pad(&mut self, left: u32, top: u32, right: u32, bottom: u32, background: [u8; 3]) {
+        let width = self.width + left + right;
+        let height = self.height + top + bottom;
+        let size = (width * height) as usize;
+        let channels = self.format.channels();
+        let old_width = self.width as usize;
+        let offset = |x: usize, y: usize| (y + top as usize) * width as usize + x + left as usize;
+
+        let samples = match &self.samples {
+            Samples::U8(data) => {
+                let mut padded = opaque_pixel(self.format, background).repeat(size);
+                for (i, pixel) in data.chunks(channels).enumerate() {
+                    let j = offset(i % old_width, i / old_width);
+                    padded[j * channels..(j + 1) * channels].copy_from_slice(pixel);
+                }
+                Samples::U8(padded)
+            }
+            Samples::U16(data) => {
+                let mut padded = vec![(luma(background) * 257f32).round() as u16; size];
+                for (i, &sample) in data.iter().enumerate() {
+                    padded[offset(i % old_width, i / old_width)] = sample;
+                }
+                Samples::U16(padded)
+            }
+            Samples::F32(data) => {
+                let mut padded = vec![f32::NAN; size];
+                for (i, &sample) in data.iter().enumerate() {
+                    padded[offset(i % old_width, i / old_width)] = sample;
+                }
+                Samples::F32(padded)
+            }
+        };
+
+        self.width = width;
+        self.height = height;
+        self.samples = samples;
+    }
+
+    /// Overwrites a pixel with an annotation color, ignoring positions outside
+    /// the image. Gray formats get the color's luma.
+    pub fn paint(&mut self, x: i64, y: i64, color: [u8; 3]) {
+        if x < 0 || y < 0 || x >= i64::from(self.width) || y >= i64::from(self.height) {
+            return;
+        }
+
+        let i = (y * i64::from(self.width) + x) as usize;
+        let channels = self.format.channels();
+
+        match &mut self.samples {
+            Samples::U8(data) => data[i * channels..(i + 1) * channels]
+                .copy_from_slice(&opaque_pixel(self.format, color)),
+            Samples::U16(data) => data[i] = (luma(color) * 257f32).round() as u16,
+            Samples::F32(_) => {}
+        }
+    }
+
+    /// Mixes an annotation color into a pixel by `coverage`, from 0 leaving
+    /// it alone to 1 overwriting it like `paint`.
+    pub fn blend(&mut self, x: i64, y: i64, color: [u8; 3], coverage: f32) {
+        if coverage >= 1f32 {
+            return self.paint(x, y, color);
+        }
+        if x < 0 || y < 0 || x >= i64::from(self.width) || y >= i64::from(self.height) {
+            return;
+        }
+
+        let i = (y * i64::from(self.width) + x) as usize;
+        let channels = self.format.channels();
+        let mix = |below: f32, above: f32| below + (above - below) * coverage;
+
+        match &mut self.samples {
+            Samples::U8(data) => {
+                let pixel = &mut data[i * channels..(i + 1) * channels];
+                for (sample, above) in pixel.iter_mut().zip(opaque_pixel(self.format, color)) {
+                    *sample = mix(f32::from(*sample), f32::from(above)).round() as u8;
+                }
+            }
+            Samples::U16(data) => data[i] = mix(f32::from(data[i]), luma(color) * 257f32).round() as u16,
+            Samples::F32(_) => {}
+        }
+    }
+}
+
+fn luma([r, g, b]: [u8; 3]) -> f32 {
+    0.299 * f32::from(r) + 0.587 * f32::from(g) + 0.114 * f32::from(b)
+}
+
+/// The 8-bit samples of an opaque color in the given pixel format.
+fn opaque_pixel(format: PixelFormat, [r, g, b]: [u8; 3]) -> Vec<u8> {
+    match format {
+        PixelFormat::Rgb8 => vec![r, g, b],
+        PixelFormat::Rgba8 => vec![r, g, b, 255],
+        PixelFormat::Gray8Alpha => vec![luma([r, g, b]).round() as u8, 255],
+        _ => vec![luma([r, g, b]).round() as u8],
+    }
+}
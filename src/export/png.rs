@@ -0,0 +1,65 @@
+use std::io;
+use std::io::prelude::*;
+
+use super::ColorSpace;
+use super::Context;
+use super::Format;
+use super::pixels::PixelBuffer;
+use super::pixels::PixelFormat;
+use super::pixels::Samples;
+
+/// Writes the pixels as a PNG, with each header field of the context
+/// stored as a `heightmap:<field>` text chunk.
+pub fn write(pixels: &PixelBuffer, context: &Context, w: impl Write) -> io::Result<()> {
+    let icc_profile = context.icc_profile;
+
+    let (color, depth) = match pixels.format {
+        PixelFormat::Gray8 => (png::ColorType::Grayscale, png::BitDepth::Eight),
+        PixelFormat::Gray16 => (png::ColorType::Grayscale, png::BitDepth::Sixteen),
+        PixelFormat::Rgb8 => (png::ColorType::Rgb, png::BitDepth::Eight),
+        PixelFormat::Rgba8 => (png::ColorType::Rgba, png::BitDepth::Eight),
+        PixelFormat::Gray8Alpha => (png::ColorType::GrayscaleAlpha, png::BitDepth::Eight),
+        PixelFormat::F32 => return Err(super::unsupported(Format::Png, pixels.format)),
+    };
+
+    // PNG stores 16-bit samples big-endian.
+    let data = match &pixels.samples {
+        Samples::U8(data) => data.clone(),
+        Samples::U16(data) => data.iter().flat_map(|s| s.to_be_bytes()).collect(),
+        Samples::F32(_) => return Err(super::unsupported(Format::Png, pixels.format)),
+    };
+
+    let mut info = png::Info::with_size(pixels.width, pixels.height);
+    info.icc_profile = icc_profile.map(|profile| profile.to_vec().into());
+
+    let mut encoder = png::Encoder::with_info(w, info).map_err(io::Error::other)?;
+    encoder.set_color(color);
+    encoder.set_depth(depth);
+
+    match context.options.color_space() {
+        // A gAMA of 1.0 tells readers not to apply any transfer curve.
+        ColorSpace::Linear => encoder.set_source_gamma(png::ScaledFloat::new(1.0)),
+        // An sRGB chunk would make readers ignore the embedded profile.
+        ColorSpace::Srgb if icc_profile.is_some() => {}
+        ColorSpace::Srgb => {
+            encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+            // The fallback gAMA for readers that don't understand sRGB.
+            encoder.set_source_gamma(png::ScaledFloat::from_scaled(45455));
+        }
+    }
+
+    for (field, value) in &context.metadata {
+        let keyword = format!("heightmap:{}", field);
+
+        // tEXt is Latin-1 only, the map name may need UTF-8.
+        if value.is_ascii() {
+            encoder.add_text_chunk(keyword, value.clone()).map_err(io::Error::other)?;
+        } else {
+            encoder.add_itxt_chunk(keyword, value.clone()).map_err(io::Error::other)?;
+        }
+    }
+
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer.write_image_data(&data).map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)
+}
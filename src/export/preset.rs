@@ -0,0 +1,169 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+
+use crate::Map;
+
+use super::Context;
+use super::ExportOptions;
+use super::PlannedOutput;
+use super::escape;
+use super::pixels::PixelBuffer;
+use super::pixels::PixelFormat;
+use super::pixels::Samples;
+
+/// Bundled output settings matching what a game's editor imports: the
+/// resolution, bit depth, value range and file names.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Preset {
+    /// GIANTS Editor, for the Farming Simulator maps this format comes from.
+    FarmingSimulator,
+    /// CryEngine and Lumberyard Sandbox.
+    #[value(name = "cryengine")]
+    CryEngine,
+}
+
+impl Preset {
+    /// The side length of the square heightmap the editor wants for a map
+    /// of `w` x `h` tiles.
+    pub fn resolution(&self, w: u32, h: u32) -> u32 {
+        let longest = w.max(h);
+
+        match self {
+            // Terrains are 2^n cells, so one more sample than that per side.
+            Preset::FarmingSimulator => (longest.saturating_sub(1).next_power_of_two() + 1).clamp(257, 8193),
+            // Sandbox only accepts these resolutions.
+            Preset::CryEngine => longest.next_power_of_two().clamp(128, 8192),
+        }
+    }
+
+    /// What the preset writes, for `presets`.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Preset::FarmingSimulator => concat!(
+                "<name>/map_dem.png: square 2^n+1 16-bit grayscale PNG over the full height range\n",
+                "<name>/terrain.xml: TerrainTransformGroup with the matching heightScale and unitsPerPixel"),
+            Preset::CryEngine => concat!(
+                "<name>.raw: square power-of-two 16-bit little-endian RAW over the full height range\n",
+                "<name>.xml: resolution, unit size and height range for the import dialog"),
+        }
+    }
+}
+
+/// Writes the files of `preset` to the output directory, in place of
+/// `--format`.
+pub fn export(map: &Map, file_stem: &str, preset: Preset, options: &ExportOptions) -> io::Result<Vec<PathBuf>> {
+    match preset {
+        Preset::FarmingSimulator => farming_simulator(map, file_stem, options),
+        Preset::CryEngine => cryengine(map, file_stem, options),
+    }
+}
+
+/// The files `export` writes for a `w` x `h` map.
+pub fn plan(w: u32, h: u32, file_stem: &str, preset: Preset, options: &ExportOptions) -> Vec<PlannedOutput> {
+    let size = preset.resolution(w, h);
+    let planned = |path: String, description: String| PlannedOutput { path: options.output_path(&path), description };
+
+    match preset {
+        Preset::FarmingSimulator => vec![
+            planned(format!("{}/map_dem.png", file_stem), format!("png gray16, {}x{} pixels", size, size)),
+            planned(format!("{}/terrain.xml", file_stem), String::from("terrain settings")),
+        ],
+        Preset::CryEngine => vec![
+            planned(format!("{}.raw", file_stem), format!("16-bit RAW, {}x{} samples", size, size)),
+            planned(format!("{}.xml", file_stem), String::from("import settings")),
+        ],
+    }
+}
+
+fn farming_simulator(map: &Map, file_stem: &str, options: &ExportOptions) -> io::Result<Vec<PathBuf>> {
+    let size = Preset::FarmingSimulator.resolution(map.header.w, map.header.h);
+    let samples = resample_square(map, size);
+    let dir = options.output_path(file_stem);
+    fs::create_dir_all(&dir)?;
+
+    let pixels = PixelBuffer {
+        width: size,
+        height: size,
+        format: PixelFormat::Gray16,
+        samples: Samples::U16(samples.iter().map(|&sample| (sample * 65535f32).round() as u16).collect()),
+    };
+    let png_options = ExportOptions { pixel_format: PixelFormat::Gray16, ..options.clone() };
+    let context = Context { options: &png_options, icc_profile: None, metadata: Vec::new() };
+    let dem_path = dir.join("map_dem.png");
+    super::png::write(&pixels, &context, BufWriter::new(File::create(&dem_path)?))?;
+    println!("Wrote {}", dem_path.display());
+
+    // The DEM spans 2^n cells, between the first and last sample.
+    let scale = f64::from(map.header.w.max(map.header.h)) / f64::from(size);
+    let units_per_pixel = options.georef.cell_size() * scale * f64::from(size) / f64::from(size - 1);
+    let xml_path = dir.join("terrain.xml");
+    let xml = format!(concat!(
+        "<!-- Terrain settings for {}, merge into the map's i3d. -->\n",
+        "<TerrainTransformGroup name=\"terrain\" translation=\"0 {} 0\" heightScale=\"{}\" unitsPerPixel=\"{}\" />\n"),
+        escape(&map.header.name), map.header.min_height, map.header.max_height - map.header.min_height, units_per_pixel);
+    fs::write(&xml_path, xml)?;
+    println!("Wrote {}", xml_path.display());
+
+    Ok(vec![dem_path, xml_path])
+}
+
+fn cryengine(map: &Map, file_stem: &str, options: &ExportOptions) -> io::Result<Vec<PathBuf>> {
+    let size = Preset::CryEngine.resolution(map.header.w, map.header.h);
+    let samples = resample_square(map, size);
+
+    let raw_path = options.output_path(&format!("{}.raw", file_stem));
+    let raw: Vec<u8> = samples.iter()
+        .flat_map(|&sample| ((sample * 65535f32).round() as u16).to_le_bytes())
+        .collect();
+    fs::write(&raw_path, raw)?;
+    println!("Wrote {}", raw_path.display());
+
+    let scale = f64::from(map.header.w.max(map.header.h)) / f64::from(size);
+    let unit_size = options.georef.cell_size() * scale;
+    let xml_path = options.output_path(&format!("{}.xml", file_stem));
+    let xml = format!(concat!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+        "<Heightmap Name=\"{}\" File=\"{}.raw\" Format=\"R16\" ByteOrder=\"LittleEndian\"\n",
+        "           Resolution=\"{}\" UnitSize=\"{}\" MinHeight=\"{}\" MaxHeight=\"{}\" />\n"),
+        escape(&map.header.name), escape(file_stem), size, unit_size, map.header.min_height, map.header.max_height);
+    fs::write(&xml_path, xml)?;
+    println!("Wrote {}", xml_path.display());
+
+    Ok(vec![raw_path, xml_path])
+}
+
+/// Scales the heights, normalized over the header range, so the longer side
+/// spans `size` samples, bilinearly and in image order. The rest of the
+/// square and disabled tiles are left at the bottom of the range.
+fn resample_square(map: &Map, size: u32) -> Vec<f32> {
+    let w = map.header.w;
+    let h = map.header.h;
+    let heights: Vec<f32> = map.normalized_heights(map.height_range()).into_iter()
+        .map(|normalized| if normalized.is_nan() { 0f32 } else { normalized.clamp(0f32, 1f32) })
+        .collect();
+
+    let scale = f64::from(w.max(h)) / f64::from(size);
+    let (new_w, new_h) = ((f64::from(w) / scale).round() as u32, (f64::from(h) / scale).round() as u32);
+    let at = |x: i64, y: i64| heights[(y.clamp(0, i64::from(h) - 1) * i64::from(w) + x.clamp(0, i64::from(w) - 1)) as usize];
+    let mut samples = vec![0f32; (size * size) as usize];
+
+    for y in 0..new_h.min(size) {
+        for x in 0..new_w.min(size) {
+            let u = (f64::from(x) + 0.5) * scale - 0.5;
+            let v = (f64::from(y) + 0.5) * scale - 0.5;
+            let (x0, y0) = (u.floor() as i64, v.floor() as i64);
+            let (fx, fy) = ((u - u.floor()) as f32, (v - v.floor()) as f32);
+
+            let top = at(x0, y0) + (at(x0 + 1, y0) - at(x0, y0)) * fx;
+            let bottom = at(x0, y0 + 1) + (at(x0 + 1, y0 + 1) - at(x0, y0 + 1)) * fx;
+            samples[(y * size + x) as usize] = top + (bottom - top) * fy;
+        }
+    }
+
+    samples
+}
@@ -0,0 +1,84 @@
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::prelude::*;
+use std::path::Path;
+
+use clap::Args;
+use clap::ValueEnum;
+
+use crate::Map;
+
+use super::Context;
+use super::pixels::PixelBuffer;
+use super::pixels::PixelFormat;
+use super::pixels::Samples;
+
+/// Which image row a raw file starts with.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum RowOrder {
+    /// The top row first, as the Unreal landscape importer reads it.
+    TopDown,
+    /// The bottom row first, the order rows are stored in the map.
+    BottomUp,
+}
+
+/// Whether a raw file stores the samples row by row or column by column.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Layout {
+    /// One row after another, as most importers expect.
+    RowMajor,
+    /// One column after another, each in the row order.
+    ColumnMajor,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct R16Options {
+    /// The order the rows of raw `.r16` files are written in.
+    #[arg(long, alias = "r16-row-order", value_enum, default_value = "top-down")]
+    pub row_order: RowOrder,
+
+    /// Whether raw `.r16` files hold rows or columns one after another.
+    #[arg(long, value_enum, default_value = "row-major")]
+    pub layout: Layout,
+}
+
+impl Default for R16Options {
+    fn default() -> R16Options {
+        R16Options { row_order: RowOrder::TopDown, layout: Layout::RowMajor }
+    }
+}
+
+/// Writes the heights as an Unreal Engine RAW16 file: no header, one
+/// little-endian 16-bit sample per tile over the header height range, and
+/// disabled tiles at 0.
+pub fn write(map: &Map, context: &Context, path: &Path) -> io::Result<()> {
+    let pixels = PixelBuffer::render(map, PixelFormat::Gray16, context.options.flat_level);
+    let samples = match &pixels.samples {
+        Samples::U16(samples) => samples,
+        _ => unreachable!("Gray16 is rendered into 16-bit samples"),
+    };
+    let options = &context.options.r16;
+
+    let mut rows: Vec<&[u16]> = samples.chunks(pixels.width as usize).collect();
+    if options.row_order == RowOrder::BottomUp {
+        rows.reverse();
+    }
+
+    let mut w = BufWriter::new(File::create(path)?);
+    match options.layout {
+        Layout::RowMajor => {
+            for sample in rows.into_iter().flatten() {
+                w.write_all(&sample.to_le_bytes())?;
+            }
+        }
+        Layout::ColumnMajor => {
+            for x in 0..pixels.width as usize {
+                for row in &rows {
+                    w.write_all(&row[x].to_le_bytes())?;
+                }
+            }
+        }
+    }
+    w.flush()
+}
@@ -0,0 +1,127 @@
+use clap::Args;
+use clap::ValueEnum;
+
+use crate::Map;
+
+use super::gradient;
+use super::pixels::PixelBuffer;
+use super::pixels::PixelFormat;
+use super::pixels::Samples;
+
+/// What the raster formats show of the heights.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Render {
+    /// The heights themselves, scaled over the height range.
+    Heights,
+    /// The terrain lit by a distant light, colors shaded where they are shown.
+    Hillshade,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ReliefOptions {
+    /// What the raster formats show of the heights.
+    #[arg(long, value_enum, default_value = "heights")]
+    pub render: Render,
+
+    /// Where the hillshade light comes from, in degrees clockwise from north.
+    #[arg(long, value_name = "DEGREES", default_value_t = 315f32)]
+    pub light_azimuth: f32,
+
+    /// How high the hillshade light stands, in degrees above the horizon.
+    #[arg(long, value_name = "DEGREES", default_value_t = 45f32, value_parser = parse_altitude)]
+    pub light_altitude: f32,
+}
+
+impl Default for ReliefOptions {
+    fn default() -> ReliefOptions {
+        ReliefOptions { render: Render::Heights, light_azimuth: 315f32, light_altitude: 45f32 }
+    }
+}
+
+impl ReliefOptions {
+    /// Replaces the heights of rendered pixels with the hillshade when it is
+    /// asked for, from dark to bright in gray and as 0..1 for f32. Colors
+    /// are darkened by the shade instead, and disabled tiles are left alone.
+    pub fn apply(&self, map: &Map, spacing: f32, pixels: &mut PixelBuffer) {
+        if self.render != Render::Hillshade {
+            return;
+        }
+
+        let shade = hillshade(map, spacing, self.light_azimuth, self.light_altitude);
+        let channels = pixels.format.channels();
+        // Upscaled colors cover a tile with several pixels.
+        let scale = (pixels.width / map.header.w.max(1)).max(1);
+        let width = pixels.width;
+        let shade_at = |i: usize| shade[((i as u32 / width / scale) * map.header.w + i as u32 % width / scale) as usize];
+
+        match &mut pixels.samples {
+            // The shade replaces the gray of the heights, like in gray8.
+            Samples::U8(data) if pixels.format == PixelFormat::Gray8Alpha => {
+                for (i, pixel) in data.chunks_mut(channels).enumerate() {
+                    if let Some(shade) = shade_at(i) {
+                        pixel[0] = (255f32 * shade).round() as u8;
+                    }
+                }
+            }
+            Samples::U8(data) if channels >= 3 => {
+                for (i, pixel) in data.chunks_mut(channels).enumerate() {
+                    if let Some(shade) = shade_at(i) {
+                        for c in &mut pixel[..3] {
+                            *c = (f32::from(*c) * shade).round() as u8;
+                        }
+                    }
+                }
+            }
+            Samples::U8(data) => for (i, sample) in data.iter_mut().enumerate() {
+                if let Some(shade) = shade_at(i) {
+                    *sample = (255f32 * shade).round() as u8;
+                }
+            },
+            Samples::U16(data) => for (i, sample) in data.iter_mut().enumerate() {
+                if let Some(shade) = shade_at(i) {
+                    *sample = (65535f32 * shade).round() as u16;
+                }
+            },
+            Samples::F32(data) => for (i, sample) in data.iter_mut().enumerate() {
+                if let Some(shade) = shade_at(i) {
+                    *sample = shade;
+                }
+            },
+        }
+    }
+}
+
+/// Lambertian shading of the terrain lit from `azimuth` degrees clockwise
+/// from north and `altitude` degrees above the horizon, in image order from
+/// the top-left, for tiles `spacing` height units apart as `tile_spacing`
+/// gives them. Disabled tiles are `None`.
+pub fn hillshade(map: &Map, spacing: f32, azimuth: f32, altitude: f32) -> Vec<Option<f32>> {
+    let w = map.header.w as usize;
+    let h = map.header.h as usize;
+    let heights = map.tile_heights();
+
+    let zenith = (90f32 - altitude).to_radians();
+    // Image y grows southwards, so the light vector's y is flipped.
+    let azimuth = azimuth.to_radians();
+    let light = (zenith.sin() * azimuth.sin(), -zenith.sin() * azimuth.cos(), zenith.cos());
+
+    (0..w * h)
+        .map(|i| {
+            let (dx, dy) = gradient(&heights, w, h, i % w, i / w, spacing)?;
+
+            // The surface normal is (-dx, -dy, 1).
+            let length = (dx * dx + dy * dy + 1f32).sqrt();
+            let shade = (-dx * light.0 - dy * light.1 + light.2) / length;
+
+            Some(shade.max(0f32))
+        })
+        .collect()
+}
+
+fn parse_altitude(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(altitude) if (0f32..=90f32).contains(&altitude) => Ok(altitude),
+        Ok(_) => Err(format!("Invalid altitude '{}', it has to be within 0..90 degrees", s)),
+        Err(e) => Err(format!("Invalid altitude '{}': {}", s, e)),
+    }
+}
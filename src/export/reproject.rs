@@ -0,0 +1,111 @@
+use std::io;
+
+use proj4rs::Proj;
+use proj4rs::transform::transform;
+
+use crate::Map;
+
+use super::Georef;
+
+impl Georef {
+    /// Warps the map from `--crs` into `target`, returning the resampled map
+    /// and its placement in the target CRS.
+    ///
+    /// The target grid has square cells covering the transformed outline of
+    /// the map with about as many tiles as the source; every target tile
+    /// takes the nearest source tile, and tiles that map outside of the
+    /// source are disabled.
+    pub fn reproject(&self, map: &Map, target: &str) -> io::Result<(Map, Georef)> {
+        let source_crs = self.crs.as_deref().unwrap_or_default();
+        let from = open(source_crs)?;
+        let to = open(target)?;
+
+        let w = map.header.w;
+        let h = map.header.h;
+        let (ox, oy) = self.origin();
+        let cell_size = self.cell_size();
+
+        // Trace the outline, since projected edges are rarely straight.
+        let mut outline = Vec::new();
+        for i in 0..=w {
+            outline.push((f64::from(i), 0f64));
+            outline.push((f64::from(i), f64::from(h)));
+        }
+        for i in 0..=h {
+            outline.push((0f64, f64::from(i)));
+            outline.push((f64::from(w), f64::from(i)));
+        }
+
+        let mut min = (f64::INFINITY, f64::INFINITY);
+        let mut max = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for (x, y) in outline {
+            let (tx, ty) = convert(&from, &to, (ox + x * cell_size, oy - y * cell_size))?;
+            min = (min.0.min(tx), min.1.min(ty));
+            max = (max.0.max(tx), max.1.max(ty));
+        }
+
+        let area = (max.0 - min.0) * (max.1 - min.1);
+        let target_cell = (area / (f64::from(w) * f64::from(h))).sqrt();
+        if !(target_cell > 0f64 && target_cell.is_finite()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "The map has no extent in the target CRS"));
+        }
+
+        let new_w = (((max.0 - min.0) / target_cell).ceil() as u32).max(1);
+        let new_h = (((max.1 - min.1) / target_cell).ceil() as u32).max(1);
+        let source = map.tiles();
+        let mut tiles = vec![None; (new_w * new_h) as usize];
+
+        for y in 0..new_h {
+            for x in 0..new_w {
+                let world = (min.0 + (f64::from(x) + 0.5) * target_cell, max.1 - (f64::from(y) + 0.5) * target_cell);
+                let (sx, sy) = match convert(&to, &from, world) {
+                    Ok(position) => position,
+                    Err(_) => continue,
+                };
+
+                let column = ((sx - ox) / cell_size).floor();
+                let row = ((oy - sy) / cell_size).floor();
+
+                if column >= 0f64 && row >= 0f64 && column < f64::from(w) && row < f64::from(h) {
+                    let index = (h - 1 - row as u32) * w + column as u32;
+                    tiles[((new_h - 1 - y) * new_w + x) as usize] = source[index as usize];
+                }
+            }
+        }
+
+        let mut header = map.header.clone();
+        header.w = new_w;
+        header.h = new_h;
+
+        let mut reprojected = Map { header, points: Vec::new(), enabled: Vec::new(), runs: Vec::new() };
+        reprojected.set_tiles(tiles);
+
+        let georef = Georef {
+            origin: Some((min.0, max.1)),
+            cell_size: Some(target_cell),
+            crs: Some(String::from(target)),
+            target_crs: None,
+        };
+
+        Ok((reprojected, georef))
+    }
+}
+
+fn open(crs: &str) -> io::Result<Proj> {
+    Proj::from_user_string(crs)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("Unknown CRS '{}': {}", crs, e)))
+}
+
+/// Transforms a point, in degrees for geographic systems.
+fn convert(from: &Proj, to: &Proj, (x, y): (f64, f64)) -> io::Result<(f64, f64)> {
+    let mut point = if from.is_latlong() { (x.to_radians(), y.to_radians(), 0f64) } else { (x, y, 0f64) };
+
+    transform(from, to, &mut point)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Reprojection failed: {}", e)))?;
+
+    if to.is_latlong() {
+        Ok((point.0.to_degrees(), point.1.to_degrees()))
+    } else {
+        Ok((point.0, point.1))
+    }
+}
@@ -0,0 +1,239 @@
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::prelude::*;
+use std::path::Path;
+
+use clap::Args;
+
+use crate::Map;
+use crate::contours::Contour;
+
+use super::Context;
+use super::ExportOptions;
+use super::escape;
+use super::overlay::nice_step;
+use super::overlay::parse_color;
+use super::pixels::PixelBuffer;
+use super::pixels::PixelFormat;
+use super::pixels::Samples;
+use super::png;
+use super::relief;
+use super::tile_spacing;
+
+/// Index contours shorter than this many tiles go unlabeled, there is no
+/// room along them.
+const MIN_LABELED_LENGTH: f32 = 16f32;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[derive(Args, Debug, Clone)]
+pub struct SvgOptions {
+    /// The height between SVG contour lines, picked for about eight lines
+    /// over the height range by default.
+    #[arg(long, value_name = "HEIGHT")]
+    pub svg_interval: Option<f32>,
+
+    /// Draw every Nth SVG contour bolder and label it with its height.
+    #[arg(long, value_name = "N", default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+    pub svg_index_every: u32,
+
+    /// How many SVG pixels a tile covers.
+    #[arg(long, value_name = "PIXELS", default_value_t = 4f32)]
+    pub svg_scale: f32,
+
+    /// The color of SVG contour lines and their labels, as hex `rrggbb`.
+    #[arg(long, value_name = "COLOR", value_parser = parse_color, default_value = "8b5a2b")]
+    pub svg_contour_color: [u8; 3],
+
+    /// The color of the SVG coastline at `--water-level`, as hex `rrggbb`.
+    #[arg(long, value_name = "COLOR", value_parser = parse_color, default_value = "1f5fa8")]
+    pub svg_coast_color: [u8; 3],
+
+    /// Leave out the shaded relief under the SVG lines.
+    #[arg(long)]
+    pub svg_no_relief: bool,
+}
+
+impl Default for SvgOptions {
+    fn default() -> SvgOptions {
+        SvgOptions {
+            svg_interval: None,
+            svg_index_every: 5,
+            svg_scale: 4f32,
+            svg_contour_color: [0x8b, 0x5a, 0x2b],
+            svg_coast_color: [0x1f, 0x5f, 0xa8],
+            svg_no_relief: false,
+        }
+    }
+}
+
+impl SvgOptions {
+    /// The height between contour lines over `min_height..max_height`.
+    pub fn interval(&self, min_height: f32, max_height: f32) -> f32 {
+        self.svg_interval
+            .filter(|&interval| interval > 0f32)
+            .unwrap_or_else(|| nice_step(f64::from(max_height - min_height)) as f32)
+    }
+}
+
+/// Writes a vector map: contour lines with their heights on every index
+/// line, the coastline at `--water-level` when one is given and the map
+/// name, over the hillshade lit like `--render hillshade`, embedded as a PNG.
+pub fn write(map: &Map, context: &Context, path: &Path) -> io::Result<()> {
+    let options = context.options;
+    let svg = &options.svg;
+    let scale = svg.svg_scale.max(f32::MIN_POSITIVE);
+    let (width, height) = (map.header.w as f32 * scale, map.header.h as f32 * scale);
+    let font_size = options.overlay.font_size.unwrap_or(10) as f32;
+    let font_family = options.overlay.font.as_deref()
+        .and_then(Path::file_stem)
+        .map_or_else(|| String::from("sans-serif"), |stem| format!("{}, sans-serif", stem.to_string_lossy()));
+    let label_color = options.overlay.label_color.unwrap_or(svg.svg_contour_color);
+
+    let mut w = BufWriter::new(File::create(path)?);
+
+    writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(w, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="0 0 {} {}">"#,
+             width, height, width, height)?;
+    writeln!(w, "  <title>{}</title>", escape(&map.header.name))?;
+
+    if !svg.svg_no_relief {
+        writeln!(w, r#"  <image x="0" y="0" width="{}" height="{}" preserveAspectRatio="none" style="image-rendering: pixelated" href="data:image/png;base64,{}"/>"#,
+                 width, height, base64(&relief_png(map, options)?))?;
+    }
+
+    let (min, max) = (map.header.min_height, map.header.max_height);
+    let interval = svg.interval(min, max);
+    let first = (min / interval).ceil() as i64;
+    let last = (max / interval).floor() as i64;
+    let mut labels = Vec::new();
+
+    writeln!(w, r#"  <g id="contours" fill="none" stroke="{}" stroke-linejoin="round">"#, hex(svg.svg_contour_color))?;
+    for step in first..=last {
+        let level = step as f32 * interval;
+        let index = step.rem_euclid(i64::from(svg.svg_index_every)) == 0;
+        let stroke = if index { 1.2 } else { 0.5 };
+
+        for contour in map.contours(level) {
+            writeln!(w, r#"    <path stroke-width="{}" d="{}"/>"#, stroke, path_data(&contour, scale))?;
+            if index {
+                labels.extend(label_position(&contour, scale).map(|position| (position, level)));
+            }
+        }
+    }
+    writeln!(w, "  </g>")?;
+
+    if let Some(level) = options.water_level {
+        writeln!(w, r#"  <g id="coastline" fill="none" stroke="{}" stroke-width="1.5" stroke-linejoin="round">"#,
+                 hex(svg.svg_coast_color))?;
+        for contour in map.contours(level) {
+            writeln!(w, r#"    <path d="{}"/>"#, path_data(&contour, scale))?;
+        }
+        writeln!(w, "  </g>")?;
+    }
+
+    // A white halo keeps labels readable over the lines and the relief.
+    writeln!(w, r#"  <g id="labels" font-family="{}" fill="{}" stroke="white" stroke-width="2" paint-order="stroke" text-anchor="middle">"#,
+             escape(&font_family), hex(label_color))?;
+    for ((x, y, angle), level) in labels {
+        writeln!(w, r#"    <text font-size="{}" dominant-baseline="central" transform="translate({:.2} {:.2}) rotate({:.1})">{}</text>"#,
+                 font_size * 0.8, x, y, angle, level)?;
+    }
+    writeln!(w, r#"    <text x="{}" y="{}" font-size="{}" text-anchor="start">{}</text>"#,
+             font_size * 0.5, font_size * 1.5, font_size * 1.4, escape(&map.header.name))?;
+    writeln!(w, "  </g>")?;
+
+    writeln!(w, "</svg>")?;
+    w.flush()
+}
+
+/// The hillshade at one pixel per tile, transparent where tiles are disabled.
+fn relief_png(map: &Map, options: &ExportOptions) -> io::Result<Vec<u8>> {
+    let shade = relief::hillshade(map, tile_spacing(map, options), options.relief.light_azimuth, options.relief.light_altitude);
+    let samples = shade.iter()
+        .flat_map(|shade| match shade {
+            Some(shade) => {
+                let level = (shade * 255f32).round() as u8;
+                [level, level, level, 255]
+            }
+            None => [0; 4],
+        })
+        .collect();
+    let pixels = PixelBuffer { width: map.header.w, height: map.header.h, format: PixelFormat::Rgba8, samples: Samples::U8(samples) };
+
+    let png_options = ExportOptions { pixel_format: PixelFormat::Rgba8, color_space: None, ..options.clone() };
+    let context = Context { options: &png_options, icc_profile: None, metadata: Vec::new() };
+
+    let mut data = Vec::new();
+    png::write(&pixels, &context, &mut data)?;
+    Ok(data)
+}
+
+/// The contour as SVG path data in pixels.
+fn path_data(contour: &Contour, scale: f32) -> String {
+    let mut data = String::new();
+
+    for (i, &(x, y)) in contour.points.iter().enumerate() {
+        data += &format!("{}{:.2},{:.2}", if i == 0 { "M" } else { " L" }, x * scale, y * scale);
+    }
+    if contour.closed {
+        data += " Z";
+    }
+
+    data
+}
+
+/// Where the label of a contour goes, halfway along it, and the angle in
+/// degrees it is turned to follow the line, kept upright. `None` for lines
+/// too short to label.
+fn label_position(contour: &Contour, scale: f32) -> Option<(f32, f32, f32)> {
+    let lengths: Vec<f32> = contour.points.windows(2)
+        .map(|pair| ((pair[1].0 - pair[0].0).powi(2) + (pair[1].1 - pair[0].1).powi(2)).sqrt())
+        .collect();
+    let total: f32 = lengths.iter().sum();
+    if total < MIN_LABELED_LENGTH {
+        return None;
+    }
+
+    let mut along = total / 2f32;
+    for (pair, length) in contour.points.windows(2).zip(lengths) {
+        if along <= length && length > 0f32 {
+            let t = along / length;
+            let (x, y) = (pair[0].0 + (pair[1].0 - pair[0].0) * t, pair[0].1 + (pair[1].1 - pair[0].1) * t);
+            let mut angle = (pair[1].1 - pair[0].1).atan2(pair[1].0 - pair[0].0).to_degrees();
+            if angle > 90f32 {
+                angle -= 180f32;
+            } else if angle < -90f32 {
+                angle += 180f32;
+            }
+            return Some((x * scale, y * scale, angle));
+        }
+        along -= length;
+    }
+
+    None
+}
+
+fn hex([r, g, b]: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn base64(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], chunk.get(1).copied().unwrap_or(0), chunk.get(2).copied().unwrap_or(0)];
+        let bits = (u32::from(bytes[0]) << 16) | (u32::from(bytes[1]) << 8) | u32::from(bytes[2]);
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(char::from(BASE64[(bits >> (18 - 6 * i) & 0x3f) as usize]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
@@ -0,0 +1,104 @@
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+
+use crate::MapHeader;
+
+use super::ExportOptions;
+use super::Format;
+
+/// Stands for the extension of each format, only at the end of a template.
+const EXTENSION: &str = ".{ext}";
+
+impl ExportOptions {
+    /// The options and file stem `--output` comes to for a map with
+    /// `header`, decoded from a file named `file_stem`. Without `--output`
+    /// they stay as they are.
+    ///
+    /// The template's directory replaces `--output-dir` and its file name
+    /// becomes the stem. A template ending in `.{ext}` keeps `--format`,
+    /// while a plain extension picks the format it belongs to.
+    pub fn resolve_output(&self, header: &MapHeader, file_stem: &str) -> io::Result<(ExportOptions, String)> {
+        let template = match &self.output {
+            Some(template) => template,
+            None => return Ok((self.clone(), file_stem.to_string())),
+        };
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+
+        let (template, any_format) = match template.strip_suffix(EXTENSION) {
+            Some(template) => (template, true),
+            None => (template.as_str(), false),
+        };
+        let path = PathBuf::from(expand(template, header, &self.output_dir, file_stem).map_err(invalid)?);
+        let mut formats = self.formats.clone();
+
+        if !any_format {
+            if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+                let format = Format::value_variants().iter()
+                    .find(|format| format.extension().eq_ignore_ascii_case(extension))
+                    .ok_or_else(|| invalid(format!("--output ends in '.{}', which is no output format", extension)))?;
+                formats = vec![*format];
+            }
+        }
+
+        let stem = if any_format { path.file_name() } else { path.file_stem() }
+            .and_then(|stem| stem.to_str())
+            .filter(|stem| !stem.is_empty())
+            .ok_or_else(|| invalid(format!("--output '{}' names no file", path.display())))?
+            .to_string();
+        let output_dir = match path.parent() {
+            Some(dir) if dir != Path::new("") => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+
+        Ok((ExportOptions { output: None, output_dir, formats, ..self.clone() }, stem))
+    }
+}
+
+/// Replaces the placeholders of `template`: `{dir}` with the output
+/// directory, `{stem}` with the input's file stem, `{name}` with the map's
+/// name, `{w}` and `{h}` with its size and `{min}` and `{max}` with its
+/// height range.
+fn expand(template: &str, header: &MapHeader, dir: &Path, file_stem: &str) -> Result<String, String> {
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..].find('}')
+            .ok_or_else(|| format!("--output '{}' has an unclosed '{{'", template))?;
+
+        let value = match &rest[start + 1..start + end] {
+            "dir" => dir.display().to_string(),
+            "stem" => file_stem.to_string(),
+            "name" => file_name(&header.name),
+            "w" => header.w.to_string(),
+            "h" => header.h.to_string(),
+            "min" => header.min_height.to_string(),
+            "max" => header.max_height.to_string(),
+            "ext" => return Err(String::from("{ext} can only end --output, as in out/{name}.{ext}")),
+            other => return Err(format!(
+                "Unknown placeholder {{{}}} in --output, expected dir, stem, name, w, h, min, max or ext", other)),
+        };
+        expanded.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+
+    Ok(expanded)
+}
+
+/// `name` with the characters file systems reject replaced, so a map's name
+/// can't point the output elsewhere.
+fn file_name(name: &str) -> String {
+    let name: String = name.chars()
+        .map(|c| if c.is_control() || ['/', '\\', ':', '*', '?', '"', '<', '>', '|'].contains(&c) { '_' } else { c })
+        .collect();
+
+    match name.trim() {
+        "" | "." | ".." => String::from("_"),
+        name => name.to_string(),
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::hash_map::Entry;
+
+/// A vertex of an indexed mesh: its tile, and whether it is the copy lowered
+/// for a skirt.
+pub type Corner = ((u32, u32), bool);
+
+/// Triangulates a `w` by `h` grid of heights with a right-triangulated
+/// irregular network, only splitting triangles whose heights stray more than
+/// `max_error` from their flat surface. A triangle is always split along with
+/// the neighbor across its long edge, so the mesh has no cracks.
+///
+/// `heights` is in image order, `None` for disabled tiles, which are left out
+/// of the mesh. Returns the triangles as tile positions in image coordinates.
+pub fn triangulate(heights: &[Option<f32>], w: u32, h: u32, max_error: f32) -> Vec<[(u32, u32); 3]> {
+    // The network covers a square of 2^n + 1 points, the tiles beyond the
+    // map count as disabled.
+    let size = (w.max(h).saturating_sub(1)).next_power_of_two().max(1);
+    let grid = size + 1;
+    let height = |(x, y): (u32, u32)| if x < w && y < h { heights[(y * w + x) as usize] } else { None };
+
+    let triangles = 2 * (size as usize).pow(2) - 2;
+    let parents = triangles.saturating_sub((size as usize).pow(2));
+    let mut errors = vec![0f32; (grid * grid) as usize];
+    let index = |(x, y): (u32, u32)| (y * grid + x) as usize;
+
+    // Children come after their parents, so going backwards every triangle
+    // sees the final errors of the ones it splits into.
+    for i in (0..triangles).rev() {
+        let (a, b) = triangle(i, size);
+        let m = middle(a, b);
+        let c = (m.0 + m.1 - a.1, m.1 + a.0 - m.0);
+
+        let mut error = match (height(a), height(b), height(c), height(m)) {
+            (Some(ha), Some(hb), Some(_), Some(hm)) => ((ha + hb) / 2f32 - hm).abs(),
+            // Forces the split down to the single tiles, so holes stay open.
+            _ => f32::INFINITY,
+        };
+
+        if i < parents {
+            error = error.max(errors[index(middle(a, c))]).max(errors[index(middle(b, c))]);
+        }
+
+        errors[index(m)] = errors[index(m)].max(error);
+    }
+
+    let mut mesh = Vec::new();
+    let mut emit = |a, b, c| {
+        if height(a).is_some() && height(b).is_some() && height(c).is_some() {
+            mesh.push(wind(a, b, c));
+        }
+    };
+
+    split((0, 0), (size, size), (size, 0), &errors, grid, max_error, &mut emit);
+    split((size, size), (0, 0), (0, size), &errors, grid, max_error, &mut emit);
+
+    mesh
+}
+
+/// The long edge of triangle `i` of the network, walking down from one of
+/// the two halves of the square along the bits of its number.
+fn triangle(i: usize, size: u32) -> ((u32, u32), (u32, u32)) {
+    let mut id = i + 2;
+    let (mut a, mut b, mut c) = if id & 1 == 1 {
+        ((0, 0), (size, size), (size, 0))
+    } else {
+        ((size, size), (0, 0), (0, size))
+    };
+
+    loop {
+        id >>= 1;
+        if id <= 1 {
+            break;
+        }
+
+        let m = middle(a, b);
+        if id & 1 == 1 {
+            b = a;
+            a = c;
+        } else {
+            a = b;
+            b = c;
+        }
+        c = m;
+    }
+
+    (a, b)
+}
+
+fn middle(a: (u32, u32), b: (u32, u32)) -> (u32, u32) {
+    ((a.0 + b.0) / 2, (a.1 + b.1) / 2)
+}
+
+/// Emits triangle `a, b, c` whole if it is within the error, otherwise the
+/// two halves on either side of the line from `c` to the middle of `a, b`.
+fn split(a: (u32, u32), b: (u32, u32), c: (u32, u32), errors: &[f32], grid: u32, max_error: f32,
+         emit: &mut impl FnMut((u32, u32), (u32, u32), (u32, u32))) {
+    let m = middle(a, b);
+    let leg = a.0.abs_diff(c.0) + a.1.abs_diff(c.1);
+
+    if leg > 1 && errors[(m.1 * grid + m.0) as usize] > max_error {
+        split(c, a, m, errors, grid, max_error, emit);
+        split(b, c, m, errors, grid, max_error, emit);
+    } else {
+        emit(a, b, c);
+    }
+}
+
+/// Orders the corners like the faces of the uniform grid, facing up once
+/// image rows become the depth axis.
+fn wind(a: (u32, u32), b: (u32, u32), c: (u32, u32)) -> [(u32, u32); 3] {
+    let cross = (i64::from(b.0) - i64::from(a.0)) * (i64::from(c.1) - i64::from(a.1))
+        - (i64::from(b.1) - i64::from(a.1)) * (i64::from(c.0) - i64::from(a.0));
+
+    if cross < 0 { [a, b, c] } else { [a, c, b] }
+}
+
+/// Two triangles for every quad of four enabled tiles.
+pub fn grid(heights: &[Option<f32>], width: u32, height: u32) -> Vec<[(u32, u32); 3]> {
+    let mut triangles = Vec::new();
+
+    for y in 0..height.saturating_sub(1) {
+        for x in 0..width.saturating_sub(1) {
+            let quad = [(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)];
+
+            if quad.iter().all(|&(x, y)| heights[(y * width + x) as usize].is_some()) {
+                triangles.push([quad[0], quad[2], quad[1]]);
+                triangles.push([quad[1], quad[2], quad[3]]);
+            }
+        }
+    }
+
+    triangles
+}
+
+/// The triangle edges with nothing on their other side that run along the
+/// border of the map, in the direction their triangle goes around.
+pub fn outer_edges(triangles: &[[(u32, u32); 3]], width: u32, height: u32) -> Vec<((u32, u32), (u32, u32))> {
+    let edges: Vec<_> = triangles.iter()
+        .flat_map(|&[a, b, c]| [(a, b), (b, c), (c, a)])
+        .collect();
+    let lookup: HashSet<_> = edges.iter().copied().collect();
+
+    let on_border = |a: (u32, u32), b: (u32, u32)| {
+        (a.0 == b.0 && (a.0 == 0 || a.0 == width - 1)) || (a.1 == b.1 && (a.1 == 0 || a.1 == height - 1))
+    };
+
+    edges.into_iter()
+        .filter(|&(a, b)| !lookup.contains(&(b, a)) && on_border(a, b))
+        .collect()
+}
+
+/// Numbers the corners of `triangles` in the order they come up, along
+/// with walls hanging from the outer edges if `skirt` is set. Returns the
+/// corners and three indices into them per triangle.
+pub fn indexed(triangles: &[[(u32, u32); 3]], w: u32, h: u32, skirt: bool) -> (Vec<Corner>, Vec<u32>) {
+    let mut corners = Vec::new();
+    let mut numbers = HashMap::new();
+    let mut indices = Vec::with_capacity(triangles.len() * 3);
+
+    let mut number = |corner: Corner| match numbers.entry(corner) {
+        Entry::Occupied(entry) => *entry.get(),
+        Entry::Vacant(entry) => {
+            corners.push(corner);
+            *entry.insert(corners.len() as u32 - 1)
+        }
+    };
+
+    for &[a, b, c] in triangles {
+        indices.extend_from_slice(&[number((a, false)), number((b, false)), number((c, false))]);
+    }
+
+    if skirt {
+        // Walks each edge backwards, so the walls face the same way as the
+        // surface they hang from.
+        for (a, b) in outer_edges(triangles, w, h) {
+            let [top_b, top_a, bottom_a, bottom_b] = [number((b, false)), number((a, false)), number((a, true)), number((b, true))];
+            indices.extend_from_slice(&[top_b, top_a, bottom_a, top_b, bottom_a, bottom_b]);
+        }
+    }
+
+    (corners, indices)
+}
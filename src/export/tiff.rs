@@ -0,0 +1,263 @@
+use std::io;
+use std::io::prelude::*;
+
+use byteorder::LE;
+use byteorder::WriteBytesExt;
+
+use super::Context;
+use super::Georef;
+use super::pixels::PixelBuffer;
+use super::pixels::PixelFormat;
+use super::pixels::Samples;
+
+const IMAGE_WIDTH: u16 = 256;
+const IMAGE_LENGTH: u16 = 257;
+const BITS_PER_SAMPLE: u16 = 258;
+const COMPRESSION: u16 = 259;
+const PHOTOMETRIC: u16 = 262;
+const DOCUMENT_NAME: u16 = 269;
+const IMAGE_DESCRIPTION: u16 = 270;
+const STRIP_OFFSETS: u16 = 273;
+const SAMPLES_PER_PIXEL: u16 = 277;
+const ROWS_PER_STRIP: u16 = 278;
+const STRIP_BYTE_COUNTS: u16 = 279;
+const SOFTWARE: u16 = 305;
+const EXTRA_SAMPLES: u16 = 338;
+const SAMPLE_FORMAT: u16 = 339;
+const MODEL_PIXEL_SCALE: u16 = 33550;
+const MODEL_TIEPOINT: u16 = 33922;
+const ICC_PROFILE: u16 = 34675;
+const GEO_KEY_DIRECTORY: u16 = 34735;
+const GEO_ASCII_PARAMS: u16 = 34737;
+const GDAL_NODATA: u16 = 42113;
+
+const GT_MODEL_TYPE: u16 = 1024;
+const GT_RASTER_TYPE: u16 = 1025;
+const GT_CITATION: u16 = 1026;
+const GEOGRAPHIC_TYPE: u16 = 2048;
+const PROJECTED_CS_TYPE: u16 = 3072;
+
+/// The value of an IFD entry.
+pub enum TagValue {
+    Ascii(String),
+    Short(Vec<u16>),
+    Long(Vec<u32>),
+    Undefined(Vec<u8>),
+    Double(Vec<f64>),
+}
+
+impl TagValue {
+    fn kind(&self) -> u16 {
+        match self {
+            TagValue::Ascii(_) => 2,
+            TagValue::Short(_) => 3,
+            TagValue::Long(_) => 4,
+            TagValue::Undefined(_) => 7,
+            TagValue::Double(_) => 12,
+        }
+    }
+
+    fn count(&self) -> usize {
+        match self {
+            TagValue::Ascii(text) => text.len() + 1,
+            TagValue::Short(values) => values.len(),
+            TagValue::Long(values) => values.len(),
+            TagValue::Undefined(bytes) => bytes.len(),
+            TagValue::Double(values) => values.len(),
+        }
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            TagValue::Ascii(text) => text.bytes().chain(Some(0)).collect(),
+            TagValue::Short(values) => values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+            TagValue::Long(values) => values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+            TagValue::Undefined(bytes) => bytes.clone(),
+            TagValue::Double(values) => values.iter().flat_map(|v| v.to_le_bytes()).collect(),
+        }
+    }
+}
+
+pub struct Tag {
+    pub id: u16,
+    pub value: TagValue,
+}
+
+/// Writes the pixels as a TIFF. The map name goes into DocumentName, all
+/// header fields into ImageDescription as `field=value` lines, and the
+/// georeferencing into GeoTIFF tags.
+pub fn write(pixels: &PixelBuffer, context: &Context, w: impl Write) -> io::Result<()> {
+    let (tags, data) = tags(pixels, context);
+
+    write_tiff(w, tags, &data)
+}
+
+/// Writes f32 pixels as a GeoTIFF for GIS software, like `write` but with
+/// `nodata` in place of the NaN samples, tagged as the no data value the way
+/// GDAL reads it.
+pub fn write_geotiff(pixels: &PixelBuffer, context: &Context, nodata: f32, w: impl Write) -> io::Result<()> {
+    let samples = match &pixels.samples {
+        Samples::F32(data) => data.iter().map(|&s| if s.is_nan() { nodata } else { s }).collect(),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "GeoTIFF output needs f32 samples")),
+    };
+    let pixels = PixelBuffer { samples: Samples::F32(samples), ..*pixels };
+
+    let (mut tags, data) = tags(&pixels, context);
+    tags.push(Tag { id: GDAL_NODATA, value: TagValue::Ascii(nodata.to_string()) });
+
+    write_tiff(w, tags, &data)
+}
+
+/// The tags and the strip of a TIFF of the pixels.
+fn tags(pixels: &PixelBuffer, context: &Context) -> (Vec<Tag>, Vec<u8>) {
+    let metadata = &context.metadata;
+    let channels = pixels.format.channels();
+
+    let (bits, sample_format, data): (u16, u16, Vec<u8>) = match &pixels.samples {
+        Samples::U8(data) => (8, 1, data.clone()),
+        Samples::U16(data) => (16, 1, data.iter().flat_map(|s| s.to_le_bytes()).collect()),
+        Samples::F32(data) => (32, 3, data.iter().flat_map(|s| s.to_le_bytes()).collect()),
+    };
+
+    let photometric = match pixels.format {
+        PixelFormat::Rgb8 | PixelFormat::Rgba8 => 2,
+        _ => 1, // Black is zero.
+    };
+
+    let mut tags = vec![
+        Tag { id: IMAGE_WIDTH, value: TagValue::Long(vec![pixels.width]) },
+        Tag { id: IMAGE_LENGTH, value: TagValue::Long(vec![pixels.height]) },
+        Tag { id: BITS_PER_SAMPLE, value: TagValue::Short(vec![bits; channels]) },
+        Tag { id: COMPRESSION, value: TagValue::Short(vec![1]) },
+        Tag { id: PHOTOMETRIC, value: TagValue::Short(vec![photometric]) },
+        Tag { id: SAMPLES_PER_PIXEL, value: TagValue::Short(vec![channels as u16]) },
+        Tag { id: ROWS_PER_STRIP, value: TagValue::Long(vec![pixels.height]) },
+        Tag { id: SAMPLE_FORMAT, value: TagValue::Short(vec![sample_format; channels]) },
+    ];
+
+    if matches!(pixels.format, PixelFormat::Rgba8 | PixelFormat::Gray8Alpha) {
+        tags.push(Tag { id: EXTRA_SAMPLES, value: TagValue::Short(vec![2]) }); // Unassociated alpha.
+    }
+
+    if !metadata.is_empty() {
+        let description: Vec<String> = metadata.iter()
+            .map(|(field, value)| format!("{}={}", field, value))
+            .collect();
+
+        tags.push(Tag { id: IMAGE_DESCRIPTION, value: TagValue::Ascii(description.join("\n")) });
+        tags.push(Tag { id: SOFTWARE, value: TagValue::Ascii(String::from(env!("CARGO_PKG_NAME"))) });
+
+        if let Some((_, name)) = metadata.iter().find(|(field, _)| *field == "name") {
+            tags.push(Tag { id: DOCUMENT_NAME, value: TagValue::Ascii(name.clone()) });
+        }
+    }
+
+    if let Some(profile) = context.icc_profile {
+        tags.push(Tag { id: ICC_PROFILE, value: TagValue::Undefined(profile.to_vec()) });
+    }
+
+    let georef = &context.options.georef;
+    if georef.is_set() {
+        tags.extend(geotiff_tags(georef));
+    }
+
+    (tags, data)
+}
+
+/// The GeoTIFF tags placing the top-left corner of the raster at the origin.
+fn geotiff_tags(georef: &Georef) -> Vec<Tag> {
+    let (ox, oy) = georef.origin();
+    let cell_size = georef.cell_size();
+
+    // Key directory header: version 1.1.0, followed by (key, location, count, value).
+    let mut keys: Vec<[u16; 4]> = vec![[GT_RASTER_TYPE, 0, 1, 1]]; // Pixel is area.
+    let mut tags = vec![
+        Tag { id: MODEL_PIXEL_SCALE, value: TagValue::Double(vec![cell_size, cell_size, 0f64]) },
+        Tag { id: MODEL_TIEPOINT, value: TagValue::Double(vec![0f64, 0f64, 0f64, ox, oy, 0f64]) },
+    ];
+
+    match georef.epsg() {
+        // EPSG geographic systems live in the 4000 range.
+        Some(code) if (4000..5000).contains(&code) => {
+            keys.push([GT_MODEL_TYPE, 0, 1, 2]);
+            keys.push([GEOGRAPHIC_TYPE, 0, 1, code]);
+        }
+        Some(code) => {
+            keys.push([GT_MODEL_TYPE, 0, 1, 1]);
+            keys.push([PROJECTED_CS_TYPE, 0, 1, code]);
+        }
+        None => {
+            if let Some(crs) = &georef.crs {
+                let citation = format!("{}|", crs);
+                keys.push([GT_CITATION, GEO_ASCII_PARAMS, citation.len() as u16, 0]);
+                tags.push(Tag { id: GEO_ASCII_PARAMS, value: TagValue::Ascii(citation) });
+            }
+        }
+    }
+
+    keys.sort_by_key(|key| key[0]);
+
+    let mut directory = vec![1, 1, 0, keys.len() as u16];
+    directory.extend(keys.iter().flatten());
+    tags.push(Tag { id: GEO_KEY_DIRECTORY, value: TagValue::Short(directory) });
+
+    tags
+}
+
+/// Writes a single-strip, uncompressed little-endian TIFF with the given
+/// tags; the strip offset and byte count tags are filled in here.
+pub fn write_tiff(mut w: impl Write, mut tags: Vec<Tag>, data: &[u8]) -> io::Result<()> {
+    // Placeholders, so the directory size is known before laying out the file.
+    tags.push(Tag { id: STRIP_OFFSETS, value: TagValue::Long(vec![0]) });
+    tags.push(Tag { id: STRIP_BYTE_COUNTS, value: TagValue::Long(vec![data.len() as u32]) });
+    tags.sort_by_key(|tag| tag.id);
+
+    // Header, then the directory, then values that don't fit an entry, then the pixels.
+    let ifd_offset = 8u32;
+    let ifd_size = 2 + tags.len() as u32 * 12 + 4;
+    let overflow_size: u32 = tags.iter()
+        .map(|tag| tag.value.bytes().len() as u32)
+        .filter(|&len| len > 4)
+        .map(|len| len + len % 2)
+        .sum();
+    let data_offset = ifd_offset + ifd_size + overflow_size;
+
+    for tag in tags.iter_mut() {
+        if tag.id == STRIP_OFFSETS {
+            tag.value = TagValue::Long(vec![data_offset]);
+        }
+    }
+
+    w.write_all(b"II")?;
+    w.write_u16::<LE>(42)?;
+    w.write_u32::<LE>(ifd_offset)?;
+
+    let mut overflow = Vec::new();
+    let overflow_offset = ifd_offset + ifd_size;
+
+    w.write_u16::<LE>(tags.len() as u16)?;
+    for tag in &tags {
+        let mut bytes = tag.value.bytes();
+
+        w.write_u16::<LE>(tag.id)?;
+        w.write_u16::<LE>(tag.value.kind())?;
+        w.write_u32::<LE>(tag.value.count() as u32)?;
+
+        if bytes.len() > 4 {
+            w.write_u32::<LE>(overflow_offset + overflow.len() as u32)?;
+            if bytes.len() % 2 == 1 {
+                bytes.push(0);
+            }
+            overflow.extend(bytes);
+        } else {
+            bytes.resize(4, 0);
+            w.write_all(&bytes)?;
+        }
+    }
+    w.write_u32::<LE>(0)?;
+
+    w.write_all(&overflow)?;
+    w.write_all(data)?;
+
+    w.flush()
+}
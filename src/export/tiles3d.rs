@@ -0,0 +1,304 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::path::Path;
+use std::path::PathBuf;
+
+use clap::Args;
+use serde_json::Value;
+use serde_json::json;
+
+use crate::Map;
+use crate::region::Region;
+
+use super::Context;
+use super::PlannedOutput;
+use super::gltf;
+use super::gltf::Mesh;
+use super::tessellate;
+
+/// The WGS 84 ellipsoid the globe of 3D Tiles viewers is shaped like.
+const SEMI_MAJOR_AXIS: f64 = 6_378_137f64;
+const ECCENTRICITY_SQUARED: f64 = 6.694_379_990_14e-3;
+
+#[derive(Args, Debug, Clone)]
+pub struct TilesetOptions {
+    /// The most tiles a side of a full detail 3D Tiles leaf covers, larger
+    /// areas are split into quadrants at coarser levels.
+    #[arg(long, value_name = "TILES", default_value_t = 64, value_parser = clap::value_parser!(u32).range(2..))]
+    pub tileset_leaf_size: u32,
+
+    /// How far the level right above the leaves may stray from the heights,
+    /// every level further up allows twice as much.
+    #[arg(long, value_name = "HEIGHT", default_value_t = 1f32)]
+    pub tileset_error: f32,
+
+    /// Place the tileset on the globe with the center of the map at this
+    /// longitude and latitude, in degrees, instead of at the earth's center.
+    #[arg(long, value_name = "LON,LAT", value_parser = parse_location, allow_hyphen_values = true)]
+    pub tileset_location: Option<(f64, f64)>,
+}
+
+impl Default for TilesetOptions {
+    fn default() -> TilesetOptions {
+        TilesetOptions { tileset_leaf_size: 64, tileset_error: 1f32, tileset_location: None }
+    }
+}
+
+/// The lowest and highest corner of a box in glTF coordinates.
+type Bounds = ([f32; 3], [f32; 3]);
+
+/// A tile of the quadtree, covering `region` of the map.
+struct Node {
+    level: u32,
+    column: u32,
+    row: u32,
+    region: Region,
+    children: Vec<Node>,
+}
+
+impl Node {
+    /// The content of the node, relative to the tileset.
+    fn uri(&self) -> String {
+        format!("tiles/{}/{}_{}.glb", self.level, self.column, self.row)
+    }
+}
+
+/// The quadtree over a `w` x `h` map, halving the regions until their
+/// longest side is at most `leaf_size`, and its number of levels.
+fn quadtree(w: u32, h: u32, leaf_size: u32) -> (Node, u32) {
+    let mut levels = 1;
+    while w.max(h).div_ceil(1 << (levels - 1)) > leaf_size {
+        levels += 1;
+    }
+
+    let root = split(Region { x: 0, y: 0, w, h }, 0, 0, 0, levels);
+
+    (root, levels)
+}
+
+fn split(region: Region, level: u32, column: u32, row: u32, levels: u32) -> Node {
+    let mut children = Vec::new();
+
+    if level + 1 < levels {
+        let (left, top) = (region.w.div_ceil(2), region.h.div_ceil(2));
+
+        for (dy, y, h) in [(0, region.y, top), (1, region.y + top, region.h - top)] {
+            for (dx, x, w) in [(0, region.x, left), (1, region.x + left, region.w - left)] {
+                if w > 0 && h > 0 {
+                    children.push(split(Region { x, y, w, h }, level + 1, column * 2 + dx, row * 2 + dy, levels));
+                }
+            }
+        }
+    }
+
+    Node { level, column, row, region, children }
+}
+
+/// The height error a node at `level` is tessellated with, 0 for the leaves.
+fn level_error(options: &TilesetOptions, level: u32, levels: u32) -> f32 {
+    if level + 1 >= levels {
+        0f32
+    } else {
+        options.tileset_error * 2f32.powi((levels - level - 2) as i32)
+    }
+}
+
+/// Writes the terrain as a 3D Tiles 1.1 tileset of binary glTF tiles, to
+/// `tileset.json` at `path` with the tiles in `tiles/` next to it.
+///
+/// The root covers the whole map and every level halves the area of the
+/// tiles and the error they are tessellated with, down to full detail
+/// leaves of at most `--tileset-leaf-size` tiles. Every tile hangs a skirt
+/// as deep as the error of its parent, hiding the cracks between tiles of
+/// different levels. Tiles without enabled tiles are left out.
+///
+/// Returns the glTF tiles written.
+pub fn write(map: &Map, context: &Context, path: &Path) -> io::Result<Vec<PathBuf>> {
+    let options = &context.options.tileset;
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let w = map.header.w;
+    let h = map.header.h;
+
+    let heights = map.tile_heights();
+
+    let (root, levels) = quadtree(w, h, options.tileset_leaf_size);
+    let writer = TileWriter { map, context, heights: &heights, dir, levels };
+    let mut written = Vec::new();
+
+    let (mut tile, _) = writer.write(&root, &mut written)?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "A tileset needs at least one enabled tile"))?;
+
+    tile["refine"] = json!("REPLACE");
+    if let Some(location) = options.tileset_location {
+        tile["transform"] = json!(east_north_up(location));
+    }
+
+    let tileset = json!({
+        "asset": { "version": "1.1", "generator": "gti2bmp" },
+        "geometricError": options.tileset_error * 2f32.powi(levels as i32 - 1),
+        "root": tile,
+    });
+    fs::write(path, serde_json::to_string_pretty(&tileset)?)?;
+
+    Ok(written)
+}
+
+/// The glTF tiles `write` produces for a `w` x `h` map next to the tileset
+/// at `path`, at most, since tiles over disabled areas are left out.
+pub fn plan(w: u32, h: u32, path: &Path, options: &TilesetOptions) -> Vec<PlannedOutput> {
+    let (root, levels) = quadtree(w, h, options.tileset_leaf_size);
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let mut planned = Vec::new();
+
+    // In the order `write` goes through them, children before their parent.
+    fn visit(node: &Node, levels: u32, dir: &Path, planned: &mut Vec<PlannedOutput>) {
+        for child in &node.children {
+            visit(child, levels, dir, planned);
+        }
+
+        planned.push(PlannedOutput {
+            path: dir.join(node.uri()),
+            description: format!("glTF tile, level {} of {}, {}x{} tiles", node.level + 1, levels, node.region.w, node.region.h),
+        });
+    }
+    visit(&root, levels, dir, &mut planned);
+
+    planned
+}
+
+struct TileWriter<'a> {
+    map: &'a Map,
+    context: &'a Context<'a>,
+    heights: &'a [Option<f32>],
+    dir: &'a Path,
+    levels: u32,
+}
+
+impl TileWriter<'_> {
+    /// Writes the glTF of `node` and its children, returning the node's
+    /// entry in the tileset and the bounds of everything below it, or `None`
+    /// if it is empty.
+    fn write(&self, node: &Node, written: &mut Vec<PathBuf>) -> io::Result<Option<(Value, Bounds)>> {
+        let mut children = Vec::new();
+        let mut bounds = None;
+        for child in &node.children {
+            if let Some((tile, child_bounds)) = self.write(child, written)? {
+                children.push(tile);
+                bounds = union(bounds, Some(child_bounds));
+            }
+        }
+
+        let options = &self.context.options.tileset;
+        let error = level_error(options, node.level, self.levels);
+        let skirt = if node.level == 0 { error } else { level_error(options, node.level - 1, self.levels) };
+        let mesh = self.mesh(&node.region, error, skirt);
+
+        let (min, max) = match union(bounds, mesh.bounds()) {
+            Some(bounds) => bounds,
+            None => return Ok(None),
+        };
+
+        let mut tile = json!({
+            "boundingVolume": { "box": bounding_box(min, max) },
+            "geometricError": error,
+        });
+
+        if !mesh.indices.is_empty() {
+            let path = self.dir.join(node.uri());
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            gltf::write_glb(&mesh, self.context.options.mesh.mesh_quantize, BufWriter::new(File::create(&path)?))?;
+            written.push(path);
+
+            tile["content"] = json!({ "uri": node.uri() });
+        }
+        if !children.is_empty() {
+            tile["children"] = Value::Array(children);
+        }
+
+        Ok(Some((tile, (min, max))))
+    }
+
+    /// The surface over `region` and the row and column of tiles past it,
+    /// which the neighbors share, centered on the map with Y up like glTF.
+    fn mesh(&self, region: &Region, max_error: f32, skirt: f32) -> Mesh {
+        let map_w = self.map.header.w;
+        let map_h = self.map.header.h;
+        let w = (region.w + 1).min(map_w - region.x);
+        let h = (region.h + 1).min(map_h - region.y);
+        let cell_size = self.context.options.georef.cell_size();
+
+        let mut heights = Vec::with_capacity(w as usize * h as usize);
+        for y in region.y..region.y + h {
+            let start = y as usize * map_w as usize + region.x as usize;
+            heights.extend_from_slice(&self.heights[start..start + w as usize]);
+        }
+
+        let triangles = tessellate::triangulate(&heights, w, h, max_error);
+        let (corners, indices) = tessellate::indexed(&triangles, w, h, skirt > 0f32);
+
+        let positions = corners.into_iter()
+            .map(|((x, y), lowered)| [
+                ((f64::from(region.x + x) + 0.5 - f64::from(map_w) / 2f64) * cell_size) as f32,
+                heights[(y * w + x) as usize].unwrap_or_default() - if lowered { skirt } else { 0f32 },
+                ((f64::from(region.y + y) + 0.5 - f64::from(map_h) / 2f64) * cell_size) as f32,
+            ])
+            .collect();
+
+        Mesh { positions, colors: Vec::new(), indices }
+    }
+}
+
+/// A 3D Tiles box around glTF bounds, which are Y up while tilesets are Z up.
+fn bounding_box(min: [f32; 3], max: [f32; 3]) -> [f64; 12] {
+    let center = |axis: usize| (f64::from(min[axis]) + f64::from(max[axis])) / 2f64;
+    let half = |axis: usize| (f64::from(max[axis]) - f64::from(min[axis])) / 2f64;
+
+    [
+        center(0), -center(2), center(1),
+        half(0), 0f64, 0f64,
+        0f64, half(2), 0f64,
+        0f64, 0f64, half(1),
+    ]
+}
+
+fn union(a: Option<Bounds>, b: Option<Bounds>) -> Option<Bounds> {
+    match (a, b) {
+        (Some((a_min, a_max)), Some((b_min, b_max))) => Some((
+            [a_min[0].min(b_min[0]), a_min[1].min(b_min[1]), a_min[2].min(b_min[2])],
+            [a_max[0].max(b_max[0]), a_max[1].max(b_max[1]), a_max[2].max(b_max[2])],
+        )),
+        (a, b) => a.or(b),
+    }
+}
+
+/// The column-major matrix from east, north and up meters at `location` on
+/// the ellipsoid to earth-centered coordinates.
+fn east_north_up((longitude, latitude): (f64, f64)) -> [f64; 16] {
+    let (sin_lon, cos_lon) = longitude.to_radians().sin_cos();
+    let (sin_lat, cos_lat) = latitude.to_radians().sin_cos();
+    let radius = SEMI_MAJOR_AXIS / (1f64 - ECCENTRICITY_SQUARED * sin_lat * sin_lat).sqrt();
+
+    [
+        -sin_lon, cos_lon, 0f64, 0f64,
+        -sin_lat * cos_lon, -sin_lat * sin_lon, cos_lat, 0f64,
+        cos_lat * cos_lon, cos_lat * sin_lon, sin_lat, 0f64,
+        radius * cos_lat * cos_lon, radius * cos_lat * sin_lon, radius * (1f64 - ECCENTRICITY_SQUARED) * sin_lat, 1f64,
+    ]
+}
+
+fn parse_location(s: &str) -> Result<(f64, f64), String> {
+    let parts = s.split(',')
+        .map(|part| part.trim().parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Invalid location '{}': {}", s, e))?;
+
+    match parts[..] {
+        [longitude, latitude] if (-90f64..=90f64).contains(&latitude) => Ok((longitude, latitude)),
+        [_, _] => Err(format!("Invalid location '{}', the latitude has to be within -90..90", s)),
+        _ => Err(format!("Invalid location '{}', expected lon,lat", s)),
+    }
+}
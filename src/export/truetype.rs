@@ -0,0 +1,414 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use byteorder::BE;
+use byteorder::ByteOrder;
+
+/// How many rows every pixel row is sampled at when filling outlines.
+const SUBSAMPLES: usize = 4;
+
+/// How many line segments approximate one quadratic curve.
+const CURVE_SEGMENTS: usize = 8;
+
+/// How deeply composite glyphs may nest.
+const MAX_COMPONENT_DEPTH: u32 = 8;
+
+/// A point of a glyph outline in font units, y up, and whether it is on the
+/// curve rather than a control point.
+type Point = (f32, f32, bool);
+
+/// A line of a flattened outline, from one point to the other.
+pub type Edge = ((f32, f32), (f32, f32));
+
+/// The parts of a TrueType font needed to draw text: the character map,
+/// the glyph outlines and the horizontal metrics. Hinting, kerning and
+/// PostScript outlines are left out.
+#[derive(Debug, Clone)]
+pub struct TrueType {
+    data: Vec<u8>,
+    units_per_em: f32,
+    ascender: f32,
+    descender: f32,
+    long_offsets: bool,
+    horizontal_metrics: usize,
+    glyph_count: usize,
+    cmap: usize,
+    loca: usize,
+    glyf: usize,
+    hmtx: usize,
+}
+
+impl TrueType {
+    /// Reads the font file at `path`.
+    pub fn open(path: &Path) -> io::Result<TrueType> {
+        TrueType::parse(fs::read(path)?).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!(
+            "{}: not a TrueType font with glyph outlines and a Unicode character map", path.display())))
+    }
+
+    fn parse(data: Vec<u8>) -> Option<TrueType> {
+        if !matches!(u32_at(&data, 0)?, 0x0001_0000 | 0x7472_7565) {
+            return None;
+        }
+
+        let table = |tag: &[u8; 4]| (0..usize::from(u16_at(&data, 4)?))
+            .map(|i| 12 + 16 * i)
+            .find(|&record| data.get(record..record + 4) == Some(&tag[..]))
+            .and_then(|record| Some(u32_at(&data, record + 8)? as usize));
+
+        let head = table(b"head")?;
+        let hhea = table(b"hhea")?;
+        let maxp = table(b"maxp")?;
+
+        let units_per_em = f32::from(u16_at(&data, head + 18)?);
+        if units_per_em == 0f32 {
+            return None;
+        }
+
+        Some(TrueType {
+            units_per_em,
+            ascender: f32::from(i16_at(&data, hhea + 4)?),
+            descender: f32::from(i16_at(&data, hhea + 6)?),
+            long_offsets: i16_at(&data, head + 50)? != 0,
+            horizontal_metrics: usize::from(u16_at(&data, hhea + 34)?.max(1)),
+            glyph_count: usize::from(u16_at(&data, maxp + 4)?),
+            cmap: unicode_subtable(&data, table(b"cmap")?)?,
+            loca: table(b"loca")?,
+            glyf: table(b"glyf")?,
+            hmtx: table(b"hmtx")?,
+            data,
+        })
+    }
+
+    /// How far above the baseline the font reaches, in pixels at `size`.
+    pub fn ascent(&self, size: f32) -> f32 {
+        self.ascender / self.units_per_em * size
+    }
+
+    /// The distance from the top of one line to the next, in pixels at `size`.
+    pub fn line_height(&self, size: f32) -> f32 {
+        (self.ascender - self.descender) / self.units_per_em * size
+    }
+
+    /// The width of `text` in pixels at `size`.
+    pub fn text_width(&self, text: &str, size: f32) -> f32 {
+        text.chars().map(|c| self.advance(self.glyph_index(c)) * size / self.units_per_em).sum()
+    }
+
+    /// The outlines of `text` at `size` in pixels, y down, with the baseline
+    /// starting at `(x, y)`.
+    pub fn layout(&self, text: &str, size: f32, (x, y): (f32, f32)) -> Vec<Edge> {
+        let scale = size / self.units_per_em;
+        let mut edges = Vec::new();
+        let mut pen = x;
+
+        for c in text.chars() {
+            let glyph = self.glyph_index(c);
+
+            for contour in self.outline(glyph, 0) {
+                let points: Vec<Point> = contour.iter().map(|&(px, py, on)| (pen + px * scale, y - py * scale, on)).collect();
+                flatten(&points, &mut edges);
+            }
+
+            pen += self.advance(glyph) * scale;
+        }
+
+        edges
+    }
+
+    /// The glyph for `c`, 0 for the missing glyph.
+    fn glyph_index(&self, c: char) -> usize {
+        let data = &self.data;
+        let c = c as u32;
+        let glyph = match u16_at(data, self.cmap) {
+            Some(4) if c <= 0xffff => (|| {
+                let segments = usize::from(u16_at(data, self.cmap + 6)? / 2);
+                let ends = self.cmap + 14;
+                let starts = ends + 2 * segments + 2;
+                let deltas = starts + 2 * segments;
+                let range_offsets = deltas + 2 * segments;
+
+                let segment = (0..segments).find(|&i| u16_at(data, ends + 2 * i).is_some_and(|end| u32::from(end) >= c))?;
+                let start = u32::from(u16_at(data, starts + 2 * segment)?);
+                if c < start {
+                    return None;
+                }
+                let delta = u32::from(u16_at(data, deltas + 2 * segment)?);
+                let range_offset = usize::from(u16_at(data, range_offsets + 2 * segment)?);
+
+                let glyph = match range_offset {
+                    0 => c,
+                    _ => match u32::from(u16_at(data, range_offsets + 2 * segment + range_offset + 2 * (c - start) as usize)?) {
+                        0 => return None,
+                        glyph => glyph,
+                    },
+                };
+                Some(((glyph + delta) & 0xffff) as usize)
+            })(),
+            Some(12) => (|| {
+                let groups = u32_at(data, self.cmap + 12)? as usize;
+                (0..groups)
+                    .map(|i| self.cmap + 16 + 12 * i)
+                    .find(|&group| u32_at(data, group).is_some_and(|start| start <= c)
+                        && u32_at(data, group + 4).is_some_and(|end| c <= end))
+                    .and_then(|group| Some((u32_at(data, group + 8)? + c - u32_at(data, group)?) as usize))
+            })(),
+            _ => None,
+        };
+
+        glyph.filter(|&glyph| glyph < self.glyph_count).unwrap_or(0)
+    }
+
+    /// The horizontal advance of `glyph` in font units.
+    fn advance(&self, glyph: usize) -> f32 {
+        let metric = glyph.min(self.horizontal_metrics - 1);
+        u16_at(&self.data, self.hmtx + 4 * metric).map_or(0f32, f32::from)
+    }
+
+    /// The contours of `glyph` in font units, empty if it is blank or broken.
+    fn outline(&self, glyph: usize, depth: u32) -> Vec<Vec<Point>> {
+        self.try_outline(glyph, depth).unwrap_or_default()
+    }
+
+    fn try_outline(&self, glyph: usize, depth: u32) -> Option<Vec<Vec<Point>>> {
+        let data = &self.data;
+        let (start, end) = if self.long_offsets {
+            (u32_at(data, self.loca + 4 * glyph)? as usize, u32_at(data, self.loca + 4 * glyph + 4)? as usize)
+        } else {
+            (usize::from(u16_at(data, self.loca + 2 * glyph)?) * 2, usize::from(u16_at(data, self.loca + 2 * glyph + 2)?) * 2)
+        };
+        if end <= start {
+            return Some(Vec::new());
+        }
+
+        let offset = self.glyf + start;
+        let contours = i16_at(data, offset)?;
+
+        if contours < 0 {
+            return self.composite_outline(offset + 10, depth);
+        }
+
+        let contours = contours as usize;
+        let ends: Vec<usize> = (0..contours).map(|i| u16_at(data, offset + 10 + 2 * i).map(usize::from)).collect::<Option<_>>()?;
+        let count = ends.last().map_or(0, |&end| end + 1);
+        let instructions = usize::from(u16_at(data, offset + 10 + 2 * contours)?);
+        let mut cursor = offset + 12 + 2 * contours + instructions;
+
+        let mut flags = Vec::with_capacity(count);
+        while flags.len() < count {
+            let flag = *data.get(cursor)?;
+            cursor += 1;
+            let repeats = if flag & 8 != 0 {
+                cursor += 1;
+                usize::from(*data.get(cursor - 1)?)
+            } else {
+                0
+            };
+            flags.extend(std::iter::repeat_n(flag, repeats + 1));
+        }
+        flags.truncate(count);
+
+        // Coordinates are deltas, short ones with the sign in the flags.
+        let mut read_coordinates = |short: u8, same_or_positive: u8| -> Option<Vec<f32>> {
+            let mut value = 0i32;
+            flags.iter()
+                .map(|&flag| {
+                    if flag & short != 0 {
+                        let delta = i32::from(*data.get(cursor)?);
+                        cursor += 1;
+                        value += if flag & same_or_positive != 0 { delta } else { -delta };
+                    } else if flag & same_or_positive == 0 {
+                        value += i32::from(i16_at(data, cursor)?);
+                        cursor += 2;
+                    }
+                    Some(value as f32)
+                })
+                .collect()
+        };
+        let xs = read_coordinates(2, 16)?;
+        let ys = read_coordinates(4, 32)?;
+
+        let mut outline = Vec::with_capacity(contours);
+        let mut first = 0;
+        for &end in &ends {
+            if end < first || end >= count {
+                return None;
+            }
+            outline.push((first..=end).map(|i| (xs[i], ys[i], flags[i] & 1 != 0)).collect());
+            first = end + 1;
+        }
+
+        Some(outline)
+    }
+
+    /// The contours of a glyph made of transformed other glyphs, whose
+    /// component records start at `offset`.
+    fn composite_outline(&self, mut offset: usize, depth: u32) -> Option<Vec<Vec<Point>>> {
+        if depth >= MAX_COMPONENT_DEPTH {
+            return None;
+        }
+
+        let data = &self.data;
+        let f2dot14 = |offset: usize| i16_at(data, offset).map(|value| f32::from(value) / 16384f32);
+        let mut outline = Vec::new();
+
+        loop {
+            let flags = u16_at(data, offset)?;
+            let glyph = usize::from(u16_at(data, offset + 2)?);
+            offset += 4;
+
+            let (dx, dy) = if flags & 1 != 0 {
+                offset += 4;
+                (f32::from(i16_at(data, offset - 4)?), f32::from(i16_at(data, offset - 2)?))
+            } else {
+                offset += 2;
+                (f32::from(*data.get(offset - 2)? as i8), f32::from(*data.get(offset - 1)? as i8))
+            };
+            // Components placed by matching points are drawn unmoved.
+            let (dx, dy) = if flags & 2 != 0 { (dx, dy) } else { (0f32, 0f32) };
+
+            let (a, b, c, d) = if flags & 8 != 0 {
+                offset += 2;
+                let scale = f2dot14(offset - 2)?;
+                (scale, 0f32, 0f32, scale)
+            } else if flags & 0x40 != 0 {
+                offset += 4;
+                (f2dot14(offset - 4)?, 0f32, 0f32, f2dot14(offset - 2)?)
+            } else if flags & 0x80 != 0 {
+                offset += 8;
+                (f2dot14(offset - 8)?, f2dot14(offset - 6)?, f2dot14(offset - 4)?, f2dot14(offset - 2)?)
+            } else {
+                (1f32, 0f32, 0f32, 1f32)
+            };
+
+            for contour in self.outline(glyph, depth + 1) {
+                outline.push(contour.into_iter().map(|(x, y, on)| (a * x + c * y + dx, b * x + d * y + dy, on)).collect());
+            }
+
+            if flags & 0x20 == 0 {
+                return Some(outline);
+            }
+        }
+    }
+}
+
+/// Fills the outline made of `edges` with the nonzero rule, returning how
+/// much of every pixel of a `width` x `height` image it covers, from 0 to 1
+/// and row by row.
+pub fn rasterize(edges: &[Edge], width: usize, height: usize) -> Vec<f32> {
+    let mut coverage = vec![0f32; width * height];
+    let mut crossings: Vec<(f32, i32)> = Vec::new();
+
+    for row in 0..height {
+        for sample in 0..SUBSAMPLES {
+            let y = row as f32 + (sample as f32 + 0.5) / SUBSAMPLES as f32;
+
+            crossings.clear();
+            for &((x0, y0), (x1, y1)) in edges {
+                if (y0 <= y) != (y1 <= y) {
+                    let x = x0 + (y - y0) * (x1 - x0) / (y1 - y0);
+                    crossings.push((x, if y1 > y0 { 1 } else { -1 }));
+                }
+            }
+            crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            let mut winding = 0;
+            let mut span_start = 0f32;
+            for &(x, direction) in &crossings {
+                let inside = winding != 0;
+                winding += direction;
+
+                if !inside && winding != 0 {
+                    span_start = x;
+                } else if inside && winding == 0 {
+                    let (start, end) = (span_start.max(0f32), x.min(width as f32));
+                    let mut column = start.floor() as usize;
+                    while (column as f32) < end && column < width {
+                        let covered = end.min(column as f32 + 1f32) - start.max(column as f32);
+                        coverage[row * width + column] += covered.max(0f32) / SUBSAMPLES as f32;
+                        column += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    for value in &mut coverage {
+        *value = value.min(1f32);
+    }
+
+    coverage
+}
+
+/// Appends the lines approximating a closed contour of on-curve points and
+/// quadratic control points to `edges`.
+fn flatten(contour: &[Point], edges: &mut Vec<Edge>) {
+    // Between two control points lies an implied point on the curve.
+    let mut points: Vec<Point> = Vec::with_capacity(contour.len() * 2);
+    for (i, &point) in contour.iter().enumerate() {
+        let next = contour[(i + 1) % contour.len()];
+        points.push(point);
+        if !point.2 && !next.2 {
+            points.push(((point.0 + next.0) / 2f32, (point.1 + next.1) / 2f32, true));
+        }
+    }
+
+    let first = match points.iter().position(|point| point.2) {
+        Some(first) => first,
+        None => return,
+    };
+    points.rotate_left(first);
+    points.push(points[0]);
+
+    let mut current = (points[0].0, points[0].1);
+    let mut i = 1;
+    while i < points.len() {
+        let (x, y, on) = points[i];
+
+        if on {
+            edges.push((current, (x, y)));
+            current = (x, y);
+            i += 1;
+        } else {
+            let end = (points[i + 1].0, points[i + 1].1);
+            for step in 1..=CURVE_SEGMENTS {
+                let t = step as f32 / CURVE_SEGMENTS as f32;
+                let u = 1f32 - t;
+                let point = (u * u * current.0 + 2f32 * u * t * x + t * t * end.0,
+                             u * u * current.1 + 2f32 * u * t * y + t * t * end.1);
+                edges.push((current, point));
+                current = point;
+            }
+            current = end;
+            i += 2;
+        }
+    }
+}
+
+/// The offset of the best Unicode subtable of the character map at `cmap`,
+/// preferring the full range of format 12 over the basic plane of format 4.
+fn unicode_subtable(data: &[u8], cmap: usize) -> Option<usize> {
+    let subtables: Vec<(u16, u16, usize)> = (0..usize::from(u16_at(data, cmap + 2)?))
+        .map(|i| cmap + 4 + 8 * i)
+        .filter_map(|record| Some((u16_at(data, record)?, u16_at(data, record + 2)?, cmap + u32_at(data, record + 4)? as usize)))
+        .collect();
+    let unicode = |&&(platform, encoding, _): &&(u16, u16, usize)| platform == 0 || (platform == 3 && matches!(encoding, 1 | 10));
+    let with_format = |format: u16| subtables.iter()
+        .filter(unicode)
+        .map(|&(_, _, offset)| offset)
+        .find(|&offset| u16_at(data, offset) == Some(format));
+
+    with_format(12).or_else(|| with_format(4))
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset + 2).map(BE::read_u16)
+}
+
+fn i16_at(data: &[u8], offset: usize) -> Option<i16> {
+    data.get(offset..offset + 2).map(BE::read_i16)
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4).map(BE::read_u32)
+}
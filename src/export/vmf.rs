@@ -0,0 +1,153 @@
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::prelude::*;
+use std::path::Path;
+
+use clap::Args;
+
+use crate::Map;
+
+use super::Context;
+
+/// Hammer's default grid limit; geometry outside of it won't load.
+const MAX_COORDINATE: f64 = 16384f64;
+/// The thickness of the brushes below the lowest point of the terrain.
+const BRUSH_DEPTH: f64 = 16f64;
+const MATERIAL: &str = "NATURE/BLENDGROUNDTOGRASS001";
+
+#[derive(Args, Debug, Clone)]
+pub struct VmfOptions {
+    /// The displacement power; each brush covers 2^power tiles square.
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(2..=4))]
+    pub vmf_power: u32,
+
+    /// How many Hammer units a tile spans. Heights get the same scale,
+    /// relative to `--cell-size`.
+    #[arg(long, default_value_t = 32f64)]
+    pub vmf_tile_units: f64,
+}
+
+impl Default for VmfOptions {
+    fn default() -> VmfOptions {
+        VmfOptions { vmf_power: 3, vmf_tile_units: 32f64 }
+    }
+}
+
+/// Writes the terrain as a Source engine map of displacement brushes, one
+/// per chunk of 2^power tiles, with the tiles as displacement vertices so
+/// neighboring brushes share their edges.
+///
+/// The map's top-left corner ends up at the brushes' maximum Y, and the top
+/// faces sit at the lowest height of the map. Disabled tiles are flattened
+/// onto that base, and chunks without any enabled tile are left out.
+pub fn write(map: &Map, context: &Context, path: &Path) -> io::Result<()> {
+    let options = &context.options.vmf;
+    let size = 1u32 << options.vmf_power;
+    let tile_units = options.vmf_tile_units;
+    let height_scale = tile_units / context.options.georef.cell_size();
+    let w = map.header.w;
+    let h = map.header.h;
+
+    let tiles = map.tiles();
+    // Samples counted from the bottom row of the image, as Hammer's Y grows north.
+    let sample = |x: u32, y: u32| tiles[(y.min(h - 1) * w + x.min(w - 1)) as usize].map(|point| point.h);
+
+    let base = map.points.iter().map(|point| point.h).fold(f32::INFINITY, f32::min);
+    // Brush planes should stay on Hammer's integer grid.
+    let base = if base.is_finite() { (f64::from(base) * height_scale).floor() } else { 0f64 };
+
+    let columns = w.saturating_sub(1).div_ceil(size).max(1);
+    let rows = h.saturating_sub(1).div_ceil(size).max(1);
+    let extent = f64::from(columns.max(rows) * size) * tile_units;
+    if extent > 2f64 * MAX_COORDINATE {
+        eprintln!("Warning: the terrain spans {} units, beyond Hammer's grid of {}; lower --vmf-tile-units",
+                  extent, 2f64 * MAX_COORDINATE);
+    }
+
+    let mut out = BufWriter::new(File::create(path)?);
+    let mut ids = 1u32;
+    let mut next_id = || { ids += 1; ids };
+
+    writeln!(out, "versioninfo\n{{\n\t\"editorversion\" \"400\"\n\t\"editorbuild\" \"8000\"\n\t\"mapversion\" \"1\"\n\t\"formatversion\" \"100\"\n\t\"prefab\" \"0\"\n}}")?;
+    writeln!(out, "world\n{{\n\t\"id\" \"1\"\n\t\"mapversion\" \"1\"\n\t\"classname\" \"worldspawn\"\n\t\"comment\" \"{}\"", map.header.name.replace('"', "'"))?;
+
+    for row in 0..rows {
+        for column in 0..columns {
+            let (sx, sy) = (column * size, row * size);
+            let heights: Vec<Vec<Option<f32>>> = (0..=size)
+                .map(|r| (0..=size).map(|c| sample(sx + c, sy + r)).collect())
+                .collect();
+
+            if heights.iter().flatten().all(Option::is_none) {
+                continue;
+            }
+
+            let x0 = f64::from(sx) * tile_units;
+            let y0 = f64::from(sy) * tile_units;
+            let (x1, y1) = (x0 + f64::from(size) * tile_units, y0 + f64::from(size) * tile_units);
+            let (z0, z1) = (base - BRUSH_DEPTH, base);
+
+            writeln!(out, "\tsolid\n\t{{\n\t\t\"id\" \"{}\"", next_id())?;
+
+            let faces = [
+                format!("({} {} {}) ({} {} {}) ({} {} {})", x0, y1, z1, x1, y1, z1, x1, y0, z1),
+                format!("({} {} {}) ({} {} {}) ({} {} {})", x0, y0, z0, x1, y0, z0, x1, y1, z0),
+                format!("({} {} {}) ({} {} {}) ({} {} {})", x0, y1, z1, x0, y0, z1, x0, y0, z0),
+                format!("({} {} {}) ({} {} {}) ({} {} {})", x1, y1, z0, x1, y0, z0, x1, y0, z1),
+                format!("({} {} {}) ({} {} {}) ({} {} {})", x1, y1, z1, x0, y1, z1, x0, y1, z0),
+                format!("({} {} {}) ({} {} {}) ({} {} {})", x1, y0, z0, x0, y0, z0, x0, y0, z1),
+            ];
+
+            for (i, plane) in faces.iter().enumerate() {
+                writeln!(out, "\t\tside\n\t\t{{\n\t\t\t\"id\" \"{}\"\n\t\t\t\"plane\" \"{}\"", next_id(), plane)?;
+                writeln!(out, "\t\t\t\"material\" \"{}\"", MATERIAL)?;
+                writeln!(out, "\t\t\t\"uaxis\" \"[1 0 0 0] 0.25\"\n\t\t\t\"vaxis\" \"[0 -1 0 0] 0.25\"")?;
+                writeln!(out, "\t\t\t\"rotation\" \"0\"\n\t\t\t\"lightmapscale\" \"16\"\n\t\t\t\"smoothing_groups\" \"0\"")?;
+
+                if i == 0 {
+                    write_dispinfo(&mut out, options.vmf_power, (x0, y0, z1), &heights, base, height_scale)?;
+                }
+
+                writeln!(out, "\t\t}}")?;
+            }
+
+            writeln!(out, "\t}}")?;
+        }
+    }
+
+    writeln!(out, "}}")?;
+    out.flush()
+}
+
+/// The displacement of a top face, rows running north from `start`.
+fn write_dispinfo<W: Write>(out: &mut W, power: u32, start: (f64, f64, f64), heights: &[Vec<Option<f32>>],
+                            base: f64, height_scale: f64) -> io::Result<()> {
+    let size = 1usize << power;
+    let rows = |name: &str, out: &mut W, row: &dyn Fn(usize) -> String, count: usize| -> io::Result<()> {
+        writeln!(out, "\t\t\t\t{}\n\t\t\t\t{{", name)?;
+        for r in 0..count {
+            writeln!(out, "\t\t\t\t\t\"row{}\" \"{}\"", r, row(r))?;
+        }
+        writeln!(out, "\t\t\t\t}}")
+    };
+    let repeat = |value: &str, count: usize| vec![value; count].join(" ");
+
+    writeln!(out, "\t\t\tdispinfo\n\t\t\t{{")?;
+    writeln!(out, "\t\t\t\t\"power\" \"{}\"\n\t\t\t\t\"startposition\" \"[{} {} {}]\"", power, start.0, start.1, start.2)?;
+    writeln!(out, "\t\t\t\t\"flags\" \"0\"\n\t\t\t\t\"elevation\" \"0\"\n\t\t\t\t\"subdiv\" \"0\"")?;
+
+    rows("normals", out, &|_| repeat("0 0 1", size + 1), size + 1)?;
+    rows("distances", out, &|r| heights[r].iter()
+        .map(|height| height.map_or(0f64, |height| f64::from(height) * height_scale - base))
+        .map(|distance| format!("{}", (distance * 100f64).round() / 100f64))
+        .collect::<Vec<_>>()
+        .join(" "), size + 1)?;
+    rows("offsets", out, &|_| repeat("0 0 0", size + 1), size + 1)?;
+    rows("offset_normals", out, &|_| repeat("0 0 1", size + 1), size + 1)?;
+    rows("alphas", out, &|_| repeat("0", size + 1), size + 1)?;
+    rows("triangle_tags", out, &|_| repeat("9", size * 2), size)?;
+
+    writeln!(out, "\t\t\t\tallowed_verts\n\t\t\t\t{{\n\t\t\t\t\t\"10\" \"{}\"\n\t\t\t\t}}", repeat("-1", 10))?;
+    writeln!(out, "\t\t\t}}")
+}
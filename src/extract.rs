@@ -0,0 +1,45 @@
+use std::io;
+
+use crate::Map;
+use crate::region::Region;
+
+impl Map {
+    /// Crops the map to the square around a circle of `radius` tiles around
+    /// `center`, in decoded image coordinates, and disables the tiles whose
+    /// centers lie outside the circle. The square is clipped to the map.
+    pub fn extract_circle(&self, center: (u32, u32), radius: u32) -> io::Result<Map> {
+        let (cx, cy) = center;
+
+        if cx >= self.header.w || cy >= self.header.h {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                "Center {},{} is outside of the {}x{} map", cx, cy, self.header.w, self.header.h)));
+        }
+
+        let x = cx.saturating_sub(radius);
+        let y = cy.saturating_sub(radius);
+        let region = Region {
+            x,
+            y,
+            w: (cx + radius + 1).min(self.header.w) - x,
+            h: (cy + radius + 1).min(self.header.h) - y,
+        };
+
+        let mut map = self.crop(&region);
+        let mut tiles = map.tiles();
+        let r2 = i64::from(radius).pow(2);
+
+        for (index, tile) in tiles.iter_mut().enumerate() {
+            let (tx, ty) = crate::get_position(&index, &region.w, &region.h);
+            let dx = i64::from(tx + region.x) - i64::from(cx);
+            let dy = i64::from(ty + region.y) - i64::from(cy);
+
+            if dx * dx + dy * dy > r2 {
+                *tile = None;
+            }
+        }
+
+        map.set_tiles(tiles);
+
+        Ok(map)
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::VecDeque;
+
+use clap::ValueEnum;
+
+use crate::Map;
+use crate::TilePoint;
+
+/// How the heights of disabled tiles are reconstructed.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum FillMethod {
+    /// Copy the closest enabled tile; fast, but leaves terraces.
+    Nearest,
+    /// Inverse distance weighting of the enabled tiles within `radius`.
+    Idw,
+    /// Discrete Sibson interpolation; follows the shape of coastlines.
+    NaturalNeighbor,
+    /// Smoothest surface through the void edges; best for interior voids.
+    Laplace,
+}
+
+/// The closest enabled tile of every tile, as a tile index.
+///
+/// Sources spread outwards breadth first and a tile takes over a neighbor's
+/// source whenever that one is closer, which is exact up to rounding at the
+/// Voronoi edges.
+fn nearest_sources(tiles: &[Option<TilePoint>], w: usize, h: usize) -> Vec<Option<usize>> {
+    let mut nearest: Vec<Option<usize>> = tiles.iter()
+        .enumerate()
+        .map(|(index, tile)| tile.map(|_| index))
+        .collect();
+    let mut queue: VecDeque<usize> = (0..tiles.len()).filter(|&index| nearest[index].is_some()).collect();
+
+    while let Some(index) = queue.pop_front() {
+        let source = nearest[index].unwrap();
+        let (x, y) = (index % w, index / w);
+
+        for (nx, ny) in neighbors(x, y, w, h) {
+            let neighbor = ny * w + nx;
+            let closer = nearest[neighbor]
+                .is_none_or(|current| distance2(current, neighbor, w) > distance2(source, neighbor, w));
+
+            if closer {
+                nearest[neighbor] = Some(source);
+                queue.push_back(neighbor);
+            }
+        }
+    }
+
+    nearest
+}
+
+fn neighbors(x: usize, y: usize, w: usize, h: usize) -> impl Iterator<Item = (usize, usize)> {
+    (-1i64..=1).flat_map(move |dy| (-1i64..=1).map(move |dx| (x as i64 + dx, y as i64 + dy)))
+        .filter(move |&(nx, ny)| (nx, ny) != (x as i64, y as i64)
+            && nx >= 0 && ny >= 0 && nx < w as i64 && ny < h as i64)
+        .map(|(nx, ny)| (nx as usize, ny as usize))
+}
+
+fn distance2(a: usize, b: usize, w: usize) -> i64 {
+    let dx = (a % w) as i64 - (b % w) as i64;
+    let dy = (a / w) as i64 - (b / w) as i64;
+    dx * dx + dy * dy
+}
+
+fn idw(tiles: &[Option<TilePoint>], index: usize, w: usize, h: usize, radius: u32) -> Option<f32> {
+    let (x, y) = ((index % w) as i64, (index / w) as i64);
+    let r = i64::from(radius);
+    let mut sum = 0f64;
+    let mut weights = 0f64;
+
+    for ny in (y - r).max(0)..=(y + r).min(h as i64 - 1) {
+        for nx in (x - r).max(0)..=(x + r).min(w as i64 - 1) {
+            let d2 = (nx - x).pow(2) + (ny - y).pow(2);
+
+            if d2 > r * r {
+                continue;
+            }
+
+            if let Some(point) = tiles[ny as usize * w + nx as usize] {
+                let weight = 1.0 / d2 as f64;
+                sum += weight * f64::from(point.h);
+                weights += weight;
+            }
+        }
+    }
+
+    if weights > 0.0 { Some((sum / weights) as f32) } else { None }
+}
+
+/// Every hole tile hands its nearest height to all holes inside the circle
+/// reaching its nearest enabled tile, at most `radius` tiles across so deep
+/// voids stay linear in their size; each hole averages what it received.
+fn natural_neighbor(tiles: &[Option<TilePoint>], nearest: &[Option<usize>], w: usize, h: usize, radius: u32) -> Vec<f32> {
+    let mut sums = vec![0f64; tiles.len()];
+    let mut counts = vec![0u32; tiles.len()];
+
+    for (index, source) in nearest.iter().enumerate() {
+        let source = match source {
+            Some(source) if tiles[index].is_none() => *source,
+            _ => continue,
+        };
+
+        let value = f64::from(tiles[source].unwrap().h);
+        let d2 = distance2(source, index, w).min(i64::from(radius).pow(2));
+        let r = (d2 as f64).sqrt() as i64;
+        let (x, y) = ((index % w) as i64, (index / w) as i64);
+
+        for ny in (y - r).max(0)..=(y + r).min(h as i64 - 1) {
+            for nx in (x - r).max(0)..=(x + r).min(w as i64 - 1) {
+                let target = ny as usize * w + nx as usize;
+
+                if tiles[target].is_none() && (nx - x).pow(2) + (ny - y).pow(2) < d2 {
+                    sums[target] += value;
+                    counts[target] += 1;
+                }
+            }
+        }
+    }
+
+    sums.iter().zip(&counts)
+        .map(|(&sum, &count)| if count > 0 { (sum / f64::from(count)) as f32 } else { f32::NAN })
+        .collect()
+}
+
+/// Relaxes the hole heights towards the mean of their four neighbors, with
+/// the enabled tiles held fixed.
+fn laplace(tiles: &[Option<TilePoint>], heights: &mut [f32], w: usize, h: usize, iterations: u32) {
+    for _ in 0..iterations {
+        for y in 0..h {
+            for x in 0..w {
+                let index = y * w + x;
+
+                if tiles[index].is_some() {
+                    continue;
+                }
+
+                let mut sum = 0f32;
+                let mut count = 0f32;
+
+                for (nx, ny) in [(x.wrapping_sub(1), y), (x + 1, y), (x, y.wrapping_sub(1)), (x, y + 1)] {
+                    if nx < w && ny < h {
+                        sum += heights[ny * w + nx];
+                        count += 1.0;
+                    }
+                }
+
+                heights[index] = sum / count;
+            }
+        }
+    }
+}
+
+/// Blends the heights within `feather` tiles of the seam between real and
+/// filled tiles with their local mean, fully at the seam and fading out
+/// with distance, so the fill does not leave a crease.
+fn feather_seam(heights: &mut [f32], filled: &[bool], w: usize, h: usize, feather: u32) {
+    let mut distance = vec![u32::MAX; heights.len()];
+    let mut queue = VecDeque::new();
+
+    for index in 0..heights.len() {
+        let (x, y) = (index % w, index / w);
+
+        if neighbors(x, y, w, h).any(|(nx, ny)| filled[ny * w + nx] != filled[index]) {
+            distance[index] = 0;
+            queue.push_back(index);
+        }
+    }
+
+    while let Some(index) = queue.pop_front() {
+        if distance[index] >= feather {
+            continue;
+        }
+
+        for (nx, ny) in neighbors(index % w, index / w, w, h) {
+            let neighbor = ny * w + nx;
+
+            if distance[neighbor] == u32::MAX {
+                distance[neighbor] = distance[index] + 1;
+                queue.push_back(neighbor);
+            }
+        }
+    }
+
+    // Summed area table for the box means.
+    let mut sums = vec![0f64; (w + 1) * (h + 1)];
+    for y in 0..h {
+        for x in 0..w {
+            sums[(y + 1) * (w + 1) + x + 1] = f64::from(heights[y * w + x])
+                + sums[y * (w + 1) + x + 1] + sums[(y + 1) * (w + 1) + x] - sums[y * (w + 1) + x];
+        }
+    }
+
+    let r = feather as usize;
+    let original = heights.to_vec();
+
+    for (index, height) in heights.iter_mut().enumerate() {
+        if distance[index] > feather {
+            continue;
+        }
+
+        let (x, y) = (index % w, index / w);
+        let (x0, y0, x1, y1) = (x.saturating_sub(r), y.saturating_sub(r), (x + r + 1).min(w), (y + r + 1).min(h));
+        let sum = sums[y1 * (w + 1) + x1] - sums[y0 * (w + 1) + x1] - sums[y1 * (w + 1) + x0] + sums[y0 * (w + 1) + x0];
+        let mean = (sum / ((x1 - x0) * (y1 - y0)) as f64) as f32;
+        let weight = 1.0 - distance[index] as f32 / (feather + 1) as f32;
+
+        *height = original[index] + (mean - original[index]) * weight;
+    }
+}
+
+impl Map {
+    /// Enables every disabled tile with a height interpolated from the
+    /// enabled ones. Filled tiles take the color of the closest enabled tile.
+    ///
+    /// `radius` limits the IDW search and the natural neighbor circles and
+    /// `iterations` the Laplace relaxation; holes they cannot reach fall
+    /// back to the nearest height.
+    /// A non-zero `feather` smooths that many tiles on both sides of the
+    /// fill boundary. Returns the number of tiles filled.
+    pub fn fill_holes(&mut self, method: FillMethod, radius: u32, iterations: u32, feather: u32) -> usize {
+        let w = self.header.w as usize;
+        let h = self.header.h as usize;
+        let mut tiles = self.tiles();
+        let nearest = nearest_sources(&tiles, w, h);
+
+        let mut heights: Vec<f32> = nearest.iter()
+            .map(|source| source.map_or(f32::NAN, |source| tiles[source].unwrap().h))
+            .collect();
+
+        match method {
+            FillMethod::Nearest => {}
+            FillMethod::Idw => {
+                for (index, height) in heights.iter_mut().enumerate() {
+                    if tiles[index].is_none() {
+                        *height = idw(&tiles, index, w, h, radius).unwrap_or(*height);
+                    }
+                }
+            }
+            FillMethod::NaturalNeighbor => {
+                let interpolated = natural_neighbor(&tiles, &nearest, w, h, radius);
+
+                for (index, height) in heights.iter_mut().enumerate() {
+                    if tiles[index].is_none() && !interpolated[index].is_nan() {
+                        *height = interpolated[index];
+                    }
+                }
+            }
+            FillMethod::Laplace => laplace(&tiles, &mut heights, w, h, iterations),
+        }
+
+        let filled_tiles: Vec<bool> = tiles.iter().map(|tile| tile.is_none()).collect();
+
+        if feather > 0 && nearest.iter().all(|source| source.is_some()) {
+            feather_seam(&mut heights, &filled_tiles, w, h, feather);
+        }
+
+        let mut filled = 0usize;
+
+        for index in 0..tiles.len() {
+            match (tiles[index].as_mut(), nearest[index]) {
+                (Some(point), _) => point.h = heights[index],
+                (None, Some(source)) => {
+                    tiles[index] = Some(TilePoint { h: heights[index], filled: true, ..tiles[source].unwrap() });
+                    filled += 1;
+                }
+                (None, None) => {}
+            }
+        }
+
+        self.set_tiles(tiles);
+
+        filled
+    }
+}
@@ -0,0 +1,44 @@
+//! Small maps built byte by byte for the unit tests.
+
+use byteorder::LE;
+use byteorder::WriteBytesExt;
+
+/// The size of the fixture maps, in tiles.
+pub const W: u32 = 4;
+pub const H: u32 = 3;
+
+/// The bytes of a 4x3 map with the given run control bytes, each enabled
+/// run followed by its points. The name has bytes after its terminator, as
+/// game files do.
+pub fn map_bytes(runs: &[i8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.write_u32::<LE>(crate::SIGNATURE).unwrap();
+    bytes.write_u32::<LE>(1).unwrap();
+    bytes.extend_from_slice(&[0u8; 8]);
+    bytes.write_f32::<LE>(-10f32).unwrap();
+    bytes.write_f32::<LE>(90f32).unwrap();
+    bytes.write_u32::<LE>(W).unwrap();
+    bytes.write_u32::<LE>(H).unwrap();
+    bytes.resize(crate::HEADER_FIELDS_SIZE, 0u8);
+
+    let mut name = b"Fixture\0junk".to_vec();
+    name.resize(crate::NAME_SIZE, 0u8);
+    bytes.extend_from_slice(&name);
+
+    let mut point = 0u8;
+    for &n in runs {
+        bytes.write_i8(n).unwrap();
+
+        for _ in 0..if n >= 0 { n as usize + 1 } else { 0 } {
+            bytes.write_f32::<LE>(f32::from(point) * 2.5 - 10f32).unwrap();
+            bytes.extend_from_slice(&[0, point, 255 - point, point / 2]);
+            point += 1;
+        }
+    }
+
+    bytes
+}
+
+/// Runs covering the 12 tiles, with the first enabled tiles split over two
+/// runs where a single one would do.
+pub const RUNS: [i8; 4] = [1, 0, -3, 5];
@@ -0,0 +1,135 @@
+use std::io;
+use std::mem;
+
+use clap::ValueEnum;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::Request;
+use tonic::Response;
+use tonic::Status;
+use tonic::transport::Server;
+
+use crate::Map;
+use crate::export;
+use crate::export::ExportOptions;
+use crate::export::Format;
+use crate::export::PixelFormat;
+use crate::limits::Limits;
+
+use self::proto::DecodeRequest;
+use self::proto::ExportChunk;
+use self::proto::ExportRequest;
+use self::proto::MapInfo;
+use self::proto::heightmap_server::Heightmap;
+use self::proto::heightmap_server::HeightmapServer;
+
+mod proto {
+    tonic::include_proto!("heightmap");
+}
+
+const DEFAULT_CHUNK_SIZE: usize = 64 << 10;
+
+/// How many chunks are encoded ahead of a slow client.
+const STREAM_BUFFER: usize = 4;
+
+struct Service {
+    limits: Limits,
+}
+
+impl Service {
+    /// Decodes an uploaded map on the blocking pool, so a large one doesn't
+    /// hold up the requests sharing its worker thread.
+    async fn parse(&self, bytes: Vec<u8>) -> Result<Map, Status> {
+        self.limits.check_file_size(bytes.len() as u64).map_err(invalid)?;
+
+        let limits = self.limits.clone();
+        tokio::task::spawn_blocking(move || Map::parse(&mut &bytes[..], &limits))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .map_err(|e| invalid(e.into()))
+    }
+}
+
+#[tonic::async_trait]
+impl Heightmap for Service {
+    async fn decode(&self, request: Request<DecodeRequest>) -> Result<Response<MapInfo>, Status> {
+        let map = self.parse(request.into_inner().map).await?;
+
+        Ok(Response::new(MapInfo {
+            width: map.header.w,
+            height: map.header.h,
+            points: map.points.len() as u64,
+            header: map.header.fields().into_iter()
+                .map(|(field, value)| (String::from(field), value))
+                .collect(),
+        }))
+    }
+
+    type ExportStream = ReceiverStream<Result<ExportChunk, Status>>;
+
+    async fn export(&self, request: Request<ExportRequest>) -> Result<Response<Self::ExportStream>, Status> {
+        let mut request = request.into_inner();
+        let map = self.parse(mem::take(&mut request.map)).await?;
+
+        let format = match request.format.as_str() {
+            "" => Format::Png,
+            name => Format::from_str(name, true).map_err(Status::invalid_argument)?,
+        };
+        let pixel_format = match request.pixel_format.as_str() {
+            "" => PixelFormat::Gray8,
+            name => PixelFormat::from_str(name, true).map_err(Status::invalid_argument)?,
+        };
+        let chunk_size = match request.chunk_size {
+            0 => DEFAULT_CHUNK_SIZE,
+            size => size as usize,
+        };
+
+        let options = ExportOptions { formats: vec![format], pixel_format, ..ExportOptions::default() };
+        let bytes = tokio::task::spawn_blocking(move || export::render(&map, format, &options))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .map_err(invalid)?;
+
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        tokio::spawn(async move {
+            let total = bytes.len() as u64;
+
+            for (i, data) in bytes.chunks(chunk_size).enumerate() {
+                let chunk = ExportChunk { data: data.to_vec(), offset: (i * chunk_size) as u64, total };
+
+                // The client went away, nothing left to send to.
+                if sender.send(Ok(chunk)).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+}
+
+fn invalid(e: io::Error) -> Status {
+    match e.kind() {
+        io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput | io::ErrorKind::UnexpectedEof =>
+            Status::invalid_argument(e.to_string()),
+        _ => Status::internal(e.to_string()),
+    }
+}
+
+/// Serves the `heightmap.Heightmap` gRPC service on `address` until the
+/// process is stopped.
+pub fn serve(address: &str, limits: &Limits) -> io::Result<()> {
+    let address = address.parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid address '{}': {}", address, e)))?;
+
+    // Requests carry whole map files, far beyond the default message size.
+    let max_message_size = (limits.max_file_size as usize).saturating_add(64);
+    let service = HeightmapServer::new(Service { limits: limits.clone() })
+        .max_decoding_message_size(max_message_size);
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    println!("Serving gRPC on {}", address);
+
+    runtime.block_on(Server::builder().add_service(service).serve(address))
+        .map_err(io::Error::other)
+}
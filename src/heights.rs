@@ -0,0 +1,78 @@
+use std::ops::Range;
+
+use crate::Map;
+use crate::TilePoint;
+
+impl Map {
+    /// The height range from the header, which images are scaled over.
+    pub fn height_range(&self) -> Range<f32> {
+        self.header.min_height..self.header.max_height
+    }
+
+    /// The lowest and highest finite stored height, `None` if there are none.
+    pub fn data_range(&self) -> Option<Range<f32>> {
+        self.points.iter()
+            .map(|point| point.h)
+            .filter(|h| h.is_finite())
+            .fold(None, |range: Option<Range<f32>>, h| match range {
+                Some(range) => Some(range.start.min(h)..range.end.max(h)),
+                None => Some(h..h),
+            })
+    }
+
+    /// Every tile's height scaled so `range` maps onto 0..1, row-major from
+    /// the top-left and NaN where tiles are disabled. Heights outside of the
+    /// range aren't clamped, and an empty range scales everything to 0.
+    pub fn normalized_heights(&self, range: Range<f32>) -> Vec<f32> {
+        let span = range.end - range.start;
+        let empty = !(span > 0f32 && span.is_finite());
+
+        let mut heights = vec![f32::NAN; self.enabled.len()];
+
+        for (index, offset) in self.enabled_tiles() {
+            let (x, y) = crate::get_position(&index, &self.header.w, &self.header.h);
+            heights[(y * self.header.w + x) as usize] = if empty { 0f32 } else { (self.points[offset].h - range.start) / span };
+        }
+
+        heights
+    }
+
+    /// Every tile's stored height, row-major from the top-left and `None`
+    /// where tiles are disabled.
+    pub fn tile_heights(&self) -> Vec<Option<f32>> {
+        self.tile_points().iter().map(|point| point.map(|point| point.h)).collect()
+    }
+
+    /// Every tile's point, row-major from the top-left like `tile_heights`
+    /// and `None` where tiles are disabled.
+    pub fn tile_points(&self) -> Vec<Option<TilePoint>> {
+        let mut points = vec![None; self.enabled.len()];
+
+        for (index, offset) in self.enabled_tiles() {
+            let (x, y) = crate::get_position(&index, &self.header.w, &self.header.h);
+            points[(y * self.header.w + x) as usize] = Some(self.points[offset]);
+        }
+
+        points
+    }
+
+    /// The stored height of the tile at `x, y` in image coordinates, `None`
+    /// if it is disabled or outside of the map.
+    ///
+    /// Finding the point walks the mask up to the tile, so use
+    /// `normalized_heights` when reading the whole map.
+    pub fn real_height(&self, x: u32, y: u32) -> Option<f32> {
+        if x >= self.header.w || y >= self.header.h {
+            return None;
+        }
+
+        let index = ((self.header.h - 1 - y) * self.header.w + x) as usize;
+        if self.enabled[index] == 0 {
+            return None;
+        }
+
+        let offset = self.enabled[..index].iter().filter(|&&enabled| enabled > 0).count();
+
+        Some(self.points[offset].h)
+    }
+}
@@ -0,0 +1,275 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use crate::Map;
+
+/// The gentlest slope the wetness index divides by, keeping pits and flats
+/// finite.
+const MIN_SLOPE: f32 = 1e-3;
+
+/// The eight neighbors water can flow to, as image offsets, and the D8 code
+/// ESRI and most hydrology tools give each direction.
+const NEIGHBORS: [(i64, i64, u8); 8] = [
+    (1, 0, 1),
+    (1, 1, 2),
+    (0, 1, 4),
+    (-1, 1, 8),
+    (-1, 0, 16),
+    (-1, -1, 32),
+    (0, -1, 64),
+    (1, -1, 128),
+];
+
+/// Where water runs off each tile, following the steepest descent to one of
+/// its eight neighbors.
+#[derive(Debug, Clone)]
+pub struct FlowDirections {
+    pub w: u32,
+    pub h: u32,
+    /// The D8 code of every tile in image order: 1 for east, doubling
+    /// clockwise up to 128 for northeast. Pits and flats, which have no lower
+    /// neighbor, are 0, and disabled tiles are `None`.
+    pub codes: Vec<Option<u8>>,
+}
+
+impl FlowDirections {
+    /// The tile the tile at `i` drains into, as an image index.
+    pub fn downstream(&self, i: usize) -> Option<usize> {
+        let code = self.codes[i]?;
+        let &(dx, dy, _) = NEIGHBORS.iter().find(|&&(_, _, neighbor)| neighbor == code)?;
+        let (x, y) = ((i % self.w as usize) as i64 + dx, (i / self.w as usize) as i64 + dy);
+
+        Some(y as usize * self.w as usize + x as usize)
+    }
+}
+
+/// A closed depression that would fill with water up to the height it
+/// spills over at.
+#[derive(Debug, Clone)]
+pub struct Lake {
+    /// The label of the lake's tiles, counting from 1.
+    pub label: u32,
+    pub tiles: u32,
+    /// The height of the water surface.
+    pub level: f32,
+    pub max_depth: f32,
+    /// The water held, in height units times the area of the tiles.
+    pub volume: f64,
+    /// The image position of the deepest tile.
+    pub deepest: (u32, u32),
+}
+
+/// The lakes of a map and which one covers each tile.
+#[derive(Debug, Clone)]
+pub struct Lakes {
+    /// The lake label of every tile in image order, 0 for dry tiles.
+    pub labels: Vec<u32>,
+    pub lakes: Vec<Lake>,
+}
+
+/// A tile waiting to be flooded, lowest water level first.
+struct Flooded {
+    level: f32,
+    index: usize,
+}
+
+impl PartialEq for Flooded {
+    fn eq(&self, other: &Flooded) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Flooded {}
+
+impl PartialOrd for Flooded {
+    fn partial_cmp(&self, other: &Flooded) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Flooded {
+    fn cmp(&self, other: &Flooded) -> Ordering {
+        other.level.total_cmp(&self.level).then(other.index.cmp(&self.index))
+    }
+}
+
+impl Map {
+    /// The D8 flow direction of every tile. Disabled tiles and the area
+    /// outside of the map aren't flowed into.
+    pub fn flow_directions(&self) -> FlowDirections {
+        let (w, h) = (i64::from(self.header.w), i64::from(self.header.h));
+        let heights = self.tile_heights();
+        let at = |x: i64, y: i64| if x >= 0 && y >= 0 && x < w && y < h { heights[(y * w + x) as usize] } else { None };
+
+        let codes = (0..w * h)
+            .map(|i| {
+                let (x, y) = (i % w, i / w);
+                let center = at(x, y)?;
+
+                let steepest = NEIGHBORS.iter()
+                    .filter_map(|&(dx, dy, code)| {
+                        let drop = (center - at(x + dx, y + dy)?) / ((dx * dx + dy * dy) as f32).sqrt();
+                        if drop > 0f32 { Some((drop, code)) } else { None }
+                    })
+                    .fold(None, |steepest: Option<(f32, u8)>, (drop, code)| match steepest {
+                        Some((steepest_drop, _)) if steepest_drop >= drop => steepest,
+                        _ => Some((drop, code)),
+                    });
+
+                Some(steepest.map_or(0, |(_, code)| code))
+            })
+            .collect();
+
+        FlowDirections { w: self.header.w, h: self.header.h, codes }
+    }
+
+    /// How many tiles drain through every tile, itself included, following
+    /// `directions`. In image order, `None` for disabled tiles.
+    pub fn flow_accumulation(&self, directions: &FlowDirections) -> Vec<Option<u32>> {
+        let heights = self.tile_heights();
+        let mut accumulation: Vec<Option<u32>> = heights.iter().map(|h| h.map(|_| 1)).collect();
+
+        // Water only runs downhill, so going from the highest tile down every
+        // tile has collected all of its inflow before passing it on.
+        let mut order: Vec<usize> = (0..heights.len()).filter(|&i| heights[i].is_some()).collect();
+        order.sort_by(|&a, &b| heights[b].partial_cmp(&heights[a]).unwrap_or(Ordering::Equal));
+
+        for i in order {
+            if let (Some(downstream), Some(inflow)) = (directions.downstream(i), accumulation[i]) {
+                if let Some(total) = &mut accumulation[downstream] {
+                    *total += inflow;
+                }
+            }
+        }
+
+        accumulation
+    }
+
+    /// The topographic wetness index ln(a / tan β) of every tile, in image
+    /// order and `None` for disabled tiles, with tiles `spacing` apart.
+    ///
+    /// `a` is the area draining through a tile per unit of contour width and
+    /// β the slope along its flow direction, at least `MIN_SLOPE`.
+    pub fn wetness_index(&self, spacing: f32) -> Vec<Option<f32>> {
+        let heights = self.tile_heights();
+        let directions = self.flow_directions();
+        let accumulation = self.flow_accumulation(&directions);
+        let w = self.header.w as usize;
+
+        (0..heights.len())
+            .map(|i| {
+                let tiles = accumulation[i]?;
+                let slope = match (heights[i], directions.downstream(i)) {
+                    (Some(center), Some(downstream)) => {
+                        let (dx, dy) = ((i % w) as f32 - (downstream % w) as f32, (i / w) as f32 - (downstream / w) as f32);
+                        (center - heights[downstream].unwrap_or(center)) / ((dx * dx + dy * dy).sqrt() * spacing)
+                    }
+                    _ => 0f32,
+                };
+
+                Some((tiles as f32 * spacing / slope.max(MIN_SLOPE)).ln())
+            })
+            .collect()
+    }
+
+    /// The level water would fill every tile to before running off the map,
+    /// in image order and `None` for disabled tiles. Water runs off at the
+    /// edges of the map and into disabled tiles, so depressions are filled
+    /// up to the lowest rim between them and an edge.
+    pub fn water_levels(&self) -> Vec<Option<f32>> {
+        let (w, h) = (i64::from(self.header.w), i64::from(self.header.h));
+        let heights = self.tile_heights();
+        let mut levels: Vec<Option<f32>> = vec![None; heights.len()];
+        let mut queue = BinaryHeap::new();
+
+        let outside = |x: i64, y: i64| x < 0 || y < 0 || x >= w || y >= h || heights[(y * w + x) as usize].is_none();
+
+        // Flooding inwards from the outlets, lowest first, every tile is
+        // reached over the lowest rim there is.
+        for i in 0..heights.len() {
+            let (x, y) = (i as i64 % w, i as i64 / w);
+            if let Some(height) = heights[i] {
+                if NEIGHBORS.iter().any(|&(dx, dy, _)| outside(x + dx, y + dy)) {
+                    levels[i] = Some(height);
+                    queue.push(Flooded { level: height, index: i });
+                }
+            }
+        }
+
+        while let Some(Flooded { level, index }) = queue.pop() {
+            let (x, y) = (index as i64 % w, index as i64 / w);
+
+            for &(dx, dy, _) in &NEIGHBORS {
+                if outside(x + dx, y + dy) {
+                    continue;
+                }
+
+                let neighbor = ((y + dy) * w + x + dx) as usize;
+                if let (None, Some(height)) = (levels[neighbor], heights[neighbor]) {
+                    let level = height.max(level);
+                    levels[neighbor] = Some(level);
+                    queue.push(Flooded { level, index: neighbor });
+                }
+            }
+        }
+
+        levels
+    }
+
+    /// The lakes the depressions of the map form. Neighboring flooded tiles
+    /// with the same water level are one lake, numbered in image order of
+    /// their first tile. Tiles are `spacing` apart.
+    pub fn lakes(&self, spacing: f32) -> Lakes {
+        let (w, h) = (i64::from(self.header.w), i64::from(self.header.h));
+        let heights = self.tile_heights();
+        let levels = self.water_levels();
+        let depth = |i: usize| match (heights[i], levels[i]) {
+            (Some(height), Some(level)) if level > height => Some((level, level - height)),
+            _ => None,
+        };
+
+        let mut labels = vec![0u32; heights.len()];
+        let mut lakes = Vec::new();
+
+        for start in 0..heights.len() {
+            let level = match depth(start) {
+                Some((level, _)) if labels[start] == 0 => level,
+                _ => continue,
+            };
+
+            let label = lakes.len() as u32 + 1;
+            let mut lake = Lake { label, tiles: 0, level, max_depth: 0f32, volume: 0f64, deepest: (0, 0) };
+            let mut stack = vec![start];
+            labels[start] = label;
+
+            while let Some(i) = stack.pop() {
+                let (x, y) = (i as i64 % w, i as i64 / w);
+                let (_, tile_depth) = depth(i).expect("Only flooded tiles are labeled");
+
+                lake.tiles += 1;
+                lake.volume += f64::from(tile_depth) * f64::from(spacing) * f64::from(spacing);
+                if tile_depth > lake.max_depth {
+                    lake.max_depth = tile_depth;
+                    lake.deepest = (x as u32, y as u32);
+                }
+
+                for &(dx, dy, _) in &NEIGHBORS {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if nx < 0 || ny < 0 || nx >= w || ny >= h {
+                        continue;
+                    }
+
+                    let neighbor = (ny * w + nx) as usize;
+                    if labels[neighbor] == 0 && depth(neighbor).is_some_and(|(neighbor_level, _)| neighbor_level == level) {
+                        labels[neighbor] = label;
+                        stack.push(neighbor);
+                    }
+                }
+            }
+
+            lakes.push(lake);
+        }
+
+        Lakes { labels, lakes }
+    }
+}
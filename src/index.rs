@@ -0,0 +1,347 @@
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::SeekFrom;
+use std::io::prelude::*;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::thread;
+use std::time::UNIX_EPOCH;
+
+use byteorder::LE;
+use byteorder::ReadBytesExt;
+use byteorder::WriteBytesExt;
+
+use crate::Map;
+use crate::MapHeader;
+use crate::TilePoint;
+use crate::cache;
+use crate::limits::Limits;
+use crate::region::Region;
+use crate::status;
+
+const INDEX_MAGIC: &[u8; 4] = b"HMI4";
+const POINT_SIZE: i64 = 8;
+
+/// Where the RLE stream has to be entered to decode a row.
+#[derive(Debug, Clone, Copy)]
+struct RowEntry {
+    /// Byte offset of the control byte of the run covering the row's first tile.
+    offset: u64,
+    /// The tile index that run starts at.
+    tile: u64,
+}
+
+/// Byte offsets of the RLE runs, one entry per map row, so a region can be
+/// decoded without walking the whole point stream.
+#[derive(Debug)]
+pub struct RunIndex {
+    file_len: u64,
+    /// When the map file was last modified, in nanoseconds since the epoch.
+    modified: u64,
+    /// The hash of the header and of the control bytes the rows enter the
+    /// stream at, see `fingerprint`.
+    fingerprint: u64,
+    rows: Vec<RowEntry>,
+}
+
+impl RunIndex {
+    /// Scans the run headers of a map file, skipping over the point payloads.
+    pub fn build(file_location: &str, limits: &Limits) -> io::Result<RunIndex> {
+        let file = File::open(file_location)?;
+        let (file_len, modified) = stamp(&file)?;
+        let mut b = BufReader::new(file);
+
+        let header = MapHeader::parse(&mut b)?;
+        limits.check_header(&header)?;
+
+        let total = u64::from(header.w) * u64::from(header.h);
+        let mut rows = Vec::with_capacity(header.h as usize);
+        let mut controls = Vec::with_capacity(header.h as usize);
+        let mut counter = 0u64;
+
+        while counter < total {
+            let offset = b.stream_position()?;
+            let n = b.read_i8()? as i32;
+
+            let amount = if n >= 0 {
+                let read_size = 1 + n as u64;
+                b.seek_relative(read_size as i64 * POINT_SIZE)?;
+                read_size
+            } else {
+                u64::from(n.unsigned_abs())
+            };
+
+            // Every row starting inside this run enters the stream here.
+            while (rows.len() as u32) < header.h && rows.len() as u64 * u64::from(header.w) < counter + amount {
+                rows.push(RowEntry { offset, tile: counter });
+                controls.push(n as u8);
+            }
+
+            counter += amount;
+        }
+
+        let fingerprint = cache::fnv1a(header_hash(&header), &controls);
+        Ok(RunIndex { file_len, modified, fingerprint, rows })
+    }
+
+    /// Loads the sidecar index if it still matches the map file, and builds
+    /// (and optionally saves) a new one otherwise.
+    ///
+    /// Indexes are matched by the size and modification time of the file
+    /// and by their fingerprint, which only takes a byte per row to check,
+    /// so opening one never reads the whole map.
+    pub fn open(file_location: &str, save: bool, limits: &Limits) -> io::Result<RunIndex> {
+        let (file_len, modified) = stamp(&File::open(file_location)?)?;
+
+        if let Ok(index) = RunIndex::load(file_location) {
+            if index.file_len == file_len && index.modified == modified
+                && fingerprint(file_location, &index.rows).ok() == Some(index.fingerprint) {
+                return Ok(index);
+            }
+        }
+
+        let index = RunIndex::build(file_location, limits)?;
+
+        if save {
+            index.save(file_location)?;
+            status!("Saved run index: {}", sidecar_path(file_location).display());
+        }
+
+        Ok(index)
+    }
+
+    fn load(file_location: &str) -> io::Result<RunIndex> {
+        let mut r = BufReader::new(File::open(sidecar_path(file_location))?);
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+
+        if &magic != INDEX_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Unknown index format"));
+        }
+
+        let file_len = r.read_u64::<LE>()?;
+        let modified = r.read_u64::<LE>()?;
+        let fingerprint = r.read_u64::<LE>()?;
+        let count = r.read_u32::<LE>()? as usize;
+        let mut rows = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            rows.push(RowEntry { offset: r.read_u64::<LE>()?, tile: r.read_u64::<LE>()? });
+        }
+
+        Ok(RunIndex { file_len, modified, fingerprint, rows })
+    }
+
+    fn save(&self, file_location: &str) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(sidecar_path(file_location))?);
+
+        w.write_all(INDEX_MAGIC)?;
+        w.write_u64::<LE>(self.file_len)?;
+        w.write_u64::<LE>(self.modified)?;
+        w.write_u64::<LE>(self.fingerprint)?;
+        w.write_u32::<LE>(self.rows.len() as u32)?;
+
+        for row in &self.rows {
+            w.write_u64::<LE>(row.offset)?;
+            w.write_u64::<LE>(row.tile)?;
+        }
+
+        w.flush()
+    }
+}
+
+/// Decodes the tiles of the given map rows and columns, entering the RLE
+/// stream at the run index entry of the first row.
+fn read_rows(file_location: &str, width: u32, index: &RunIndex, rows: Range<u32>, columns: Range<u32>)
+    -> io::Result<Vec<Option<TilePoint>>> {
+    let mut b = BufReader::new(File::open(file_location)?);
+    let first_tile = u64::from(rows.start) * u64::from(width);
+    let end_tile = u64::from(rows.end) * u64::from(width);
+    let entry = index.rows[rows.start as usize];
+
+    b.seek(SeekFrom::Start(entry.offset))?;
+
+    let mut tiles = Vec::with_capacity(rows.len() * columns.len());
+    let mut counter = entry.tile;
+
+    while counter < end_tile {
+        let n = b.read_i8()? as i32;
+        let enabled = n >= 0;
+        let amount = if enabled { 1 + n as u64 } else { u64::from(n.unsigned_abs()) };
+
+        for tile in counter..counter + amount {
+            let point = if enabled { Some(TilePoint::parse(&mut b)?) } else { None };
+
+            if tile >= first_tile && tile < end_tile && columns.contains(&((tile % u64::from(width)) as u32)) {
+                tiles.push(point);
+            }
+        }
+
+        counter += amount;
+    }
+
+    Ok(tiles)
+}
+
+/// The size and modification time of a map file, which an index has to
+/// have been built at.
+fn stamp(file: &File) -> io::Result<(u64, u64)> {
+    let metadata = file.metadata()?;
+    let modified = metadata.modified().ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_nanos() as u64);
+
+    Ok((metadata.len(), modified))
+}
+
+fn header_hash(header: &MapHeader) -> u64 {
+    cache::fnv1a(cache::fnv1a(cache::FNV_OFFSET, &header.to_bytes()), &header.raw_name)
+}
+
+/// Hashes the header of the map file with the control bytes at the offsets
+/// of `rows`, reading one byte per row instead of the whole file. A stream
+/// whose runs moved no longer has the same bytes at every offset.
+fn fingerprint(file_location: &str, rows: &[RowEntry]) -> io::Result<u64> {
+    let mut file = File::open(file_location)?;
+    let header = MapHeader::parse(&mut BufReader::new(&file))?;
+    let mut controls = Vec::with_capacity(rows.len());
+
+    for row in rows {
+        file.seek(SeekFrom::Start(row.offset))?;
+        controls.push(file.read_u8()?);
+    }
+
+    Ok(cache::fnv1a(header_hash(&header), &controls))
+}
+
+fn sidecar_path(file_location: &str) -> PathBuf {
+    PathBuf::from(String::from(file_location) + ".idx")
+}
+
+impl Map {
+    /// Decodes only the tiles inside `region` (in decoded image coordinates)
+    /// into a smaller map, seeking straight to the first needed row.
+    pub fn parse_region(file_location: &str, index: &RunIndex, region: &Region, limits: &Limits) -> io::Result<Map> {
+        let mut b = BufReader::new(File::open(file_location)?);
+        let mut header = MapHeader::parse(&mut b)?;
+
+        limits.check_header(&header)?;
+
+        if region.w == 0 || region.h == 0 || !region.fits(header.w, header.h) || index.rows.len() != header.h as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                "Region {:?} is outside of the {}x{} map", region, header.w, header.h)));
+        }
+
+        // Image rows are flipped, so the region's bottom row comes first in the file.
+        let first_row = header.h - region.y - region.h;
+        let tiles = read_rows(file_location, header.w, index, first_row..first_row + region.h,
+                              region.x..region.x + region.w)?;
+
+        header.w = region.w;
+        header.h = region.h;
+
+        let mut map = Map { header, points: Vec::new(), enabled: Vec::new(), runs: Vec::new() };
+        map.set_tiles(tiles);
+
+        Ok(map)
+    }
+
+    /// Decodes the whole map with `threads` workers, each handling an
+    /// independent range of rows located through the run index.
+    pub fn parse_parallel(file_location: &str, index: &RunIndex, threads: usize, limits: &Limits) -> io::Result<Map> {
+        let mut b = BufReader::new(File::open(file_location)?);
+        let header = MapHeader::parse(&mut b)?;
+
+        limits.check_header(&header)?;
+
+        if index.rows.len() != header.h as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Run index does not match the map"));
+        }
+
+        let width = header.w;
+        let rows_per_thread = (header.h as usize).div_ceil(threads.max(1)).max(1) as u32;
+        let chunks: Vec<Range<u32>> = (0..header.h)
+            .step_by(rows_per_thread as usize)
+            .map(|start| start..(start + rows_per_thread).min(header.h))
+            .collect();
+
+        let results: Vec<io::Result<Vec<Option<TilePoint>>>> = thread::scope(|scope| {
+            let workers: Vec<_> = chunks.into_iter()
+                .map(|rows| scope.spawn(move || read_rows(file_location, width, index, rows, 0..width)))
+                .collect();
+
+            workers.into_iter()
+                .map(|worker| worker.join().expect("Decoding thread panicked"))
+                .collect()
+        });
+
+        let mut tiles = Vec::with_capacity(header.w as usize * header.h as usize);
+        for result in results {
+            tiles.extend(result?);
+        }
+
+        let mut map = Map { header, points: Vec::new(), enabled: Vec::new(), runs: Vec::new() };
+        map.set_tiles(tiles);
+
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use crate::Map;
+    use crate::fixture;
+    use crate::limits::Limits;
+    use crate::region::Region;
+
+    use super::RunIndex;
+
+    /// The fixture written to a file of its own, as the index reads files.
+    fn fixture_file(case: &str) -> String {
+        let path = env::temp_dir().join(format!("gti2bmp-index-{}-{}.gti", process::id(), case));
+        fs::write(&path, fixture::map_bytes(&fixture::RUNS)).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn assert_same(decoded: &Map, expected: &Map) {
+        // Points have no equality, but their debug output lists every field.
+        let points = |map: &Map| format!("{:?}", map.tiles());
+
+        assert_eq!(decoded.header.to_bytes(), expected.header.to_bytes());
+        assert_eq!(decoded.header.raw_name, expected.header.raw_name);
+        assert_eq!(decoded.enabled, expected.enabled);
+        assert_eq!(points(decoded), points(expected));
+    }
+
+    #[test]
+    fn parallel_decodes_match_the_serial_one() {
+        let file = fixture_file("parallel");
+        let expected = Map::from_reader(&fixture::map_bytes(&fixture::RUNS)[..]).unwrap();
+        let index = RunIndex::build(&file, &Limits::default()).unwrap();
+
+        for threads in 1..=4 {
+            assert_same(&Map::parse_parallel(&file, &index, threads, &Limits::default()).unwrap(), &expected);
+        }
+        fs::remove_file(&file).ok();
+    }
+
+    #[test]
+    fn region_decodes_match_cropping_the_serial_one() {
+        let file = fixture_file("region");
+        let map = Map::from_reader(&fixture::map_bytes(&fixture::RUNS)[..]).unwrap();
+        let index = RunIndex::build(&file, &Limits::default()).unwrap();
+
+        let whole = Region { x: 0, y: 0, w: fixture::W, h: fixture::H };
+        for region in [whole, Region { x: 1, y: 0, w: 2, h: 2 }, Region { x: 3, y: 2, w: 1, h: 1 }] {
+            let decoded = Map::parse_region(&file, &index, &region, &Limits::default()).unwrap();
+            assert_same(&decoded, &map.crop(&region));
+        }
+        fs::remove_file(&file).ok();
+    }
+}
@@ -0,0 +1,377 @@
+//! Decoding, editing and exporting of heightmap files, shared by the
+//! `gti2bmp` command line tool.
+
+use std::io;
+use std::io::prelude::*;
+
+use byteorder::ByteOrder;
+use byteorder::LE;
+use byteorder::ReadBytesExt;
+use serde_json::Value;
+use serde_json::json;
+
+use error::Counting;
+use error::HeightmapError;
+use limits::Limits;
+use unknowns::UnknownFields;
+
+pub mod cache;
+pub mod checkpoint;
+pub mod colors;
+pub mod compare;
+pub mod contours;
+#[cfg(feature = "async")]
+pub mod decode_async;
+pub mod dedup;
+pub mod despike;
+pub mod distance;
+pub mod diff;
+pub mod edit;
+pub mod encode;
+pub mod error;
+pub mod export;
+pub mod extract;
+pub mod fill;
+#[cfg(test)]
+mod fixture;
+pub mod heights;
+pub mod hydrology;
+pub mod index;
+pub mod limits;
+pub mod mask;
+pub mod ops;
+pub mod outliers;
+pub mod patch;
+pub mod polygon;
+pub mod precision;
+pub mod quantize;
+pub mod raster;
+pub mod region;
+pub mod resize;
+pub mod rotate;
+pub mod session;
+pub mod stats;
+pub mod status;
+pub mod stitch;
+pub mod summary;
+pub mod timings;
+pub mod unknowns;
+
+pub fn get_position(index: &usize, width: &u32, height: &u32) -> (u32, u32) {
+    let i = *index as u32;
+    let x = i % width;
+    let y = height - 1 - (i / width);
+
+    (x, y)
+}
+
+/// How many bytes of the header come before the map name, which fills
+/// the rest of it.
+pub const HEADER_FIELDS_SIZE: usize = 0x40;
+
+/// The size of the zero padded name field.
+pub const NAME_SIZE: usize = 0x20;
+
+/// The first four bytes of every map, `GTI ` in ASCII.
+pub const SIGNATURE: u32 = 0x2049_5447;
+
+#[derive(Debug, Clone)]
+pub struct MapHeader {
+    pub signature: u32,
+    pub unk: u32,
+    pub min_height: f32,
+    pub max_height: f32,
+    pub w: u32,
+    pub h: u32,
+    pub unknowns: UnknownFields,
+    pub name: String,
+    /// The name field as stored, written back unchanged while it still
+    /// decodes to `name`, so bytes after the terminator survive.
+    pub raw_name: Vec<u8>,
+}
+
+impl MapHeader {
+    pub fn parse(file: &mut impl Read) -> Result<MapHeader, HeightmapError> {
+        let mut file = Counting::new(file, 0);
+
+        let mut raw = [0u8; HEADER_FIELDS_SIZE];
+        file.read_exact(&mut raw).map_err(|e| file.error(e))?;
+
+        let mut name = [0u8; NAME_SIZE];
+        file.read_exact(&mut name).map_err(|e| file.error(e))?;
+
+        let header = MapHeader::from_bytes(&raw, &name);
+        header.check_signature()?;
+
+        Ok(header)
+    }
+
+    /// Checks that the header starts with the map signature, so other files
+    /// aren't decoded as garbage.
+    pub fn check_signature(&self) -> Result<(), HeightmapError> {
+        if self.signature != SIGNATURE {
+            return Err(HeightmapError::InvalidSignature { found: self.signature });
+        }
+
+        Ok(())
+    }
+
+    /// Reads the fields from the header bytes before the name, followed by
+    /// the name field.
+    pub fn from_bytes(raw: &[u8; HEADER_FIELDS_SIZE], name: &[u8]) -> MapHeader {
+        MapHeader {
+            signature: LE::read_u32(&raw[0x00..]),
+            unk: LE::read_u32(&raw[0x04..]),
+            min_height: LE::read_f32(&raw[0x10..]),
+            max_height: LE::read_f32(&raw[0x14..]),
+            w: LE::read_u32(&raw[0x18..]),
+            h: LE::read_u32(&raw[0x1c..]),
+            unknowns: UnknownFields::from_bytes(raw),
+            name: decode_name(name),
+            raw_name: name.to_vec(),
+        }
+    }
+
+    /// The header bytes before the name, unknown fields included.
+    pub fn to_bytes(&self) -> [u8; HEADER_FIELDS_SIZE] {
+        let mut raw = [0u8; HEADER_FIELDS_SIZE];
+
+        LE::write_u32(&mut raw[0x00..], self.signature);
+        LE::write_u32(&mut raw[0x04..], self.unk);
+        LE::write_f32(&mut raw[0x10..], self.min_height);
+        LE::write_f32(&mut raw[0x14..], self.max_height);
+        LE::write_u32(&mut raw[0x18..], self.w);
+        LE::write_u32(&mut raw[0x1c..], self.h);
+        self.unknowns.write_to(&mut raw);
+
+        raw
+    }
+
+    /// The horizontal distance between tiles, in height units, taken from
+    /// `u5`, which looks like the map's scale. `None` unless it reads as a
+    /// positive number.
+    pub fn tile_spacing(&self) -> Option<f32> {
+        self.unknowns.get("u5")
+            .filter(|field| field.raw.len() == 4)
+            .map(|field| LE::read_f32(&field.raw))
+            .filter(|spacing| spacing.is_finite() && *spacing > 0f32)
+    }
+
+    /// The header as a JSON object, with the unknown fields keyed by their
+    /// placeholder names.
+    pub fn to_json(&self) -> Value {
+        let unknowns: serde_json::Map<String, Value> = self.unknowns.iter()
+            .map(|field| (String::from(field.name), field.to_json()))
+            .collect();
+
+        json!({
+            "signature": self.signature,
+            "unk": self.unk,
+            "min_height": self.min_height,
+            "max_height": self.max_height,
+            "w": self.w,
+            "h": self.h,
+            "name": self.name,
+            "unknowns": unknowns,
+        })
+    }
+
+    /// Every field as a `(name, value)` pair, in file order.
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            (0x00, "signature", format!("{:#010x}", self.signature)),
+            (0x04, "unk", self.unk.to_string()),
+            (0x10, "min_height", format!("{:?}", self.min_height)),
+            (0x14, "max_height", format!("{:?}", self.max_height)),
+            (0x18, "w", self.w.to_string()),
+            (0x1c, "h", self.h.to_string()),
+            (HEADER_FIELDS_SIZE, "name", self.name.clone()),
+        ];
+        fields.extend(self.unknowns.iter().map(|field| (field.offset, field.name, field.value())));
+        fields.sort_by_key(|&(offset, _, _)| offset);
+
+        fields.into_iter().map(|(_, name, value)| (name, value)).collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Map {
+    pub header: MapHeader,
+    pub points: Vec<TilePoint>,
+    pub enabled: Vec<u8>,
+    /// The run control bytes of the file the map was decoded from. They are
+    /// written back as long as they still describe `enabled`, so unedited
+    /// maps encode byte for byte, even where runs were split differently.
+    pub runs: Vec<i8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TilePoint {
+    pub h: f32,
+    pub unk: u8,
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    /// Whether an edit made the point up for a disabled tile, rather than it
+    /// being decoded. Not part of the file format.
+    pub filled: bool,
+}
+
+impl TilePoint {
+    pub fn parse(file: &mut impl Read) -> io::Result<TilePoint> {
+        Ok(TilePoint {
+            h: file.read_f32::<LE>()?,
+            unk: file.read_u8()?,
+            r: file.read_u8()?,
+            g: file.read_u8()?,
+            b: file.read_u8()?,
+            filled: false,
+        })
+    }
+}
+
+/// The enabled mask, the points and the run control bytes of a map.
+pub type DecodedPoints = (Vec<u8>, Vec<TilePoint>, Vec<i8>);
+
+/// Decodes the run-length encoded points following the header.
+pub fn parse_points(header: &MapHeader, limits: &Limits, b: &mut impl Read) -> Result<DecodedPoints, HeightmapError> {
+    let mut b = Counting::new(b, (HEADER_FIELDS_SIZE + NAME_SIZE) as u64);
+    // In u64, as the limits may allow more than 2^32 tiles.
+    let total = u64::from(header.w) * u64::from(header.h);
+    let mut counter = 0u64;
+
+    let size = total as usize;
+
+    let mut points = Vec::with_capacity(size);
+    let mut enabled_points: Vec<u8> = Vec::with_capacity(size);
+    let mut runs = Vec::new();
+
+    let read_error = |b: &Counting<_>, counter: u64, e: io::Error| read_error(b.offset, counter, total, e);
+
+    while counter < total {
+        let start = b.offset;
+        let n = b.read_i8().map_err(|e| read_error(&b, counter, e))? as i32;
+        runs.push(n as i8);
+
+        // Negative values = skip |n|
+        // Positive value = read n + 1
+
+        let enabled = n >= 0;
+        let amount = if n >= 0 {
+            let read_size = 1 + n as u64;
+            limits.check_points(points.len() as u64 + read_size)
+                .map_err(|e| HeightmapError::Io { offset: Some(start), source: e })?;
+
+            for _ in 0..read_size {
+                points.push(TilePoint::parse(&mut b).map_err(|e| read_error(&b, counter, e))?);
+            }
+
+            read_size
+        } else {
+            u64::from(n.unsigned_abs())
+        };
+
+        check_run(start, counter, amount, total)?;
+
+        enabled_points.extend(vec![if enabled { 1 } else { 0 }; amount as usize]);
+        counter += amount;
+    }
+
+    Ok((enabled_points, points, runs))
+}
+
+/// The error of a read at `offset` failing after `counter` of the `total`
+/// tiles: the stream is cut off where it ends early, anything else is
+/// passed on.
+pub(crate) fn read_error(offset: u64, counter: u64, total: u64, e: io::Error) -> HeightmapError {
+    match e.kind() {
+        io::ErrorKind::UnexpectedEof => HeightmapError::TruncatedRle { offset, tiles: counter, total },
+        _ => HeightmapError::Io { offset: Some(offset), source: e },
+    }
+}
+
+/// Fails if the run of `amount` tiles after the first `counter`, whose
+/// control byte is at `start`, goes past the `total` tiles of the map.
+pub(crate) fn check_run(start: u64, counter: u64, amount: u64, total: u64) -> Result<(), HeightmapError> {
+    if counter + amount > total {
+        return Err(HeightmapError::PointCountMismatch { offset: start, tiles: counter + amount, total });
+    }
+
+    Ok(())
+}
+
+impl Map {
+    /// Iterates the enabled tiles as `(tile index, point index)` pairs.
+    pub fn enabled_tiles(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.enabled.iter()
+            .enumerate()
+            .filter(|&(_, &enabled)| enabled > 0u8)
+            .enumerate()
+            .map(|(offset, (index, _))| (index, offset))
+    }
+
+    /// Expands the sparse point list into one optional point per tile.
+    pub fn tiles(&self) -> Vec<Option<TilePoint>> {
+        let mut tiles = vec![None; self.enabled.len()];
+
+        for (index, offset) in self.enabled_tiles() {
+            tiles[index] = Some(self.points[offset]);
+        }
+
+        tiles
+    }
+
+    /// Replaces the enabled mask and points from one optional point per tile.
+    pub fn set_tiles(&mut self, tiles: Vec<Option<TilePoint>>) {
+        self.enabled = tiles.iter()
+            .map(|tile| if tile.is_some() { 1 } else { 0 })
+            .collect();
+        self.points = tiles.into_iter().flatten().collect();
+    }
+
+    /// Checks that the mask holds one entry per tile and that there is exactly
+    /// one point per enabled tile, which every lookup of a point by its
+    /// enabled offset relies on.
+    pub fn validate(&self) -> io::Result<()> {
+        let tiles = self.header.w as usize * self.header.h as usize;
+        if self.enabled.len() != tiles {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+                "The mask has {} entries for {}x{} tiles", self.enabled.len(), self.header.w, self.header.h)));
+        }
+
+        let enabled = self.enabled.iter().filter(|&&enabled| enabled > 0).count();
+        if self.points.len() != enabled {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+                "The map has {} points for {} enabled tiles", self.points.len(), enabled)));
+        }
+
+        Ok(())
+    }
+
+    pub fn parse(file: &mut impl Read, limits: &Limits) -> Result<Map, HeightmapError> {
+        let header = MapHeader::parse(file)?;
+
+        limits.check_header(&header).map_err(|e| HeightmapError::Io { offset: Some(0), source: e })?;
+
+        let (enabled, points, runs) = parse_points(&header, limits, file)?;
+
+        let map = Map { header, points, enabled, runs };
+        map.validate()?;
+
+        Ok(map)
+    }
+
+    /// Decodes a map with the default limits. Wrap unbuffered sources in a
+    /// `BufReader`, the stream is read in small pieces.
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Map, HeightmapError> {
+        Map::parse(&mut reader, &Limits::default())
+    }
+}
+
+/// The name up to its zero padding, empty if it isn't UTF-8.
+fn decode_name(bytes: &[u8]) -> String {
+    String::from_utf8(bytes.to_vec())
+        .unwrap_or_default()
+        .trim_matches(char::from(0))
+        .to_string()
+}
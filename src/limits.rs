@@ -0,0 +1,68 @@
+use std::io;
+
+use clap::Args;
+
+use crate::MapHeader;
+
+/// Upper bounds checked before anything is allocated for a map, so a
+/// malformed or hostile file can't exhaust memory.
+#[derive(Args, Debug, Clone)]
+pub struct Limits {
+    /// Reject maps wider than this many tiles.
+    #[arg(long, default_value_t = 16384)]
+    pub max_width: u32,
+
+    /// Reject maps taller than this many tiles.
+    #[arg(long, default_value_t = 16384)]
+    pub max_height: u32,
+
+    /// Reject maps with more tiles or points than this.
+    #[arg(long, default_value_t = 1 << 28)]
+    pub max_points: u64,
+
+    /// Reject files larger than this many bytes.
+    #[arg(long, default_value_t = 4 << 30)]
+    pub max_file_size: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_width: 16384,
+            max_height: 16384,
+            max_points: 1 << 28,
+            max_file_size: 4 << 30,
+        }
+    }
+}
+
+impl Limits {
+    pub fn check_file_size(&self, len: u64) -> io::Result<()> {
+        if len > self.max_file_size {
+            return Err(limit_error(format!("File is {} bytes, the limit is {}", len, self.max_file_size)));
+        }
+
+        Ok(())
+    }
+
+    pub fn check_header(&self, header: &MapHeader) -> io::Result<()> {
+        if header.w > self.max_width || header.h > self.max_height {
+            return Err(limit_error(format!("Map is {}x{}, the limit is {}x{}",
+                header.w, header.h, self.max_width, self.max_height)));
+        }
+
+        self.check_points(u64::from(header.w) * u64::from(header.h))
+    }
+
+    pub fn check_points(&self, count: u64) -> io::Result<()> {
+        if count > self.max_points {
+            return Err(limit_error(format!("Map holds {} points, the limit is {}", count, self.max_points)));
+        }
+
+        Ok(())
+    }
+}
+
+fn limit_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
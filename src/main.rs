@@ -1,225 +1,1392 @@
-use std::env;
+use std::any::Any;
 use std::ffi::OsStr;
+use std::fmt;
 use std::fs;
 use std::fs::File;
 use std::io;
 use std::io::BufReader;
+use std::io::Cursor;
 use std::io::prelude::*;
 use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::time::Instant;
 
-use bmp::Image;
-use bmp::Pixel;
-use byteorder::LE;
-use byteorder::ReadBytesExt;
+use clap::Args;
+use clap::Parser;
+use clap::Subcommand;
+use clap::ValueEnum;
+use serde_json::Value;
 
-fn main() {
-    let file_location = env::args()
-        .nth(1)
-        .expect("No file provided.");
+use gti2bmp::Map;
+use gti2bmp::MapHeader;
+use gti2bmp::cache;
+use gti2bmp::compare::Comparison;
+use gti2bmp::dedup;
+use gti2bmp::diff;
+use gti2bmp::edit::ClampEdit;
+use gti2bmp::edit::ColorsEdit;
+use gti2bmp::edit::DespikeEdit;
+use gti2bmp::edit::Edit;
+use gti2bmp::edit::ExtractEdit;
+use gti2bmp::edit::FillEdit;
+use gti2bmp::edit::FitRangeEdit;
+use gti2bmp::edit::MaskEdit;
+use gti2bmp::edit::MorphEdit;
+use gti2bmp::edit::OpEdit;
+use gti2bmp::edit::PatchEdit;
+use gti2bmp::edit::PolygonEdit;
+use gti2bmp::edit::QuantizeEdit;
+use gti2bmp::edit::ResizeEdit;
+use gti2bmp::edit::RotateEdit;
+use gti2bmp::export;
+use gti2bmp::export::ExportOptions;
+use gti2bmp::export::Format;
+use gti2bmp::export::MatrixStyle;
+use gti2bmp::export::Normalize;
+use gti2bmp::export::Preset;
+use gti2bmp::fill::FillMethod;
+use gti2bmp::index::RunIndex;
+use gti2bmp::limits;
+use gti2bmp::limits::Limits;
+use gti2bmp::mask::MaskMode;
+use gti2bmp::mask::Morphology;
+use gti2bmp::ops;
+use gti2bmp::precision::Precision;
+use gti2bmp::raster::Raster;
+use gti2bmp::region::Region;
+use gti2bmp::resize;
+use gti2bmp::resize::ResizeFilter;
+use gti2bmp::session::Session;
+use gti2bmp::status;
+use gti2bmp::stitch;
+use gti2bmp::stitch::Seam;
+use gti2bmp::summary;
+use gti2bmp::timings;
+use gti2bmp::unknowns::FieldNotes;
+
+use queue::QueueOptions;
+
+mod batch;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod queue;
+mod server;
 
-    println!("Decoding file: {}", &file_location);
+/// The file name that reads a map from stdin instead.
+const STDIN: &str = "-";
 
-    fs::create_dir_all("./output")
-        .expect("Failed to create output directory");
+#[derive(Parser)]
+#[command(
+    about = "Converts heightmap files into images.",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+)]
+struct Cli {
+    #[command(flatten)]
+    decode: DecodeArgs,
 
-    decode_file(file_location)
-        .expect("File decoding failed.");
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-fn decode_file(file_location: String) -> io::Result<()> {
-    let file = File::open(&file_location)?;
-    let mut b = BufReader::new(file);
+#[derive(Args, Clone)]
+struct DecodeArgs {
+    /// The map file to decode, or `-` to read it from stdin.
+    #[arg(required_unless_present = "batch")]
+    file: Option<String>,
 
-    let map = Map::parse(&mut b)
-        .expect("Failed to parse the map");
+    /// Convert every `.gti` map below a directory, or every file matching a
+    /// glob like `maps/**/*.gti`, keeping their directories in the output.
+    #[arg(long, value_name = "DIR|GLOB", conflicts_with_all = ["file", "save_map"])]
+    batch: Option<String>,
 
-    println!("{:#?}", &map.header);
-    println!("Points: {}", &map.points.len());
-    println!("Enabled: {}", &map.enabled.len());
-    println!("Map Size: {}", &map.header.w * &map.header.h);
+    /// Convert this many batched maps at once, each on its own thread.
+    #[arg(long, value_name = "N", default_value_t = 1, requires = "batch")]
+    jobs: usize,
 
-    // Let's generate a bmp.
-    let file_stem = Path::new(&file_location)
-        .file_stem()
-        .and_then(OsStr::to_str)
-        .unwrap();
+    /// Read every batched map first and render them all over their combined
+    /// height range, from the headers or from the stored heights after the
+    /// edits, so adjacent maps come out seamless and comparable.
+    #[arg(long, value_enum, value_name = "RANGE", requires = "batch", conflicts_with = "height_range")]
+    global_range: Option<Normalize>,
 
-    create_map_image(file_stem, &map);
+    /// Disable tiles where this image is black before exporting.
+    #[arg(long, value_name = "IMAGE")]
+    apply_mask: Option<String>,
 
-    Ok(())
+    /// Whether white mask pixels also enable disabled tiles.
+    #[arg(long, value_enum, default_value = "clip")]
+    mask_mode: MaskMode,
+
+    /// Clamp heights further than SIGMA standard deviations from the mean,
+    /// and NaN or infinite heights, before exporting.
+    #[arg(long, value_name = "SIGMA")]
+    clamp: Option<f32>,
+
+    /// Replace tiles standing more than THRESHOLD above or below all of their
+    /// neighbors with the neighbors' mean height.
+    #[arg(long, value_name = "THRESHOLD")]
+    despike: Option<f32>,
+
+    /// Enable the disabled tiles, interpolating their heights with METHOD.
+    #[arg(long, value_enum, value_name = "METHOD")]
+    fill: Option<FillMethod>,
+
+    /// How far in tiles IDW looks for enabled tiles and natural neighbor
+    /// hands heights on.
+    #[arg(long, value_name = "TILES", default_value_t = 16, requires = "fill")]
+    fill_radius: u32,
+
+    /// How many relaxation passes Laplace runs.
+    #[arg(long, value_name = "PASSES", default_value_t = 500, requires = "fill")]
+    fill_iterations: u32,
+
+    /// Smooth this many tiles on both sides of the filled holes' edges.
+    #[arg(long, value_name = "TILES", default_value_t = 0, requires = "fill")]
+    fill_feather: u32,
+
+    /// Disable the tiles outside of the polygon in this GeoJSON or WKT file,
+    /// given in tile coordinates of the decoded image.
+    #[arg(long, value_name = "PATH")]
+    polygon: Option<String>,
+
+    /// Crop the map to the bounding box of `--polygon`.
+    #[arg(long, requires = "polygon")]
+    polygon_crop: bool,
+
+    /// Clean up the enabled mask after masking: erode or open to drop
+    /// small enabled specks, dilate or close to fill pinholes.
+    #[arg(long, value_enum, value_name = "OPERATION")]
+    morph: Option<Morphology>,
+
+    /// How many tiles around each tile `--morph` looks at.
+    #[arg(long, value_name = "TILES", default_value_t = 1, requires = "morph")]
+    morph_radius: u32,
+
+    /// Rotate the map clockwise by this many degrees, enlarging it to fit.
+    #[arg(long, value_name = "DEGREES", allow_hyphen_values = true)]
+    rotate_deg: Option<f64>,
+
+    /// Overwrite the tile colors with the pixels of this RGB image.
+    #[arg(long, value_name = "IMAGE")]
+    set_colors: Option<String>,
+
+    /// Reduce the tile colors to at most N by median cut and print the
+    /// palette, for tools expecting an indexed color layer. Also applies to
+    /// `--save-map`.
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..=256))]
+    quantize_colors: Option<u32>,
+
+    /// Resample the map to `WxH` tiles after the other edits, e.g. `513x513`
+    /// for engines wanting power-of-two-plus-one sizes. `--origin` and
+    /// `--cell-size` then describe the resampled tiles.
+    #[arg(long, value_name = "WxH", value_parser = resize::parse_size)]
+    resize: Option<(u32, u32)>,
+
+    /// How `--resize` interpolates the heights.
+    #[arg(long, value_enum, default_value = "bilinear", requires = "resize")]
+    resize_filter: ResizeFilter,
+
+    /// How much of the highest height `--resize-filter peaks` keeps, from 0
+    /// for the mean of the covered tiles to 1 for their maximum.
+    #[arg(long, value_name = "WEIGHT", default_value_t = 0.5, value_parser = resize::parse_weight, requires = "resize")]
+    resize_peak_weight: f32,
+
+    /// Reuse a previously decoded copy of the map when its contents are unchanged.
+    #[arg(long)]
+    cache: bool,
+
+    /// Where cached maps are kept, defaults to `~/.cache/heightmap-generator`.
+    #[arg(long, value_name = "DIR")]
+    cache_dir: Option<PathBuf>,
+
+    /// Only decode the tiles inside `x,y,w,h`, seeking through a run index.
+    /// `--save-map` then writes the smaller map, and georeferenced outputs
+    /// stay lined up with the full map.
+    #[arg(long)]
+    crop: Option<Region>,
+
+    /// Keep the run index next to the map as `<file>.idx` for later crops.
+    #[arg(long)]
+    save_index: bool,
+
+    /// Decode row ranges on this many threads, located through a run index.
+    #[arg(long, default_value_t = 1)]
+    threads: usize,
+
+    /// Set the header height range to the range of the stored heights, or
+    /// with `--fit-range=MIN,MAX` to that range with the stored heights
+    /// stretched onto it, e.g. to tighten a wastefully wide range before
+    /// `--save-map`. Applied after every other edit.
+    #[arg(long, value_name = "MIN,MAX", num_args = 0..=1, require_equals = true,
+          value_parser = ops::parse_height_range)]
+    fit_range: Option<Option<(f32, f32)>>,
+
+    /// Also write the edited map back in the game's format.
+    #[arg(long, value_name = "PATH")]
+    save_map: Option<PathBuf>,
+
+    /// Stream the one `--format` output to stdout instead of writing files,
+    /// printing the progress messages to stderr. Raster formats only, and
+    /// nothing that writes more than one file.
+    #[arg(long, conflicts_with_all = ["batch", "dry_run", "output", "tile_size", "layers", "color_map"])]
+    stdout: bool,
+
+    /// Append a row describing this conversion to a CSV file, for auditing
+    /// many converted maps at once.
+    #[arg(long, value_name = "CSV")]
+    summary: Option<PathBuf>,
+
+    /// Annotate the unknown header fields with the notes in this TOML file,
+    /// on top of the ones built in.
+    #[arg(long, value_name = "TOML")]
+    field_notes: Option<PathBuf>,
+
+    /// Print the wall time and peak memory of every stage of the conversion:
+    /// parse, filters, render and encode.
+    #[arg(long)]
+    timings: bool,
+
+    /// Only read the header and list the files that would be written,
+    /// without decoding the map or writing anything.
+    #[arg(long)]
+    dry_run: bool,
+
+    #[command(flatten)]
+    export: ExportOptions,
+
+    #[command(flatten)]
+    limits: Limits,
+}
+
+impl DecodeArgs {
+    fn file(&self) -> &str {
+        self.file.as_deref().expect("No file provided.")
+    }
+
+    /// The edits requested through flags, in the order they are applied.
+    fn edits(&self) -> Vec<Edit> {
+        let mut edits = Vec::new();
+
+        if let Some(sigma) = self.clamp {
+            edits.push(Edit::Clamp(ClampEdit { sigma }));
+        }
+
+        if let Some(threshold) = self.despike {
+            edits.push(Edit::Despike(DespikeEdit { threshold }));
+        }
+
+        if let Some(method) = self.fill {
+            edits.push(Edit::Fill(FillEdit { method, radius: self.fill_radius, iterations: self.fill_iterations, feather: self.fill_feather }));
+        }
+
+        if let Some(image) = &self.apply_mask {
+            edits.push(Edit::Mask(MaskEdit { image: image.clone(), mode: self.mask_mode }));
+        }
+
+        if let Some(path) = &self.polygon {
+            edits.push(Edit::Polygon(PolygonEdit { path: path.clone(), crop: self.polygon_crop }));
+        }
+
+        if let Some(morphology) = self.morph {
+            edits.push(Edit::Morph(MorphEdit { morphology, radius: self.morph_radius }));
+        }
+
+        if let Some(degrees) = self.rotate_deg {
+            edits.push(Edit::Rotate(RotateEdit { degrees }));
+        }
+
+        if let Some(image) = &self.set_colors {
+            edits.push(Edit::Colors(ColorsEdit { image: image.clone() }));
+        }
+
+        if let Some(colors) = self.quantize_colors {
+            edits.push(Edit::Quantize(QuantizeEdit { colors }));
+        }
+
+        if let Some(size) = self.resize {
+            edits.push(Edit::Resize(ResizeEdit { size, filter: self.resize_filter, peak_weight: self.resize_peak_weight }));
+        }
+
+        if let Some(range) = self.fit_range {
+            edits.push(Edit::FitRange(FitRangeEdit { range }));
+        }
+
+        edits
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Decodes a map and writes the outputs, like running without a
+    /// subcommand.
+    Export(DecodeArgs),
+    /// Decodes and checks a map, printing its header and tile counts,
+    /// without exporting it.
+    Decode(DecodeArgs),
+    /// Prints the header of a map, without decoding the tiles.
+    Info {
+        file: String,
+
+        /// Annotate the unknown header fields with the notes in this TOML
+        /// file, on top of the ones built in.
+        #[arg(long, value_name = "TOML")]
+        field_notes: Option<PathBuf>,
+
+        /// Print the header as a single line of JSON instead, with the file
+        /// name under `file`.
+        #[arg(long)]
+        json: bool,
+
+        #[command(flatten)]
+        limits: Limits,
+    },
+    /// Adjusts heights numerically before exporting the map.
+    Op(OpArgs),
+    /// Splices the heights of a grayscale image into the map.
+    Patch(PatchArgs),
+    /// Exports only a circular area around a point of interest.
+    Extract(ExtractArgs),
+    /// Writes the enabled tiles as a 3D surface to preview in Blender and
+    /// the like, spaced by the header scale and colored like the tiles.
+    Mesh(MeshArgs),
+    /// Compares the headers of two map files field by field.
+    DiffHeaders {
+        a: String,
+        b: String,
+
+        /// Read the unknown fields as the kinds given in this TOML file.
+        #[arg(long, value_name = "TOML")]
+        field_notes: Option<PathBuf>,
+    },
+    /// Exports only the area where map `b` differs from map `a`, as a patch
+    /// that can be shared instead of the whole map, with its place in the
+    /// map in `<name>.patch.json`.
+    DiffPatch {
+        a: String,
+        b: String,
+
+        /// The largest height difference that still counts as unchanged.
+        #[arg(long, default_value_t = 0.0)]
+        threshold: f32,
+
+        /// How many unchanged tiles around the changes are kept for stitching.
+        #[arg(long, default_value_t = 1)]
+        margin: u32,
+
+        /// Disable the tiles further than the margin from any change, instead
+        /// of keeping the whole bounding box of the changes.
+        #[arg(long)]
+        changed_only: bool,
+
+        #[command(flatten)]
+        export: ExportOptions,
+
+        #[command(flatten)]
+        limits: Limits,
+    },
+    /// Compares two maps: their headers field by field, how many tiles
+    /// changed, and `b - a` exported as `<a>_to_<b>_diff`, mid gray where
+    /// nothing changed. Float TIFF keeps the exact signed differences.
+    Diff {
+        a: String,
+        b: String,
+
+        /// The largest height difference that still counts as unchanged.
+        #[arg(long, default_value_t = 0.0)]
+        threshold: f32,
+
+        /// Read the unknown fields as the kinds given in this TOML file.
+        #[arg(long, value_name = "TOML")]
+        field_notes: Option<PathBuf>,
+
+        #[command(flatten)]
+        export: ExportOptions,
+
+        #[command(flatten)]
+        limits: Limits,
+    },
+    /// Assembles adjacent maps of the same size into one mosaic and exports
+    /// it as `<name>`. Give each map its grid place as `<file>@column,row`,
+    /// or read the places from two header fields with `--position-fields`.
+    Stitch {
+        #[arg(required = true, value_parser = stitch::parse_input)]
+        inputs: Vec<(String, Option<(u32, u32)>)>,
+
+        /// The unknown header fields holding each map's column and row, as
+        /// `FIELD,FIELD`, for maps without an explicit place.
+        #[arg(long, value_name = "X,Y", value_parser = parse_field_pair)]
+        position_fields: Option<(String, String)>,
+
+        /// How many rows and columns of tiles neighboring maps share.
+        #[arg(long, default_value_t = 0)]
+        overlap: u32,
+
+        /// How the tiles where maps overlap are settled.
+        #[arg(long, value_enum, default_value = "average")]
+        seam: Seam,
+
+        /// The file name of the outputs, without the extension.
+        #[arg(long, default_value = "stitched")]
+        name: String,
+
+        /// Also write the mosaic in the game's format.
+        #[arg(long, value_name = "PATH")]
+        save_map: Option<PathBuf>,
+
+        #[command(flatten)]
+        export: ExportOptions,
+
+        #[command(flatten)]
+        limits: Limits,
+    },
+    /// Renders the shaded reliefs of two maps next to their difference.
+    CompareRender {
+        a: String,
+        b: String,
+
+        /// The image formats to write.
+        #[arg(long = "format", value_enum, value_delimiter = ',', default_value = "png")]
+        formats: Vec<Format>,
+
+        #[command(flatten)]
+        limits: Limits,
+    },
+    /// Renders one map in a labeled grid with a row per style and a column
+    /// per normalization, to pick the settings that show it best.
+    RenderMatrix {
+        file: String,
+
+        /// The styles to compare, one row each.
+        #[arg(long, value_enum, value_delimiter = ',', default_value = "gray,hypsometric,shaded,terrain,viridis")]
+        styles: Vec<MatrixStyle>,
+
+        /// The normalizations to compare, one column each.
+        #[arg(long, value_enum, value_delimiter = ',', default_value = "header,data")]
+        normalizations: Vec<Normalize>,
+
+        /// The image formats to write.
+        #[arg(long = "format", value_enum, value_delimiter = ',', default_value = "png")]
+        formats: Vec<Format>,
+
+        #[command(flatten)]
+        limits: Limits,
+    },
+    /// Reports maps with identical or nearly identical contents.
+    Dedup {
+        #[arg(required = true)]
+        files: Vec<String>,
+
+        /// The largest height difference of maps with the same enabled
+        /// tiles that still counts as nearly identical.
+        #[arg(long, default_value_t = 0.001)]
+        tolerance: f32,
+
+        #[command(flatten)]
+        limits: Limits,
+    },
+    /// Lists the game presets and the files each of them writes.
+    Presets,
+    /// Checks whether two images match within a tolerance, e.g. against golden files.
+    CompareImages {
+        a: String,
+        b: String,
+
+        /// The largest allowed difference per sample, from 0 to 1.
+        #[arg(long, default_value_t = 0.0)]
+        tolerance: f32,
+
+        /// Write an image highlighting the differences.
+        #[arg(long, value_name = "PATH")]
+        diff_image: Option<PathBuf>,
+    },
+    /// Reports how finely the stored heights are quantized.
+    Precision(DecodeArgs),
+    /// Lists NaN, infinite and statistically unlikely heights.
+    Outliers {
+        /// How many standard deviations from the mean count as an outlier.
+        #[arg(long, default_value_t = 4.0)]
+        sigma: f32,
+
+        /// The most suspect tiles to list per category.
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+
+        #[command(flatten)]
+        decode: Box<DecodeArgs>,
+    },
+    /// Reports the distribution of the stored heights over the enabled
+    /// tiles, which often differs from the range in the header.
+    Stats {
+        /// The steps of the height histogram.
+        #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u32).range(1..))]
+        bins: u32,
+
+        /// Also draw the histogram as `<output dir>/<name>_histogram.png`.
+        #[arg(long)]
+        histogram_image: bool,
+
+        #[command(flatten)]
+        decode: Box<DecodeArgs>,
+    },
+    /// Converts maps queued as JSON jobs on a pool of workers, writing a
+    /// result manifest for each, e.g. `{"file": "a.gti", "args": ["--format", "png"]}`.
+    Queue(QueueOptions),
+    /// Serves `POST /decode` and `GET /render?id=...&format=...&pixel_format=...&palette=...`
+    /// over HTTP, for web tools converting uploaded maps.
+    Server {
+        /// The address to listen on.
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        address: String,
+
+        /// How many requests are handled at once.
+        #[arg(long, default_value_t = 4)]
+        workers: usize,
+
+        /// How many decoded maps are kept for rendering, oldest dropped first.
+        #[arg(long, default_value_t = 16)]
+        max_maps: usize,
+
+        #[command(flatten)]
+        limits: Limits,
+    },
+    /// Serves the `heightmap.Heightmap` gRPC service from `proto/heightmap.proto`,
+    /// streaming exports back in chunks.
+    #[cfg(feature = "grpc")]
+    Grpc {
+        /// The address to listen on.
+        #[arg(long, default_value = "127.0.0.1:50051")]
+        address: String,
+
+        #[command(flatten)]
+        limits: Limits,
+    },
+    /// Records and previews a reusable list of edits.
+    #[command(subcommand)]
+    Session(SessionCommand),
+}
+
+#[derive(Args)]
+struct OpArgs {
+    #[command(flatten)]
+    edit: OpEdit,
+
+    #[command(flatten)]
+    decode: DecodeArgs,
+}
+
+#[derive(Args)]
+struct PatchArgs {
+    #[command(flatten)]
+    edit: PatchEdit,
+
+    #[command(flatten)]
+    decode: DecodeArgs,
+}
+
+#[derive(Args)]
+struct ExtractArgs {
+    #[command(flatten)]
+    edit: ExtractEdit,
+
+    #[command(flatten)]
+    decode: DecodeArgs,
+}
+
+/// The formats the `mesh` subcommand writes.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum MeshFormat {
+    Obj,
+    /// Binary glTF.
+    Gltf,
+}
+
+#[derive(Args)]
+struct MeshArgs {
+    /// The format of the surface.
+    #[arg(long, value_enum, default_value = "obj")]
+    mesh_format: MeshFormat,
+
+    #[command(flatten)]
+    decode: DecodeArgs,
+}
+
+impl MeshArgs {
+    /// The decode arguments writing just the mesh, spaced and colored.
+    fn decode_args(&self) -> DecodeArgs {
+        let mut args = self.decode.clone();
+        args.export.formats = vec![match self.mesh_format {
+            MeshFormat::Obj => Format::Obj,
+            MeshFormat::Gltf => Format::Glb,
+        }];
+        args.export.mesh.mesh_scale = true;
+        args.export.mesh.mesh_colors = !args.export.mesh.mesh_confidence;
+        args
+    }
+}
+
+#[derive(Subcommand)]
+enum SessionCommand {
+    /// Appends an edit (e.g. `op add --value 5`) to the session file.
+    Record {
+        session: String,
+
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        edit: Vec<String>,
+    },
+    /// Lists the edits of a session file.
+    List {
+        session: String,
+    },
+    /// Renders a map with the session's edits applied, without touching the map.
+    Preview {
+        session: String,
+
+        #[command(flatten)]
+        decode: Box<DecodeArgs>,
+    },
+    /// Applies the session's edits to a map and re-encodes the result, leaving
+    /// the map itself as it is.
+    Commit {
+        session: String,
+
+        file: String,
+
+        /// Where the edited map is written.
+        #[arg(long, value_name = "PATH")]
+        save_map: PathBuf,
+
+        #[command(flatten)]
+        limits: Limits,
+    },
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let started = Instant::now();
+
+    let mesh_args = match &cli.command {
+        Some(Command::Mesh(args)) => Some(args.decode_args()),
+        _ => None,
+    };
+
+    let plain = match &cli.command {
+        None => Some(&cli.decode),
+        Some(Command::Export(args)) => Some(args),
+        Some(Command::Mesh(_)) => mesh_args.as_ref(),
+        _ => None,
+    };
+
+    if let Some((pattern, args)) = plain.and_then(|args| Some((args.batch.as_deref()?, args))) {
+        let succeeded = batch::run(pattern, args)
+            .unwrap_or_else(|e| fail("Batch conversion failed", e));
+
+        if !succeeded {
+            process::exit(1);
+        }
+        return;
+    }
+
+    let dry_run = match &cli.command {
+        None => Some((&cli.decode, None)),
+        Some(Command::Export(args)) => Some((args, None)),
+        Some(Command::Op(args)) => Some((&args.decode, None)),
+        Some(Command::Patch(args)) => Some((&args.decode, None)),
+        Some(Command::Extract(args)) => Some((&args.decode, Some("extract"))),
+        Some(Command::Mesh(_)) => mesh_args.as_ref().map(|args| (args, None)),
+        _ => None,
+    };
+
+    if let Some((args, resized_by)) = dry_run.filter(|(args, _)| args.dry_run) {
+        print_plan(args, resized_by)
+            .unwrap_or_else(|e| fail("Dry run failed", e));
+        return;
+    }
+
+    match cli.command {
+        Some(Command::Export(args)) => {
+            let map = load_map(&args)
+                .unwrap_or_else(|e| decode_failed(args.file(), e));
+
+            write_outputs(&args, &map, started);
+        }
+        Some(Command::Decode(args)) => {
+            load_map(&args)
+                .unwrap_or_else(|e| decode_failed(args.file(), e));
+
+            println!("Decoded in {:.2?}", started.elapsed());
+            if args.timings {
+                timings::print(args.file(), &timings::finish());
+            }
+        }
+        Some(Command::Info { file, field_notes, json, limits }) => {
+            print_info(&file, field_notes.as_deref(), json, &limits)
+                .unwrap_or_else(|e| decode_failed(&file, e));
+        }
+        Some(Command::Op(args)) => {
+            let mut map = load_map(&args.decode)
+                .unwrap_or_else(|e| decode_failed(args.decode.file(), e));
+
+            Edit::Op(args.edit).apply(&mut map, &args.decode.limits)
+                .unwrap_or_else(|e| fail("Failed to adjust the heights", e));
+
+            write_outputs(&args.decode, &map, started);
+        }
+        Some(Command::Patch(args)) => {
+            let mut map = load_map(&args.decode)
+                .unwrap_or_else(|e| decode_failed(args.decode.file(), e));
+
+            Edit::Patch(args.edit).apply(&mut map, &args.decode.limits)
+                .unwrap_or_else(|e| fail("Failed to patch the map", e));
+
+            write_outputs(&args.decode, &map, started);
+        }
+        Some(Command::Extract(args)) => {
+            let mut map = load_map(&args.decode)
+                .unwrap_or_else(|e| decode_failed(args.decode.file(), e));
+
+            Edit::Extract(args.edit).apply(&mut map, &args.decode.limits)
+                .unwrap_or_else(|e| fail("Failed to extract the region", e));
+
+            write_outputs(&args.decode, &map, started);
+        }
+        Some(Command::Mesh(_)) => {
+            let args = mesh_args.expect("Made from the mesh arguments above");
+            let map = load_map(&args)
+                .unwrap_or_else(|e| decode_failed(args.file(), e));
+
+            write_outputs(&args, &map, started);
+        }
+        Some(Command::Session(SessionCommand::Record { session, edit })) => {
+            let edit = Session::record(&session, &edit)
+                .unwrap_or_else(|e| fail("Failed to record the edit", e));
+
+            println!("Recorded {:?}", edit);
+        }
+        Some(Command::Session(SessionCommand::List { session })) => {
+            let session = Session::load(&session)
+                .unwrap_or_else(|e| fail("Failed to load the session", e));
+
+            for (number, edit) in session.edits.iter().enumerate() {
+                println!("{}: {:?}", number + 1, edit);
+            }
+        }
+        Some(Command::Session(SessionCommand::Preview { session, decode })) => {
+            let session = Session::load(&session)
+                .unwrap_or_else(|e| fail("Failed to load the session", e));
+            let mut map = load_map(&decode)
+                .unwrap_or_else(|e| decode_failed(decode.file(), e));
+
+            session.apply(&mut map, &decode.limits)
+                .unwrap_or_else(|e| fail("Failed to apply the session", e));
+
+            if decode.stdout {
+                write_stdout(&map, &ExportOptions { limits: decode.limits.clone(), ..decode.export.clone() })
+                    .unwrap_or_else(|e| fail("Failed to write the preview", e));
+            } else {
+                export_map(&decode, ".preview", &map);
+            }
+        }
+        Some(Command::Session(SessionCommand::Commit { session, file, save_map: path, limits })) => {
+            let session = Session::load(&session)
+                .unwrap_or_else(|e| fail("Failed to load the session", e));
+            let mut map = open_map(&file, &limits)
+                .unwrap_or_else(|e| decode_failed(&file, e));
+
+            session.apply(&mut map, &limits)
+                .unwrap_or_else(|e| fail("Failed to apply the session", e));
+            println!("Applied {} edits", session.edits.len());
+
+            save_map(&path, &map)
+                .unwrap_or_else(|e| fail("Failed to save the map", e));
+        }
+        Some(Command::DiffHeaders { a, b, field_notes }) => {
+            let mut a = read_header(&a)
+                .unwrap_or_else(|e| decode_failed(&a, e));
+            let mut b = read_header(&b)
+                .unwrap_or_else(|e| decode_failed(&b, e));
+
+            if let Some(path) = field_notes {
+                let notes = FieldNotes::load(&path)
+                    .unwrap_or_else(|e| fail("Failed to read the field notes", e));
+                a.unknowns.annotate(&notes);
+                b.unknowns.annotate(&notes);
+            }
+
+            let changed = diff::print_header_diff(&a, &b, diff::use_color());
+            println!("{} fields differ", changed);
+        }
+        Some(Command::DiffPatch { a, b, threshold, margin, changed_only, mut export, limits }) => {
+            let map_a = open_map(&a, &limits)
+                .unwrap_or_else(|e| decode_failed(&a, e));
+            let map_b = open_map(&b, &limits)
+                .unwrap_or_else(|e| decode_failed(&b, e));
+
+            let (region, patch) = match diff::changed_patch(&map_a, &map_b, threshold, margin, changed_only)
+                .unwrap_or_else(|e| fail("Failed to compare the maps", e)) {
+                Some(changed) => changed,
+                None => {
+                    println!("The maps don't differ, no patch written");
+                    return;
+                }
+            };
+
+            // Keep geospatial outputs lined up with the full map.
+            if export.georef.is_set() {
+                let (x, y) = export.georef.origin();
+                let cell_size = export.georef.cell_size();
+                export.georef.origin = Some((x + f64::from(region.x) * cell_size, y - f64::from(region.y) * cell_size));
+            }
+
+            export.limits = limits;
+            let stem = format!("{}_to_{}_patch", file_stem(&a), file_stem(&b));
+            export::export_all(&patch, &stem, &export)
+                .unwrap_or_else(|e| fail("Failed to export the patch", e));
+
+            let placement = export.resolve_output(&patch.header, &stem)
+                .map(|(export, stem)| export.output_path(&format!("{}.patch.json", stem)))
+                .and_then(|path| diff::write_placement(&path, &region, &map_b.header).map(|_| path))
+                .unwrap_or_else(|e| fail("Failed to write the patch placement", e));
+            println!("Wrote {}", placement.display());
+
+            println!("Patch of {}x{} tiles at {},{}, {} enabled", region.w, region.h, region.x, region.y,
+                     patch.points.len());
+        }
+        Some(Command::Diff { a, b, threshold, field_notes, export, limits }) => {
+            let mut map_a = open_map(&a, &limits)
+                .unwrap_or_else(|e| decode_failed(&a, e));
+            let mut map_b = open_map(&b, &limits)
+                .unwrap_or_else(|e| decode_failed(&b, e));
+
+            if let Some(path) = field_notes {
+                let notes = FieldNotes::load(&path)
+                    .unwrap_or_else(|e| fail("Failed to read the field notes", e));
+                map_a.header.unknowns.annotate(&notes);
+                map_b.header.unknowns.annotate(&notes);
+            }
+
+            let fields = diff::print_header_diff(&map_a.header, &map_b.header, diff::use_color());
+            println!("{} fields differ", fields);
+
+            let changed = diff::changed_tiles(&map_a, &map_b, threshold)
+                .unwrap_or_else(|e| fail("Failed to compare the maps", e));
+            let difference = diff::difference_map(&map_a, &map_b)
+                .unwrap_or_else(|e| fail("Failed to compare the maps", e));
+            let count = changed.iter().filter(|&&changed| changed).count();
+
+            println!("{} of {} tiles changed ({:.1}%)", count, changed.len(),
+                     count as f64 / changed.len().max(1) as f64 * 100f64);
+            if let Some(range) = difference.data_range() {
+                println!("Height differences from {} to {}", range.start, range.end);
+            }
+
+            let export = ExportOptions { limits, ..export };
+            export::export_all(&difference, &format!("{}_to_{}_diff", file_stem(&a), file_stem(&b)), &export)
+                .unwrap_or_else(|e| fail("Failed to export the difference", e));
+        }
+        Some(Command::Stitch { inputs, position_fields, overlap, seam, name, save_map: save_path, export, limits }) => {
+            let maps: Vec<(Map, (i64, i64))> = inputs.iter()
+                .map(|(file, position)| {
+                    let map = open_map(file, &limits)
+                        .unwrap_or_else(|e| decode_failed(file, e));
+                    let position = match (position, &position_fields) {
+                        (Some((column, row)), _) => (i64::from(*column), i64::from(*row)),
+                        (None, Some((x, y))) => stitch::header_position(&map.header, (x, y))
+                            .unwrap_or_else(|e| decode_failed(file, e)),
+                        (None, None) => decode_failed(file, io::Error::new(io::ErrorKind::InvalidInput, format!(
+                            "There is no grid place to put it, give it as {}@column,row or use --position-fields", file))),
+                    };
+                    println!("{} at column {}, row {}", file, position.0, position.1);
+
+                    (map, position)
+                })
+                .collect();
+
+            let mosaic = stitch::stitch(&maps, overlap, seam, &limits)
+                .unwrap_or_else(|e| fail("Failed to stitch the maps", e));
+            println!("Stitched {} maps into {}x{} tiles", maps.len(), mosaic.header.w, mosaic.header.h);
+
+            let export = ExportOptions { limits, ..export };
+            export::export_all(&mosaic, &name, &export)
+                .unwrap_or_else(|e| fail("Failed to export the mosaic", e));
+
+            if let Some(path) = save_path {
+                save_map(&path, &mosaic)
+                    .unwrap_or_else(|e| fail("Failed to save the mosaic", e));
+            }
+        }
+        Some(Command::CompareRender { a, b, formats, limits }) => {
+            let map_a = open_map(&a, &limits)
+                .unwrap_or_else(|e| decode_failed(&a, e));
+            let map_b = open_map(&b, &limits)
+                .unwrap_or_else(|e| decode_failed(&b, e));
+
+            let stem = |file: &str| Path::new(file).file_stem().and_then(OsStr::to_str).unwrap_or_default().to_string();
+            let options = ExportOptions { formats, ..ExportOptions::default() };
+            let max_diff = export::export_comparison((&map_a, &stem(&a)), (&map_b, &stem(&b)),
+                                                     &format!("{}_vs_{}", stem(&a), stem(&b)), &options)
+                .unwrap_or_else(|e| fail("Failed to render the comparison", e));
+
+            println!("Max difference: {}", max_diff);
+        }
+        Some(Command::RenderMatrix { file, styles, normalizations, formats, limits }) => {
+            let map = open_map(&file, &limits)
+                .unwrap_or_else(|e| decode_failed(&file, e));
+
+            let options = ExportOptions { formats, ..ExportOptions::default() };
+            export::export_matrix(&map, &styles, &normalizations, &format!("{}_matrix", file_stem(&file)), &options)
+                .unwrap_or_else(|e| fail("Failed to render the matrix", e));
+        }
+        Some(Command::Dedup { files, tolerance, limits }) => {
+            let maps: Vec<Map> = files.iter()
+                .map(|file| open_map(file, &limits).unwrap_or_else(|e| decode_failed(file, e)))
+                .collect();
+            let hashes: Vec<u64> = maps.iter().map(Map::content_hash).collect();
+
+            let groups = dedup::identical_groups(&hashes);
+            println!("Identical: {}", groups.len());
+            for group in &groups {
+                let names: Vec<&str> = group.iter().map(|&i| files[i].as_str()).collect();
+                println!("  {}", names.join(", "));
+            }
+
+            let mut near = Vec::new();
+            for i in 0..maps.len() {
+                for j in i + 1..maps.len() {
+                    if hashes[i] == hashes[j] {
+                        continue;
+                    }
+
+                    if let Some(diff) = maps[i].max_height_difference(&maps[j]).filter(|&diff| diff <= tolerance) {
+                        near.push((i, j, diff));
+                    }
+                }
+            }
+
+            println!("Nearly identical (within {}): {}", tolerance, near.len());
+            for (i, j, diff) in near {
+                println!("  {} ~ {}: max height difference {}", files[i], files[j], diff);
+            }
+        }
+        Some(Command::Presets) => {
+            for preset in Preset::value_variants() {
+                let name = preset.to_possible_value().expect("Presets are never hidden");
+                println!("{}: {}", name.get_name(), name.get_help().map(ToString::to_string).unwrap_or_default());
+
+                for line in preset.description().lines() {
+                    println!("  {}", line);
+                }
+            }
+        }
+        Some(Command::CompareImages { a, b, tolerance, diff_image }) => {
+            let a = Raster::open(&a)
+                .unwrap_or_else(|e| fail("Failed to open the first image", e));
+            let b = Raster::open(&b)
+                .unwrap_or_else(|e| fail("Failed to open the second image", e));
+
+            let comparison = Comparison::new(&a, &b, tolerance)
+                .unwrap_or_else(|e| fail("Failed to compare the images", e));
+
+            println!("Max difference: {}", comparison.max_diff);
+            println!("Mean difference: {}", comparison.mean_diff);
+            println!("RMSE: {}", comparison.rmse);
+            println!("Pixels beyond tolerance: {} / {}", comparison.failed_pixels, comparison.total_pixels);
+
+            if let Some(path) = diff_image {
+                comparison.diff_image(tolerance).save(path)
+                    .unwrap_or_else(|e| fail("Failed to save the diff image", e));
+            }
+
+            if !comparison.passed() {
+                println!("FAIL");
+                process::exit(1);
+            }
+
+            println!("PASS");
+        }
+        Some(Command::Precision(args)) => {
+            let map = load_map(&args)
+                .unwrap_or_else(|e| decode_failed(args.file(), e));
+            let precision = Precision::analyze(&map);
+
+            println!("Heights: {} ({} unique) from {} to {}",
+                     precision.samples, precision.unique, precision.min, precision.max);
+
+            if let Some(step) = precision.min_step {
+                println!("Smallest step: {}", step);
+            }
+
+            match (precision.quantum, precision.effective_bits()) {
+                (Some(quantum), Some(bits)) => {
+                    println!("Quantized to multiples of {} from {}", quantum, precision.base);
+                    println!("Effective precision: {} bits", bits);
+
+                    let depths = match bits {
+                        0..=8 => "gray8, gray16 and f32",
+                        9..=16 => "gray16 and f32",
+                        _ => "f32",
+                    };
+                    println!("Pixel formats with enough levels: {}", depths);
+                }
+                _ => println!("Not quantized, only f32 output is lossless"),
+            }
+        }
+        Some(Command::Outliers { sigma, limit, decode }) => {
+            let map = load_map(&decode)
+                .unwrap_or_else(|e| decode_failed(decode.file(), e));
+            let report = map.find_outliers(sigma);
+            let (low, high) = report.bounds();
+
+            println!("Mean: {}, standard deviation: {}", report.mean, report.std_dev);
+            println!("Non-finite heights: {}", report.non_finite.len());
+            for suspect in report.non_finite.iter().take(limit) {
+                println!("  {},{}: {}", suspect.x, suspect.y, suspect.h);
+            }
+
+            println!("Outliers beyond {} sigma ({}..{}): {}", sigma, low, high, report.outliers.len());
+            for suspect in report.outliers.iter().take(limit) {
+                println!("  {},{}: {}", suspect.x, suspect.y, suspect.h);
+            }
+        }
+        Some(Command::Stats { bins, histogram_image, decode }) => {
+            let map = load_map(&decode)
+                .unwrap_or_else(|e| decode_failed(decode.file(), e));
+            let stats = map.stats();
+            let show = |value: Option<f32>| value.map_or_else(|| String::from("none"), |value| value.to_string());
+            let show_f64 = |value: Option<f64>| value.map_or_else(|| String::from("none"), |value| format!("{:.3}", value));
+
+            println!("Enabled tiles: {} of {} ({:.1}%), disabled: {}",
+                     stats.enabled, stats.tiles, stats.coverage() * 100f64, stats.tiles - stats.enabled);
+            println!("Header range: {} to {}", map.header.min_height, map.header.max_height);
+            println!("Min: {}, max: {}", show(stats.min), show(stats.max));
+            println!("Mean: {}, median: {}, standard deviation: {}",
+                     show_f64(stats.mean), show(stats.median), show_f64(stats.std_dev));
+            if stats.non_finite > 0 {
+                println!("Non-finite heights: {}", stats.non_finite);
+            }
+
+            let histogram = match map.histogram(bins as usize) {
+                Some(histogram) => histogram,
+                None => return,
+            };
+            let tallest = histogram.counts.iter().copied().max().unwrap_or_default().max(1);
+            let labels: Vec<String> = (0..histogram.counts.len())
+                .map(|i| {
+                    let range = histogram.bin_range(i);
+                    format!("{:.2}..{:.2}", range.start, range.end)
+                })
+                .collect();
+            let label_width = labels.iter().map(String::len).max().unwrap_or_default();
+
+            println!("Histogram:");
+            for (label, &count) in labels.iter().zip(&histogram.counts) {
+                let bar = (count as f64 / tallest as f64 * 50f64).round() as usize;
+                let bar = if count > 0 { bar.max(1) } else { 0 };
+                println!("  {:>width$} {:>8} {}", label, count, "#".repeat(bar), width = label_width);
+            }
+
+            if histogram_image {
+                let options = ExportOptions { formats: vec![Format::Png], ..decode.export.clone() };
+                export::export_histogram(&histogram, &format!("{}_histogram", file_stem(decode.file())), &options)
+                    .unwrap_or_else(|e| fail("Failed to draw the histogram", e));
+            }
+        }
+        Some(Command::Queue(options)) => {
+            queue::run(&options)
+                .unwrap_or_else(|e| fail("Failed to run the job queue", e));
+        }
+        Some(Command::Server { address, workers, max_maps, limits }) => {
+            server::serve(&address, workers, max_maps, &limits)
+                .unwrap_or_else(|e| fail("Failed to run the server", e));
+        }
+        #[cfg(feature = "grpc")]
+        Some(Command::Grpc { address, limits }) => {
+            grpc::serve(&address, &limits)
+                .unwrap_or_else(|e| fail("Failed to run the gRPC server", e));
+        }
+        None => {
+            let args = cli.decode;
+            let map = load_map(&args)
+                .unwrap_or_else(|e| decode_failed(args.file(), e));
+
+            write_outputs(&args, &map, started);
+        }
+    }
+}
+
+/// Reports a file that couldn't be decoded, with the byte offset where the
+/// error tells it, and exits.
+fn decode_failed(file: &str, e: io::Error) -> ! {
+    eprintln!("Failed to decode {}: {}", file, e);
+    process::exit(1);
+}
+
+/// Reports an error of what `context` describes and exits, like
+/// `decode_failed` for everything besides decoding.
+fn fail(context: &str, e: impl fmt::Display) -> ! {
+    eprintln!("{}: {}", context, e);
+    process::exit(1);
 }
 
-fn create_map_image(file_stem: &str, map: &Map) {
-    let map_size = (map.header.w * map.header.h) as usize;
-    let mut img = Image::new(map.header.w, map.header.h);
-
-    // Loop over all the pixels.
-    // Then check if they are enabled.
-    // If they are enabled, scale them and write the pixels.
-
-    let mut offset = 0usize;
-    let height_diff = map.header.max_height - map.header.min_height;
-
-    (0..map_size)
-        .filter(|&index| { map.enabled[index] > 0u8 })
-        .for_each(|index| {
-            // Let's write all enabled pixels.
-            let position = get_position(&index, &map.header.w, &map.header.h);
-            let point = &map.points[offset];
-
-            let height_offset = (point.h - map.header.min_height) / height_diff;
-            let pixel = (255f32 * height_offset) as u8;
-
-            img.set_pixel(position.0, position.1, Pixel::new(pixel, pixel, pixel));
-
-            offset += 1;
-        });
-
-
-    let save_path = String::from("./output/") + file_stem + ".bmp";
-    img.save(save_path).unwrap();
-}
-
-fn get_position(index: &usize, width: &u32, height: &u32) -> (u32, u32) {
-    let i = *index as u32;
-    let x = i % width;
-    let y = height - 1 - (i / width);
-
-    (x, y)
-}
-
-#[derive(Debug)]
-struct MapHeader {
-    signature: u32,
-    unk: u32,
-    u1: f32,
-    u2: f32,
-    min_height: f32,
-    max_height: f32,
-    w: u32,
-    h: u32,
-    u5: f32,
-    u6: f32,
-    u7: f32,
-    u8: f32,
-    u9: f32,
-    us1: u16,
-    us2: u16,
-    u10: f32,
-    u11: f32,
-    name: String,
-}
-
-impl MapHeader {
-    fn parse(file: &mut BufReader<File>) -> io::Result<MapHeader> {
-        Ok(MapHeader {
-            signature: file.read_u32::<LE>()?,
-            unk: file.read_u32::<LE>()?,
-            u1: file.read_f32::<LE>()?,
-            u2: file.read_f32::<LE>()?,
-            min_height: file.read_f32::<LE>()?,
-            max_height: file.read_f32::<LE>()?,
-            w: file.read_u32::<LE>()?,
-            h: file.read_u32::<LE>()?,
-            u5: file.read_f32::<LE>()?, // Scale?
-            u6: file.read_f32::<LE>()?,
-            u7: file.read_f32::<LE>()?,
-            u8: file.read_f32::<LE>()?,
-            u9: file.read_f32::<LE>()?,
-            us1: file.read_u16::<LE>()?,
-            us2: file.read_u16::<LE>()?,
-            u10: file.read_f32::<LE>()?,
-            u11: file.read_f32::<LE>()?,
-            name: read_fixed_string(file, 0x20),
-        })
-    }
-}
-
-#[derive(Debug)]
-struct Map {
-    header: MapHeader,
-    points: Vec<TilePoint>,
-    enabled: Vec<u8>,
-}
-
-#[derive(Debug)]
-struct TilePoint {
-    h: f32,
-    unk: u8,
-    r: u8,
-    g: u8,
-    b: u8,
-}
-
-impl TilePoint {
-    fn parse(file: &mut BufReader<File>) -> io::Result<TilePoint> {
-        Ok(TilePoint {
-            h: file.read_f32::<LE>()?,
-            unk: file.read_u8()?,
-            r: file.read_u8()?,
-            g: file.read_u8()?,
-            b: file.read_u8()?,
-        })
-    }
-}
-
-fn parse_points(header: &MapHeader, b: &mut BufReader<File>) -> io::Result<(Vec<u8>, Vec<TilePoint>)> {
-    let total = header.w * header.h;
-    let mut counter = 0u32;
-
-    let size = total as usize;
-
-    let mut points = Vec::with_capacity(size);
-    let mut enabled_points: Vec<u8> = Vec::with_capacity(size);
-
-    while counter < total {
-        let n = b.read_i8()? as i32;
-
-        // Negative values = skip |n|
-        // Positive value = read n + 1
-
-        let enabled = n >= 0;
-        let amount = if n >= 0 {
-            let read_size = 1 + n as u32;
-
-            (0..read_size).for_each(|_| {
-                points.push(TilePoint::parse(b).expect("Failed to parse point"));
-            });
-
-            read_size
-        } else {
-            n.abs() as u32
+/// The message a caught panic was raised with, for the batch and queue
+/// workers that carry on past it.
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    panic.downcast_ref::<&str>().map(|s| s.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| String::from("The conversion panicked"))
+}
+
+fn read_header(file_location: &str) -> io::Result<MapHeader> {
+    let file = File::open(file_location)?;
+    Ok(MapHeader::parse(&mut BufReader::new(file))?)
+}
+
+fn load_map(args: &DecodeArgs) -> io::Result<Map> {
+    if args.stdout {
+        status::use_stderr();
+    }
+
+    status!("Decoding file: {}", args.file());
+
+    if args.file() == STDIN {
+        if args.crop.is_some() || args.threads > 1 || args.cache || args.save_index {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "--crop, --threads, --cache and --save-index need a map file, not stdin"));
+        }
+    } else {
+        args.limits.check_file_size(fs::metadata(args.file())?.len())?;
+    }
+
+    if args.timings {
+        timings::start();
+    }
+
+    let cache_dir = args.cache_dir.clone().or_else(cache::default_dir);
+
+    let mut map = timings::measure("parse", || -> io::Result<Map> {
+        let map = match (&args.crop, cache_dir) {
+            (Some(crop), _) => {
+                let index = RunIndex::open(args.file(), args.save_index, &args.limits)?;
+                Map::parse_region(args.file(), &index, crop, &args.limits)?
+            }
+            (None, _) if args.threads > 1 => {
+                let index = RunIndex::open(args.file(), args.save_index, &args.limits)?;
+                Map::parse_parallel(args.file(), &index, args.threads, &args.limits)?
+            }
+            (None, Some(dir)) if args.cache => cache::load(args.file(), &dir, &args.limits)?,
+            _ if args.file() == STDIN => Map::parse(&mut open_input(STDIN, &args.limits)?, &args.limits)?,
+            _ => {
+                let file = File::open(args.file())?;
+                let mut b = BufReader::new(file);
+
+                Map::parse(&mut b, &args.limits)?
+            }
         };
 
-        enabled_points.extend(vec![if enabled { 1 } else { 0 }; amount as usize]);
-        counter += amount;
+        map.validate()?;
+        Ok(map)
+    })?;
+
+    if let Some(path) = &args.field_notes {
+        map.header.unknowns.annotate(&FieldNotes::load(path)?);
+    }
+
+    status!("{:#?}", &map.header);
+    status!("Points: {}", &map.points.len());
+    status!("Enabled: {}", &map.enabled.len());
+    status!("Map Size: {}", u64::from(map.header.w) * u64::from(map.header.h));
+
+    timings::measure("filters", || args.edits().iter().try_for_each(|edit| edit.apply(&mut map, &args.limits)))?;
+
+    Ok(map)
+}
+
+/// Decodes a map without any of the decode options.
+fn open_map(file_location: &str, limits: &Limits) -> io::Result<Map> {
+    Ok(Map::parse(&mut open_input(file_location, limits)?, limits)?)
+}
+
+/// Opens the map file at `file_location`, or stdin for `-`, checked against
+/// the file size limit. Stdin is read up front, as it can't be measured.
+fn open_input(file_location: &str, limits: &Limits) -> io::Result<Box<dyn BufRead>> {
+    if file_location != STDIN {
+        limits.check_file_size(fs::metadata(file_location)?.len())?;
+        return Ok(Box::new(BufReader::new(File::open(file_location)?)));
+    }
+
+    let mut bytes = Vec::new();
+    io::stdin().lock().take(limits.max_file_size + 1).read_to_end(&mut bytes)?;
+    limits.check_file_size(bytes.len() as u64)?;
+
+    Ok(Box::new(Cursor::new(bytes)))
+}
+
+fn write_outputs(args: &DecodeArgs, map: &Map, started: Instant) {
+    convert(args, map, started)
+        .unwrap_or_else(|e| fail("Failed to write the outputs", e));
+}
+
+/// Exports the decoded map and writes the extras requested by `args`,
+/// returning every file written.
+fn convert(args: &DecodeArgs, map: &Map, started: Instant) -> io::Result<Vec<PathBuf>> {
+    let mut export = ExportOptions { limits: args.limits.clone(), ..args.export.clone() };
+
+    // Keep geospatial outputs of a crop lined up with the full map.
+    if let Some(crop) = args.crop.as_ref().filter(|_| export.georef.is_set()) {
+        let (x, y) = export.georef.origin();
+        let cell_size = export.georef.cell_size();
+        export.georef.origin = Some((x + f64::from(crop.x) * cell_size, y - f64::from(crop.y) * cell_size));
+    }
+    let export = &export;
+
+    let mut outputs = if args.stdout {
+        write_stdout(map, export)?;
+        Vec::new()
+    } else {
+        export::export_all(map, &file_stem(args.file()), export)?
+    };
+
+    if let Some(path) = &args.save_map {
+        timings::measure("encode", || save_map(path, map))?;
+        outputs.push(path.clone());
+    }
+
+    if let Some(path) = &args.summary {
+        summary::append(path, args.file(), map, &outputs, started.elapsed())?;
+    }
+
+    if args.timings {
+        timings::print(args.file(), &timings::finish());
+    }
+
+    Ok(outputs)
+}
+
+/// Writes the single output format of `options` to stdout.
+fn write_stdout(map: &Map, options: &ExportOptions) -> io::Result<()> {
+    let format = match options.formats[..] {
+        [format @ (Format::Bmp | Format::Png | Format::Png16 | Format::Tiff)] => format,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                       "--stdout streams a single bmp, png, png16 or tiff output")),
+    };
+    let bytes = export::render(map, format, options)?;
+
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(&bytes)?;
+    stdout.flush()
+}
+
+/// Prints the header of the map at `file_location`, checked against `limits`.
+fn print_info(file_location: &str, field_notes: Option<&Path>, json: bool, limits: &Limits) -> io::Result<()> {
+    let mut header = MapHeader::parse(&mut open_input(file_location, limits)?)?;
+    limits.check_header(&header)?;
+
+    if let Some(path) = field_notes {
+        header.unknowns.annotate(&FieldNotes::load(path)?);
     }
 
-    Ok((enabled_points, points))
+    if json {
+        let mut document = header.to_json();
+        document["file"] = Value::String(String::from(file_location));
+        println!("{}", document);
+        return Ok(());
+    }
+
+    println!("{}: '{}', {}x{} tiles", file_location, header.name, header.w, header.h);
+    println!("{:#?}", header);
+
+    Ok(())
 }
 
-impl Map {
-    fn parse(file: &mut BufReader<File>) -> io::Result<Map> {
-        let header = MapHeader::parse(file)
-            .expect("Invalid map header");
+/// Lists what decoding with `args` would write, from the header alone.
+/// `resized_by` names a subcommand that changes the map size.
+fn print_plan(args: &DecodeArgs, resized_by: Option<&str>) -> io::Result<()> {
+    let header = MapHeader::parse(&mut open_input(args.file(), &args.limits)?)?;
+    args.limits.check_header(&header)?;
+
+    let (w, h) = match &args.crop {
+        Some(crop) if !crop.fits(header.w, header.h) =>
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                "Region {:?} is outside of the {}x{} map", crop, header.w, header.h))),
+        Some(crop) => (crop.w, crop.h),
+        None => (header.w, header.h),
+    };
+    // Resizing comes after the other edits, so they don't change the size.
+    let (w, h) = args.resize.unwrap_or((w, h));
 
-        let point_data = parse_points(&header, file)
-            .unwrap_or_default();
+    println!("{}: '{}', {}x{} tiles", args.file(), header.name, header.w, header.h);
 
-        let enabled = point_data.0;
-        let points = point_data.1;
+    let mut resizing: Vec<&str> = resized_by.into_iter().collect();
+    if args.rotate_deg.is_some() && args.resize.is_none() {
+        resizing.push("--rotate-deg");
+    }
+    if args.polygon_crop && args.resize.is_none() {
+        resizing.push("--polygon-crop");
+    }
+    #[cfg(feature = "reproject")]
+    if args.export.georef.target_crs.is_some() {
+        resizing.push("--target-crs");
+    }
+    if !resizing.is_empty() {
+        println!("Sizes are of {}x{} tiles, {} will change them", w, h, resizing.join(", "));
+    }
+
+    // The template sees the size after the outputs are turned.
+    let mut planned_header = header.clone();
+    (planned_header.w, planned_header.h) = if args.export.rotate.unwrap_or_default() / 90 % 2 == 1 { (h, w) } else { (w, h) };
+    let (export, stem) = args.export.resolve_output(&planned_header, &file_stem(args.file()))?;
+
+    let planned = export::plan(w, h, &stem, &export)?;
+
+    println!("Would write:");
+    for output in planned {
+        println!("  {}: {}", output.path.display(), output.description);
+    }
 
-        Ok(Map { header, points, enabled })
+    if let Some(path) = &args.save_map {
+        println!("  {}: map file", path.display());
     }
+
+    if let Some(path) = &args.summary {
+        println!("  {}: summary row appended", path.display());
+    }
+
+    Ok(())
 }
 
-fn read_fixed_string(file: &mut BufReader<File>, size: usize) -> String {
-    let mut buf = vec![0u8; size];
+fn export_map(args: &DecodeArgs, suffix: &str, map: &Map) -> Vec<PathBuf> {
+    export::export_all(map, &(file_stem(args.file()) + suffix), &ExportOptions { limits: args.limits.clone(), ..args.export.clone() })
+        .unwrap_or_else(|e| fail("Failed to export the map", e))
+}
 
-    file.read_exact(&mut buf).unwrap();
+fn file_stem(file_location: &str) -> String {
+    if file_location == STDIN {
+        return String::from("stdin");
+    }
 
-    String::from_utf8(buf)
+    Path::new(file_location)
+        .file_stem()
+        .and_then(OsStr::to_str)
         .unwrap_or_default()
-        .trim_matches(char::from(0))
         .to_string()
 }
+
+/// Parses two header field names written as `A,B`.
+fn parse_field_pair(s: &str) -> Result<(String, String), String> {
+    match s.split_once(',') {
+        Some((a, b)) if !a.trim().is_empty() && !b.trim().is_empty() => Ok((a.trim().to_string(), b.trim().to_string())),
+        _ => Err(format!("Invalid fields '{}', expected two names like u6,u7", s)),
+    }
+}
+
+fn save_map(path: &Path, map: &Map) -> io::Result<()> {
+    let mut w = io::BufWriter::new(File::create(path)?);
+    map.write(&mut w)?;
+    w.flush()?;
+
+    status!("Saved map: {}", path.display());
+
+    Ok(())
+}
@@ -0,0 +1,168 @@
+use std::io;
+
+use clap::ValueEnum;
+
+use crate::Map;
+use crate::TilePoint;
+use crate::raster::Raster;
+
+/// How an external mask image changes the enabled tiles.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum MaskMode {
+    /// Disable tiles where the mask is black, leave the rest untouched.
+    Clip,
+    /// Disable tiles where the mask is black and enable them where it is white.
+    Set,
+}
+
+/// A morphological operation on the enabled mask, with a square of
+/// `2 * radius + 1` tiles on a side.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Morphology {
+    /// Disable the tiles within the radius of a disabled tile, shrinking the
+    /// enabled areas.
+    Erode,
+    /// Enable the tiles within the radius of an enabled tile, growing the
+    /// enabled areas.
+    Dilate,
+    /// Erode, then dilate: removes enabled specks smaller than the square.
+    Open,
+    /// Dilate, then erode: closes pinholes smaller than the square.
+    Close,
+}
+
+impl Map {
+    /// Applies a black/white mask of the same size as the map.
+    ///
+    /// Tiles enabled by [`MaskMode::Set`] get a point at the header's
+    /// minimum height, as the file has no data for them.
+    /// Returns the number of tiles that were disabled and enabled.
+    pub fn apply_mask(&mut self, mask: &Raster, mode: MaskMode) -> io::Result<(usize, usize)> {
+        mask.check_size(self.header.w, self.header.h)?;
+
+        let w = self.header.w;
+        let h = self.header.h;
+        let base = TilePoint { h: self.header.min_height, unk: 0, r: 0, g: 0, b: 0, filled: true };
+        let mut tiles = self.tiles();
+        let mut disabled = 0usize;
+        let mut enabled = 0usize;
+
+        for (index, tile) in tiles.iter_mut().enumerate() {
+            let (x, y) = crate::get_position(&index, &w, &h);
+            let white = mask.luma(x, y) >= 0.5;
+
+            match (white, tile.is_some()) {
+                (false, true) => {
+                    *tile = None;
+                    disabled += 1;
+                }
+                (true, false) if mode == MaskMode::Set => {
+                    *tile = Some(base);
+                    enabled += 1;
+                }
+                _ => {}
+            }
+        }
+
+        self.set_tiles(tiles);
+
+        Ok((disabled, enabled))
+    }
+
+    /// Applies `morphology` to the enabled mask. The map's edges count as
+    /// enabled when eroding, so they aren't eaten away.
+    ///
+    /// Tiles that become enabled get the mean height and color of the
+    /// enabled tiles within the radius, which they always have. Returns the
+    /// number of tiles that were disabled and enabled.
+    pub fn morph_mask(&mut self, morphology: Morphology, radius: u32) -> (usize, usize) {
+        let (w, h) = (self.header.w, self.header.h);
+        // Dilating and eroding are symmetric, so rows can stay in file order.
+        let mask: Vec<bool> = self.enabled.iter().map(|&enabled| enabled > 0).collect();
+        let erode = |mask: &[bool]| -> Vec<bool> {
+            let inverse: Vec<bool> = mask.iter().map(|&enabled| !enabled).collect();
+            dilate(&inverse, w, h, radius).into_iter().map(|disabled| !disabled).collect()
+        };
+
+        let morphed = match morphology {
+            Morphology::Erode => erode(&mask),
+            Morphology::Dilate => dilate(&mask, w, h, radius),
+            Morphology::Open => dilate(&erode(&mask), w, h, radius),
+            Morphology::Close => erode(&dilate(&mask, w, h, radius)),
+        };
+
+        let tiles = self.tiles();
+        let (w, h, radius) = (w as usize, h as usize, radius as usize);
+        let mut disabled = 0usize;
+        let mut enabled = 0usize;
+        let mut morphed_tiles = Vec::with_capacity(tiles.len());
+
+        for (index, (tile, &keep)) in tiles.iter().zip(&morphed).enumerate() {
+            morphed_tiles.push(match (tile, keep) {
+                (Some(_), false) => {
+                    disabled += 1;
+                    None
+                }
+                (None, true) => {
+                    enabled += 1;
+                    let (x, y) = (index % w, index / w);
+                    let near: Vec<&TilePoint> = (y.saturating_sub(radius)..(y + radius + 1).min(h))
+                        .flat_map(|ny| (x.saturating_sub(radius)..(x + radius + 1).min(w)).map(move |nx| ny * w + nx))
+                        .filter_map(|i| tiles[i].as_ref())
+                        .collect();
+                    Some(mean_point(&near, self.header.min_height))
+                }
+                (tile, _) => *tile,
+            });
+        }
+
+        self.set_tiles(morphed_tiles);
+
+        (disabled, enabled)
+    }
+}
+
+/// A made up point with the mean height and color of `points`, at `base`
+/// if there are none.
+fn mean_point(points: &[&TilePoint], base: f32) -> TilePoint {
+    if points.is_empty() {
+        return TilePoint { h: base, unk: 0, r: 0, g: 0, b: 0, filled: true };
+    }
+
+    let count = points.len() as f32;
+    let channel = |get: fn(&TilePoint) -> u8| (points.iter().map(|point| f32::from(get(point))).sum::<f32>() / count).round() as u8;
+
+    TilePoint {
+        h: points.iter().map(|point| point.h).sum::<f32>() / count,
+        unk: points[0].unk,
+        r: channel(|point| point.r),
+        g: channel(|point| point.g),
+        b: channel(|point| point.b),
+        filled: true,
+    }
+}
+
+/// Grows the set cells of a `w` by `h` mask by `radius` cells in every
+/// direction, a row pass followed by a column pass.
+pub(crate) fn dilate(mask: &[bool], w: u32, h: u32, radius: u32) -> Vec<bool> {
+    let (w, h, radius) = (w as usize, h as usize, radius as usize);
+    let grow = |get: &dyn Fn(usize) -> bool, len: usize, i: usize| {
+        (i.saturating_sub(radius)..(i + radius + 1).min(len)).any(get)
+    };
+
+    let mut rows = vec![false; mask.len()];
+    for y in 0..h {
+        for x in 0..w {
+            rows[y * w + x] = grow(&|x| mask[y * w + x], w, x);
+        }
+    }
+
+    let mut grown = vec![false; mask.len()];
+    for y in 0..h {
+        for x in 0..w {
+            grown[y * w + x] = grow(&|y| rows[y * w + x], h, y);
+        }
+    }
+
+    grown
+}
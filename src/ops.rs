@@ -0,0 +1,107 @@
+use clap::ValueEnum;
+
+use crate::Map;
+use crate::region::Region;
+
+/// The kind of arithmetic applied by a height operation.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum OpKind {
+    Add,
+    Sub,
+    Mul,
+    Set,
+}
+
+/// A numeric adjustment of tile heights.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeightOp {
+    pub kind: OpKind,
+    pub value: f32,
+}
+
+impl HeightOp {
+    pub fn new(kind: OpKind, value: f32) -> HeightOp {
+        HeightOp { kind, value }
+    }
+
+    pub fn apply(&self, h: f32) -> f32 {
+        match self.kind {
+            OpKind::Add => h + self.value,
+            OpKind::Sub => h - self.value,
+            OpKind::Mul => h * self.value,
+            OpKind::Set => self.value,
+        }
+    }
+}
+
+impl Map {
+    /// Applies `op` to every enabled tile, or only to those inside `region`.
+    ///
+    /// The header height range is widened afterwards so it still covers
+    /// every stored height.
+    pub fn apply_height_op(&mut self, op: &HeightOp, region: Option<&Region>) -> usize {
+        let w = self.header.w;
+        let h = self.header.h;
+        let tiles: Vec<(usize, usize)> = self.enabled_tiles().collect();
+        let mut changed = 0usize;
+
+        for (index, offset) in tiles {
+            let position = crate::get_position(&index, &w, &h);
+
+            if region.is_none_or(|region| region.contains(position)) {
+                let point = &mut self.points[offset];
+                point.h = op.apply(point.h);
+                changed += 1;
+            }
+        }
+
+        self.expand_height_range();
+
+        changed
+    }
+
+    /// Widens `min_height`/`max_height` to include every stored height.
+    pub fn expand_height_range(&mut self) {
+        for point in &self.points {
+            self.header.min_height = self.header.min_height.min(point.h);
+            self.header.max_height = self.header.max_height.max(point.h);
+        }
+    }
+
+    /// Sets `min_height`/`max_height` to the range the stored heights span,
+    /// or to `range`, linearly rescaling the stored heights from their span
+    /// onto it. Non-finite heights are left alone. Returns the header range
+    /// from before, `None` if there are no finite heights to fit.
+    pub fn fit_height_range(&mut self, range: Option<(f32, f32)>) -> Option<(f32, f32)> {
+        let data = self.data_range()?;
+        let before = (self.header.min_height, self.header.max_height);
+        let (min, max) = range.unwrap_or((data.start, data.end));
+
+        if range.is_some() {
+            let span = data.end - data.start;
+            for point in self.points.iter_mut().filter(|point| point.h.is_finite()) {
+                // A flat map has nothing to stretch, so it sits at the bottom.
+                let t = if span > 0f32 { (point.h - data.start) / span } else { 0f32 };
+                point.h = min + (max - min) * t;
+            }
+        }
+
+        self.header.min_height = min;
+        self.header.max_height = max;
+
+        Some(before)
+    }
+}
+
+/// Parses a height range written as `MIN,MAX`, e.g. `-10,250`.
+pub fn parse_height_range(s: &str) -> Result<(f32, f32), String> {
+    let (min, max) = s.split_once(',')
+        .ok_or_else(|| format!("Invalid range '{}', expected MIN,MAX", s))?;
+    let parse = |part: &str| part.trim().parse::<f32>()
+        .map_err(|e| format!("Invalid range '{}': {}", s, e));
+
+    match (parse(min)?, parse(max)?) {
+        (min, max) if min < max => Ok((min, max)),
+        _ => Err(format!("Invalid range '{}', MIN has to be below MAX", s)),
+    }
+}
@@ -0,0 +1,76 @@
+use crate::Map;
+
+/// A tile whose height looks wrong, in decoded image coordinates.
+#[derive(Debug)]
+pub struct Suspect {
+    pub x: u32,
+    pub y: u32,
+    pub h: f32,
+}
+
+#[derive(Debug)]
+pub struct OutlierReport {
+    pub mean: f64,
+    pub std_dev: f64,
+    pub sigma: f32,
+    /// NaN or infinite heights.
+    pub non_finite: Vec<Suspect>,
+    /// Finite heights further than `sigma` standard deviations from the mean.
+    pub outliers: Vec<Suspect>,
+}
+
+impl OutlierReport {
+    /// The range heights are clamped to.
+    pub fn bounds(&self) -> (f32, f32) {
+        let spread = f64::from(self.sigma) * self.std_dev;
+        ((self.mean - spread) as f32, (self.mean + spread) as f32)
+    }
+}
+
+impl Map {
+    pub fn find_outliers(&self, sigma: f32) -> OutlierReport {
+        let finite: Vec<f64> = self.points.iter()
+            .map(|point| f64::from(point.h))
+            .filter(|h| h.is_finite())
+            .collect();
+
+        let count = finite.len().max(1) as f64;
+        let mean = finite.iter().sum::<f64>() / count;
+        let std_dev = (finite.iter().map(|h| (h - mean).powi(2)).sum::<f64>() / count).sqrt();
+
+        let mut report = OutlierReport { mean, std_dev, sigma, non_finite: Vec::new(), outliers: Vec::new() };
+        let (low, high) = report.bounds();
+
+        for (index, offset) in self.enabled_tiles() {
+            let (x, y) = crate::get_position(&index, &self.header.w, &self.header.h);
+            let h = self.points[offset].h;
+
+            if !h.is_finite() {
+                report.non_finite.push(Suspect { x, y, h });
+            } else if h < low || h > high {
+                report.outliers.push(Suspect { x, y, h });
+            }
+        }
+
+        report
+    }
+
+    /// Clamps outliers to the `sigma` bounds; infinities go to the nearest
+    /// bound and NaN to the mean. Returns the number of heights changed.
+    pub fn clamp_outliers(&mut self, sigma: f32) -> usize {
+        let report = self.find_outliers(sigma);
+        let (low, high) = report.bounds();
+        let mut changed = 0usize;
+
+        for point in self.points.iter_mut() {
+            let clamped = if point.h.is_nan() { report.mean as f32 } else { point.h.clamp(low, high) };
+
+            if clamped.to_bits() != point.h.to_bits() {
+                point.h = clamped;
+                changed += 1;
+            }
+        }
+
+        changed
+    }
+}
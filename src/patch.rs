@@ -0,0 +1,57 @@
+use clap::ValueEnum;
+
+use crate::Map;
+use crate::raster::Raster;
+use crate::region::Region;
+
+/// How patched heights are merged with the existing terrain.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Blend {
+    /// Overwrite the heights inside the patch outright.
+    Replace,
+    /// Fade the patch in towards its edges to avoid visible seams.
+    Feather,
+}
+
+impl Map {
+    /// Splices the heights of a grayscale image into the map with its
+    /// top-left corner at `at`.
+    ///
+    /// Pixel values are scaled over the header height range, so an exported
+    /// image can be retouched and patched back. Disabled tiles stay disabled.
+    /// Returns the number of tiles changed.
+    pub fn patch(&mut self, patch: &Raster, at: (u32, u32), blend: Blend, feather: u32) -> usize {
+        let w = self.header.w;
+        let h = self.header.h;
+        let min_height = self.header.min_height;
+        let height_diff = self.header.max_height - min_height;
+        let region = Region { x: at.0, y: at.1, w: patch.width, h: patch.height };
+        let tiles: Vec<(usize, usize)> = self.enabled_tiles().collect();
+        let mut changed = 0usize;
+
+        for (index, offset) in tiles {
+            let position = crate::get_position(&index, &w, &h);
+
+            if !region.contains(position) {
+                continue;
+            }
+
+            let px = position.0 - region.x;
+            let py = position.1 - region.y;
+            let weight = match blend {
+                Blend::Replace => 1f32,
+                Blend::Feather => {
+                    let edge = px.min(py).min(region.w - 1 - px).min(region.h - 1 - py);
+                    ((edge + 1) as f32 / (feather + 1) as f32).min(1f32)
+                }
+            };
+
+            let point = &mut self.points[offset];
+            let patched = min_height + patch.luma(px, py) * height_diff;
+            point.h = point.h * (1f32 - weight) + patched * weight;
+            changed += 1;
+        }
+
+        changed
+    }
+}
@@ -0,0 +1,198 @@
+use std::fs;
+use std::io;
+
+use serde_json::Value;
+
+use crate::Map;
+use crate::region::Region;
+
+/// The rings of one or more polygons in decoded image tile coordinates
+/// (x right, y down). Holes need no special treatment since containment
+/// uses the even-odd rule over all rings.
+#[derive(Debug)]
+pub struct Polygon {
+    rings: Vec<Vec<(f64, f64)>>,
+}
+
+impl Polygon {
+    /// Reads a GeoJSON geometry, feature or feature collection, or a WKT
+    /// `POLYGON`/`MULTIPOLYGON`.
+    pub fn load(path: &str) -> io::Result<Polygon> {
+        let text = fs::read_to_string(path)?;
+
+        let rings = if text.trim_start().starts_with('{') {
+            let value: Value = serde_json::from_str(&text)
+                .map_err(|e| invalid_data(format!("Invalid GeoJSON: {}", e)))?;
+            let mut rings = Vec::new();
+            geojson_rings(&value, &mut rings)?;
+            rings
+        } else {
+            wkt_rings(&text)?
+        };
+
+        if rings.iter().all(|ring| ring.len() < 3) {
+            return Err(invalid_data(format!("{} contains no polygon", path)));
+        }
+
+        Ok(Polygon { rings })
+    }
+
+    pub fn contains(&self, (x, y): (f64, f64)) -> bool {
+        let mut inside = false;
+
+        for ring in &self.rings {
+            for (i, &(x1, y1)) in ring.iter().enumerate() {
+                let (x2, y2) = ring[(i + 1) % ring.len()];
+
+                if (y1 > y) != (y2 > y) && x < x1 + (y - y1) * (x2 - x1) / (y2 - y1) {
+                    inside = !inside;
+                }
+            }
+        }
+
+        inside
+    }
+
+    /// The tiles touched by the polygon's bounding box, clipped to a map of
+    /// `w` x `h` tiles.
+    fn bounds(&self, w: u32, h: u32) -> Option<Region> {
+        let points = self.rings.iter().flatten();
+        let min_x = points.clone().map(|p| p.0).fold(f64::INFINITY, f64::min).floor().max(0.0);
+        let min_y = points.clone().map(|p| p.1).fold(f64::INFINITY, f64::min).floor().max(0.0);
+        let max_x = points.clone().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max).ceil().min(f64::from(w));
+        let max_y = points.map(|p| p.1).fold(f64::NEG_INFINITY, f64::max).ceil().min(f64::from(h));
+
+        if max_x <= min_x || max_y <= min_y {
+            return None;
+        }
+
+        Some(Region { x: min_x as u32, y: min_y as u32, w: (max_x - min_x) as u32, h: (max_y - min_y) as u32 })
+    }
+}
+
+fn geojson_rings(value: &Value, rings: &mut Vec<Vec<(f64, f64)>>) -> io::Result<()> {
+    match value["type"].as_str() {
+        Some("FeatureCollection") => {
+            for feature in value["features"].as_array().into_iter().flatten() {
+                geojson_rings(feature, rings)?;
+            }
+        }
+        Some("Feature") => geojson_rings(&value["geometry"], rings)?,
+        Some("GeometryCollection") => {
+            for geometry in value["geometries"].as_array().into_iter().flatten() {
+                geojson_rings(geometry, rings)?;
+            }
+        }
+        Some("Polygon") => polygon_rings(&value["coordinates"], rings)?,
+        Some("MultiPolygon") => {
+            for polygon in value["coordinates"].as_array().into_iter().flatten() {
+                polygon_rings(polygon, rings)?;
+            }
+        }
+        // Points and lines don't enclose any tiles.
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn polygon_rings(coordinates: &Value, rings: &mut Vec<Vec<(f64, f64)>>) -> io::Result<()> {
+    for ring in coordinates.as_array().into_iter().flatten() {
+        let points = ring.as_array()
+            .into_iter()
+            .flatten()
+            .map(|point| match (point[0].as_f64(), point[1].as_f64()) {
+                (Some(x), Some(y)) => Ok((x, y)),
+                _ => Err(invalid_data(format!("Invalid GeoJSON position {}", point))),
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        rings.push(points);
+    }
+
+    Ok(())
+}
+
+/// Every innermost parenthesized list of a WKT polygon is a ring.
+fn wkt_rings(text: &str) -> io::Result<Vec<Vec<(f64, f64)>>> {
+    let keyword = text.trim_start()
+        .split(|c: char| c == '(' || c.is_whitespace())
+        .next()
+        .unwrap_or_default()
+        .to_ascii_uppercase();
+
+    if keyword != "POLYGON" && keyword != "MULTIPOLYGON" {
+        return Err(invalid_data(format!("Expected a WKT POLYGON or MULTIPOLYGON, found '{}'", keyword)));
+    }
+
+    let mut rings = Vec::new();
+    let mut rest = text;
+
+    while let Some(close) = rest.find(')') {
+        let open = rest[..close].rfind('(');
+
+        if let Some(open) = open {
+            let ring = rest[open + 1..close].split(',')
+                .map(|point| {
+                    let mut numbers = point.split_whitespace().map(str::parse::<f64>);
+
+                    match (numbers.next(), numbers.next()) {
+                        (Some(Ok(x)), Some(Ok(y))) => Ok((x, y)),
+                        _ => Err(invalid_data(format!("Invalid WKT point '{}'", point.trim()))),
+                    }
+                })
+                .collect::<io::Result<Vec<_>>>()?;
+
+            rings.push(ring);
+        }
+
+        // Skip past this ring and any closing parentheses of the enclosing lists.
+        rest = &rest[close + 1..];
+        rest = rest.trim_start_matches(|c: char| c == ')' || c.is_whitespace());
+    }
+
+    Ok(rings)
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl Map {
+    /// Disables every tile whose center lies outside `polygon`, after
+    /// cropping to its bounding box if `crop` is set. Returns the number of
+    /// tiles disabled by the mask.
+    pub fn mask_polygon(&mut self, polygon: &Polygon, crop: bool) -> io::Result<usize> {
+        if crop {
+            let region = polygon.bounds(self.header.w, self.header.h)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "The polygon lies outside of the map"))?;
+            *self = self.crop(&region);
+
+            // Keep testing against the polygon's original coordinates.
+            return self.mask_polygon_at(polygon, (region.x, region.y));
+        }
+
+        self.mask_polygon_at(polygon, (0, 0))
+    }
+
+    fn mask_polygon_at(&mut self, polygon: &Polygon, (ox, oy): (u32, u32)) -> io::Result<usize> {
+        let w = self.header.w;
+        let h = self.header.h;
+        let mut tiles = self.tiles();
+        let mut disabled = 0usize;
+
+        for (index, tile) in tiles.iter_mut().enumerate() {
+            let (x, y) = crate::get_position(&index, &w, &h);
+            let center = (f64::from(x + ox) + 0.5, f64::from(y + oy) + 0.5);
+
+            if tile.is_some() && !polygon.contains(center) {
+                *tile = None;
+                disabled += 1;
+            }
+        }
+
+        self.set_tiles(tiles);
+
+        Ok(disabled)
+    }
+}
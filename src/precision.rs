@@ -0,0 +1,61 @@
+use crate::Map;
+
+/// What the stored heights reveal about the precision of the source data.
+#[derive(Debug)]
+pub struct Precision {
+    pub samples: usize,
+    pub unique: usize,
+    pub min: f32,
+    pub max: f32,
+    /// The smallest gap between two distinct heights.
+    pub min_step: Option<f64>,
+    /// The largest power-of-two step every height is a multiple of,
+    /// counted from `base`.
+    pub quantum: Option<f64>,
+    pub base: f64,
+}
+
+impl Precision {
+    pub fn analyze(map: &Map) -> Precision {
+        let mut heights: Vec<f32> = map.points.iter()
+            .map(|point| point.h)
+            .filter(|h| h.is_finite())
+            .collect();
+        heights.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        heights.dedup();
+
+        let min = heights.first().cloned().unwrap_or_default();
+        let max = heights.last().cloned().unwrap_or_default();
+
+        let min_step = heights.windows(2)
+            .map(|pair| f64::from(pair[1]) - f64::from(pair[0]))
+            .fold(None, |acc: Option<f64>, step| Some(acc.map_or(step, |acc| acc.min(step))));
+
+        // Quantization is usually relative to zero or to the header minimum,
+        // zero wins ties as the last candidate.
+        let (quantum, base) = [f64::from(map.header.min_height), 0f64].iter()
+            .map(|&base| (find_quantum(&heights, base), base))
+            .max_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+            .unwrap();
+
+        Precision { samples: map.points.len(), unique: heights.len(), min, max, min_step, quantum, base }
+    }
+
+    /// The number of distinct levels the quantum allows across the data range.
+    pub fn levels(&self) -> Option<f64> {
+        self.quantum.map(|q| ((f64::from(self.max) - f64::from(self.min)) / q).round() + 1f64)
+    }
+
+    /// The bits needed to store every level without loss.
+    pub fn effective_bits(&self) -> Option<u32> {
+        self.levels().map(|levels| (levels.max(1f64)).log2().ceil() as u32)
+    }
+}
+
+/// The largest step `2^k` (k from 8 down to -24) such that every height is
+/// an exact multiple of it away from `base`.
+fn find_quantum(heights: &[f32], base: f64) -> Option<f64> {
+    (-24i32..=8).rev()
+        .map(|k| 2f64.powi(k))
+        .find(|&step| heights.iter().all(|&h| ((f64::from(h) - base) / step).fract() == 0f64))
+}
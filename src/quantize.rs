@@ -0,0 +1,106 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+use crate::Map;
+
+/// A color of a quantized palette and how many tiles took it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PaletteEntry {
+    pub color: [u8; 3],
+    pub tiles: usize,
+}
+
+impl Map {
+    /// Reduces the colors of the enabled tiles to at most `colors` by median
+    /// cut, so the color layer can be stored as an indexed image. Returns the
+    /// palette, the most used color first.
+    ///
+    /// The result only depends on the colors and how often they appear, so
+    /// the same map always gets the same palette.
+    pub fn quantize_colors(&mut self, colors: usize) -> Vec<PaletteEntry> {
+        let mut counts: BTreeMap<[u8; 3], usize> = BTreeMap::new();
+        for point in &self.points {
+            *counts.entry([point.r, point.g, point.b]).or_default() += 1;
+        }
+
+        let mut boxes: Vec<Vec<([u8; 3], usize)>> = vec![counts.into_iter().collect()];
+        boxes.retain(|colors| !colors.is_empty());
+
+        while boxes.len() < colors {
+            // Split the box spanning the most along any channel, the earlier
+            // one on ties.
+            let widest = boxes.iter()
+                .enumerate()
+                .filter(|(_, colors)| colors.len() > 1)
+                .map(|(i, colors)| (i, widest_channel(colors)))
+                .fold(None, |widest: Option<(usize, (usize, u8))>, (i, channel)| match widest {
+                    Some((_, (_, spread))) if spread >= channel.1 => widest,
+                    _ => Some((i, channel)),
+                });
+            let (i, (channel, _)) = match widest {
+                Some(widest) => widest,
+                None => break,
+            };
+
+            let mut colors = boxes.swap_remove(i);
+            colors.sort_by_key(|&(color, _)| (color[channel], color));
+
+            // Cut where half of the box's tiles are below, keeping a color on
+            // either side.
+            let total: usize = colors.iter().map(|&(_, count)| count).sum();
+            let mut below = 0usize;
+            let cut = colors.iter()
+                .position(|&(_, count)| {
+                    below += count;
+                    below * 2 >= total
+                })
+                .map_or(1, |i| i + 1)
+                .clamp(1, colors.len() - 1);
+
+            let upper = colors.split_off(cut);
+            boxes.insert(i, upper);
+            boxes.insert(i, colors);
+        }
+
+        let mut mapping: BTreeMap<[u8; 3], [u8; 3]> = BTreeMap::new();
+        let mut palette: Vec<PaletteEntry> = Vec::with_capacity(boxes.len());
+
+        for colors in &boxes {
+            let tiles: usize = colors.iter().map(|&(_, count)| count).sum();
+            let mean = [0, 1, 2].map(|c| {
+                let sum: usize = colors.iter().map(|&(color, count)| usize::from(color[c]) * count).sum();
+                ((sum as f64 / tiles as f64).round()) as u8
+            });
+
+            for &(color, _) in colors {
+                mapping.insert(color, mean);
+            }
+            // Boxes can average out to the same color.
+            match palette.iter_mut().find(|entry| entry.color == mean) {
+                Some(entry) => entry.tiles += tiles,
+                None => palette.push(PaletteEntry { color: mean, tiles }),
+            }
+        }
+
+        for point in &mut self.points {
+            let [r, g, b] = mapping[&[point.r, point.g, point.b]];
+            point.r = r;
+            point.g = g;
+            point.b = b;
+        }
+
+        palette.sort_by_key(|entry| (Reverse(entry.tiles), entry.color));
+
+        palette
+    }
+}
+
+/// The channel the colors spread the furthest along, and how far.
+fn widest_channel(colors: &[([u8; 3], usize)]) -> (usize, u8) {
+    (0..3)
+        .map(|c| {
+            let (min, max) = colors.iter().fold((u8::MAX, u8::MIN), |(min, max), &(color, _)| (min.min(color[c]), max.max(color[c])));
+            (c, max - min)
+        })
+        .fold((0, 0), |widest, channel| if channel.1 > widest.1 { channel } else { widest })
+}
@@ -0,0 +1,247 @@
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::iter;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::mpsc;
+use std::sync::mpsc::SyncSender;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use clap::Args;
+use clap::Parser;
+use serde_json::Value;
+use serde_json::json;
+
+use crate::DecodeArgs;
+
+#[derive(Args, Debug)]
+pub struct QueueOptions {
+    /// Pick up `*.json` job files dropped into this directory. Without it,
+    /// jobs are read from stdin as JSON lines until it is closed.
+    #[arg(long, value_name = "DIR")]
+    pub watch: Option<PathBuf>,
+
+    /// How many jobs are converted at once.
+    #[arg(long, default_value_t = 4)]
+    pub workers: usize,
+
+    /// Where the result manifest of every job is written, as `<id>.json`.
+    #[arg(long, value_name = "DIR", default_value = "./output/manifests")]
+    pub manifests: PathBuf,
+
+    /// How often the watched directory is scanned for new jobs.
+    #[arg(long, value_name = "MS", default_value_t = 1000)]
+    pub poll_ms: u64,
+
+    /// Stop once the watched directory has no jobs left.
+    #[arg(long, requires = "watch")]
+    pub once: bool,
+}
+
+/// The flags of a job, parsed like the options of a plain decode.
+#[derive(Parser)]
+#[command(no_binary_name = true)]
+struct JobArgs {
+    #[command(flatten)]
+    decode: DecodeArgs,
+}
+
+/// A conversion to run: the map file and the decode flags to run it with,
+/// written as `{"id": "...", "file": "...", "args": ["--format", "png"]}`.
+struct Job {
+    id: String,
+    file: String,
+    args: Vec<String>,
+    /// The job file in the watched directory, renamed while it's being worked on.
+    claimed: Option<PathBuf>,
+}
+
+impl Job {
+    fn parse(text: &str, default_id: String) -> Result<Job, String> {
+        let value: Value = serde_json::from_str(text).map_err(|e| format!("Invalid job: {}", e))?;
+
+        let file = value["file"].as_str().ok_or("The job has no file")?;
+        let args = match &value["args"] {
+            Value::Null => Vec::new(),
+            Value::Array(args) => args.iter()
+                .map(|arg| arg.as_str().map(String::from).ok_or("Job arguments have to be strings"))
+                .collect::<Result<_, _>>()?,
+            _ => return Err(String::from("The job arguments have to be a list")),
+        };
+
+        Ok(Job {
+            id: value["id"].as_str().map(String::from).unwrap_or(default_id),
+            file: String::from(file),
+            args,
+            claimed: None,
+        })
+    }
+
+    /// Decodes and exports the map, returning the files written.
+    fn run(&self) -> Result<Vec<PathBuf>, String> {
+        let started = Instant::now();
+        let args = JobArgs::try_parse_from(self.args.iter().chain(iter::once(&self.file)))
+            .map_err(|e| e.to_string().lines().next().unwrap_or_default().to_string())?
+            .decode;
+
+        if args.dry_run {
+            return crate::print_plan(&args, None).map(|_| Vec::new()).map_err(|e| format!("Dry run failed: {}", e));
+        }
+
+        let map = crate::load_map(&args).map_err(|e| format!("Decoding failed: {}", e))?;
+
+        crate::convert(&args, &map, started).map_err(|e| format!("Export failed: {}", e))
+    }
+}
+
+/// Runs jobs on a pool of workers until stdin is closed, or forever when
+/// watching a directory, writing a manifest for each of them.
+pub fn run(options: &QueueOptions) -> io::Result<()> {
+    fs::create_dir_all(&options.manifests)?;
+
+    // Only hand out as many jobs as there are idle workers, so other
+    // daemons watching the same directory can pick up the rest.
+    let (sender, receiver) = mpsc::sync_channel::<Job>(0);
+    let receiver = Mutex::new(receiver);
+
+    thread::scope(|scope| {
+        for _ in 0..options.workers.max(1) {
+            scope.spawn(|| loop {
+                let job = receiver.lock().expect("Job queue poisoned").recv();
+
+                match job {
+                    Ok(job) => process(&job, options),
+                    Err(_) => break,
+                }
+            });
+        }
+
+        let result = match &options.watch {
+            Some(dir) => watch(dir, options, &sender),
+            None => read_stdin(options, &sender),
+        };
+        drop(sender);
+
+        result
+    })
+}
+
+fn read_stdin(options: &QueueOptions, sender: &SyncSender<Job>) -> io::Result<()> {
+    for (number, line) in io::stdin().lock().lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let id = format!("job-{}", number + 1);
+        match Job::parse(&line, id.clone()) {
+            Ok(job) => send(sender, job)?,
+            Err(e) => write_manifest(options, &id, None, &Err(e), Duration::default())?,
+        }
+    }
+
+    Ok(())
+}
+
+fn watch(dir: &Path, options: &QueueOptions, sender: &SyncSender<Job>) -> io::Result<()> {
+    println!("Watching {} for jobs", dir.display());
+
+    loop {
+        let mut pending: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+            .collect();
+        pending.sort();
+
+        if pending.is_empty() {
+            if options.once {
+                return Ok(());
+            }
+
+            thread::sleep(Duration::from_millis(options.poll_ms));
+            continue;
+        }
+
+        for path in pending {
+            let claimed = path.with_extension("json.working");
+
+            // Another daemon got to it first.
+            if fs::rename(&path, &claimed).is_err() {
+                continue;
+            }
+
+            let id = crate::file_stem(&path.to_string_lossy());
+            let job = fs::read_to_string(&claimed)
+                .map_err(|e| format!("Failed to read the job: {}", e))
+                .and_then(|text| Job::parse(&text, id.clone()));
+
+            match job {
+                Ok(job) => send(sender, Job { claimed: Some(claimed), ..job })?,
+                Err(e) => {
+                    write_manifest(options, &id, None, &Err(e), Duration::default())?;
+                    fs::rename(&claimed, claimed.with_extension("failed"))?;
+                }
+            }
+        }
+    }
+}
+
+fn send(sender: &SyncSender<Job>, job: Job) -> io::Result<()> {
+    sender.send(job).map_err(|_| io::Error::other("All workers have stopped"))
+}
+
+fn process(job: &Job, options: &QueueOptions) {
+    let started = Instant::now();
+
+    // A bad map shouldn't take the worker down with it.
+    let result = panic::catch_unwind(AssertUnwindSafe(|| job.run()))
+        .unwrap_or_else(|panic| Err(crate::panic_message(&*panic)));
+
+    let finished = write_manifest(options, &job.id, Some(&job.file), &result, started.elapsed())
+        .and_then(|_| match &job.claimed {
+            Some(claimed) => fs::rename(claimed, claimed.with_extension(if result.is_ok() { "done" } else { "failed" })),
+            None => Ok(()),
+        });
+
+    if let Err(e) = finished {
+        eprintln!("Failed to finish job {}: {}", job.id, e);
+    }
+}
+
+fn write_manifest(options: &QueueOptions, id: &str, file: Option<&str>, result: &Result<Vec<PathBuf>, String>,
+                  duration: Duration) -> io::Result<()> {
+    let (status, outputs, error) = match result {
+        Ok(outputs) => ("done", outputs.iter().map(|path| path.display().to_string()).collect(), None),
+        Err(e) => ("failed", Vec::new(), Some(e.as_str())),
+    };
+
+    match error {
+        Some(e) => eprintln!("Job {} failed: {}", id, e),
+        None => println!("Job {} done, {} outputs", id, outputs.len()),
+    }
+
+    let manifest = json!({
+        "id": id,
+        "file": file,
+        "status": status,
+        "outputs": outputs,
+        "error": error,
+        "duration_ms": duration.as_millis() as u64,
+    });
+
+    // Ids come from job files, keep them from escaping the manifest directory.
+    let name: String = id.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '_' })
+        .collect();
+
+    let mut text = serde_json::to_string_pretty(&manifest).map_err(io::Error::other)?;
+    text.push('\n');
+
+    fs::write(options.manifests.join(name + ".json"), text)
+}
@@ -0,0 +1,103 @@
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::path::Path;
+
+/// An external image loaded for editing, with every sample scaled to `0.0..=1.0`.
+#[derive(Debug)]
+pub struct Raster {
+    pub width: u32,
+    pub height: u32,
+    pub channels: usize,
+    pub samples: Vec<f32>,
+}
+
+impl Raster {
+    /// Loads a PNG or BMP image, picking the decoder from the file extension.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Raster> {
+        let path = path.as_ref();
+        let extension = path.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("png") => Raster::open_png(path),
+            Some("bmp") => Raster::open_bmp(path),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Unsupported image format: {}", path.display()))),
+        }
+    }
+
+    fn open_png(path: &Path) -> io::Result<Raster> {
+        let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+        decoder.set_transformations(png::Transformations::EXPAND);
+
+        let mut reader = decoder.read_info().map_err(invalid_data)?;
+        let mut buf = vec![0u8; reader.output_buffer_size().unwrap_or(0)];
+        let frame = reader.next_frame(&mut buf).map_err(invalid_data)?;
+        let data = &buf[..frame.buffer_size()];
+
+        let channels = frame.color_type.samples();
+        let samples = match frame.bit_depth {
+            png::BitDepth::Sixteen => data.chunks_exact(2)
+                .map(|s| f32::from(u16::from_be_bytes([s[0], s[1]])) / 65535f32)
+                .collect(),
+            _ => data.iter()
+                .map(|&s| f32::from(s) / 255f32)
+                .collect(),
+        };
+
+        Ok(Raster { width: frame.width, height: frame.height, channels, samples })
+    }
+
+    fn open_bmp(path: &Path) -> io::Result<Raster> {
+        let img = bmp::open(path).map_err(invalid_data)?;
+        let mut samples = Vec::with_capacity((img.get_width() * img.get_height() * 3) as usize);
+
+        for y in 0..img.get_height() {
+            for x in 0..img.get_width() {
+                let pixel = img.get_pixel(x, y);
+                samples.extend([pixel.r, pixel.g, pixel.b].iter().map(|&s| f32::from(s) / 255f32));
+            }
+        }
+
+        Ok(Raster { width: img.get_width(), height: img.get_height(), channels: 3, samples })
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> &[f32] {
+        let start = (y * self.width + x) as usize * self.channels;
+        &self.samples[start..start + self.channels]
+    }
+
+    /// The brightness of a pixel, ignoring any alpha channel.
+    pub fn luma(&self, x: u32, y: u32) -> f32 {
+        match self.pixel(x, y) {
+            [r, g, b, ..] => 0.299 * r + 0.587 * g + 0.114 * b,
+            [l, ..] => *l,
+            [] => 0f32,
+        }
+    }
+
+    /// The 8-bit color of a pixel; grayscale images give equal channels.
+    pub fn rgb(&self, x: u32, y: u32) -> (u8, u8, u8) {
+        let to_u8 = |s: f32| (s * 255f32).round() as u8;
+
+        match self.pixel(x, y) {
+            [r, g, b, ..] => (to_u8(*r), to_u8(*g), to_u8(*b)),
+            [l, ..] => (to_u8(*l), to_u8(*l), to_u8(*l)),
+            [] => (0, 0, 0),
+        }
+    }
+
+    pub fn check_size(&self, w: u32, h: u32) -> io::Result<()> {
+        if self.width != w || self.height != h {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+                "Image is {}x{} but the map is {}x{}", self.width, self.height, w, h)));
+        }
+
+        Ok(())
+    }
+}
+
+fn invalid_data<E: std::fmt::Display>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
@@ -0,0 +1,81 @@
+use std::str::FromStr;
+
+use crate::Map;
+
+/// A rectangular area of the map, in tile coordinates of the decoded image
+/// (origin in the top-left corner).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl Region {
+    pub fn contains(&self, (x, y): (u32, u32)) -> bool {
+        x >= self.x && x - self.x < self.w && y >= self.y && y - self.y < self.h
+    }
+
+    /// Whether the region lies within a map of `w` x `h` tiles.
+    pub fn fits(&self, w: u32, h: u32) -> bool {
+        self.x.checked_add(self.w).is_some_and(|right| right <= w)
+            && self.y.checked_add(self.h).is_some_and(|bottom| bottom <= h)
+    }
+}
+
+impl Map {
+    /// Copies the tiles inside `region`, which has to lie within the map,
+    /// into a smaller map with the same header otherwise.
+    pub fn crop(&self, region: &Region) -> Map {
+        let w = self.header.w;
+        let h = self.header.h;
+        let tiles = self.tiles();
+
+        // Image rows are flipped, so the region's bottom row comes first in the file.
+        let first_row = h - region.y - region.h;
+        let cropped = (first_row..first_row + region.h)
+            .flat_map(|row| (region.x..region.x + region.w).map(move |x| (row * w + x) as usize))
+            .map(|index| tiles[index])
+            .collect();
+
+        let mut header = self.header.clone();
+        header.w = region.w;
+        header.h = region.h;
+
+        let mut map = Map { header, points: Vec::new(), enabled: Vec::new(), runs: Vec::new() };
+        map.set_tiles(cropped);
+
+        map
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    /// Parses a region written as `x,y,w,h`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.split(',')
+            .map(|part| part.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Invalid region '{}': {}", s, e))?;
+
+        match parts[..] {
+            [x, y, w, h] => Ok(Region { x, y, w, h }),
+            _ => Err(format!("Invalid region '{}', expected x,y,w,h", s)),
+        }
+    }
+}
+
+/// Parses a tile position written as `x,y`.
+pub fn parse_position(s: &str) -> Result<(u32, u32), String> {
+    let parts = s.split(',')
+        .map(|part| part.trim().parse::<u32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Invalid position '{}': {}", s, e))?;
+
+    match parts[..] {
+        [x, y] => Ok((x, y)),
+        _ => Err(format!("Invalid position '{}', expected x,y", s)),
+    }
+}
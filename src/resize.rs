@@ -0,0 +1,154 @@
+use clap::ValueEnum;
+
+use crate::Map;
+use crate::TilePoint;
+
+/// How `Map::resize` interpolates the heights between tiles.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ResizeFilter {
+    /// Blend the four surrounding tiles; never overshoots.
+    Bilinear,
+    /// Catmull-Rom over the sixteen surrounding tiles; smoother slopes, but
+    /// can overshoot slightly at sharp edges.
+    Bicubic,
+    /// When downscaling, blend the highest and the mean height of the tiles
+    /// each new tile covers, so peaks aren't flattened in distant terrain.
+    /// Bilinear when upscaling.
+    Peaks,
+}
+
+impl Map {
+    /// Resamples the map to `w` x `h` tiles. The corner tiles stay at the
+    /// corners, so e.g. 512x512 to 513x513 keeps the heights along the
+    /// edges, as engines with power-of-two-plus-one sizes expect.
+    ///
+    /// Where the filter would reach a disabled tile it falls back to
+    /// bilinear and then to the nearest tile; colors always come from the
+    /// nearest tile, and a tile is disabled if its nearest tile is. An empty
+    /// map, with no rows or columns, comes out all disabled.
+    ///
+    /// `peak_weight` is how much of the highest height the `peaks` filter
+    /// takes, from 0 for the mean to 1 for the maximum.
+    pub fn resize(&self, w: u32, h: u32, filter: ResizeFilter, peak_weight: f32) -> Map {
+        let (old_w, old_h) = (self.header.w as i64, self.header.h as i64);
+        let source = self.tiles();
+        // A map without rows or columns has no tile to clamp to.
+        let empty = old_w == 0 || old_h == 0;
+        let at = |x: i64, y: i64| if empty { None } else { source[(y.clamp(0, old_h - 1) * old_w + x.clamp(0, old_w - 1)) as usize] };
+
+        // Scaling is symmetric, so rows can stay in file order.
+        let position = |i: u32, new: u32, old: i64| if new > 1 {
+            f64::from(i) * (old - 1) as f64 / f64::from(new - 1)
+        } else {
+            (old - 1) as f64 / 2f64
+        };
+
+        // How far each new tile reaches into the old ones, half the step
+        // between new tiles.
+        let reach = |new: u32, old: i64| if new > 1 { (old - 1) as f64 / f64::from(new - 1) / 2f64 } else { old as f64 / 2f64 };
+        let (reach_x, reach_y) = (reach(w, old_w), reach(h, old_h));
+
+        let mut tiles = Vec::with_capacity(w as usize * h as usize);
+        for y in 0..h {
+            let sy = position(y, h, old_h);
+            for x in 0..w {
+                let sx = position(x, w, old_w);
+
+                let nearest = match at(sx.round() as i64, sy.round() as i64) {
+                    Some(point) => point,
+                    None => {
+                        tiles.push(None);
+                        continue;
+                    }
+                };
+
+                // The last row and column interpolate from the one before.
+                let (x0, y0) = ((sx.floor() as i64).min(old_w - 2).max(0), (sy.floor() as i64).min(old_h - 2).max(0));
+                let (fx, fy) = ((sx - x0 as f64) as f32, (sy - y0 as f64) as f32);
+
+                let bicubic = || -> Option<f32> {
+                    let (wx, wy) = (catmull_rom(fx), catmull_rom(fy));
+                    let mut height = 0f32;
+                    for (j, wy) in wy.iter().enumerate() {
+                        for (i, wx) in wx.iter().enumerate() {
+                            height += at(x0 - 1 + i as i64, y0 - 1 + j as i64)?.h * wx * wy;
+                        }
+                    }
+                    Some(height)
+                };
+                let bilinear = || -> Option<f32> {
+                    let (a, b, c, d) = (at(x0, y0)?.h, at(x0 + 1, y0)?.h, at(x0, y0 + 1)?.h, at(x0 + 1, y0 + 1)?.h);
+                    let top = a + (b - a) * fx;
+                    let bottom = c + (d - c) * fx;
+                    Some(top + (bottom - top) * fy)
+                };
+
+                let peaks = || -> Option<f32> {
+                    let (mut max, mut sum, mut count) = (f32::MIN, 0f32, 0u32);
+                    for y in (sy - reach_y).ceil() as i64..=(sy + reach_y).floor() as i64 {
+                        for x in (sx - reach_x).ceil() as i64..=(sx + reach_x).floor() as i64 {
+                            if let Some(point) = at(x, y) {
+                                max = max.max(point.h);
+                                sum += point.h;
+                                count += 1;
+                            }
+                        }
+                    }
+                    (count > 0).then(|| max * peak_weight + sum / count as f32 * (1f32 - peak_weight))
+                };
+
+                let height = match filter {
+                    ResizeFilter::Bicubic => bicubic().or_else(bilinear),
+                    ResizeFilter::Peaks if reach_x > 0.5 || reach_y > 0.5 => peaks(),
+                    ResizeFilter::Bilinear | ResizeFilter::Peaks => bilinear(),
+                };
+
+                tiles.push(Some(TilePoint { h: height.unwrap_or(nearest.h), ..nearest }));
+            }
+        }
+
+        let mut header = self.header.clone();
+        header.w = w;
+        header.h = h;
+
+        let mut map = Map { header, points: Vec::new(), enabled: Vec::new(), runs: Vec::new() };
+        map.set_tiles(tiles);
+
+        map
+    }
+}
+
+/// The Catmull-Rom weights of the tiles at -1, 0, 1 and 2 for a point `t`
+/// of the way from tile 0 to tile 1.
+fn catmull_rom(t: f32) -> [f32; 4] {
+    let (t2, t3) = (t * t, t * t * t);
+
+    [
+        (-t3 + 2f32 * t2 - t) / 2f32,
+        (3f32 * t3 - 5f32 * t2 + 2f32) / 2f32,
+        (-3f32 * t3 + 4f32 * t2 + t) / 2f32,
+        (t3 - t2) / 2f32,
+    ]
+}
+
+/// Parses a size written as `WxH`, e.g. `513x513`.
+pub fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    let (w, h) = s.split_once(['x', 'X'])
+        .ok_or_else(|| format!("Invalid size '{}', expected WxH", s))?;
+    let parse = |part: &str| part.trim().parse::<u32>()
+        .map_err(|e| format!("Invalid size '{}': {}", s, e));
+
+    match (parse(w)?, parse(h)?) {
+        (0, _) | (_, 0) => Err(format!("Invalid size '{}', both sides have to be at least 1", s)),
+        size => Ok(size),
+    }
+}
+
+/// Parses a weight from 0 to 1.
+pub fn parse_weight(s: &str) -> Result<f32, String> {
+    match s.trim().parse::<f32>() {
+        Ok(weight) if (0f32..=1f32).contains(&weight) => Ok(weight),
+        Ok(weight) => Err(format!("Invalid weight {}, expected 0 to 1", weight)),
+        Err(e) => Err(format!("Invalid weight '{}': {}", s, e)),
+    }
+}
@@ -0,0 +1,183 @@
+use crate::Map;
+use crate::TilePoint;
+
+impl Map {
+    /// The size of the canvas `rotate` turns the map onto, enlarged to fit
+    /// the rotated corners.
+    pub fn rotated_size(&self, degrees: f64) -> (u32, u32) {
+        let (w, h) = (f64::from(self.header.w), f64::from(self.header.h));
+        let (sin, cos) = degrees.to_radians().sin_cos();
+
+        // Round away the floating point noise of right angles before sizing.
+        let extent = |a: f64, b: f64| ((a * 1e9).round() / 1e9).abs() + ((b * 1e9).round() / 1e9).abs();
+        let new_w = (extent(w * cos, h * sin).ceil() as u32).max(1);
+        let new_h = (extent(w * sin, h * cos).ceil() as u32).max(1);
+
+        (new_w, new_h)
+    }
+
+    /// Rotates the map clockwise by `degrees` as seen in the decoded image,
+    /// onto a canvas of `rotated_size`, which callers check against the
+    /// limits first.
+    ///
+    /// Heights are interpolated bilinearly where all four surrounding tiles
+    /// are enabled and taken from the nearest tile otherwise; colors always
+    /// come from the nearest tile. Tiles that fall outside of the source map
+    /// are disabled.
+    pub fn rotate(&self, degrees: f64) -> Map {
+        let w = self.header.w;
+        let h = self.header.h;
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let (new_w, new_h) = self.rotated_size(degrees);
+
+        let source = self.tiles();
+        let at = |x: i64, y: i64| -> Option<TilePoint> {
+            if x < 0 || y < 0 || x >= i64::from(w) || y >= i64::from(h) {
+                return None;
+            }
+            source[((i64::from(h) - 1 - y) * i64::from(w) + x) as usize]
+        };
+
+        let (cx, cy) = (f64::from(w) / 2.0, f64::from(h) / 2.0);
+        let (new_cx, new_cy) = (f64::from(new_w) / 2.0, f64::from(new_h) / 2.0);
+        let mut tiles = vec![None; new_w as usize * new_h as usize];
+
+        for y in 0..new_h {
+            for x in 0..new_w {
+                let dx = f64::from(x) + 0.5 - new_cx;
+                let dy = f64::from(y) + 0.5 - new_cy;
+                let sx = cx + dx * cos + dy * sin;
+                let sy = cy - dx * sin + dy * cos;
+
+                let nearest = match at(sx.floor() as i64, sy.floor() as i64) {
+                    Some(point) => point,
+                    None => continue,
+                };
+
+                let (u, v) = (sx - 0.5, sy - 0.5);
+                let (x0, y0) = (u.floor() as i64, v.floor() as i64);
+                let (fx, fy) = ((u - u.floor()) as f32, (v - v.floor()) as f32);
+
+                let height = match (at(x0, y0), at(x0 + 1, y0), at(x0, y0 + 1), at(x0 + 1, y0 + 1)) {
+                    (Some(a), Some(b), Some(c), Some(d)) => {
+                        let top = a.h + (b.h - a.h) * fx;
+                        let bottom = c.h + (d.h - c.h) * fx;
+                        top + (bottom - top) * fy
+                    }
+                    _ => nearest.h,
+                };
+
+                tiles[(new_h - 1 - y) as usize * new_w as usize + x as usize] = Some(TilePoint { h: height, ..nearest });
+            }
+        }
+
+        let mut header = self.header.clone();
+        header.w = new_w;
+        header.h = new_h;
+
+        let mut map = Map { header, points: Vec::new(), enabled: Vec::new(), runs: Vec::new() };
+        map.set_tiles(tiles);
+
+        map
+    }
+
+    /// Mirrors the map left to right and top to bottom as asked, then turns
+    /// it clockwise by `quarter_turns` right angles, as seen in the decoded
+    /// image. Unlike `rotate` nothing is interpolated.
+    pub fn orient(&self, flip_x: bool, flip_y: bool, quarter_turns: u32) -> Map {
+        let (w, h) = (self.header.w, self.header.h);
+        let turned = quarter_turns % 2 == 1;
+        let (new_w, new_h) = if turned { (h, w) } else { (w, h) };
+        let source = self.tiles();
+
+        let mut tiles = vec![None; source.len()];
+        for y in 0..new_h {
+            for x in 0..new_w {
+                // Undo the turn, then the flips, to find the source tile.
+                let (mut sx, mut sy) = match quarter_turns % 4 {
+                    1 => (y, h - 1 - x),
+                    2 => (w - 1 - x, h - 1 - y),
+                    3 => (w - 1 - y, x),
+                    _ => (x, y),
+                };
+                if flip_x {
+                    sx = w - 1 - sx;
+                }
+                if flip_y {
+                    sy = h - 1 - sy;
+                }
+
+                // Image rows are flipped in the file.
+                tiles[((new_h - 1 - y) * new_w + x) as usize] = source[((h - 1 - sy) * w + sx) as usize];
+            }
+        }
+
+        let mut header = self.header.clone();
+        header.w = new_w;
+        header.h = new_h;
+
+        let mut map = Map { header, points: Vec::new(), enabled: Vec::new(), runs: Vec::new() };
+        map.set_tiles(tiles);
+
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Map;
+    use crate::fixture;
+
+    fn fixture_map() -> Map {
+        Map::from_reader(&fixture::map_bytes(&fixture::RUNS)[..]).unwrap()
+    }
+
+    fn assert_same_tiles(map: &Map, expected: &Map) {
+        assert_eq!((map.header.w, map.header.h), (expected.header.w, expected.header.h));
+        assert_eq!(map.tile_heights(), expected.tile_heights());
+    }
+
+    #[test]
+    fn four_quarter_turns_come_back_around() {
+        let map = fixture_map();
+        let turned = (0..4).fold(fixture_map(), |turned, _| turned.orient(false, false, 1));
+
+        assert_same_tiles(&turned, &map);
+        assert_same_tiles(&map.orient(false, false, 4), &map);
+    }
+
+    #[test]
+    fn flipping_twice_comes_back() {
+        let map = fixture_map();
+
+        assert_same_tiles(&map.orient(true, false, 0).orient(true, false, 0), &map);
+        assert_same_tiles(&map.orient(false, true, 0).orient(false, true, 0), &map);
+        assert_same_tiles(&map.orient(true, true, 0).orient(true, true, 0), &map);
+    }
+
+    #[test]
+    fn a_quarter_turn_moves_the_top_left_corner_to_the_top_right() {
+        let map = fixture_map();
+        let (w, h) = (fixture::W as usize, fixture::H as usize);
+        let before = map.tile_heights();
+        let turned = map.orient(false, false, 1);
+        let after = turned.tile_heights();
+
+        assert_eq!((turned.header.w, turned.header.h), (fixture::H, fixture::W));
+        assert!(before[0].is_some());
+        // The new image is h wide, its top-right corner is tile h - 1.
+        assert_eq!(after[h - 1], before[0]);
+        // And the bottom-left corner comes up to the top-left.
+        assert_eq!(after[0], before[(h - 1) * w]);
+    }
+
+    #[test]
+    fn flips_mirror_the_corners() {
+        let map = fixture_map();
+        let (w, h) = (fixture::W as usize, fixture::H as usize);
+        let before = map.tile_heights();
+
+        assert_eq!(map.orient(true, false, 0).tile_heights()[w - 1], before[0]);
+        assert_eq!(map.orient(false, true, 0).tile_heights()[(h - 1) * w], before[0]);
+    }
+}
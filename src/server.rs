@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::io;
+use std::io::Cursor;
+use std::io::prelude::*;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+
+use clap::ValueEnum;
+use serde_json::Value;
+use serde_json::json;
+use tiny_http::Header;
+use tiny_http::Method;
+use tiny_http::Request;
+use tiny_http::Response;
+use tiny_http::Server;
+
+use crate::Map;
+use crate::export;
+use crate::export::ExportOptions;
+use crate::export::Format;
+use crate::export::Palette;
+use crate::export::PixelFormat;
+use crate::limits::Limits;
+
+type Reply = Response<Cursor<Vec<u8>>>;
+
+/// Decoded maps waiting to be rendered, keyed by a hash of the uploaded
+/// file. The oldest map is dropped once `capacity` are kept.
+struct MapStore {
+    capacity: usize,
+    order: VecDeque<u64>,
+    maps: HashMap<u64, Arc<Map>>,
+}
+
+impl MapStore {
+    fn insert(&mut self, id: u64, map: Map) {
+        if self.maps.insert(id, Arc::new(map)).is_some() {
+            return;
+        }
+
+        self.order.push_back(id);
+        while self.order.len() > self.capacity.max(1) {
+            if let Some(oldest) = self.order.pop_front() {
+                self.maps.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// A request that can't be answered, sent back as `{"error": ...}`.
+struct Failure {
+    status: u16,
+    message: String,
+}
+
+impl Failure {
+    fn new(status: u16, message: impl Into<String>) -> Failure {
+        Failure { status, message: message.into() }
+    }
+
+    fn reply(&self) -> Reply {
+        json_reply(&json!({ "error": self.message })).with_status_code(self.status)
+    }
+}
+
+impl From<io::Error> for Failure {
+    fn from(e: io::Error) -> Failure {
+        let status = match e.kind() {
+            io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput | io::ErrorKind::UnexpectedEof => 400,
+            _ => 500,
+        };
+
+        Failure::new(status, e.to_string())
+    }
+}
+
+/// Answers `POST /decode` and `GET /render` on `address` with `workers`
+/// threads until the process is stopped.
+pub fn serve(address: &str, workers: usize, capacity: usize, limits: &Limits) -> io::Result<()> {
+    let server = Server::http(address).map_err(io::Error::other)?;
+    let store = Mutex::new(MapStore { capacity, order: VecDeque::new(), maps: HashMap::new() });
+
+    println!("Listening on http://{}", server.server_addr());
+
+    thread::scope(|scope| {
+        for _ in 0..workers.max(1) {
+            scope.spawn(|| loop {
+                match server.recv() {
+                    Ok(request) => handle(request, &store, limits),
+                    Err(e) => {
+                        eprintln!("Failed to receive a request: {}", e);
+                        break;
+                    }
+                }
+            });
+        }
+    });
+
+    Ok(())
+}
+
+fn handle(mut request: Request, store: &Mutex<MapStore>, limits: &Limits) {
+    let method = request.method().clone();
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+    let result = match (&method, path) {
+        (Method::Post, "/decode") => decode(&mut request, store, limits),
+        (Method::Get, "/render") => render(query, store),
+        (_, "/decode") | (_, "/render") => Err(Failure::new(405, format!("{} is not allowed on {}", method, path))),
+        _ => Err(Failure::new(404, format!("Unknown endpoint {}", path))),
+    };
+
+    let reply = result.unwrap_or_else(|failure| failure.reply());
+    println!("{} {} {}", method, url, reply.status_code().0);
+
+    if let Err(e) = request.respond(reply) {
+        eprintln!("Failed to respond: {}", e);
+    }
+}
+
+/// Decodes the map file in the request body and keeps it for rendering,
+/// answering with its id and header.
+fn decode(request: &mut Request, store: &Mutex<MapStore>, limits: &Limits) -> Result<Reply, Failure> {
+    let too_large = |e: io::Error| Failure::new(413, e.to_string());
+
+    if let Some(len) = request.body_length() {
+        limits.check_file_size(len as u64).map_err(too_large)?;
+    }
+
+    let mut body = Vec::new();
+    request.as_reader().take(limits.max_file_size + 1).read_to_end(&mut body)?;
+    limits.check_file_size(body.len() as u64).map_err(too_large)?;
+
+    let map = Map::parse(&mut body.as_slice(), limits).map_err(io::Error::from)?;
+
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    let id = hasher.finish();
+
+    let header: serde_json::Map<String, Value> = map.header.fields().into_iter()
+        .map(|(field, value)| (String::from(field), Value::String(value)))
+        .collect();
+    let reply = json_reply(&json!({
+        "id": format!("{:016x}", id),
+        "width": map.header.w,
+        "height": map.header.h,
+        "points": map.points.len(),
+        "header": header,
+    }));
+
+    store.lock().expect("Map store poisoned").insert(id, map);
+
+    Ok(reply)
+}
+
+/// Renders a decoded map as `format` (png, bmp or tiff) in `pixel_format`,
+/// e.g. gray16 or rgb8 for the color layer, colored by the built-in ramp
+/// named by `palette`. Palette files are left to the CLI, so requests can't
+/// read the server's files.
+fn render(query: &str, store: &Mutex<MapStore>) -> Result<Reply, Failure> {
+    let params: HashMap<&str, &str> = query.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .collect();
+
+    let id = params.get("id").ok_or_else(|| Failure::new(400, "Missing the id of a decoded map"))?;
+    let id = u64::from_str_radix(id, 16).map_err(|_| Failure::new(400, format!("Invalid map id '{}'", id)))?;
+
+    let format = match params.get("format") {
+        Some(name) => Format::from_str(name, true).map_err(|e| Failure::new(400, e))?,
+        None => Format::Png,
+    };
+    let pixel_format = match params.get("pixel_format") {
+        Some(name) => PixelFormat::from_str(name, true).map_err(|e| Failure::new(400, e))?,
+        None => PixelFormat::Gray8,
+    };
+    let palette = params.get("palette")
+        .map(|name| Palette::built_in(name).ok_or_else(|| Failure::new(400, format!(
+            "Unknown palette '{}', expected {}", name, Palette::built_in_names().join(", ")))))
+        .transpose()?;
+
+    let map = store.lock().expect("Map store poisoned").maps.get(&id).cloned()
+        .ok_or_else(|| Failure::new(404, format!("No decoded map {:016x}, POST it to /decode first", id)))?;
+
+    let options = ExportOptions { formats: vec![format], pixel_format, palette, ..ExportOptions::default() };
+    let bytes = export::render(&map, format, &options)?;
+
+    let content_type = match format {
+        Format::Bmp => "image/bmp",
+        Format::Png | Format::Png16 => "image/png",
+        _ => "image/tiff",
+    };
+
+    Ok(Response::from_data(bytes).with_header(header("Content-Type", content_type)))
+}
+
+fn json_reply(value: &Value) -> Reply {
+    Response::from_string(value.to_string()).with_header(header("Content-Type", "application/json"))
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field, value).expect("Header names and values are ASCII")
+}
@@ -0,0 +1,135 @@
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+
+use clap::Parser;
+
+use crate::Map;
+use crate::edit::Edit;
+use crate::limits::Limits;
+
+/// A line of a session file, parsed with the same syntax as the CLI edits.
+#[derive(Parser)]
+#[command(no_binary_name = true)]
+struct EditLine {
+    #[command(subcommand)]
+    edit: Edit,
+}
+
+/// A reviewable list of edits stored as a text file, one edit per line.
+///
+/// Blank lines and lines starting with `#` are ignored. Arguments with
+/// spaces are put in double quotes, inside which `\"` and `\\` stand for a
+/// quote and a backslash, e.g.:
+///
+/// ```text
+/// op add --value 25
+/// mask island.png --mode set
+/// patch --from "crater v2.png" --at 120,80 --blend feather
+/// ```
+#[derive(Debug, Default)]
+pub struct Session {
+    pub edits: Vec<Edit>,
+}
+
+impl Session {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Session> {
+        let path = path.as_ref();
+        let mut edits = Vec::new();
+
+        for (number, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let edit = split_line(line)
+                .and_then(parse_edit)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!(
+                    "{}:{}: {}", path.display(), number + 1, e)))?;
+            edits.push(edit);
+        }
+
+        Ok(Session { edits })
+    }
+
+    /// Validates an edit and appends it to the session file, creating it if needed.
+    pub fn record<P: AsRef<Path>>(path: P, args: &[String]) -> io::Result<Edit> {
+        let edit = parse_edit(args.iter())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        let line: Vec<String> = args.iter().map(|arg| quote(arg)).collect();
+        writeln!(file, "{}", line.join(" "))?;
+
+        Ok(edit)
+    }
+
+    pub fn apply(&self, map: &mut Map, limits: &Limits) -> io::Result<()> {
+        for edit in &self.edits {
+            edit.apply(map, limits)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_edit<I, T>(args: I) -> Result<Edit, String>
+    where I: IntoIterator<Item = T>, T: Into<std::ffi::OsString> + Clone {
+    EditLine::try_parse_from(args)
+        .map(|line| line.edit)
+        .map_err(|e| e.to_string())
+}
+
+/// Splits a session line into its arguments, undoing `quote`.
+fn split_line(line: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut arg: Option<String> = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => arg.extend(chars.next()),
+                        Some(c) => arg.push(c),
+                        None => return Err(String::from("The line ends inside quotes")),
+                    }
+                }
+            }
+            c if c.is_whitespace() => args.extend(arg.take()),
+            c => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(arg);
+
+    Ok(args)
+}
+
+/// An argument as written to a session line, quoted if it would otherwise
+/// split or change when read back.
+fn quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.chars().any(|c| c.is_whitespace() || c == '"') {
+        return arg.to_string();
+    }
+
+    let mut quoted = String::from("\"");
+    for c in arg.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+
+    quoted
+}
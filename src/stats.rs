@@ -0,0 +1,86 @@
+use std::ops::Range;
+
+use crate::Map;
+
+/// The distribution of the stored heights over the enabled tiles, which
+/// often differs from the header's range.
+#[derive(Debug)]
+pub struct Stats {
+    /// The tiles of the map, enabled or not.
+    pub tiles: usize,
+    pub enabled: usize,
+    /// NaN or infinite heights, left out of everything below.
+    pub non_finite: usize,
+    /// `None` without any finite heights, as are the others.
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+    pub mean: Option<f64>,
+    pub median: Option<f32>,
+    pub std_dev: Option<f64>,
+}
+
+impl Stats {
+    /// The share of the tiles that are enabled, from 0 to 1.
+    pub fn coverage(&self) -> f64 {
+        self.enabled as f64 / self.tiles.max(1) as f64
+    }
+}
+
+/// How many finite heights fall into each of `counts.len()` equal steps of
+/// `range`, the last step including the top.
+#[derive(Debug)]
+pub struct Histogram {
+    pub range: Range<f32>,
+    pub counts: Vec<usize>,
+}
+
+impl Histogram {
+    /// The heights bin `i` covers.
+    pub fn bin_range(&self, i: usize) -> Range<f32> {
+        let step = (self.range.end - self.range.start) / self.counts.len() as f32;
+        self.range.start + step * i as f32..self.range.start + step * (i + 1) as f32
+    }
+}
+
+impl Map {
+    pub fn stats(&self) -> Stats {
+        let mut finite: Vec<f32> = self.points.iter()
+            .map(|point| point.h)
+            .filter(|h| h.is_finite())
+            .collect();
+        finite.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let count = finite.len() as f64;
+        let mean = (!finite.is_empty()).then(|| finite.iter().map(|&h| f64::from(h)).sum::<f64>() / count);
+        let std_dev = mean.map(|mean| (finite.iter().map(|&h| (f64::from(h) - mean).powi(2)).sum::<f64>() / count).sqrt());
+        // The lower middle height of an even count, so it is one of them.
+        let median = finite.get(finite.len().saturating_sub(1) / 2).copied();
+
+        Stats {
+            tiles: self.enabled.len(),
+            enabled: self.points.len(),
+            non_finite: self.points.len() - finite.len(),
+            min: finite.first().copied(),
+            max: finite.last().copied(),
+            mean,
+            median,
+            std_dev,
+        }
+    }
+
+    /// The histogram of the finite heights in `bins` steps over the data
+    /// range, `None` if there are none.
+    pub fn histogram(&self, bins: usize) -> Option<Histogram> {
+        let range = self.data_range()?;
+        let bins = bins.max(1);
+        let span = range.end - range.start;
+        let mut counts = vec![0usize; bins];
+
+        for h in self.points.iter().map(|point| point.h).filter(|h| h.is_finite()) {
+            let bin = if span > 0f32 { ((h - range.start) / span * bins as f32) as usize } else { 0 };
+            counts[bin.min(bins - 1)] += 1;
+        }
+
+        Some(Histogram { range, counts })
+    }
+}
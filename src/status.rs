@@ -0,0 +1,28 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+/// Whether `status!` prints to stderr, for while stdout carries an output.
+static ON_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Sends the messages of `status!` to stderr from now on, so they don't mix
+/// into an output streamed to stdout.
+pub fn use_stderr() {
+    ON_STDERR.store(true, Ordering::Relaxed);
+}
+
+pub fn on_stderr() -> bool {
+    ON_STDERR.load(Ordering::Relaxed)
+}
+
+/// Prints a progress message like `println!`, or like `eprintln!` once
+/// `status::use_stderr` was called.
+#[macro_export]
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if $crate::status::on_stderr() {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
@@ -0,0 +1,201 @@
+use std::io;
+
+use clap::ValueEnum;
+
+use crate::Map;
+use crate::MapHeader;
+use crate::TilePoint;
+use crate::limits::Limits;
+use crate::region;
+
+/// How `stitch` settles the tiles where neighboring maps overlap.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Seam {
+    /// Average the heights and colors of every map enabled there.
+    Average,
+    /// Keep the map given first.
+    First,
+    /// Keep the map given last.
+    Last,
+}
+
+/// Parses a stitch input, a map path optionally followed by `@column,row`
+/// for its place in the grid.
+pub fn parse_input(s: &str) -> Result<(String, Option<(u32, u32)>), String> {
+    match s.rsplit_once('@') {
+        Some((path, position)) if !path.is_empty() => Ok((path.to_string(), Some(region::parse_position(position)?))),
+        _ => Ok((s.to_string(), None)),
+    }
+}
+
+/// The grid position a map's header gives in the unknown fields named
+/// `fields`, read as whole columns and rows.
+pub fn header_position(header: &MapHeader, fields: (&str, &str)) -> io::Result<(i64, i64)> {
+    let read = |name: &str| -> io::Result<i64> {
+        let field = header.unknowns.get(name).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!(
+            "There is no header field '{}'", name)))?;
+        let value = field.value().parse::<f64>().ok().filter(|value| value.is_finite());
+
+        value.map(|value| value.round() as i64).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!(
+            "The header field '{}' of '{}' holds {}, not a grid position", name, header.name, field.value())))
+    };
+
+    Ok((read(fields.0)?, read(fields.1)?))
+}
+
+/// Assembles maps of the same size into one, each placed at its
+/// `(column, row)` in a grid with the top-left map at 0,0. Neighbors share
+/// `overlap` rows and columns of tiles along their seams, settled by `seam`.
+/// Grid cells without a map are left disabled.
+///
+/// The mosaic takes the first map's header, with its size and height range
+/// grown to cover all of them. Mosaics larger than `limits` allow are
+/// rejected before any tile is placed.
+pub fn stitch(maps: &[(Map, (i64, i64))], overlap: u32, seam: Seam, limits: &Limits) -> io::Result<Map> {
+    let (first, _) = maps.first().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No maps to stitch"))?;
+    let (w, h) = (first.header.w, first.header.h);
+
+    if let Some((map, _)) = maps.iter().find(|(map, _)| map.header.w != w || map.header.h != h) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+            "'{}' is {}x{} tiles, but the maps of a grid have to be the same size as '{}', {}x{}",
+            map.header.name, map.header.w, map.header.h, first.header.name, w, h)));
+    }
+    if overlap >= w || overlap >= h {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!(
+            "An overlap of {} tiles leaves nothing of {}x{} maps", overlap, w, h)));
+    }
+
+    let min_column = maps.iter().map(|&(_, (column, _))| column).min().unwrap_or_default();
+    let min_row = maps.iter().map(|&(_, (_, row))| row).min().unwrap_or_default();
+    let (step_x, step_y) = (u64::from(w - overlap), u64::from(h - overlap));
+
+    // How many tiles the grid cells up to the furthest offset cover, `None`
+    // past what a map can hold.
+    let span = |furthest: Option<i64>, step: u64| -> Option<u32> {
+        (furthest? as u64 + 1).checked_mul(step)?
+            .checked_add(u64::from(overlap))
+            .filter(|&tiles| tiles <= u64::from(u32::MAX))
+            .map(|tiles| tiles as u32)
+    };
+    let furthest = |offset: fn(&(i64, i64)) -> i64, min: i64| maps.iter()
+        .try_fold(0i64, |furthest, (_, position)| Some(furthest.max(offset(position).checked_sub(min)?)));
+    let total_w = span(furthest(|&(column, _)| column, min_column), step_x);
+    let total_h = span(furthest(|&(_, row)| row, min_row), step_y);
+    let (total_w, total_h) = total_w.zip(total_h).ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput,
+        "The grid positions are too far apart for one mosaic"))?;
+
+    limits.check_header(&MapHeader { w: total_w, h: total_h, ..first.header.clone() })?;
+
+    // Every map enabled at a tile of the mosaic, in image order.
+    let mut covering: Vec<Vec<TilePoint>> = vec![Vec::new(); (total_w * total_h) as usize];
+    for (map, (column, row)) in maps {
+        let left = (*column - min_column) as u32 * step_x as u32;
+        let top = (*row - min_row) as u32 * step_y as u32;
+
+        for (index, tile) in map.tiles().into_iter().enumerate() {
+            if let Some(point) = tile {
+                let (x, y) = crate::get_position(&index, &w, &h);
+                covering[((top + y) * total_w + left + x) as usize].push(point);
+            }
+        }
+    }
+
+    let mut tiles = vec![None; covering.len()];
+    for (i, points) in covering.into_iter().enumerate() {
+        let point = match (seam, points.first(), points.last()) {
+            (Seam::First, Some(&point), _) | (Seam::Last, _, Some(&point)) => point,
+            (Seam::Average, Some(&point), _) => {
+                let count = points.len() as f32;
+                let channel = |get: fn(&TilePoint) -> u8| (points.iter().map(|point| f32::from(get(point))).sum::<f32>() / count).round() as u8;
+                TilePoint {
+                    h: points.iter().map(|point| point.h).sum::<f32>() / count,
+                    r: channel(|point| point.r),
+                    g: channel(|point| point.g),
+                    b: channel(|point| point.b),
+                    ..point
+                }
+            }
+            _ => continue,
+        };
+
+        // Back from image order to the file's bottom-up rows.
+        let (x, y) = (i as u32 % total_w, i as u32 / total_w);
+        tiles[((total_h - 1 - y) * total_w + x) as usize] = Some(point);
+    }
+
+    let mut header = first.header.clone();
+    header.w = total_w;
+    header.h = total_h;
+    for (map, _) in maps {
+        header.min_height = header.min_height.min(map.header.min_height);
+        header.max_height = header.max_height.max(map.header.max_height);
+    }
+
+    let mut mosaic = Map { header, points: Vec::new(), enabled: Vec::new(), runs: Vec::new() };
+    mosaic.set_tiles(tiles);
+
+    Ok(mosaic)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Map;
+    use crate::fixture;
+    use crate::limits::Limits;
+
+    use super::Seam;
+    use super::stitch;
+
+    /// Two fully enabled fixture maps side by side, the second 100 higher.
+    fn neighbors() -> (Map, Map) {
+        let left = Map::from_reader(&fixture::map_bytes(&[11])[..]).unwrap();
+        let mut right = left.clone();
+        for point in &mut right.points {
+            point.h += 100f32;
+        }
+
+        (left, right)
+    }
+
+    fn stitched(seam: Seam, positions: [(i64, i64); 2]) -> Map {
+        let (left, right) = neighbors();
+        stitch(&[(left, positions[0]), (right, positions[1])], 1, seam, &Limits::default()).unwrap()
+    }
+
+    /// The image heights of the mosaic at column `x`, top to bottom.
+    fn column(map: &Map, x: u32) -> Vec<Option<f32>> {
+        let heights = map.tile_heights();
+        (0..map.header.h).map(|y| heights[(y * map.header.w + x) as usize]).collect()
+    }
+
+    #[test]
+    fn overlapping_neighbors_share_a_column() {
+        let mosaic = stitched(Seam::Average, [(0, 0), (1, 0)]);
+
+        assert_eq!((mosaic.header.w, mosaic.header.h), (2 * fixture::W - 1, fixture::H));
+    }
+
+    #[test]
+    fn seams_are_averaged_or_kept() {
+        let (left, right) = neighbors();
+        let seam = fixture::W - 1;
+        let both: Vec<Option<f32>> = column(&left, seam).into_iter().zip(column(&right, 0))
+            .map(|(a, b)| Some((a? + b?) / 2f32))
+            .collect();
+
+        assert_eq!(column(&stitched(Seam::Average, [(0, 0), (1, 0)]), seam), both);
+        assert_eq!(column(&stitched(Seam::First, [(0, 0), (1, 0)]), seam), column(&left, seam));
+        assert_eq!(column(&stitched(Seam::Last, [(0, 0), (1, 0)]), seam), column(&right, 0));
+        // Away from the seam each map keeps its own tiles.
+        assert_eq!(column(&stitched(Seam::Average, [(0, 0), (1, 0)]), 2 * seam), column(&right, seam));
+    }
+
+    #[test]
+    fn negative_positions_are_shifted_to_the_top_left() {
+        let shifted = stitched(Seam::First, [(-1, -2), (0, -2)]);
+        let expected = stitched(Seam::First, [(0, 0), (1, 0)]);
+
+        assert_eq!((shifted.header.w, shifted.header.h), (expected.header.w, expected.header.h));
+        assert_eq!(shifted.tile_heights(), expected.tile_heights());
+    }
+}
@@ -0,0 +1,55 @@
+use std::fs::OpenOptions;
+use std::io;
+use std::io::prelude::*;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::Map;
+
+const COLUMNS: &str = "file,name,width,height,min_height,max_height,coverage,outputs,duration_ms";
+
+/// Appends one row describing a conversion to a summary CSV, writing the
+/// column names first if the file is new, so that a loop over many maps
+/// builds up a single audit table.
+pub fn append(path: &Path, file_location: &str, map: &Map, outputs: &[PathBuf], duration: Duration) -> io::Result<()> {
+    append_rows(path, &[row(file_location, map, outputs, duration)])
+}
+
+/// Appends rows made by `row`, writing the column names first if the file
+/// is new.
+pub fn append_rows(path: &Path, rows: &[String]) -> io::Result<()> {
+    let mut csv = OpenOptions::new().create(true).append(true).open(path)?;
+
+    if csv.metadata()?.len() == 0 {
+        writeln!(csv, "{}", COLUMNS)?;
+    }
+
+    for row in rows {
+        writeln!(csv, "{}", row)?;
+    }
+
+    Ok(())
+}
+
+/// The row describing a conversion, without the line break.
+pub fn row(file_location: &str, map: &Map, outputs: &[PathBuf], duration: Duration) -> String {
+    let outputs = outputs.iter()
+        .map(|output| output.display().to_string())
+        .collect::<Vec<_>>()
+        .join(";");
+    let coverage = map.points.len() as f64 / (map.enabled.len().max(1)) as f64;
+
+    format!("{},{},{},{},{},{},{},{},{}",
+            field(file_location), field(&map.header.name), map.header.w, map.header.h,
+            map.header.min_height, map.header.max_height, coverage, field(&outputs), duration.as_millis())
+}
+
+/// Quotes a CSV field if it contains a separator, quote or line break.
+fn field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        String::from(value)
+    }
+}
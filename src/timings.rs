@@ -0,0 +1,90 @@
+use std::cell::RefCell;
+use std::fs;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::status;
+
+thread_local! {
+    /// The stages measured on this thread since `start`, `None` when
+    /// nothing is being recorded.
+    static STAGES: RefCell<Option<Vec<Stage>>> = const { RefCell::new(None) };
+}
+
+/// The time spent in a stage of converting a map and the most memory the
+/// process held while in it.
+#[derive(Debug, Clone)]
+pub struct Stage {
+    pub name: &'static str,
+    pub duration: Duration,
+    /// The peak resident set size in bytes, where the system reports it.
+    pub peak_memory: Option<u64>,
+}
+
+/// Starts recording the stages measured on this thread, dropping any
+/// recorded before.
+pub fn start() {
+    STAGES.with(|stages| *stages.borrow_mut() = Some(Vec::new()));
+}
+
+/// Stops recording and returns the stages in the order they first ran.
+pub fn finish() -> Vec<Stage> {
+    STAGES.with(|stages| stages.borrow_mut().take()).unwrap_or_default()
+}
+
+/// Runs `f` as part of the stage `name` if this thread is recording.
+/// Repeated stages add up their durations and keep the highest peak.
+///
+/// The peak is of the whole process, so it includes maps converted on
+/// other threads at the same time.
+pub fn measure<T>(name: &'static str, f: impl FnOnce() -> T) -> T {
+    if !STAGES.with(|stages| stages.borrow().is_some()) {
+        return f();
+    }
+
+    reset_peak_memory();
+    let started = Instant::now();
+    let result = f();
+    let duration = started.elapsed();
+    let peak_memory = peak_memory();
+
+    STAGES.with(|stages| {
+        if let Some(stages) = stages.borrow_mut().as_mut() {
+            match stages.iter_mut().find(|stage| stage.name == name) {
+                Some(stage) => {
+                    stage.duration += duration;
+                    stage.peak_memory = stage.peak_memory.max(peak_memory);
+                }
+                None => stages.push(Stage { name, duration, peak_memory }),
+            }
+        }
+    });
+
+    result
+}
+
+/// Prints the stages of converting `file` as a small table.
+pub fn print(file: &str, stages: &[Stage]) {
+    status!("Timings for {}:", file);
+    for stage in stages {
+        let peak = stage.peak_memory
+            .map_or_else(|| String::from("n/a"), |bytes| format!("{:.1} MiB", bytes as f64 / (1024f64 * 1024f64)));
+        status!("  {:<8} {:>10.2?}  peak {}", stage.name, stage.duration, peak);
+    }
+}
+
+/// Starts the kernel's peak memory count over at the current usage, so the
+/// next reading covers only what comes after. Linux only.
+fn reset_peak_memory() {
+    let _ = fs::write("/proc/self/clear_refs", "5");
+}
+
+/// The peak resident set size in bytes since the last reset, from
+/// `/proc/self/status`.
+fn peak_memory() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes: u64 = line.trim_start_matches("VmHWM:").trim().trim_end_matches("kB").trim().parse().ok()?;
+
+    Some(kilobytes * 1024)
+}
@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use byteorder::ByteOrder;
+use byteorder::LE;
+use serde_json::Value;
+use serde_json::json;
+
+/// The notes that ship with the program.
+const BUILTIN_NOTES: &str = include_str!("../fields.toml");
+
+/// The unidentified header fields: their placeholder name, where they are in
+/// the header and how they have been read so far.
+const LAYOUT: [(&str, usize, Kind); 11] = [
+    ("u1", 0x08, Kind::F32),
+    ("u2", 0x0c, Kind::F32),
+    ("u5", 0x20, Kind::F32),
+    ("u6", 0x24, Kind::F32),
+    ("u7", 0x28, Kind::F32),
+    ("u8", 0x2c, Kind::F32),
+    ("u9", 0x30, Kind::F32),
+    ("us1", 0x34, Kind::U16),
+    ("us2", 0x36, Kind::U16),
+    ("u10", 0x38, Kind::F32),
+    ("u11", 0x3c, Kind::F32),
+];
+
+/// How the raw bytes of a field are shown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    F32,
+    U32,
+    I32,
+    U16,
+    I16,
+    Hex,
+}
+
+impl Kind {
+    /// The number of bytes the kind reads, `None` if it takes any.
+    fn size(self) -> Option<usize> {
+        match self {
+            Kind::F32 | Kind::U32 | Kind::I32 => Some(4),
+            Kind::U16 | Kind::I16 => Some(2),
+            Kind::Hex => None,
+        }
+    }
+}
+
+impl Kind {
+    /// The name the kind goes by in field notes.
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::F32 => "f32",
+            Kind::U32 => "u32",
+            Kind::I32 => "i32",
+            Kind::U16 => "u16",
+            Kind::I16 => "i16",
+            Kind::Hex => "hex",
+        }
+    }
+}
+
+impl FromStr for Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Kind, String> {
+        match s {
+            "f32" => Ok(Kind::F32),
+            "u32" => Ok(Kind::U32),
+            "i32" => Ok(Kind::I32),
+            "u16" => Ok(Kind::U16),
+            "i16" => Ok(Kind::I16),
+            "hex" => Ok(Kind::Hex),
+            _ => Err(format!("Unknown field kind '{}', expected f32, u32, i32, u16, i16 or hex", s)),
+        }
+    }
+}
+
+/// A header field whose meaning isn't known, kept as its raw bytes so it is
+/// written back exactly as it was read.
+#[derive(Clone, PartialEq)]
+pub struct UnknownField {
+    pub name: &'static str,
+    pub offset: usize,
+    pub raw: Vec<u8>,
+    pub kind: Kind,
+    pub meaning: Option<String>,
+}
+
+impl UnknownField {
+    /// The bytes read as the field's kind.
+    pub fn value(&self) -> String {
+        match self.kind {
+            Kind::F32 => format!("{:?}", LE::read_f32(&self.raw)),
+            Kind::U32 => LE::read_u32(&self.raw).to_string(),
+            Kind::I32 => LE::read_i32(&self.raw).to_string(),
+            Kind::U16 => LE::read_u16(&self.raw).to_string(),
+            Kind::I16 => LE::read_i16(&self.raw).to_string(),
+            Kind::Hex => self.raw.iter().map(|byte| format!("{:02x}", byte)).collect(),
+        }
+    }
+
+    /// The field as a JSON object of its offset, kind, value and meaning.
+    /// Values are numbers where the kind reads one, and `null` for floats
+    /// that JSON has no number for.
+    pub fn to_json(&self) -> Value {
+        let value = match self.kind {
+            Kind::F32 => json!(LE::read_f32(&self.raw)),
+            Kind::U32 => json!(LE::read_u32(&self.raw)),
+            Kind::I32 => json!(LE::read_i32(&self.raw)),
+            Kind::U16 => json!(LE::read_u16(&self.raw)),
+            Kind::I16 => json!(LE::read_i16(&self.raw)),
+            Kind::Hex => json!(self.value()),
+        };
+
+        json!({
+            "offset": self.offset,
+            "kind": self.kind.name(),
+            "value": value,
+            "meaning": self.meaning,
+        })
+    }
+}
+
+impl fmt::Debug for UnknownField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {:#04x}", self.value(), self.offset)?;
+
+        match &self.meaning {
+            Some(meaning) => write!(f, " ({})", meaning),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Every unidentified field of a header, in file order.
+#[derive(Clone, PartialEq)]
+pub struct UnknownFields {
+    fields: Vec<UnknownField>,
+}
+
+impl UnknownFields {
+    /// Picks the unknown fields out of the header bytes before the name,
+    /// annotated with the built-in notes.
+    pub fn from_bytes(raw: &[u8]) -> UnknownFields {
+        let fields = LAYOUT.iter()
+            .map(|&(name, offset, kind)| UnknownField {
+                name,
+                offset,
+                raw: raw[offset..offset + kind.size().unwrap_or_default()].to_vec(),
+                kind,
+                meaning: None,
+            })
+            .collect();
+
+        let mut unknowns = UnknownFields { fields };
+        unknowns.annotate(FieldNotes::builtin());
+        unknowns
+    }
+
+    /// Puts the raw bytes back in their place in the header.
+    pub fn write_to(&self, raw: &mut [u8]) {
+        for field in &self.fields {
+            raw[field.offset..field.offset + field.raw.len()].copy_from_slice(&field.raw);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &UnknownField> {
+        self.fields.iter()
+    }
+
+    /// The field with the placeholder `name`.
+    pub fn get(&self, name: &str) -> Option<&UnknownField> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// Applies the meaning and kind the notes give each field.
+    pub fn annotate(&mut self, notes: &FieldNotes) {
+        for field in &mut self.fields {
+            if let Some(note) = notes.notes.get(field.name) {
+                if let Some(meaning) = &note.meaning {
+                    field.meaning = Some(meaning.clone());
+                }
+                if let Some(kind) = note.kind {
+                    field.kind = kind;
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for UnknownFields {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.fields.iter().map(|field| (field.name, field))).finish()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct FieldNote {
+    meaning: Option<String>,
+    kind: Option<Kind>,
+}
+
+/// What the community has worked out about the unknown fields, read from a
+/// TOML file of `[name]` tables with an optional `meaning` and `kind`.
+#[derive(Debug, Clone, Default)]
+pub struct FieldNotes {
+    notes: HashMap<String, FieldNote>,
+}
+
+impl FieldNotes {
+    /// The notes in `fields.toml`, built into the program.
+    pub fn builtin() -> &'static FieldNotes {
+        static NOTES: OnceLock<FieldNotes> = OnceLock::new();
+
+        NOTES.get_or_init(|| FieldNotes::parse(BUILTIN_NOTES).expect("The built-in field notes are invalid"))
+    }
+
+    /// The notes in the file at `path`.
+    pub fn load(path: &Path) -> io::Result<FieldNotes> {
+        let text = fs::read_to_string(path)?;
+
+        FieldNotes::parse(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData,
+                                                            format!("{}: {}", path.display(), e)))
+    }
+
+    fn parse(text: &str) -> Result<FieldNotes, String> {
+        let table: toml::Table = text.parse().map_err(|e: toml::de::Error| e.message().to_string())?;
+        let mut notes = HashMap::new();
+
+        for (name, value) in table {
+            let (offset, kind) = match LAYOUT.iter().find(|(field, _, _)| *field == name) {
+                Some(&(_, offset, kind)) => (offset, kind),
+                None => return Err(format!("There is no unknown header field named '{}'", name)),
+            };
+            let value = value.as_table().ok_or_else(|| format!("The notes on {} have to be a table", name))?;
+
+            let mut note = FieldNote::default();
+            for (key, value) in value {
+                match key.as_str() {
+                    "meaning" => note.meaning = Some(value.as_str()
+                        .ok_or_else(|| format!("The meaning of {} has to be a string", name))?
+                        .to_string()),
+                    "kind" => {
+                        let text = value.as_str()
+                            .ok_or_else(|| format!("The kind of {} has to be a string", name))?;
+                        let new_kind: Kind = text.parse()?;
+
+                        if new_kind.size().is_some_and(|size| Some(size) != kind.size()) {
+                            return Err(format!("{} at {:#04x} is {} bytes long, it can't be read as {}",
+                                               name, offset, kind.size().unwrap_or_default(), text));
+                        }
+                        note.kind = Some(new_kind);
+                    }
+                    _ => return Err(format!("Unknown note '{}' on {}, expected meaning or kind", key, name)),
+                }
+            }
+
+            notes.insert(name, note);
+        }
+
+        Ok(FieldNotes { notes })
+    }
+}